@@ -17,6 +17,7 @@ pub struct Deserializer<'de> {
     input: &'de [u8],
     last_parsed_len: Option<usize>,
     cursor: usize,
+    context: bool,
 }
 
 impl<'de> Deserializer<'de> {
@@ -30,6 +31,26 @@ impl<'de> Deserializer<'de> {
             input,
             last_parsed_len: None,
             cursor: 0,
+            context: false,
+        }
+    }
+
+    // When enabled, errors are wrapped in `Error::Context` carrying the byte
+    // offset of the failure and the names of the types being decoded.
+    pub fn with_context(mut self, context: bool) -> Self {
+        self.context = context;
+        self
+    }
+
+    fn decoding<T>(
+        &mut self,
+        name: &'static str,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let res = f(self);
+        match res {
+            Err(err) if self.context => Err(err.within(name, self.cursor)),
+            res => res,
         }
     }
 }
@@ -52,17 +73,37 @@ where
     }
 }
 
+// Same as `from_bytes`, but errors carry the byte offset at which decoding
+// failed and the types being decoded, e.g. "unexpected end of input at
+// offset 42 while decoding LongStr in FieldValue in FieldTable".
+pub fn from_bytes_with_context<'a, T>(s: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_bytes(s).with_context(true);
+    let t = T::deserialize(&mut deserializer)?;
+    if deserializer.input.is_empty() {
+        Ok(t)
+    } else {
+        Err(Error::Context {
+            source: Box::new(Error::Incomplete),
+            offset: deserializer.cursor,
+            types: vec![],
+        })
+    }
+}
+
 /////////////////////////////////////////////////////////////////////////////
 macro_rules! impl_inner {
     ($self:ident, $typ:tt, $($index:literal),+) => {{
         let size = std::mem::size_of::<$typ>();
         $self.last_parsed_len = None;
-        $self.cursor += size;
         if $self.input.len() < size {
             Err(Error::Eof)
         } else {
             let bytes = [$($self.input[$index],)+];
             $self.input = &$self.input[size..];
+            $self.cursor += size;
             Ok(<$typ>::from_be_bytes(bytes))
         }}
     };
@@ -147,12 +188,13 @@ impl<'de> Deserializer<'de> {
 
     fn parse_string(&mut self) -> Result<&'de str> {
         let len = self.get_parsed_length()?;
-        self.cursor += len;
 
         let s = &self.input[..len];
+        let s = std::str::from_utf8(s)
+            .map_err(|_| Error::Message(format!("len = {}, content = {:02X?}", len, s)))?;
         self.input = &self.input[len..];
-        std::str::from_utf8(s)
-            .map_err(|_| Error::Message(format!("len = {}, content = {:02X?}", len, s)))
+        self.cursor += len;
+        Ok(s)
     }
 
     fn next_bytes(&mut self) -> Result<&'de [u8]> {
@@ -340,11 +382,11 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_unit(visitor)
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_newtype_struct(self)
+        self.decoding(name, |de| visitor.visit_newtype_struct(de))
     }
 
     // no length input, so expect length prefix
@@ -368,14 +410,16 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     // given length input, do not need length prefix in input data
     fn deserialize_tuple_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         len: usize,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_seq(DataSequence::new_struct(self, len))
+        self.decoding(name, |de| {
+            visitor.visit_seq(DataSequence::new_struct(de, len))
+        })
     }
 
     // Much like `deserialize_seq` but calls the visitors `visit_map` method
@@ -394,26 +438,28 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     // length can be derived by fields
     fn deserialize_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_seq(DataSequence::new_struct(self, fields.len()))
+        self.decoding(name, |de| {
+            visitor.visit_seq(DataSequence::new_struct(de, fields.len()))
+        })
     }
 
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_enum(Enum::new(self))
+        self.decoding(name, |de| visitor.visit_enum(Enum::new(de)))
     }
 
     // An identifier in Serde is the type that identifies a field of a struct or
@@ -455,11 +501,19 @@ struct DataSequence<'a, 'de: 'a> {
 
 impl<'a, 'de> DataSequence<'a, 'de> {
     fn new(de: &'a mut Deserializer<'de>, len: usize) -> Self {
-        DataSequence { de, len, is_struct: false }
+        DataSequence {
+            de,
+            len,
+            is_struct: false,
+        }
     }
 
     fn new_struct(de: &'a mut Deserializer<'de>, len: usize) -> Self {
-        DataSequence { de, len, is_struct: true }
+        DataSequence {
+            de,
+            len,
+            is_struct: true,
+        }
     }
 }
 
@@ -544,18 +598,19 @@ impl<'de, 'a> EnumAccess<'de> for Enum<'a, 'de> {
     where
         V: DeserializeSeed<'de>,
     {
-        match self.de.next_byte()? {
+        match self.de.peek_byte()? {
             v if v.is_ascii_alphabetic() => {
                 let val = [v];
 
                 let variant = unsafe { std::str::from_utf8_unchecked(&val[..]) };
                 let val = seed.deserialize(variant.into_deserializer())?;
+                self.de.next_byte()?;
                 Ok((val, self))
             }
-            v => panic!(
-                "unsupported enum variant for AMQP field value: {}, cursor: {}",
-                v, self.de.cursor
-            ),
+            v => Err(Error::Message(format!(
+                "unsupported enum variant for AMQP field value: {:#04x}",
+                v
+            ))),
         }
     }
 }
@@ -595,51 +650,54 @@ impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
 
 #[cfg(test)]
 mod tests {
-    use crate::de::from_bytes;
+    use crate::de::{from_bytes, from_bytes_with_context};
+    use crate::error::Error;
     use crate::types::*;
     use serde::Deserialize;
 
     #[test]
     fn test_array() {
         let input = vec![
-            0x41, 0x00, 0x00, 0x01, 0x7c, 0x53, 0x00, 0x00, 0x00, 0x32, 0x7b, 0x3c, 0x3c, 0x22, 0x63, 0x6f,
-            0x6e, 0x6e, 0x65, 0x63, 0x74, 0x69, 0x6f, 0x6e, 0x5f, 0x6e, 0x61, 0x6d, 0x65, 0x22, 0x3e, 0x3e,
-            0x2c, 0x6c, 0x6f, 0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22, 0x30, 0x45, 0x30, 0x45,
-            0x30, 0x45, 0x42, 0x30, 0x42, 0x30, 0x32, 0x30, 0x22, 0x3e, 0x3e, 0x7d, 0x53, 0x00, 0x00, 0x00,
-            0x26, 0x7b, 0x3c, 0x3c, 0x22, 0x63, 0x6f, 0x70, 0x79, 0x72, 0x69, 0x67, 0x68, 0x74, 0x22, 0x3e,
-            0x3e, 0x2c, 0x6c, 0x6f, 0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22, 0x47, 0x61, 0x6c,
-            0x67, 0x75, 0x73, 0x22, 0x3e, 0x3e, 0x7d, 0x53, 0x00, 0x00, 0x00, 0x25, 0x7b, 0x3c, 0x3c, 0x22,
-            0x68, 0x6f, 0x73, 0x74, 0x6e, 0x61, 0x6d, 0x65, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f, 0x6e, 0x67,
-            0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22, 0x47, 0x61, 0x6c, 0x67, 0x75, 0x73, 0x22, 0x3e, 0x3e,
-            0x7d, 0x53, 0x00, 0x00, 0x00, 0x21, 0x7b, 0x3c, 0x3c, 0x22, 0x70, 0x72, 0x6f, 0x64, 0x75, 0x63,
-            0x74, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f, 0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22,
-            0x43, 0x48, 0x54, 0x22, 0x3e, 0x3e, 0x7d, 0x53, 0x00, 0x00, 0x00, 0x38, 0x7b, 0x3c, 0x3c, 0x22,
-            0x69, 0x6e, 0x66, 0x6f, 0x72, 0x6d, 0x61, 0x74, 0x69, 0x6f, 0x6e, 0x22, 0x3e, 0x3e, 0x2c, 0x6c,
-            0x6f, 0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22, 0x68, 0x74, 0x74, 0x70, 0x73, 0x3a,
-            0x2f, 0x2f, 0x77, 0x77, 0x77, 0x2e, 0x67, 0x61, 0x6c, 0x67, 0x75, 0x73, 0x2e, 0x6e, 0x65, 0x74,
-            0x22, 0x3e, 0x3e, 0x7d, 0x53, 0x00, 0x00, 0x00, 0x29, 0x7b, 0x3c, 0x3c, 0x22, 0x76, 0x65, 0x72,
-            0x73, 0x69, 0x6f, 0x6e, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f, 0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c,
-            0x3c, 0x3c, 0x22, 0x4d, 0x6f, 0x63, 0x6b, 0x53, 0x65, 0x72, 0x76, 0x69, 0x63, 0x65, 0x22, 0x3e,
-            0x3e, 0x7d, 0x53, 0x00, 0x00, 0x00, 0x29, 0x7b, 0x3c, 0x3c, 0x22, 0x70, 0x6c, 0x61, 0x74, 0x66,
-            0x6f, 0x72, 0x6d, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f, 0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c,
-            0x3c, 0x22, 0x41, 0x58, 0x31, 0x38, 0x30, 0x30, 0x59, 0x43, 0x58, 0x54, 0x22, 0x3e, 0x3e, 0x7d,
-            0x53, 0x00, 0x00, 0x00, 0x2c, 0x7b, 0x3c, 0x3c, 0x22, 0x65, 0x63, 0x6f, 0x76, 0x65, 0x72, 0x73,
-            0x69, 0x6f, 0x6e, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f, 0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c,
-            0x3c, 0x22, 0x4d, 0x6f, 0x63, 0x6b, 0x53, 0x65, 0x72, 0x76, 0x69, 0x63, 0x65, 0x22, 0x3e, 0x3e,
-            0x7d,
+            0x41, 0x00, 0x00, 0x01, 0x7c, 0x53, 0x00, 0x00, 0x00, 0x32, 0x7b, 0x3c, 0x3c, 0x22,
+            0x63, 0x6f, 0x6e, 0x6e, 0x65, 0x63, 0x74, 0x69, 0x6f, 0x6e, 0x5f, 0x6e, 0x61, 0x6d,
+            0x65, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f, 0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c,
+            0x3c, 0x22, 0x30, 0x45, 0x30, 0x45, 0x30, 0x45, 0x42, 0x30, 0x42, 0x30, 0x32, 0x30,
+            0x22, 0x3e, 0x3e, 0x7d, 0x53, 0x00, 0x00, 0x00, 0x26, 0x7b, 0x3c, 0x3c, 0x22, 0x63,
+            0x6f, 0x70, 0x79, 0x72, 0x69, 0x67, 0x68, 0x74, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f,
+            0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22, 0x47, 0x61, 0x6c, 0x67, 0x75,
+            0x73, 0x22, 0x3e, 0x3e, 0x7d, 0x53, 0x00, 0x00, 0x00, 0x25, 0x7b, 0x3c, 0x3c, 0x22,
+            0x68, 0x6f, 0x73, 0x74, 0x6e, 0x61, 0x6d, 0x65, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f,
+            0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22, 0x47, 0x61, 0x6c, 0x67, 0x75,
+            0x73, 0x22, 0x3e, 0x3e, 0x7d, 0x53, 0x00, 0x00, 0x00, 0x21, 0x7b, 0x3c, 0x3c, 0x22,
+            0x70, 0x72, 0x6f, 0x64, 0x75, 0x63, 0x74, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f, 0x6e,
+            0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22, 0x43, 0x48, 0x54, 0x22, 0x3e, 0x3e,
+            0x7d, 0x53, 0x00, 0x00, 0x00, 0x38, 0x7b, 0x3c, 0x3c, 0x22, 0x69, 0x6e, 0x66, 0x6f,
+            0x72, 0x6d, 0x61, 0x74, 0x69, 0x6f, 0x6e, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f, 0x6e,
+            0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22, 0x68, 0x74, 0x74, 0x70, 0x73, 0x3a,
+            0x2f, 0x2f, 0x77, 0x77, 0x77, 0x2e, 0x67, 0x61, 0x6c, 0x67, 0x75, 0x73, 0x2e, 0x6e,
+            0x65, 0x74, 0x22, 0x3e, 0x3e, 0x7d, 0x53, 0x00, 0x00, 0x00, 0x29, 0x7b, 0x3c, 0x3c,
+            0x22, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f,
+            0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22, 0x4d, 0x6f, 0x63, 0x6b, 0x53,
+            0x65, 0x72, 0x76, 0x69, 0x63, 0x65, 0x22, 0x3e, 0x3e, 0x7d, 0x53, 0x00, 0x00, 0x00,
+            0x29, 0x7b, 0x3c, 0x3c, 0x22, 0x70, 0x6c, 0x61, 0x74, 0x66, 0x6f, 0x72, 0x6d, 0x22,
+            0x3e, 0x3e, 0x2c, 0x6c, 0x6f, 0x6e, 0x67, 0x73, 0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22,
+            0x41, 0x58, 0x31, 0x38, 0x30, 0x30, 0x59, 0x43, 0x58, 0x54, 0x22, 0x3e, 0x3e, 0x7d,
+            0x53, 0x00, 0x00, 0x00, 0x2c, 0x7b, 0x3c, 0x3c, 0x22, 0x65, 0x63, 0x6f, 0x76, 0x65,
+            0x72, 0x73, 0x69, 0x6f, 0x6e, 0x22, 0x3e, 0x3e, 0x2c, 0x6c, 0x6f, 0x6e, 0x67, 0x73,
+            0x74, 0x72, 0x2c, 0x3c, 0x3c, 0x22, 0x4d, 0x6f, 0x63, 0x6b, 0x53, 0x65, 0x72, 0x76,
+            0x69, 0x63, 0x65, 0x22, 0x3e, 0x3e, 0x7d,
         ];
-        let f_array = FieldArray::try_from(
-            vec!(
-                FieldValue::from("{<<\"connection_name\">>,longstr,<<\"0E0E0EB0B020\">>}"),
-                FieldValue::from("{<<\"copyright\">>,longstr,<<\"Galgus\">>}"),
-                FieldValue::from("{<<\"hostname\">>,longstr,<<\"Galgus\">>}"),
-                FieldValue::from("{<<\"product\">>,longstr,<<\"CHT\">>}"),
-                FieldValue::from("{<<\"information\">>,longstr,<<\"https://www.galgus.net\">>}"),
-                FieldValue::from("{<<\"version\">>,longstr,<<\"MockService\">>}"),
-                FieldValue::from("{<<\"platform\">>,longstr,<<\"AX1800YCXT\">>}"),
-                FieldValue::from("{<<\"ecoversion\">>,longstr,<<\"MockService\">>}"),
-            )
-        ).unwrap();
+        let f_array = FieldArray::try_from(vec![
+            FieldValue::from("{<<\"connection_name\">>,longstr,<<\"0E0E0EB0B020\">>}"),
+            FieldValue::from("{<<\"copyright\">>,longstr,<<\"Galgus\">>}"),
+            FieldValue::from("{<<\"hostname\">>,longstr,<<\"Galgus\">>}"),
+            FieldValue::from("{<<\"product\">>,longstr,<<\"CHT\">>}"),
+            FieldValue::from("{<<\"information\">>,longstr,<<\"https://www.galgus.net\">>}"),
+            FieldValue::from("{<<\"version\">>,longstr,<<\"MockService\">>}"),
+            FieldValue::from("{<<\"platform\">>,longstr,<<\"AX1800YCXT\">>}"),
+            FieldValue::from("{<<\"ecoversion\">>,longstr,<<\"MockService\">>}"),
+        ])
+        .unwrap();
         let expected = FieldValue::A(f_array);
         let result: FieldValue = from_bytes(&input).unwrap();
         assert_eq!(expected, result);
@@ -750,6 +808,44 @@ mod tests {
         let _: Frame = from_bytes(&input[..]).unwrap();
     }
 
+    #[test]
+    fn test_error_context_truncated_long_str() {
+        // table of 10 bytes: key "k", value LongStr of 10 bytes with only 3 bytes present
+        let input = [0, 0, 0, 10, 1, b'k', b'S', 0, 0, 0, 10, b'a', b'b', b'c'];
+
+        let err = from_bytes::<FieldTable>(&input).unwrap_err();
+        assert!(matches!(err, Error::Syntax));
+
+        let err = from_bytes_with_context::<FieldTable>(&input).unwrap_err();
+        assert_eq!(Some(11), err.offset());
+        assert_eq!(
+            "unexpected syntax at offset 11 while decoding LongStr in FieldValue in FieldTable",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_context_unsupported_field_value() {
+        let input = [0, 0, 0, 3, 1, b'k', 0x01];
+
+        let err = from_bytes_with_context::<FieldTable>(&input).unwrap_err();
+        assert_eq!(Some(6), err.offset());
+        assert_eq!(
+            "unsupported enum variant for AMQP field value: 0x01 at offset 6 \
+            while decoding FieldValue in FieldTable",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_context_incomplete() {
+        let input = [0x03, b'a', b'b', b'c', 0xff];
+
+        let err = from_bytes_with_context::<ShortStr>(&input).unwrap_err();
+        assert_eq!(Some(4), err.offset());
+        assert_eq!("incomplete deserializaton at offset 4", err.to_string());
+    }
+
     #[test]
     fn test_deserialize_bytes_zero_copy() {
         #[derive(Deserialize)]
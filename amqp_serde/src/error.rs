@@ -16,6 +16,7 @@ pub type Result<T> = std::result::Result<T, Error>;
 // error occurred, the byte offset into the input, or the current key being
 // processed.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     // One or more variants that can be created by data structures through the
     // `ser::Error` and `de::Error` traits. For example the Serialize impl for
@@ -31,6 +32,49 @@ pub enum Error {
     Syntax,
     Incomplete,
     ExpectedLength,
+
+    // Wraps an error raised by the Deserializer with the byte offset into the
+    // input at which it failed and the types being decoded, innermost first.
+    // Only produced when context is enabled, see `from_bytes_with_context`.
+    Context {
+        source: Box<Error>,
+        offset: usize,
+        types: Vec<&'static str>,
+    },
+}
+
+impl Error {
+    // Attach the offset and the type being decoded, or record the enclosing
+    // type if the error already carries context.
+    pub(crate) fn within(self, name: &'static str, offset: usize) -> Self {
+        match self {
+            Error::Context {
+                source,
+                offset,
+                mut types,
+            } => {
+                types.push(name);
+                Error::Context {
+                    source,
+                    offset,
+                    types,
+                }
+            }
+            source => Error::Context {
+                source: Box::new(source),
+                offset,
+                types: vec![name],
+            },
+        }
+    }
+
+    /// Byte offset into the input at which deserialization failed, if known.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            Error::Context { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
 }
 
 impl ser::Error for Error {
@@ -53,8 +97,26 @@ impl fmt::Display for Error {
             Error::Syntax => f.write_str("unexpected syntax"),
             Error::Incomplete => f.write_str("incomplete deserializaton"),
             Error::ExpectedLength => f.write_str("expect length value before raw bytes"),
+            Error::Context {
+                source,
+                offset,
+                types,
+            } => {
+                write!(f, "{} at offset {}", source, offset)?;
+                if !types.is_empty() {
+                    write!(f, " while decoding {}", types.join(" in "))?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -4,6 +4,6 @@ mod ser;
 
 ///////////////////////////////////////////////
 pub mod types;
-pub use de::{from_bytes, Deserializer};
+pub use de::{from_bytes, from_bytes_with_context, Deserializer};
 pub use error::{Error, Result};
 pub use ser::{to_buffer, to_bytes, Serializer};
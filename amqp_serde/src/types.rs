@@ -3,6 +3,8 @@
 //! See [RabbitMQ's Definition](https://github.com/rabbitmq/rabbitmq-codegen/blob/main/amqp-rabbitmq-0.9.1.json).
 //!
 //! See [RabbitMQ errata](https://www.rabbitmq.com/amqp-0-9-1-errata.html)
+use bytes::BufMut;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     convert::TryFrom,
@@ -10,7 +12,6 @@ use std::{
     mem::{size_of, size_of_val},
     num::TryFromIntError,
 };
-use serde::{Deserialize, Serialize};
 
 /// DO NOT USE. No primitive rust type to represent single bit.
 ///
@@ -209,7 +210,9 @@ impl TryFrom<Vec<FieldValue>> for FieldArray {
     type Error = TryFromIntError;
 
     fn try_from(values: Vec<FieldValue>) -> Result<Self, Self::Error> {
-        let total_bytes = values.iter().fold(0, |acc, v| acc + FieldValue::TAG_SIZE + v.len());
+        let total_bytes = values
+            .iter()
+            .fold(0, |acc, v| acc + FieldValue::TAG_SIZE + v.len());
         let len = LongUint::try_from(total_bytes)?;
         Ok(Self(len, values))
     }
@@ -219,6 +222,11 @@ impl From<FieldArray> for Vec<FieldValue> {
         arr.1
     }
 }
+impl AsRef<[FieldValue]> for FieldArray {
+    fn as_ref(&self) -> &[FieldValue] {
+        &self.1
+    }
+}
 
 impl fmt::Display for FieldArray {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -282,23 +290,137 @@ impl FieldValue {
 
     fn len(&self) -> usize {
         match self {
-            Self::V => 0, // fixed size
-            Self::t(_) => size_of::<Boolean>(), // fixed size
-            Self::b(_) => size_of::<ShortShortInt>(), // fixed size
-            Self::B(_) => size_of::<ShortShortUint>(), // fixed size
-            Self::s(_) => size_of::<ShortInt>(), // fixed size
-            Self::u(_) => size_of::<ShortUint>(), // fixed size
-            Self::I(_) => size_of::<LongInt>(), // fixed size
-            Self::i(_) => size_of::<LongUint>(), // fixed size
-            Self::l(_) => size_of::<LongLongInt>(), // fixed size
-            Self::f(_) => size_of::<Float>(), // fixed size
-            Self::d(_) => size_of::<Double>(), // fixed size
-            Self::T(_) => size_of::<TimeStamp>(), // fixed size
+            Self::V => 0,                                        // fixed size
+            Self::t(_) => size_of::<Boolean>(),                  // fixed size
+            Self::b(_) => size_of::<ShortShortInt>(),            // fixed size
+            Self::B(_) => size_of::<ShortShortUint>(),           // fixed size
+            Self::s(_) => size_of::<ShortInt>(),                 // fixed size
+            Self::u(_) => size_of::<ShortUint>(),                // fixed size
+            Self::I(_) => size_of::<LongInt>(),                  // fixed size
+            Self::i(_) => size_of::<LongUint>(),                 // fixed size
+            Self::l(_) => size_of::<LongLongInt>(),              // fixed size
+            Self::f(_) => size_of::<Float>(),                    // fixed size
+            Self::d(_) => size_of::<Double>(),                   // fixed size
+            Self::T(_) => size_of::<TimeStamp>(),                // fixed size
             Self::D(v) => size_of_val(&v.0) + size_of_val(&v.1), // fixed size
-            Self::S(v) => size_of_val(&v.0) + v.0 as usize, // variable size
-            Self::A(v) => size_of_val(&v.0) + v.0 as usize, // variable size
-            Self::F(v) => size_of_val(&v.0) + v.0 as usize, // variable size
-            Self::x(v) => size_of_val(&v.0) + v.0 as usize, // variable size
+            Self::S(v) => size_of_val(&v.0) + v.0 as usize,      // variable size
+            Self::A(v) => size_of_val(&v.0) + v.0 as usize,      // variable size
+            Self::F(v) => size_of_val(&v.0) + v.0 as usize,      // variable size
+            Self::x(v) => size_of_val(&v.0) + v.0 as usize,      // variable size
+        }
+    }
+}
+
+impl FieldValue {
+    /// Number of bytes of the encoded value, including its type tag.
+    pub fn encoded_len(&self) -> usize {
+        Self::TAG_SIZE + self.len()
+    }
+
+    /// Encode the value into `buf`, e.g. a buffer reused for building frames.
+    ///
+    /// Produces the same bytes as serializing the value by [`to_bytes`](crate::to_bytes),
+    /// without allocating intermediate buffers. Reserve [`encoded_len`](Self::encoded_len)
+    /// bytes beforehand to avoid growing `buf` while encoding.
+    ///
+    /// # Usage
+    ///
+    /// ```
+    /// # use amqp_serde::types::FieldValue;
+    /// let mut buf = Vec::new();
+    /// FieldValue::u(7).encode_into(&mut buf);
+    /// assert_eq!(vec![b'u', 0, 7], buf);
+    /// ```
+    pub fn encode_into<B: BufMut>(&self, buf: &mut B) {
+        match self {
+            Self::t(v) => {
+                buf.put_u8(b't');
+                buf.put_u8(*v as u8);
+            }
+            Self::b(v) => {
+                buf.put_u8(b'b');
+                buf.put_i8(*v);
+            }
+            Self::B(v) => {
+                buf.put_u8(b'B');
+                buf.put_u8(*v);
+            }
+            Self::s(v) => {
+                buf.put_u8(b's');
+                buf.put_i16(*v);
+            }
+            Self::u(v) => {
+                buf.put_u8(b'u');
+                buf.put_u16(*v);
+            }
+            Self::I(v) => {
+                buf.put_u8(b'I');
+                buf.put_i32(*v);
+            }
+            Self::i(v) => {
+                buf.put_u8(b'i');
+                buf.put_u32(*v);
+            }
+            Self::l(v) => {
+                buf.put_u8(b'l');
+                buf.put_i64(*v);
+            }
+            Self::f(v) => {
+                buf.put_u8(b'f');
+                buf.put_f32(*v);
+            }
+            Self::d(v) => {
+                buf.put_u8(b'd');
+                buf.put_f64(*v);
+            }
+            Self::D(v) => {
+                buf.put_u8(b'D');
+                buf.put_u8(v.0);
+                buf.put_i32(v.1);
+            }
+            Self::S(v) => {
+                buf.put_u8(b'S');
+                buf.put_u32(v.0);
+                buf.put_slice(v.1.as_bytes());
+            }
+            Self::A(v) => {
+                buf.put_u8(b'A');
+                v.encode_into(buf);
+            }
+            Self::T(v) => {
+                buf.put_u8(b'T');
+                buf.put_u64(*v);
+            }
+            Self::F(v) => {
+                buf.put_u8(b'F');
+                v.encode_into(buf);
+            }
+            Self::V => buf.put_u8(b'V'),
+            Self::x(v) => {
+                buf.put_u8(b'x');
+                buf.put_u32(v.0);
+                buf.put_slice(&v.1);
+            }
+        }
+    }
+}
+
+impl FieldArray {
+    fn encode_into<B: BufMut>(&self, buf: &mut B) {
+        buf.put_u32(self.0);
+        for value in self.1.iter() {
+            value.encode_into(buf);
+        }
+    }
+}
+
+impl FieldTable {
+    fn encode_into<B: BufMut>(&self, buf: &mut B) {
+        buf.put_u32(self.0);
+        for (key, value) in self.1.iter() {
+            buf.put_u8(key.0);
+            buf.put_slice(key.1.as_bytes());
+            value.encode_into(buf);
         }
     }
 }
@@ -384,7 +506,6 @@ impl<'a> TryInto<&'a str> for &'a FieldValue {
     }
 }
 
-
 /// RabbitMQ's field value support only long string variant, so rust string type
 /// always converted to long string variant.
 impl From<String> for FieldValue {
@@ -446,13 +567,22 @@ impl FieldTable {
     }
 
     pub fn insert(&mut self, k: FieldName, v: FieldValue) -> Option<FieldValue> {
-        self.0 += LongUint::try_from(size_of_val(&k.0) + k.0 as usize + FieldValue::TAG_SIZE + v.len()).unwrap();
-        self.1.insert(k, v)
+        let key_len = size_of_val(&k.0) + k.0 as usize + FieldValue::TAG_SIZE;
+        self.0 += LongUint::try_from(key_len + v.len()).unwrap();
+        let old = self.1.insert(k, v);
+        // the replaced entry is no longer serialized
+        if let Some(old) = &old {
+            self.0 -= LongUint::try_from(key_len + old.len()).unwrap();
+        }
+        old
     }
 
     pub fn remove(&mut self, k: &FieldName) -> Option<FieldValue> {
         if let Some(v) = self.1.remove(k) {
-            self.0 -= LongUint::try_from(size_of_val(&k.0) + k.0 as usize + FieldValue::TAG_SIZE + v.len()).unwrap();
+            self.0 -= LongUint::try_from(
+                size_of_val(&k.0) + k.0 as usize + FieldValue::TAG_SIZE + v.len(),
+            )
+            .unwrap();
             Some(v)
         } else {
             None
@@ -462,7 +592,59 @@ impl FieldTable {
     pub fn get(&self, k: &FieldName) -> Option<&FieldValue> {
         self.1.get(k)
     }
+
+    /// Get the nested table of key `k`.
+    ///
+    /// Returns `None` if the key does not exist or its value is not a table.
+    pub fn get_table(&self, k: &FieldName) -> Option<&FieldTable> {
+        match self.1.get(k)? {
+            FieldValue::F(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    /// Get the nested array of key `k`.
+    ///
+    /// Returns `None` if the key does not exist or its value is not an array.
+    pub fn get_array(&self, k: &FieldName) -> Option<&FieldArray> {
+        match self.1.get(k)? {
+            FieldValue::A(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// Number of entries in the table.
+    pub fn len(&self) -> usize {
+        self.1.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.1.is_empty()
+    }
+
+    /// Iterate over the entries without cloning the table.
+    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, FieldName, FieldValue> {
+        self.1.iter()
+    }
+
+    /// Serialized size in bytes of the table entries, excluding the length prefix itself.
+    ///
+    /// The value is maintained on every `insert` and `remove`, so it is never
+    /// recomputed when the same table is serialized repeatedly.
+    pub fn len_in_bytes(&self) -> LongUint {
+        self.0
+    }
 }
+
+impl<'a> IntoIterator for &'a FieldTable {
+    type Item = (&'a FieldName, &'a FieldValue);
+    type IntoIter = std::collections::hash_map::Iter<'a, FieldName, FieldValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.1.iter()
+    }
+}
+
 impl fmt::Display for FieldTable {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{{ ")?;
@@ -486,6 +668,340 @@ impl AsRef<HashMap<FieldName, FieldValue>> for FieldTable {
     }
 }
 
+//////////////////////////////////////////////////////////////////////////////
+/// Callbacks invoked by [`FieldValue::visit`] and [`FieldTable::visit`] when walking
+/// a value tree of any depth.
+///
+/// `path` is the location of the visited value from the root, made of table keys and
+/// array indices, e.g. `["headers", "0", "name"]`. It is empty for the root.
+/// Entries of a table are visited in arbitrary order.
+///
+/// All callbacks have empty default implementations, so a visitor only implements
+/// the ones it is interested in.
+///
+/// # Example
+///
+/// ```
+/// # use amqp_serde::types::{FieldTable, FieldValue, FieldVisitor};
+/// // collect all string values regardless of nesting
+/// struct Strings(Vec<String>);
+///
+/// impl FieldVisitor for Strings {
+///     fn visit_scalar(&mut self, _path: &[String], value: &FieldValue) {
+///         if let FieldValue::S(v) = value {
+///             self.0.push(v.to_string());
+///         }
+///     }
+/// }
+///
+/// let mut inner = FieldTable::new();
+/// inner.insert("name".try_into().unwrap(), "amqprs".into());
+/// let mut table = FieldTable::new();
+/// table.insert("inner".try_into().unwrap(), inner.into());
+///
+/// let mut strings = Strings(vec![]);
+/// table.visit(&mut strings);
+/// assert_eq!(vec!["amqprs".to_string()], strings.0);
+/// ```
+pub trait FieldVisitor {
+    /// Called for every value which is neither a table nor an array.
+    fn visit_scalar(&mut self, _path: &[String], _value: &FieldValue) {}
+
+    /// Called for a table, before its entries are visited.
+    fn visit_table(&mut self, _path: &[String], _table: &FieldTable) {}
+
+    /// Called for an array, before its elements are visited.
+    fn visit_array(&mut self, _path: &[String], _array: &FieldArray) {}
+}
+
+impl FieldValue {
+    /// Walk the value tree depth-first, invoking the callbacks of `visitor`
+    /// for this value and all values nested in it.
+    pub fn visit<V: FieldVisitor>(&self, visitor: &mut V) {
+        self.walk(&mut Vec::new(), visitor);
+    }
+
+    fn walk<V: FieldVisitor>(&self, path: &mut Vec<String>, visitor: &mut V) {
+        match self {
+            Self::F(table) => table.walk(path, visitor),
+            Self::A(array) => {
+                visitor.visit_array(path, array);
+                for (index, value) in array.1.iter().enumerate() {
+                    path.push(index.to_string());
+                    value.walk(path, visitor);
+                    path.pop();
+                }
+            }
+            scalar => visitor.visit_scalar(path, scalar),
+        }
+    }
+}
+
+impl FieldTable {
+    /// Walk the table depth-first, invoking the callbacks of `visitor`
+    /// for the table itself and all values nested in it.
+    pub fn visit<V: FieldVisitor>(&self, visitor: &mut V) {
+        self.walk(&mut Vec::new(), visitor);
+    }
+
+    fn walk<V: FieldVisitor>(&self, path: &mut Vec<String>, visitor: &mut V) {
+        visitor.visit_table(path, self);
+        for (key, value) in self.1.iter() {
+            path.push(key.to_string());
+            value.walk(path, visitor);
+            path.pop();
+        }
+    }
+}
+
+/// Indentation of each nesting level in [`FieldTable::pretty`].
+const PRETTY_INDENT: &str = "  ";
+
+impl FieldTable {
+    /// Format the table in multiple lines, one entry per line sorted by key,
+    /// with nested tables and arrays indented further.
+    ///
+    /// Unlike the single line [`Display`](fmt::Display), it keeps large tables readable in logs,
+    /// e.g. the `x-death` header or server properties.
+    ///
+    /// # Usage
+    ///
+    /// ```
+    /// # use amqp_serde::types::{FieldTable, FieldValue};
+    /// let mut table = FieldTable::new();
+    /// table.insert("count".try_into().unwrap(), FieldValue::l(1));
+    /// assert_eq!("{\n  count: 1,\n}", table.pretty());
+    /// ```
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, depth: usize) {
+        if self.1.is_empty() {
+            out.push_str("{}");
+            return;
+        }
+        let mut entries: Vec<(String, &FieldValue)> =
+            self.1.iter().map(|(k, v)| (k.to_string(), v)).collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        out.push_str("{\n");
+        for (key, value) in entries {
+            out.push_str(&PRETTY_INDENT.repeat(depth + 1));
+            out.push_str(&key);
+            out.push_str(": ");
+            value.write_pretty(out, depth + 1);
+            out.push_str(",\n");
+        }
+        out.push_str(&PRETTY_INDENT.repeat(depth));
+        out.push('}');
+    }
+}
+
+impl FieldValue {
+    fn write_pretty(&self, out: &mut String, depth: usize) {
+        match self {
+            Self::F(table) => table.write_pretty(out, depth),
+            Self::A(array) if array.1.is_empty() => out.push_str("[]"),
+            Self::A(array) => {
+                out.push_str("[\n");
+                for value in array.1.iter() {
+                    out.push_str(&PRETTY_INDENT.repeat(depth + 1));
+                    value.write_pretty(out, depth + 1);
+                    out.push_str(",\n");
+                }
+                out.push_str(&PRETTY_INDENT.repeat(depth));
+                out.push(']');
+            }
+            scalar => out.push_str(&scalar.to_string()),
+        }
+    }
+}
+
+/// Difference of an entry between two tables, returned by [`FieldTable::diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldDiff {
+    /// Key only exists in the other table.
+    Added { key: FieldName, value: FieldValue },
+    /// Key only exists in this table.
+    Removed { key: FieldName, value: FieldValue },
+    /// Key exists in both tables with different values.
+    Changed {
+        key: FieldName,
+        old: FieldValue,
+        new: FieldValue,
+    },
+}
+
+impl FieldDiff {
+    /// Key of the entry which differs.
+    pub fn key(&self) -> &FieldName {
+        match self {
+            Self::Added { key, .. } | Self::Removed { key, .. } | Self::Changed { key, .. } => key,
+        }
+    }
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { key, value } => write!(f, "+{}: {}", key, value),
+            Self::Removed { key, value } => write!(f, "-{}: {}", key, value),
+            Self::Changed { key, old, new } => write!(f, "~{}: {} -> {}", key, old, new),
+        }
+    }
+}
+
+impl FieldTable {
+    /// Compare the table with `other`, e.g. the arguments of an existing queue with
+    /// the arguments to declare it, and list the keys which are added, removed or changed
+    /// in `other`, sorted by key.
+    ///
+    /// Values are compared including their types, e.g. `FieldValue::I(1)` and
+    /// `FieldValue::l(1)` differ. Nested tables are compared as a whole.
+    ///
+    /// # Usage
+    ///
+    /// ```
+    /// # use amqp_serde::types::{FieldDiff, FieldTable, FieldValue};
+    /// let mut existing = FieldTable::new();
+    /// existing.insert("x-max-length".try_into().unwrap(), FieldValue::I(100));
+    /// let mut declared = FieldTable::new();
+    /// declared.insert("x-max-length".try_into().unwrap(), FieldValue::I(200));
+    ///
+    /// let diff = existing.diff(&declared);
+    /// assert_eq!("~x-max-length: 100 -> 200", diff[0].to_string());
+    /// ```
+    pub fn diff(&self, other: &FieldTable) -> Vec<FieldDiff> {
+        let mut diff: Vec<FieldDiff> = self
+            .1
+            .iter()
+            .filter_map(|(key, old)| match other.1.get(key) {
+                None => Some(FieldDiff::Removed {
+                    key: key.clone(),
+                    value: old.clone(),
+                }),
+                Some(new) if new != old => Some(FieldDiff::Changed {
+                    key: key.clone(),
+                    old: old.clone(),
+                    new: new.clone(),
+                }),
+                Some(_) => None,
+            })
+            .chain(
+                other
+                    .1
+                    .iter()
+                    .filter(|(key, _)| !self.1.contains_key(*key))
+                    .map(|(key, value)| FieldDiff::Added {
+                        key: key.clone(),
+                        value: value.clone(),
+                    }),
+            )
+            .collect();
+        diff.sort_by(|a, b| a.key().as_ref().cmp(b.key().as_ref()));
+        diff
+    }
+}
+
+/// Placeholder which replaces the redacted values, see [`FieldTable::redacted`].
+pub const REDACTED_PLACEHOLDER: &str = "[REDACTED]";
+
+/// Patterns of the keys whose values are sensitive, e.g. tokens or personal data,
+/// and should be redacted before logging a table by [`FieldTable::redacted`].
+///
+/// Patterns match keys case-insensitively. A pattern can start or end with `*` to match
+/// the suffix or prefix of a key, or both to match any part of it, e.g. `x-auth-*`,
+/// `*-token` or `*password*`.
+///
+/// # Usage
+///
+/// ```
+/// # use amqp_serde::types::{FieldTable, RedactionPolicy};
+/// let policy = RedactionPolicy::new(["authorization", "*token*"]);
+/// let mut headers = FieldTable::new();
+/// headers.insert("X-Access-Token".try_into().unwrap(), "secret".into());
+/// headers.insert("x-app".try_into().unwrap(), "orders".into());
+///
+/// let redacted = headers.redacted(&policy);
+/// let get = |key: &str| redacted.get(&key.try_into().unwrap()).unwrap().to_string();
+/// assert_eq!("[REDACTED]", get("X-Access-Token"));
+/// assert_eq!("orders", get("x-app"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RedactionPolicy {
+    /// lowercase patterns
+    patterns: Vec<String>,
+}
+
+impl RedactionPolicy {
+    /// Create a policy of the key `patterns`.
+    pub fn new<I, S>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(|p| p.as_ref().to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Returns `true` if `key` matches any pattern of the policy.
+    pub fn matches(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.patterns.iter().any(|pattern| {
+            match (pattern.strip_prefix('*'), pattern.strip_suffix('*')) {
+                (Some(suffix), Some(_)) => {
+                    // `*` alone matches any key
+                    let part = suffix.strip_suffix('*').unwrap_or("");
+                    key.contains(part)
+                }
+                (Some(suffix), None) => key.ends_with(suffix),
+                (None, Some(prefix)) => key.starts_with(prefix),
+                (None, None) => key == *pattern,
+            }
+        })
+    }
+}
+
+impl FieldTable {
+    /// Returns a copy of the table whose values of the keys matching `policy` are replaced
+    /// by [`REDACTED_PLACEHOLDER`], so that it can be logged safely.
+    ///
+    /// Nested tables, including tables in arrays, are redacted too.
+    pub fn redacted(&self, policy: &RedactionPolicy) -> FieldTable {
+        let mut redacted = FieldTable::new();
+        for (key, value) in self.1.iter() {
+            let value = if policy.matches(key.as_ref()) {
+                FieldValue::S(REDACTED_PLACEHOLDER.try_into().unwrap())
+            } else {
+                value.redacted(policy)
+            };
+            redacted.insert(key.clone(), value);
+        }
+        redacted
+    }
+}
+
+impl FieldValue {
+    fn redacted(&self, policy: &RedactionPolicy) -> FieldValue {
+        match self {
+            Self::F(table) => Self::F(table.redacted(policy)),
+            Self::A(array) => {
+                let values: Vec<FieldValue> = array.1.iter().map(|v| v.redacted(policy)).collect();
+                // same number of values, and the placeholder is short
+                Self::A(values.try_into().unwrap())
+            }
+            scalar => scalar.clone(),
+        }
+    }
+}
+
 /////////////////////////////////////////////////////////////////////////////
 // #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 // pub struct FieldTable(HashMap<FieldName, FieldValue>);
@@ -588,10 +1104,10 @@ pub type AmqpTimeStamp = TimeStamp;
 /////////////////////////////////////////////////////////////////////////////
 #[cfg(test)]
 mod tests {
-    use std::mem::size_of;
     use crate::types::{ByteArray, DecimalValue, FieldArray, FieldValue, LongStr, LongUint};
+    use std::mem::size_of;
 
-    use super::{FieldTable, ShortStr};
+    use super::{FieldDiff, FieldTable, RedactionPolicy, ShortStr, REDACTED_PLACEHOLDER};
     #[test]
     fn test_field_table() {
         let mut table = FieldTable::new();
@@ -604,6 +1120,58 @@ mod tests {
         assert_eq!("{ Cash: Decimal(3, 123456) }", format!("{}", table));
     }
 
+    #[test]
+    fn test_field_table_cached_len_matches_serialized_size() {
+        let mut table = FieldTable::new();
+        table.insert("a".try_into().unwrap(), FieldValue::t(true));
+        table.insert(
+            "b".try_into().unwrap(),
+            FieldValue::S("hello".try_into().unwrap()),
+        );
+        table.insert("c".try_into().unwrap(), FieldValue::l(-1));
+        table.remove(&"a".try_into().unwrap());
+        // replaced value is not counted
+        table.insert(
+            "b".try_into().unwrap(),
+            FieldValue::S("hi".try_into().unwrap()),
+        );
+
+        let buf = crate::to_bytes(&table).unwrap();
+        assert_eq!(
+            buf.len(),
+            size_of::<LongUint>() + table.len_in_bytes() as usize
+        );
+        assert_eq!(2, table.len());
+        assert_eq!(2, table.iter().count());
+    }
+
+    #[test]
+    fn test_field_table_replace_key() {
+        let mut table = FieldTable::new();
+        assert_eq!(
+            None,
+            table.insert("k".try_into().unwrap(), FieldValue::l(1))
+        );
+        // key "k" (2 bytes) + type (1 byte) + LongLongInt (8 bytes)
+        assert_eq!(11, table.len_in_bytes());
+
+        // replaced value is returned, and only the new value is counted
+        let old = table.insert(
+            "k".try_into().unwrap(),
+            FieldValue::S("hello".try_into().unwrap()),
+        );
+        assert_eq!(Some(FieldValue::l(1)), old);
+        // key "k" (2 bytes) + type (1 byte) + LongStr "hello" (4 + 5 bytes)
+        assert_eq!(12, table.len_in_bytes());
+        assert_eq!(1, table.len());
+
+        let buf = crate::to_bytes(&table).unwrap();
+        assert_eq!(
+            buf.len(),
+            size_of::<LongUint>() + table.len_in_bytes() as usize
+        );
+    }
+
     #[test]
     fn test_field_array() {
         let exp = vec![FieldValue::t(true), FieldValue::D(DecimalValue(3, 123456))];
@@ -614,6 +1182,157 @@ mod tests {
         assert_eq!(exp, arr);
     }
 
+    #[test]
+    fn test_visit_nested_field_table() {
+        use super::FieldVisitor;
+        use std::collections::BTreeMap;
+
+        #[derive(Default)]
+        struct Collector {
+            leaves: BTreeMap<String, FieldValue>,
+            tables: usize,
+            arrays: usize,
+        }
+        impl FieldVisitor for Collector {
+            fn visit_scalar(&mut self, path: &[String], value: &FieldValue) {
+                self.leaves.insert(path.join("."), value.clone());
+            }
+            fn visit_table(&mut self, _path: &[String], _table: &FieldTable) {
+                self.tables += 1;
+            }
+            fn visit_array(&mut self, _path: &[String], _array: &FieldArray) {
+                self.arrays += 1;
+            }
+        }
+
+        // { id: 1, headers: [ { name: "a", tags: [ true ] }, "b" ] }
+        let mut inner = FieldTable::new();
+        inner.insert("name".try_into().unwrap(), "a".into());
+        let tags: FieldArray = vec![FieldValue::t(true)].try_into().unwrap();
+        inner.insert("tags".try_into().unwrap(), FieldValue::A(tags));
+        let headers: FieldArray = vec![inner.into(), "b".into()].try_into().unwrap();
+        let mut table = FieldTable::new();
+        table.insert("id".try_into().unwrap(), FieldValue::l(1));
+        table.insert("headers".try_into().unwrap(), FieldValue::A(headers));
+
+        let mut collector = Collector::default();
+        table.visit(&mut collector);
+        assert_eq!(2, collector.tables);
+        assert_eq!(2, collector.arrays);
+        let expected: BTreeMap<String, FieldValue> = [
+            ("id".to_string(), FieldValue::l(1)),
+            ("headers.0.name".to_string(), "a".into()),
+            ("headers.0.tags.0".to_string(), FieldValue::t(true)),
+            ("headers.1".to_string(), "b".into()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(expected, collector.leaves);
+
+        // a scalar root is visited with an empty path
+        let mut collector = Collector::default();
+        FieldValue::V.visit(&mut collector);
+        assert_eq!(Some(&FieldValue::V), collector.leaves.get(""));
+    }
+
+    #[test]
+    fn test_encode_into_same_as_serde() {
+        let mut table = FieldTable::new();
+        table.insert("name".try_into().unwrap(), "amqprs".into());
+        table.insert("count".try_into().unwrap(), FieldValue::l(-3));
+        let array: FieldArray = vec![FieldValue::t(false), FieldValue::F(table.clone())]
+            .try_into()
+            .unwrap();
+        let values = vec![
+            FieldValue::t(true),
+            FieldValue::b(-1),
+            FieldValue::B(255),
+            FieldValue::s(-300),
+            FieldValue::u(300),
+            FieldValue::I(-70000),
+            FieldValue::i(70000),
+            FieldValue::l(i64::MIN),
+            FieldValue::f(1.5),
+            FieldValue::d(-2.25),
+            FieldValue::D(DecimalValue(2, 12345)),
+            FieldValue::S("hello".try_into().unwrap()),
+            FieldValue::A(array),
+            FieldValue::T(1_700_000_000),
+            FieldValue::F(table),
+            FieldValue::V,
+            FieldValue::x(ByteArray::try_from(vec![0, 1, 2]).unwrap()),
+        ];
+
+        // encoded back to back into a reused buffer
+        let mut buf = Vec::new();
+        for value in values.iter() {
+            let start = buf.len();
+            value.encode_into(&mut buf);
+            let expected = crate::to_bytes(value).unwrap();
+            assert_eq!(expected, buf[start..], "{:?}", value);
+            assert_eq!(expected.len(), value.encoded_len(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn test_get_nested_table_and_array() {
+        let keys: FieldArray = vec!["orders".into()].try_into().unwrap();
+        let mut entry = FieldTable::new();
+        entry.insert(
+            "routing-keys".try_into().unwrap(),
+            FieldValue::A(keys.clone()),
+        );
+        let mut headers = FieldTable::new();
+        headers.insert("entry".try_into().unwrap(), FieldValue::F(entry.clone()));
+        headers.insert("app".try_into().unwrap(), "billing".into());
+
+        let inner = headers.get_table(&"entry".try_into().unwrap()).unwrap();
+        assert_eq!(&entry, inner);
+        let routing_keys = inner
+            .get_array(&"routing-keys".try_into().unwrap())
+            .unwrap();
+        assert_eq!(&keys, routing_keys);
+
+        // wrong type or missing key
+        assert!(headers.get_array(&"entry".try_into().unwrap()).is_none());
+        assert!(headers.get_table(&"app".try_into().unwrap()).is_none());
+        assert!(headers.get_table(&"missing".try_into().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_pretty_nested_field_table() {
+        let mut entry = FieldTable::new();
+        entry.insert("reason".try_into().unwrap(), "rejected".into());
+        entry.insert("count".try_into().unwrap(), FieldValue::l(2));
+        let keys: FieldArray = vec!["orders".into()].try_into().unwrap();
+        entry.insert("routing-keys".try_into().unwrap(), FieldValue::A(keys));
+        entry.insert(
+            "empty".try_into().unwrap(),
+            FieldValue::F(FieldTable::new()),
+        );
+        let deaths: FieldArray = vec![entry.into()].try_into().unwrap();
+        let mut headers = FieldTable::new();
+        headers.insert("x-death".try_into().unwrap(), FieldValue::A(deaths));
+        headers.insert("app".try_into().unwrap(), "billing".into());
+
+        let expected = "\
+{
+  app: billing,
+  x-death: [
+    {
+      count: 2,
+      empty: {},
+      reason: rejected,
+      routing-keys: [
+        orders,
+      ],
+    },
+  ],
+}";
+        assert_eq!(expected, headers.pretty());
+        assert_eq!("{}", FieldTable::new().pretty());
+    }
+
     #[test]
     fn test_bytes_array() {
         let exp: Vec<u8> = vec![1, 2, 3];
@@ -685,6 +1404,101 @@ mod tests {
         assert_eq!(&test_longstr, extracted_longstr);
     }
 
+    #[test]
+    fn test_field_table_diff() {
+        let mut existing = FieldTable::new();
+        existing.insert("x-queue-type".try_into().unwrap(), "quorum".into());
+        existing.insert("x-max-length".try_into().unwrap(), FieldValue::I(100));
+        existing.insert("x-expires".try_into().unwrap(), FieldValue::I(60000));
+        let mut declared = FieldTable::new();
+        declared.insert("x-queue-type".try_into().unwrap(), "quorum".into());
+        declared.insert("x-max-length".try_into().unwrap(), FieldValue::l(100));
+        declared.insert("x-dead-letter-exchange".try_into().unwrap(), "dlx".into());
+
+        let diff = existing.diff(&declared);
+        assert_eq!(
+            vec![
+                FieldDiff::Added {
+                    key: "x-dead-letter-exchange".try_into().unwrap(),
+                    value: "dlx".into(),
+                },
+                FieldDiff::Removed {
+                    key: "x-expires".try_into().unwrap(),
+                    value: FieldValue::I(60000),
+                },
+                // same number of different type
+                FieldDiff::Changed {
+                    key: "x-max-length".try_into().unwrap(),
+                    old: FieldValue::I(100),
+                    new: FieldValue::l(100),
+                },
+            ],
+            diff
+        );
+        assert_eq!("+x-dead-letter-exchange: dlx", diff[0].to_string());
+        assert_eq!("-x-expires: 60000", diff[1].to_string());
+
+        // reversed comparison swaps added and removed
+        let diff = declared.diff(&existing);
+        assert!(matches!(&diff[0], FieldDiff::Removed { .. }));
+        assert!(matches!(&diff[1], FieldDiff::Added { .. }));
+        assert!(existing.diff(&existing.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_field_table_redacted() {
+        let policy = RedactionPolicy::new(["authorization", "x-secret-*", "*-token", "*password*"]);
+        assert!(policy.matches("Authorization"));
+        assert!(policy.matches("x-secret-key"));
+        assert!(policy.matches("x-refresh-token"));
+        assert!(policy.matches("db_password_hash"));
+        assert!(!policy.matches("x-authorization-mode"));
+        assert!(!policy.matches("x-token-count"));
+        assert!(RedactionPolicy::new(["*"]).matches("anything"));
+        assert!(!RedactionPolicy::default().matches("authorization"));
+
+        let mut user = FieldTable::new();
+        user.insert("name".try_into().unwrap(), "alice".into());
+        user.insert("password".try_into().unwrap(), "hunter2".into());
+        let mut headers = FieldTable::new();
+        headers.insert("Authorization".try_into().unwrap(), "Bearer abc".into());
+        headers.insert("x-refresh-token".try_into().unwrap(), FieldValue::l(42));
+        headers.insert("x-retry".try_into().unwrap(), FieldValue::I(3));
+        headers.insert("user".try_into().unwrap(), user.clone().into());
+        headers.insert(
+            "users".try_into().unwrap(),
+            FieldValue::A(vec![FieldValue::F(user)].try_into().unwrap()),
+        );
+
+        let redacted = headers.redacted(&policy);
+        let get =
+            |table: &FieldTable, key: &str| table.get(&key.try_into().unwrap()).unwrap().clone();
+        let placeholder = FieldValue::S(REDACTED_PLACEHOLDER.try_into().unwrap());
+        assert_eq!(5, redacted.len());
+        assert_eq!(placeholder, get(&redacted, "Authorization"));
+        assert_eq!(placeholder, get(&redacted, "x-refresh-token"));
+        // non-matching keys are preserved
+        assert_eq!(FieldValue::I(3), get(&redacted, "x-retry"));
+        let user: FieldTable = get(&redacted, "user").try_into().unwrap();
+        assert_eq!(placeholder, get(&user, "password"));
+        assert_eq!(FieldValue::from("alice"), get(&user, "name"));
+        match get(&redacted, "users") {
+            FieldValue::A(users) => {
+                let user: FieldTable = users.as_ref()[0].clone().try_into().unwrap();
+                assert_eq!(placeholder, get(&user, "password"));
+            }
+            other => panic!("unexpected value {}", other),
+        }
+        // length prefix is updated for the placeholders
+        let bytes = crate::to_bytes(&redacted).unwrap();
+        assert_eq!(redacted, crate::from_bytes::<FieldTable>(&bytes).unwrap());
+        // original is unchanged
+        assert_eq!(
+            FieldValue::from("Bearer abc"),
+            get(&headers, "Authorization")
+        );
+    }
+
     #[test]
     fn test_len_for_field_value_of_type_field_array() {
         let s1: LongStr = "1".try_into().unwrap();
@@ -699,7 +1513,8 @@ mod tests {
             FieldValue::S(s4),
         ];
 
-        v.iter().for_each(|_fv| total_length += FieldValue::TAG_SIZE + size_of::<LongUint>());
+        v.iter()
+            .for_each(|_fv| total_length += FieldValue::TAG_SIZE + size_of::<LongUint>());
 
         let a = FieldArray::try_from(v).unwrap();
 
@@ -723,7 +1538,8 @@ mod tests {
         ];
         let mut total_length = 0;
         v_kv.into_iter().for_each(|(k, v)| {
-            total_length += k.1.len() + v.1.len() + 2 * FieldValue::TAG_SIZE + size_of::<LongUint>();
+            total_length +=
+                k.1.len() + v.1.len() + 2 * FieldValue::TAG_SIZE + size_of::<LongUint>();
             ft.insert(k, FieldValue::S(v));
         });
 
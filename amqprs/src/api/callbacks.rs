@@ -20,11 +20,14 @@
 //! [`Channel`]: ../channel/struct.Channel.html
 //! [`Channel::register_callback`]: ../channel/struct.Channel.html#method.register_callback
 
-use super::{channel::Channel, connection::Connection};
+use super::{
+    channel::{Channel, ReturnedMessage},
+    connection::Connection,
+};
 use crate::api::Result;
 use crate::frame::Cancel;
 use crate::{
-    frame::{Ack, Close, CloseChannel, Nack, Return},
+    frame::{Ack, Close, CloseChannel, Deliver, Nack, Return},
     BasicProperties,
 };
 use async_trait::async_trait;
@@ -54,6 +57,17 @@ pub trait ConnectionCallback {
 
     /// Callback to handle connection `unblocked` indication from server
     async fn unblocked(&mut self, connection: &Connection);
+
+    /// Callback to handle a failure of the underlying network I/O, for example
+    /// the socket is reset or closed by server without closing the connection.
+    ///
+    /// It is invoked before the connection is torn down, and is not invoked if the
+    /// connection is closed by client or server.
+    ///
+    /// Default implementation does nothing.
+    async fn io_error(&mut self, connection: &Connection, error: std::io::Error) {
+        let _ = (connection, error);
+    }
 }
 
 /// Default type that implements `ConnectionCallback`.
@@ -87,6 +101,16 @@ impl ConnectionCallback for DefaultConnectionCallback {
             connection
         );
     }
+
+    async fn io_error(&mut self, connection: &Connection, error: std::io::Error) {
+        #[cfg(feature = "traces")]
+        error!(
+            "handle network i/o failure for connection {}, kind: {:?}, cause: {}",
+            connection,
+            error.kind(),
+            error
+        );
+    }
 }
 
 /////////////////////////////////////////////////////////////////////////////
@@ -156,6 +180,43 @@ pub trait ChannelCallback {
         basic_properties: BasicProperties,
         content: Vec<u8>,
     );
+
+    /// Callback to handle `return` indication with undeliverable message from server,
+    /// assembled as a [`ReturnedMessage`].
+    ///
+    /// The [message][`ReturnedMessage`] carries the reply code and text, the original exchange
+    /// and routing key, the properties and the body of the returned message.
+    ///
+    /// Default implementation forwards the message to [`publish_return`].
+    ///
+    /// [`ReturnedMessage`]: ../channel/struct.ReturnedMessage.html
+    /// [`publish_return`]: #tymethod.publish_return
+    async fn publish_returned(&mut self, channel: &Channel, message: ReturnedMessage) {
+        let (ret, basic_properties, content) = message.into_parts();
+        self.publish_return(channel, ret, basic_properties, content)
+            .await;
+    }
+
+    /// Callback to handle a delivery for a consumer tag which is unknown to the channel,
+    /// e.g. the consumer has never been registered or has already been deregistered.
+    ///
+    /// The message is not dispatched to any consumer. Unless it is acknowledged automatically,
+    /// it holds the prefetch window until it is settled, so the implementer owns its
+    /// acknowledgement: `ack`, `nack` or `reject` it by the delivery tag of [deliver][`Deliver`].
+    ///
+    /// Default implementation rejects the message to be requeued, unless it is acknowledged
+    /// automatically.
+    ///
+    /// [`Deliver`]: ../struct.Deliver.html
+    async fn orphan_delivery(
+        &mut self,
+        channel: &Channel,
+        deliver: Deliver,
+        _basic_properties: BasicProperties,
+        _content: Vec<u8>,
+    ) {
+        channel.requeue_orphan(deliver.delivery_tag()).await;
+    }
 }
 
 /// Default type that implements `ChannelCallback`.
@@ -221,4 +282,20 @@ impl ChannelCallback for DefaultChannelCallback {
             content.len()
         );
     }
+    async fn orphan_delivery(
+        &mut self,
+        channel: &Channel,
+        deliver: Deliver,
+        _basic_properties: BasicProperties,
+        content: Vec<u8>,
+    ) {
+        #[cfg(feature = "traces")]
+        warn!(
+            "handle orphan delivery {} on channel {}, content size: {}",
+            deliver,
+            channel,
+            content.len()
+        );
+        channel.requeue_orphan(deliver.delivery_tag()).await;
+    }
 }
@@ -1,11 +1,28 @@
-use amqp_serde::types::AmqpDeliveryTag;
-use tokio::sync::mpsc;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{atomic::Ordering, Arc},
+    task::{Context, Poll},
+};
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    time::{self, Duration},
+};
 #[cfg(feature = "traces")]
-use tracing::{debug, trace};
+use tracing::{debug, error, trace, warn};
 
 use crate::{
     api::{
-        channel::{ConsumerMessage, DispatcherManagementCommand, RegisterContentConsumer},
+        channel::{
+            consumer_channel, ConsumerMessage, ConsumerRx, ConsumerStream, ConsumerTx,
+            DispatcherManagementCommand, ExpectConsumeOk, ExpectContentConsumer,
+            ListContentConsumers, RegisterContentConsumer, ReturnedMessage, SharedChannelInner,
+            SwapContentConsumer,
+        },
+        connection::{ConsumerFactory, ConsumerHandler},
         consumer::AsyncConsumer,
         error::Error,
         FieldTable, Result,
@@ -13,15 +30,63 @@ use crate::{
     consumer::BlockingConsumer,
     frame::{
         Ack, BasicProperties, Cancel, CancelOk, Consume, ConsumeOk, ContentBody, ContentHeader,
-        ContentHeaderCommon, Frame, Get, GetOk, Nack, Publish, Qos, QosOk, Recover, RecoverOk,
-        Reject,
+        ContentHeaderCommon, DeliveryTag, EncodedContentHeader, EncodedProperties, Frame, Get,
+        GetOk, Nack, Publish, Qos, QosOk, Recover, RecoverOk, Reject, FRAME_HEADER_SIZE,
     },
+    AmqpChannelId, FieldValue,
 };
 
 #[cfg(feature = "compliance_assert")]
 use crate::api::compliance_asserts::{assert_exchange_name, assert_queue_name};
 
-use super::{Channel, DeregisterContentConsumer, RegisterGetContentResponder};
+use super::{
+    to_short_str, Channel, ConfirmCallback, DeregisterContentConsumer, PauseContentConsumer,
+    PublishRouting, RegisterConfirmCallback, RegisterGetContentResponder,
+};
+
+/// Consumer whose deliveries are diverted by [`Channel::drain`].
+///
+/// The consumer's tx half is given back on drop, together with the deliveries not taken,
+/// so that no delivery is lost even if the draining future is cancelled.
+struct DivertedConsumer {
+    consumer_tag: String,
+    consumer_tx: Option<ConsumerTx>,
+    rx: Option<ConsumerRx>,
+    dispatcher_mgmt_tx: mpsc::UnboundedSender<DispatcherManagementCommand>,
+}
+
+impl Drop for DivertedConsumer {
+    fn drop(&mut self) {
+        if let (Some(consumer_tx), Some(rx)) = (self.consumer_tx.take(), self.rx.take()) {
+            // dispatcher drops the diverting tx half, no need to wait for it
+            let (replaced, _) = oneshot::channel();
+            let cmd = SwapContentConsumer {
+                consumer_tag: std::mem::take(&mut self.consumer_tag),
+                consumer_tx,
+                leftover_rx: Some(rx),
+                replaced,
+            };
+            // nothing to give back if the channel is closed
+            self.dispatcher_mgmt_tx
+                .send(DispatcherManagementCommand::SwapContentConsumer(cmd))
+                .ok();
+        }
+    }
+}
+
+/// RabbitMQ pseudo-queue for [direct reply-to](https://www.rabbitmq.com/direct-reply-to.html).
+///
+/// Consume from it in no-ack mode, and publish requests with `reply_to` set to it.
+/// Server routes the replies straight back to the consumer without declaring a reply queue.
+pub const DIRECT_REPLY_TO_QUEUE: &str = "amq.rabbitmq.reply-to";
+
+/// Consumer argument of consumer priority.
+const X_PRIORITY: &str = "x-priority";
+/// Consumer argument to cancel the consumer on mirrored queue failover.
+const X_CANCEL_ON_HA_FAILOVER: &str = "x-cancel-on-ha-failover";
+
+// max time to wait for consumer tasks to process their deliveries when closing the channel
+const CONSUMER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 ////////////////////////////////////////////////////////////////////////////////
 /// Arguments for [`basic_qos`]
 ///
@@ -64,6 +129,45 @@ impl BasicQosArguments {
         self.clone()
     }
 }
+////////////////////////////////////////////////////////////////////////////////
+/// Prefetch counts applied by server on a channel, `0` means unlimited.
+#[derive(Debug, Default)]
+pub(crate) struct PrefetchCounts {
+    /// shared by all consumers on the channel, set by `basic_qos` with `global`
+    global: u16,
+    /// applied to consumers started afterwards, set by `basic_qos` without `global`
+    per_consumer: u16,
+    /// per-consumer limit of each consumer, captured when it starts
+    consumers: HashMap<String, u16>,
+}
+
+impl PrefetchCounts {
+    fn set(&mut self, prefetch_count: u16, global: bool) {
+        if global {
+            self.global = prefetch_count;
+        } else {
+            self.per_consumer = prefetch_count;
+        }
+    }
+
+    pub(crate) fn remove_consumer(&mut self, consumer_tag: &str) {
+        self.consumers.remove(consumer_tag);
+    }
+
+    /// Both limits apply to a consumer, whichever is reached first stops the deliveries.
+    fn effective(&self, consumer_tag: &str) -> u16 {
+        let per_consumer = self
+            .consumers
+            .get(consumer_tag)
+            .copied()
+            .unwrap_or(self.per_consumer);
+        match (self.global, per_consumer) {
+            (0, limit) | (limit, 0) => limit,
+            (global, per_consumer) => global.min(per_consumer),
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 /// Arguments for [`basic_consume`]
 ///
@@ -96,6 +200,25 @@ pub struct BasicConsumeArguments {
     pub no_wait: bool,
     /// Default: empty table.
     pub arguments: FieldTable,
+    /// Buffer which hands over deliveries from the channel's dispatcher to the consumer task.
+    /// Default: [`DeliveryChannel::Unbounded`].
+    ///
+    /// See [`DeliveryChannel`] for the trade-off.
+    ///
+    /// [`DeliveryChannel::Unbounded`]: enum.DeliveryChannel.html#variant.Unbounded
+    pub delivery_channel: DeliveryChannel,
+    /// Policy applied when the consumer of [`basic_consume`] fails to process a delivery.
+    /// Default: [`ConsumerErrorPolicy::Ignore`].
+    ///
+    /// [`basic_consume`]: struct.Channel.html#method.basic_consume
+    pub error_policy: ConsumerErrorPolicy,
+    /// Policy to ack the deliveries processed by the consumer of [`basic_consume`]
+    /// in batches. Default: `None`, the consumer acks the deliveries itself.
+    ///
+    /// See [`AckBatchPolicy`].
+    ///
+    /// [`basic_consume`]: struct.Channel.html#method.basic_consume
+    pub ack_batch: Option<AckBatchPolicy>,
 }
 
 impl BasicConsumeArguments {
@@ -112,8 +235,36 @@ impl BasicConsumeArguments {
             exclusive: false,
             no_wait: false,
             arguments: FieldTable::new(),
+            delivery_channel: DeliveryChannel::default(),
+            error_policy: ConsumerErrorPolicy::Ignore,
+            ack_batch: None,
         }
     }
+
+    /// Create arguments to consume replies from [`DIRECT_REPLY_TO_QUEUE`].
+    ///
+    /// Server requires the consumer to use automatic acknowledgements, so `no_ack` is set.
+    /// Requests must be published on the same channel, with `reply_to` set to
+    /// [`DIRECT_REPLY_TO_QUEUE`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use amqprs::channel::{BasicConsumeArguments, DIRECT_REPLY_TO_QUEUE};
+    /// # use amqprs::BasicProperties;
+    /// let args = BasicConsumeArguments::direct_reply_to();
+    /// assert!(args.no_ack);
+    ///
+    /// let request_props = BasicProperties::default()
+    ///     .with_reply_to(DIRECT_REPLY_TO_QUEUE)
+    ///     .with_correlation_id("1")
+    ///     .finish();
+    /// ```
+    pub fn direct_reply_to() -> Self {
+        let mut args = Self::new(DIRECT_REPLY_TO_QUEUE, "");
+        args.no_ack = true;
+        args
+    }
     impl_chainable_setter! {
         /// Chainable setter method.
         queue, String
@@ -155,6 +306,61 @@ impl BasicConsumeArguments {
         arguments, FieldTable
     }
 
+    /// Chainable setter method to set the consumer priority by `x-priority` argument.
+    ///
+    /// Server delivers messages to consumers of higher priority as long as they can receive
+    /// them, so a consumer of lower priority only takes over when the others are blocked
+    /// or disconnected. Default priority is `0`, negative priorities are allowed.
+    ///
+    /// See [RabbitMQ consumer priorities](https://www.rabbitmq.com/consumer-priority.html).
+    pub fn priority(&mut self, priority: i32) -> &mut Self {
+        self.arguments
+            .insert(X_PRIORITY.try_into().unwrap(), FieldValue::I(priority));
+        self
+    }
+
+    /// Chainable setter method to set `x-cancel-on-ha-failover` argument.
+    ///
+    /// If `true`, server cancels the consumer when the leader of the mirrored queue fails over,
+    /// so that the client is notified instead of silently consuming from the new leader.
+    pub fn cancel_on_ha_failover(&mut self, value: bool) -> &mut Self {
+        self.arguments.insert(
+            X_CANCEL_ON_HA_FAILOVER.try_into().unwrap(),
+            FieldValue::t(value),
+        );
+        self
+    }
+
+    /// Chainable setter method of the buffer which hands over deliveries to the consumer,
+    /// see [`DeliveryChannel`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if capacity of [`DeliveryChannel::Bounded`] is zero.
+    ///
+    /// [`DeliveryChannel::Bounded`]: enum.DeliveryChannel.html#variant.Bounded
+    pub fn delivery_channel(&mut self, delivery_channel: DeliveryChannel) -> &mut Self {
+        assert!(
+            delivery_channel != DeliveryChannel::Bounded(0),
+            "consumer channel buffer size must be greater than zero"
+        );
+        self.delivery_channel = delivery_channel;
+        self
+    }
+
+    impl_chainable_setter! {
+        /// Chainable setter method.
+        error_policy, ConsumerErrorPolicy
+    }
+
+    /// Chainable setter method to ack the deliveries in batches, see [`ack_batch`].
+    ///
+    /// [`ack_batch`]: #structfield.ack_batch
+    pub fn ack_batch(&mut self, policy: AckBatchPolicy) -> &mut Self {
+        self.ack_batch = Some(policy);
+        self
+    }
+
     /// Finish chained configuration and return new arguments.
     pub fn finish(&mut self) -> Self {
         #[cfg(feature = "compliance_assert")]
@@ -162,7 +368,302 @@ impl BasicConsumeArguments {
 
         self.clone()
     }
+
+    /// Build the `consume` method, returns it with the requested consumer tag.
+    fn into_consume(self) -> Result<(Consume, String)> {
+        let BasicConsumeArguments {
+            queue,
+            consumer_tag,
+            no_local,
+            no_ack,
+            exclusive,
+            no_wait,
+            arguments,
+            delivery_channel: _,
+            error_policy: _,
+            ack_batch: _,
+        } = self;
+        if queue == DIRECT_REPLY_TO_QUEUE && !no_ack {
+            // server closes the channel otherwise
+            return Err(Error::ChannelUseError(
+                "direct reply-to requires no-ack consumer".to_string(),
+            ));
+        }
+        let mut consume = Consume::new(
+            0,
+            to_short_str("queue name", queue)?,
+            to_short_str("consumer tag", consumer_tag.clone())?,
+            arguments,
+        );
+        consume.set_no_local(no_local);
+        consume.set_no_ack(no_ack);
+        consume.set_exclusive(exclusive);
+        consume.set_nowait(no_wait);
+        Ok((consume, consumer_tag))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Buffer which hands over deliveries from the channel's dispatcher to the consumer task,
+/// see [`BasicConsumeArguments::delivery_channel`].
+///
+/// The dispatcher never waits for a consumer, so a slow consumer does not delay other
+/// consumers or the replies to other requests on the channel.
+/// Once a bounded buffer is full, further deliveries of a consumer of manual acknowledgement
+/// are rejected to be requeued, so the client never holds more than the capacity of them.
+/// Deliveries acknowledged automatically can not be requeued, the dispatcher holds them
+/// and hands them over in order as the consumer catches up.
+/// An unbounded buffer hands over every delivery at once.
+/// Either way, deliveries not yet taken by the consumer count towards the buffered bytes
+/// of the connection.
+///
+/// # Interaction with prefetch
+///
+/// Backpressure to server comes from the [`basic_qos`] prefetch count: server stops
+/// delivering to a consumer of manual acknowledgement once prefetch count of deliveries
+/// are unacked, which bounds the deliveries held by the client. A capacity not less than
+/// the prefetch count never fills up. A smaller capacity rejects the excess, which server
+/// may deliver again right away, so keep the capacity not less than the prefetch count.
+///
+/// Without prefetch limit or with automatic acknowledgement, server delivers as fast as
+/// it can, and with an unbounded buffer the deliveries pile up in memory if the consumer
+/// is slower.
+///
+/// [`BasicConsumeArguments::delivery_channel`]: struct.BasicConsumeArguments.html#method.delivery_channel
+/// [`basic_qos`]: struct.Channel.html#method.basic_qos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryChannel {
+    /// Bounded buffer of given capacity.
+    Bounded(usize),
+    /// Unbounded buffer.
+    Unbounded,
+}
+
+impl Default for DeliveryChannel {
+    /// Unbounded, as consumers have always been.
+    fn default() -> Self {
+        Self::Unbounded
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Policy applied when the consumer of [`basic_consume`] returns an error from
+/// [`AsyncConsumer::try_consume`], or panics, when processing a delivery.
+///
+/// Nack is not sent if the consumer uses automatic acknowledgement.
+///
+/// [`basic_consume`]: struct.Channel.html#method.basic_consume
+/// [`AsyncConsumer::try_consume`]: ../consumer/trait.AsyncConsumer.html#method.try_consume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsumerErrorPolicy {
+    /// Nack the delivery with `requeue = true`, so that it is delivered again.
+    NackRequeue,
+    /// Nack the delivery with `requeue = false`, so that server dead-letters it if the queue
+    /// has a dead letter exchange, otherwise discards it.
+    NackDeadletter,
+    /// Do nothing, the delivery stays unacknowledged until the channel is closed.
+    Ignore,
+    /// Close the channel, server requeues all unacknowledged deliveries of the channel.
+    CloseChannel,
+}
+
+impl Default for ConsumerErrorPolicy {
+    fn default() -> Self {
+        Self::Ignore
+    }
+}
+
+impl ConsumerErrorPolicy {
+    /// Returns arguments of nack to settle a failed delivery, or `None` if no nack to send.
+    fn nack_arguments(
+        &self,
+        delivery_tag: DeliveryTag,
+        no_ack: bool,
+    ) -> Option<BasicNackArguments> {
+        if no_ack {
+            return None;
+        }
+        match self {
+            Self::NackRequeue => Some(BasicNackArguments::new(delivery_tag, false, true)),
+            Self::NackDeadletter => Some(BasicNackArguments::new(delivery_tag, false, false)),
+            Self::Ignore | Self::CloseChannel => None,
+        }
+    }
+}
+
+/// Policy to ack the deliveries processed by the consumer of [`basic_consume`] in batches.
+///
+/// A delivery is acked once [`AsyncConsumer::try_consume`] returns `Ok`, so the consumer
+/// must not ack it itself. Acks are accumulated and flushed once `max_messages` are pending,
+/// or `max_delay` after the first pending one, using `multiple = true` to ack them by a single
+/// frame. Deliveries which are still unacknowledged on the channel, e.g. failed ones with
+/// [`ConsumerErrorPolicy::Ignore`] or ones of other consumers, are never acked by `multiple`,
+/// the pending acks after such a gap are sent one by one.
+///
+/// Pending acks are flushed before the consumer task exits, i.e. after [`basic_cancel`].
+/// They count as in-flight deliveries, so cancel the consumer and call
+/// [`wait_in_flight_deliveries`] before closing the channel to make sure they are sent.
+///
+/// It has no effect if the consumer uses automatic acknowledgement.
+///
+/// [`basic_consume`]: struct.Channel.html#method.basic_consume
+/// [`basic_cancel`]: struct.Channel.html#method.basic_cancel
+/// [`wait_in_flight_deliveries`]: struct.Channel.html#method.wait_in_flight_deliveries
+/// [`AsyncConsumer::try_consume`]: ../consumer/trait.AsyncConsumer.html#method.try_consume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckBatchPolicy {
+    /// Flush once this number of acks are pending.
+    pub max_messages: usize,
+    /// Flush once the first pending ack has waited this long.
+    pub max_delay: Duration,
+}
+
+impl AckBatchPolicy {
+    /// Create a new policy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_messages` is zero.
+    pub fn new(max_messages: usize, max_delay: Duration) -> Self {
+        assert!(max_messages > 0, "ack batch size must be greater than zero");
+        Self {
+            max_messages,
+            max_delay,
+        }
+    }
+}
+
+/// Acks accumulated by the consumer task of `basic_consume` according to an [`AckBatchPolicy`].
+struct AckBatch<'a> {
+    policy: AckBatchPolicy,
+    /// delivery tags of processed deliveries to ack
+    pending: BTreeSet<u64>,
+    /// when to flush the pending acks
+    deadline: Option<time::Instant>,
+    /// pending acks count as in-flight deliveries
+    _in_flight: Option<InFlightDelivery<'a>>,
+}
+
+impl<'a> AckBatch<'a> {
+    fn new(policy: AckBatchPolicy) -> Self {
+        Self {
+            policy,
+            pending: BTreeSet::new(),
+            deadline: None,
+            _in_flight: None,
+        }
+    }
+
+    /// Add a processed delivery, returns `true` if the pending acks should be flushed.
+    fn push(&mut self, delivery_tag: DeliveryTag, in_flight: &'a watch::Sender<usize>) -> bool {
+        if self.pending.is_empty() {
+            self.deadline = Some(time::Instant::now() + self.policy.max_delay);
+            self._in_flight = Some(InFlightDelivery::start(in_flight));
+        }
+        self.pending.insert(delivery_tag.into());
+        self.pending.len() >= self.policy.max_messages
+    }
+
+    /// Ack the pending deliveries of consumer `_consumer_tag`.
+    async fn flush(&mut self, channel: &Channel, _consumer_tag: &str) {
+        let acks = {
+            let unacked = channel.shared.unacked.lock().unwrap();
+            batched_acks(&self.pending, &unacked)
+        };
+        self.pending.clear();
+        self.deadline.take();
+        for (delivery_tag, multiple) in acks {
+            let args = BasicAckArguments::new(delivery_tag.into(), multiple);
+            if let Err(_err) = channel.basic_ack(args).await {
+                #[cfg(feature = "traces")]
+                error!(
+                    "failed to flush acks of async consumer {}, cause: {}",
+                    _consumer_tag, _err
+                );
+                break;
+            }
+        }
+        self._in_flight.take();
+    }
+}
+
+/// Returns the acks `(delivery_tag, multiple)` to settle the `pending` deliveries,
+/// given the `unacked` deliveries of the channel.
+///
+/// Pending deliveries which are not unacked any more are skipped. The leading pending
+/// deliveries are acked by a single `multiple` ack, the ones after a gap one by one.
+fn batched_acks(pending: &BTreeSet<u64>, unacked: &BTreeSet<u64>) -> Vec<(u64, bool)> {
+    let mut acks = Vec::new();
+    let last = match pending.iter().next_back() {
+        Some(last) => *last,
+        None => return acks,
+    };
+    let mut leading: Option<(u64, usize)> = None;
+    let mut after_gap = false;
+    for &delivery_tag in unacked.range(..=last) {
+        if !pending.contains(&delivery_tag) {
+            after_gap = true;
+        } else if after_gap {
+            acks.push((delivery_tag, false));
+        } else {
+            let count = leading.map_or(0, |(_, count)| count);
+            leading = Some((delivery_tag, count + 1));
+        }
+    }
+    if let Some((delivery_tag, count)) = leading {
+        acks.insert(0, (delivery_tag, count > 1));
+    }
+    acks
+}
+
+/// Future which resolves to `Err` if the inner future panics when being polled.
+struct CatchUnwind<F: ?Sized>(Pin<Box<F>>);
+
+impl<F: Future + ?Sized> Future for CatchUnwind<F> {
+    type Output = std::thread::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.0.as_mut();
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(cause) => Poll::Ready(Err(cause)),
+        }
+    }
+}
+
+/// Counts a delivery as in flight from creation until drop, i.e. until its handler completes.
+struct InFlightDelivery<'a>(&'a watch::Sender<usize>);
+
+impl<'a> InFlightDelivery<'a> {
+    fn start(in_flight: &'a watch::Sender<usize>) -> Self {
+        in_flight.send_modify(|count| *count += 1);
+        Self(in_flight)
+    }
+}
+
+impl Drop for InFlightDelivery<'_> {
+    fn drop(&mut self) {
+        self.0.send_modify(|count| *count -= 1);
+    }
+}
+
+/// Counts a consumer task as running from creation until drop, i.e. until the task exits.
+struct RunningConsumer(Arc<SharedChannelInner>);
+
+impl RunningConsumer {
+    fn start(shared: &Arc<SharedChannelInner>) -> Self {
+        shared.consumer_tasks.send_modify(|count| *count += 1);
+        Self(shared.clone())
+    }
+}
+
+impl Drop for RunningConsumer {
+    fn drop(&mut self) {
+        self.0.consumer_tasks.send_modify(|count| *count -= 1);
+    }
 }
+
 ////////////////////////////////////////////////////////////////////////////////
 /// Arguments for [`basic_cancel`]
 ///
@@ -244,7 +745,11 @@ impl BasicGetArguments {
 ///
 /// `get-ok` + `message propertities` + `message body`
 ///
+/// [`GetOk::message_count`] is the number of messages remaining in the queue after this one,
+/// which estimates the queue depth without a passive `queue.declare`.
+///
 /// [`Channel::basic_get`]: struct.Channel.html#method.basic_get
+/// [`GetOk::message_count`]: ../struct.GetOk.html#method.message_count
 pub type GetMessage = (GetOk, BasicProperties, Vec<u8>);
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -256,14 +761,14 @@ pub type GetMessage = (GetOk, BasicProperties, Vec<u8>);
 #[derive(Debug, Clone, Default)]
 pub struct BasicAckArguments {
     /// Default: 0.
-    pub delivery_tag: u64,
+    pub delivery_tag: DeliveryTag,
     /// Default: `false`.
     pub multiple: bool,
 }
 
 impl BasicAckArguments {
     /// Create new arguments with defaults.
-    pub fn new(delivery_tag: AmqpDeliveryTag, multiple: bool) -> Self {
+    pub fn new(delivery_tag: DeliveryTag, multiple: bool) -> Self {
         Self {
             delivery_tag,
             multiple,
@@ -279,7 +784,7 @@ impl BasicAckArguments {
 #[derive(Debug, Clone)]
 pub struct BasicNackArguments {
     /// Default: 0.
-    pub delivery_tag: u64,
+    pub delivery_tag: DeliveryTag,
     /// Default: `false`'.
     pub multiple: bool,
     /// Default: `true`.
@@ -288,7 +793,7 @@ pub struct BasicNackArguments {
 impl Default for BasicNackArguments {
     fn default() -> Self {
         Self {
-            delivery_tag: 0,
+            delivery_tag: DeliveryTag::default(),
             multiple: false,
             requeue: true,
         }
@@ -297,7 +802,7 @@ impl Default for BasicNackArguments {
 impl BasicNackArguments {
     /// Create new arguments with defaults.
 
-    pub fn new(delivery_tag: u64, multiple: bool, requeue: bool) -> Self {
+    pub fn new(delivery_tag: DeliveryTag, multiple: bool, requeue: bool) -> Self {
         Self {
             delivery_tag,
             multiple,
@@ -314,7 +819,7 @@ impl BasicNackArguments {
 #[derive(Debug, Clone)]
 pub struct BasicRejectArguments {
     /// Default: 0.
-    pub delivery_tag: u64,
+    pub delivery_tag: DeliveryTag,
     /// Default: `true`.
     pub requeue: bool,
 }
@@ -322,14 +827,14 @@ pub struct BasicRejectArguments {
 impl Default for BasicRejectArguments {
     fn default() -> Self {
         Self {
-            delivery_tag: 0,
+            delivery_tag: DeliveryTag::default(),
             requeue: true,
         }
     }
 }
 impl BasicRejectArguments {
     /// Create new arguments with defaults.
-    pub fn new(delivery_tag: AmqpDeliveryTag, requeue: bool) -> Self {
+    pub fn new(delivery_tag: DeliveryTag, requeue: bool) -> Self {
         Self {
             delivery_tag,
             requeue,
@@ -392,6 +897,148 @@ impl BasicPublishArguments {
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////
+/// Header injected into every message published on a channel, e.g. a sequence or trace id
+/// for end-to-end tracing.
+///
+/// See [`set_publish_id_header`].
+///
+/// [`set_publish_id_header`]: struct.Channel.html#method.set_publish_id_header
+pub struct PublishIdHeader {
+    name: String,
+    source: PublishIdSource,
+}
+
+enum PublishIdSource {
+    /// next value of the counter
+    Counter(i64),
+    Generator(Box<dyn FnMut() -> FieldValue + Send>),
+}
+
+impl PublishIdHeader {
+    /// Header `name` with a monotonic counter, starts from 1 and increments per message.
+    pub fn counter(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            source: PublishIdSource::Counter(1),
+        }
+    }
+
+    /// Header `name` with the ids returned by `generator`, it is called once per message.
+    pub fn generator<F>(name: &str, generator: F) -> Self
+    where
+        F: FnMut() -> FieldValue + Send + 'static,
+    {
+        Self {
+            name: name.to_owned(),
+            source: PublishIdSource::Generator(Box::new(generator)),
+        }
+    }
+
+    /// Returns the header name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Insert next id into the headers of `basic_properties`, unless the header is already set.
+    fn inject(&mut self, basic_properties: &mut BasicProperties) -> Result<()> {
+        let name = to_short_str("publish id header", self.name.clone())?;
+        let mut headers = basic_properties.headers().cloned().unwrap_or_default();
+        if headers.get(&name).is_some() {
+            return Ok(());
+        }
+        let id = match &mut self.source {
+            PublishIdSource::Counter(next) => {
+                let id = *next;
+                *next += 1;
+                FieldValue::l(id)
+            }
+            PublishIdSource::Generator(generator) => generator(),
+        };
+        headers.insert(name, id);
+        basic_properties.with_headers(headers);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for PublishIdHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self.source {
+            PublishIdSource::Counter(next) => format!("Counter({})", next),
+            PublishIdSource::Generator(_) => "Generator".to_owned(),
+        };
+        f.debug_struct("PublishIdHeader")
+            .field("name", &self.name)
+            .field("source", &source)
+            .finish()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Generator of client-named consumer tags for consumers of a channel.
+///
+/// See [`set_consumer_tag_generator`].
+///
+/// [`set_consumer_tag_generator`]: struct.Channel.html#method.set_consumer_tag_generator
+pub struct ConsumerTagGenerator {
+    app: String,
+    source: ConsumerTagSource,
+}
+
+enum ConsumerTagSource {
+    /// next value of the counter
+    Counter(u64),
+    Generator(Box<dyn FnMut(AmqpChannelId) -> String + Send>),
+}
+
+impl ConsumerTagGenerator {
+    /// Tags in the format `ctag-<app>-<channel id>-<counter>`, the counter starts from 1
+    /// and increments per tag, e.g. "ctag-billing-1-1".
+    pub fn new(app: &str) -> Self {
+        Self {
+            app: app.to_owned(),
+            source: ConsumerTagSource::Counter(1),
+        }
+    }
+
+    /// Tags returned by `generator` with the channel id, it is called once per consumer,
+    /// e.g. to get deterministic tags in tests.
+    pub fn generator<F>(generator: F) -> Self
+    where
+        F: FnMut(AmqpChannelId) -> String + Send + 'static,
+    {
+        Self {
+            app: String::new(),
+            source: ConsumerTagSource::Generator(Box::new(generator)),
+        }
+    }
+
+    /// Returns next tag for a consumer of channel `channel_id`.
+    pub fn next_tag(&mut self, channel_id: AmqpChannelId) -> String {
+        match &mut self.source {
+            ConsumerTagSource::Counter(next) => {
+                let tag = format!("ctag-{}-{}-{}", self.app, channel_id, next);
+                *next += 1;
+                tag
+            }
+            ConsumerTagSource::Generator(generator) => generator(channel_id),
+        }
+    }
+}
+
+impl fmt::Debug for ConsumerTagGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self.source {
+            ConsumerTagSource::Counter(next) => format!("Counter({})", next),
+            ConsumerTagSource::Generator(_) => "Generator".to_owned(),
+        };
+        f.debug_struct("ConsumerTagGenerator")
+            .field("app", &self.app)
+            .field("source", &source)
+            .finish()
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 /// APIs for AMQP basic class.
 impl Channel {
@@ -411,9 +1058,62 @@ impl Channel {
             Frame::QosOk,
             Error::ChannelUseError
         )?;
+        self.shared
+            .prefetch
+            .lock()
+            .unwrap()
+            .set(args.prefetch_count, args.global);
+        self.connection
+            .record_channel_qos(self.channel_id(), args.clone());
+        self.shared.qos.lock().unwrap().replace(args);
         Ok(())
     }
 
+    /// Returns the prefetch count actually applied to the consumer of `consumer_tag`,
+    /// `0` means unlimited.
+    ///
+    /// RabbitMQ applies `basic_qos` with `global` to all consumers on the channel together,
+    /// and `basic_qos` without `global` to each consumer started afterwards separately.
+    /// Server stops delivering to a consumer once either limit is reached, so the effective
+    /// prefetch is the smaller of the non-zero limits. The actual number of deliveries may be
+    /// lower because other consumers on the channel share the global limit.
+    ///
+    /// For a consumer not started on this channel, returns the prefetch a consumer started
+    /// now would get. It is computed from the `basic_qos` accepted on this channel, without
+    /// asking server.
+    pub fn effective_prefetch(&self, consumer_tag: &str) -> u16 {
+        self.shared.prefetch.lock().unwrap().effective(consumer_tag)
+    }
+
+    /// Returns the last [`BasicQosArguments`] accepted by server on this channel,
+    /// or `None` if [`basic_qos`] was never called.
+    ///
+    /// [`basic_qos`]: struct.Channel.html#method.basic_qos
+    pub fn qos(&self) -> Option<BasicQosArguments> {
+        self.shared.qos.lock().unwrap().clone()
+    }
+
+    /// Re-apply the last `basic_qos` of `previous` channel on this channel.
+    ///
+    /// Prefetch settings are not kept by server when a connection is lost.
+    /// After reconnecting and reopening a channel, call this method before
+    /// starting consumers again, otherwise the recovered consumers get unlimited prefetch.
+    /// It is a no-op if `basic_qos` was never called on `previous` channel.
+    ///
+    /// [`Connection::recover`] re-applies the `basic_qos` of the recovered channels by itself.
+    ///
+    /// [`Connection::recover`]: ../connection/struct.Connection.html#method.recover
+    ///
+    /// # Errors
+    ///
+    /// Returns error if any failure in comunication with server.
+    pub async fn recover_qos(&self, previous: &Channel) -> Result<()> {
+        match previous.qos() {
+            Some(args) => self.basic_qos(args).await,
+            None => Ok(()),
+        }
+    }
+
     /// See [AMQP_0-9-1 Reference](https://www.rabbitmq.com/amqp-0-9-1-reference.html#basic.ack)
     ///
     /// Returns the consumer tag on success.
@@ -425,9 +1125,24 @@ impl Channel {
     where
         F: AsyncConsumer + Send + 'static,
     {
-        let consumer_tag = self.request_basic_consume(args).await?;
+        let delivery_channel = args.delivery_channel;
+        let error_policy = args.error_policy;
+        let no_ack = args.no_ack;
+        // deliveries of automatic acknowledgement are never acked
+        let ack_batch = args.ack_batch.filter(|_| !no_ack);
+        let consumer_tag = self
+            .request_basic_consume(args, ConsumerHandler::Async, None)
+            .await?;
 
-        self.spawn_consumer(consumer_tag.clone(), consumer).await?;
+        self.spawn_consumer(
+            consumer_tag.clone(),
+            consumer,
+            delivery_channel,
+            error_policy,
+            no_ack,
+            ack_batch,
+        )
+        .await?;
 
         Ok(consumer_tag)
     }
@@ -449,14 +1164,102 @@ impl Channel {
     where
         F: BlockingConsumer + Send + 'static,
     {
-        let consumer_tag = self.request_basic_consume(args).await?;
+        let delivery_channel = args.delivery_channel;
+        let consumer_tag = self
+            .request_basic_consume(args, ConsumerHandler::Blocking, None)
+            .await?;
 
-        self.spawn_blocking_consumer(consumer_tag.clone(), consumer)
+        self.spawn_blocking_consumer(consumer_tag.clone(), consumer, delivery_channel)
             .await?;
 
         Ok(consumer_tag)
     }
 
+    /// Similar as [`basic_consume`] but the consumer is created by `factory`, so that
+    /// [`Connection::recover`] can start the consumer again with a new instance after the
+    /// connection is lost.
+    ///
+    /// Returns the consumer tag on success.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a failure occurs while comunicating with the server.
+    ///
+    /// [`basic_consume`]: struct.Channel.html#method.basic_consume
+    /// [`Connection::recover`]: ../connection/struct.Connection.html#method.recover
+    pub async fn basic_consume_recoverable<F, C>(
+        &self,
+        factory: F,
+        args: BasicConsumeArguments,
+    ) -> Result<String>
+    where
+        F: Fn() -> C + Send + Sync + 'static,
+        C: AsyncConsumer + Send + 'static,
+    {
+        let factory = ConsumerFactory::Async(Arc::new(move || Box::new(factory())));
+        self.consume_with_factory(factory, args).await
+    }
+
+    /// Similar as [`basic_consume_recoverable`] but run the consumer in a blocking context.
+    ///
+    /// Returns the consumer tag on success.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a failure occurs while comunicating with the server.
+    ///
+    /// [`basic_consume_recoverable`]: struct.Channel.html#method.basic_consume_recoverable
+    pub async fn basic_consume_blocking_recoverable<F, C>(
+        &self,
+        factory: F,
+        args: BasicConsumeArguments,
+    ) -> Result<String>
+    where
+        F: Fn() -> C + Send + Sync + 'static,
+        C: BlockingConsumer + Send + 'static,
+    {
+        let factory = ConsumerFactory::Blocking(Arc::new(move || Box::new(factory())));
+        self.consume_with_factory(factory, args).await
+    }
+
+    /// Start a consumer created by `factory`, which is recorded to recover the consumer.
+    pub(crate) async fn consume_with_factory(
+        &self,
+        factory: ConsumerFactory,
+        args: BasicConsumeArguments,
+    ) -> Result<String> {
+        let delivery_channel = args.delivery_channel;
+        let error_policy = args.error_policy;
+        let no_ack = args.no_ack;
+        // deliveries of automatic acknowledgement are never acked
+        let ack_batch = args.ack_batch.filter(|_| !no_ack);
+        let handler = match factory {
+            ConsumerFactory::Async(_) => ConsumerHandler::Async,
+            ConsumerFactory::Blocking(_) => ConsumerHandler::Blocking,
+        };
+        let consumer_tag = self
+            .request_basic_consume(args, handler, Some(factory.clone()))
+            .await?;
+        match factory {
+            ConsumerFactory::Async(create) => {
+                self.spawn_consumer(
+                    consumer_tag.clone(),
+                    create(),
+                    delivery_channel,
+                    error_policy,
+                    no_ack,
+                    ack_batch,
+                )
+                .await?
+            }
+            ConsumerFactory::Blocking(create) => {
+                self.spawn_blocking_consumer(consumer_tag.clone(), create(), delivery_channel)
+                    .await?
+            }
+        }
+        Ok(consumer_tag)
+    }
+
     /// Similar to [`basic_consume`] but returns the raw unbounded [`UnboundedReceiver`]
     ///
     /// Returns the consumer tag and the [`UnboundedReceiver`] on success.
@@ -468,6 +1271,9 @@ impl Channel {
     /// If `no-ack` is true, the `prefetch-size` and `prefetch-count` are ignored, flow control
     /// on application level maybe need to be introduced, othersie it relies on TCP backpresure.
     ///
+    /// The receiver is always unbounded, use [`basic_consume_stream`] for a bounded
+    /// [`BasicConsumeArguments::delivery_channel`].
+    ///
     /// ```
     /// # use amqprs::{
     /// #     callbacks::{DefaultChannelCallback, DefaultConnectionCallback},
@@ -545,57 +1351,113 @@ impl Channel {
     ///
     /// # Errors
     ///
+    /// Returns [`Error::ChannelUseError`] if [`BasicConsumeArguments::delivery_channel`] is
+    /// bounded, the consumer is not started.
+    ///
     /// Returns an error if a failure occurs while comunicating with the server.
     ///
     /// [`basic_consume`]: struct.Channel.html#method.basic_consume
+    /// [`basic_consume_stream`]: struct.Channel.html#method.basic_consume_stream
     /// [`basic_cancel`]: struct.Channel.html#method.basic_cancel
     /// [`basic_qos`]: struct.Channel.html#method.basic_qos
+    /// [`Error::ChannelUseError`]: ../error/enum.Error.html#variant.ChannelUseError
     /// [`UnboundedReceiver`]: https://docs.rs/tokio/latest/tokio/sync/mpsc/struct.UnboundedReceiver.html
     pub async fn basic_consume_rx(
         &self,
         args: BasicConsumeArguments,
     ) -> Result<(String, mpsc::UnboundedReceiver<ConsumerMessage>)> {
-        let consumer_tag = self.request_basic_consume(args).await?;
+        if let DeliveryChannel::Bounded(capacity) = args.delivery_channel {
+            return Err(Error::ChannelUseError(format!(
+                "receiver of consumer is unbounded, but delivery channel is bounded of capacity {}",
+                capacity
+            )));
+        }
+        let consumer_tag = self
+            .request_basic_consume(args, ConsumerHandler::Receiver, None)
+            .await?;
 
         let (consumer_tx, consumer_rx): (
             mpsc::UnboundedSender<ConsumerMessage>,
             mpsc::UnboundedReceiver<ConsumerMessage>,
         ) = mpsc::unbounded_channel();
 
-        self.register_consumer(consumer_tag.clone(), consumer_tx)
+        self.register_consumer(consumer_tag.clone(), ConsumerTx::Unbounded(consumer_tx))
             .await?;
 
         Ok((consumer_tag, consumer_rx))
     }
 
-    /// Send basic consume request to server
-    async fn request_basic_consume(&self, args: BasicConsumeArguments) -> Result<String> {
-        let BasicConsumeArguments {
-            queue,
+    /// Similar to [`basic_consume_rx`] but returns a [`ConsumerStream`], which
+    /// supports receiving with timeout.
+    ///
+    /// The stream uses the buffer of [`BasicConsumeArguments::delivery_channel`].
+    ///
+    /// If you were to stop consuming before the stream has ended,
+    /// you must call [`basic_cancel`] to make sure resources are cleaned up properly.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a failure occurs while comunicating with the server.
+    ///
+    /// [`basic_consume_rx`]: struct.Channel.html#method.basic_consume_rx
+    /// [`basic_cancel`]: struct.Channel.html#method.basic_cancel
+    pub async fn basic_consume_stream(
+        &self,
+        args: BasicConsumeArguments,
+    ) -> Result<ConsumerStream> {
+        let delivery_channel = args.delivery_channel;
+        let consumer_tag = self
+            .request_basic_consume(args, ConsumerHandler::Stream, None)
+            .await?;
+
+        let (consumer_tx, consumer_rx) = consumer_channel(delivery_channel);
+        self.register_consumer(consumer_tag.clone(), consumer_tx)
+            .await?;
+
+        Ok(ConsumerStream::new(
             consumer_tag,
-            no_local,
-            no_ack,
-            exclusive,
-            no_wait,
-            arguments,
-        } = args;
-        let mut consume = Consume::new(
-            0,
-            queue.try_into().unwrap(),
-            consumer_tag.clone().try_into().unwrap(),
-            arguments,
-        );
-        consume.set_no_local(no_local);
-        consume.set_no_ack(no_ack);
-        consume.set_exclusive(exclusive);
-        consume.set_nowait(no_wait);
-        let consumer_tag = if args.no_wait {
+            consumer_rx,
+            Some(self.shared.dispatcher_mgmt_tx.clone()),
+        ))
+    }
+
+    /// Send basic consume request to server, and record the consumer in the registry of connection
+    async fn request_basic_consume(
+        &self,
+        mut args: BasicConsumeArguments,
+        handler: ConsumerHandler,
+        factory: Option<ConsumerFactory>,
+    ) -> Result<String> {
+        if args.consumer_tag.is_empty() {
+            if let Some(generator) = self.shared.consumer_tag_generator.lock().unwrap().as_mut() {
+                args.consumer_tag = generator.next_tag(self.channel_id());
+            }
+        }
+        let no_wait = args.no_wait;
+        let no_ack = args.no_ack;
+        let registered_args = args.clone();
+        let (consume, consumer_tag) = args.into_consume()?;
+        // server applies the per-consumer prefetch at the time of consume
+        let prefetch_count = self.shared.prefetch.lock().unwrap().per_consumer;
+        let consumer_tag = if no_wait {
+            // no `consume-ok` for dispatcher to learn the consumer tag
+            let command = ExpectContentConsumer {
+                consumer_tag: consumer_tag.clone(),
+                no_ack,
+            };
+            self.shared
+                .dispatcher_mgmt_tx
+                .send(DispatcherManagementCommand::ExpectContentConsumer(command))?;
             self.shared
                 .outgoing_tx
                 .send((self.shared.channel_id, consume.into_frame()))
                 .await?;
             consumer_tag
         } else {
+            let command = ExpectConsumeOk { no_ack };
+            self.shared
+                .dispatcher_mgmt_tx
+                .send(DispatcherManagementCommand::ExpectConsumeOk(command))?;
             let responder_rx = self.register_responder(ConsumeOk::header()).await?;
 
             let method = synchronous_request!(
@@ -607,24 +1469,44 @@ impl Channel {
             )?;
             method.consumer_tag.into()
         };
+        self.shared
+            .prefetch
+            .lock()
+            .unwrap()
+            .consumers
+            .insert(consumer_tag.clone(), prefetch_count);
+        self.connection.register_consumer(
+            self.shared.channel_id,
+            consumer_tag.clone(),
+            registered_args,
+            handler,
+            factory,
+        );
         Ok(consumer_tag)
     }
 
     /// Spawn async consumer task
-    async fn spawn_consumer<F>(&self, consumer_tag: String, mut consumer: F) -> Result<()>
+    async fn spawn_consumer<F>(
+        &self,
+        consumer_tag: String,
+        mut consumer: F,
+        delivery_channel: DeliveryChannel,
+        error_policy: ConsumerErrorPolicy,
+        no_ack: bool,
+        ack_batch: Option<AckBatchPolicy>,
+    ) -> Result<()>
     where
         F: AsyncConsumer + Send + 'static,
     {
-        let (consumer_tx, mut consumer_rx): (
-            mpsc::UnboundedSender<ConsumerMessage>,
-            mpsc::UnboundedReceiver<ConsumerMessage>,
-        ) = mpsc::unbounded_channel();
+        let (consumer_tx, mut consumer_rx) = consumer_channel(delivery_channel);
 
         let ctag = consumer_tag.clone();
         let channel = self.clone_as_secondary();
+        let running = RunningConsumer::start(&self.shared);
 
         // spawn consumer task
         tokio::spawn(async move {
+            let _running = running;
             #[cfg(feature = "traces")]
             trace!(
                 "starts task for async consumer {} on channel {}",
@@ -632,19 +1514,75 @@ impl Channel {
                 channel
             );
 
+            let mut ack_batch = ack_batch.map(AckBatch::new);
             loop {
-                match consumer_rx.recv().await {
+                let received = match ack_batch.as_ref().and_then(|batch| batch.deadline) {
+                    Some(deadline) => tokio::select! {
+                        received = consumer_rx.recv() => received,
+                        _ = time::sleep_until(deadline) => {
+                            // pending acks reach the max delay
+                            ack_batch.as_mut().unwrap().flush(&channel, &ctag).await;
+                            continue;
+                        }
+                    },
+                    None => consumer_rx.recv().await,
+                };
+                match received {
                     Some(mut msg) => {
-                        consumer
-                            .consume(
-                                &channel,
-                                msg.deliver.take().unwrap(),
-                                msg.basic_properties.take().unwrap(),
-                                msg.content.take().unwrap(),
-                            )
-                            .await;
+                        let _in_flight = InFlightDelivery::start(&channel.shared.in_flight);
+                        // released before the consumer runs, which may publish and wait for
+                        // the buffer limit
+                        drop(msg.buffered.take());
+                        let deliver = msg.deliver.take().unwrap();
+                        let delivery_tag = deliver.delivery_tag();
+                        let result = CatchUnwind(consumer.try_consume(
+                            &channel,
+                            deliver,
+                            msg.basic_properties.take().unwrap(),
+                            msg.content.take().unwrap(),
+                        ))
+                        .await;
+                        let failed = match result {
+                            Ok(Ok(())) => false,
+                            Ok(Err(_err)) => {
+                                #[cfg(feature = "traces")]
+                                warn!(
+                                    "async consumer {} failed to process delivery {}, cause: {}",
+                                    ctag, delivery_tag, _err
+                                );
+                                true
+                            }
+                            Err(_) => {
+                                #[cfg(feature = "traces")]
+                                warn!(
+                                    "async consumer {} panicked when processing delivery {}",
+                                    ctag, delivery_tag
+                                );
+                                true
+                            }
+                        };
+                        if failed {
+                            if let Err(_err) = channel
+                                .handle_consumer_error(error_policy, delivery_tag, no_ack)
+                                .await
+                            {
+                                #[cfg(feature = "traces")]
+                                error!(
+                                    "failed to apply {:?} for async consumer {}, cause: {}",
+                                    error_policy, ctag, _err
+                                );
+                            }
+                        } else if let Some(ref mut batch) = ack_batch {
+                            if batch.push(delivery_tag, &channel.shared.in_flight) {
+                                batch.flush(&channel, &ctag).await;
+                            }
+                        }
                     }
                     None => {
+                        // flush pending acks before the consumer ends
+                        if let Some(ref mut batch) = ack_batch {
+                            batch.flush(&channel, &ctag).await;
+                        }
                         #[cfg(feature = "traces")]
                         debug!("exit task of async consumer {}", ctag);
                         break;
@@ -657,21 +1595,41 @@ impl Channel {
         Ok(())
     }
 
+    /// Apply `error_policy` to a delivery which the consumer fails to process.
+    async fn handle_consumer_error(
+        &self,
+        error_policy: ConsumerErrorPolicy,
+        delivery_tag: DeliveryTag,
+        no_ack: bool,
+    ) -> Result<()> {
+        if let Some(args) = error_policy.nack_arguments(delivery_tag, no_ack) {
+            self.basic_nack(args).await?;
+        }
+        if error_policy == ConsumerErrorPolicy::CloseChannel {
+            self.shared.close().await?;
+        }
+        Ok(())
+    }
+
     /// Spawn blocking consumer task
-    async fn spawn_blocking_consumer<F>(&self, consumer_tag: String, mut consumer: F) -> Result<()>
+    async fn spawn_blocking_consumer<F>(
+        &self,
+        consumer_tag: String,
+        mut consumer: F,
+        delivery_channel: DeliveryChannel,
+    ) -> Result<()>
     where
         F: BlockingConsumer + Send + 'static,
     {
-        let (consumer_tx, mut consumer_rx): (
-            mpsc::UnboundedSender<ConsumerMessage>,
-            mpsc::UnboundedReceiver<ConsumerMessage>,
-        ) = mpsc::unbounded_channel();
+        let (consumer_tx, mut consumer_rx) = consumer_channel(delivery_channel);
 
         let ctag = consumer_tag.clone();
         let channel = self.clone_as_secondary();
+        let running = RunningConsumer::start(&self.shared);
 
         // spawn blocking consumer task
         tokio::task::spawn_blocking(move || {
+            let _running = running;
             #[cfg(feature = "traces")]
             trace!(
                 "starts task for blocking consumer {} on channel {}",
@@ -682,6 +1640,7 @@ impl Channel {
             loop {
                 match consumer_rx.blocking_recv() {
                     Some(mut msg) => {
+                        drop(msg.buffered.take());
                         consumer.consume(
                             &channel,
                             msg.deliver.take().unwrap(),
@@ -703,11 +1662,7 @@ impl Channel {
     }
 
     /// register consumer in dispatcher
-    async fn register_consumer(
-        &self,
-        consumer_tag: String,
-        consumer_tx: mpsc::UnboundedSender<ConsumerMessage>,
-    ) -> Result<()> {
+    async fn register_consumer(&self, consumer_tag: String, consumer_tx: ConsumerTx) -> Result<()> {
         self.shared.dispatcher_mgmt_tx.send(
             DispatcherManagementCommand::RegisterContentConsumer(RegisterContentConsumer {
                 consumer_tag,
@@ -723,11 +1678,13 @@ impl Channel {
     ///
     /// Returns error if any failure in comunication with server.
     pub async fn basic_ack(&self, args: BasicAckArguments) -> Result<()> {
-        let ack = Ack::new(args.delivery_tag, args.multiple);
+        let ack = Ack::new(args.delivery_tag.into(), args.multiple);
         self.shared
             .outgoing_tx
             .send((self.shared.channel_id, ack.into_frame()))
             .await?;
+        self.shared
+            .settle_unacked(args.delivery_tag.into(), args.multiple);
         Ok(())
     }
 
@@ -743,10 +1700,12 @@ impl Channel {
     ///
     /// [`basic_ack`]: struct.Channel.html#method.basic_ack
     pub fn basic_ack_blocking(&self, args: BasicAckArguments) -> Result<()> {
-        let ack = Ack::new(args.delivery_tag, args.multiple);
+        let ack = Ack::new(args.delivery_tag.into(), args.multiple);
         self.shared
             .outgoing_tx
             .blocking_send((self.shared.channel_id, ack.into_frame()))?;
+        self.shared
+            .settle_unacked(args.delivery_tag.into(), args.multiple);
         Ok(())
     }
 
@@ -756,13 +1715,15 @@ impl Channel {
     ///
     /// Returns error if any failure in comunication with server.
     pub async fn basic_nack(&self, args: BasicNackArguments) -> Result<()> {
-        let mut nack = Nack::new(args.delivery_tag);
+        let mut nack = Nack::new(args.delivery_tag.into());
         nack.set_multiple(args.multiple);
         nack.set_requeue(args.requeue);
         self.shared
             .outgoing_tx
             .send((self.shared.channel_id, nack.into_frame()))
             .await?;
+        self.shared
+            .settle_unacked(args.delivery_tag.into(), args.multiple);
         Ok(())
     }
 
@@ -778,26 +1739,37 @@ impl Channel {
     ///
     /// [`basic_nack`]: struct.Channel.html#method.basic_nack
     pub fn basic_nack_blocking(&self, args: BasicNackArguments) -> Result<()> {
-        let mut nack = Nack::new(args.delivery_tag);
+        let mut nack = Nack::new(args.delivery_tag.into());
         nack.set_multiple(args.multiple);
         nack.set_requeue(args.requeue);
         self.shared
             .outgoing_tx
             .blocking_send((self.shared.channel_id, nack.into_frame()))?;
+        self.shared
+            .settle_unacked(args.delivery_tag.into(), args.multiple);
         Ok(())
     }
 
     /// See [AMQP_0-9-1 Reference](https://www.rabbitmq.com/amqp-0-9-1-reference.html#basic.reject)
     ///
+    /// If `requeue` is `true`, server puts the message back to the queue to be delivered
+    /// again. Otherwise, server dead-letters the message if the queue has a dead letter
+    /// exchange configured, or drops it. Use [`reject_requeue`] or [`reject_to_deadletter`]
+    /// to make the intent explicit.
+    ///
     /// # Errors
     ///
     /// Returns error if any failure in comunication with server.
+    ///
+    /// [`reject_requeue`]: struct.Channel.html#method.reject_requeue
+    /// [`reject_to_deadletter`]: struct.Channel.html#method.reject_to_deadletter
     pub async fn basic_reject(&self, args: BasicRejectArguments) -> Result<()> {
-        let reject = Reject::new(args.delivery_tag, args.requeue);
+        let reject = Reject::new(args.delivery_tag.into(), args.requeue);
         self.shared
             .outgoing_tx
             .send((self.shared.channel_id, reject.into_frame()))
             .await?;
+        self.shared.settle_unacked(args.delivery_tag.into(), false);
         Ok(())
     }
 
@@ -807,13 +1779,59 @@ impl Channel {
     ///
     /// Returns error if any failure in comunication with server.
     pub fn basic_reject_blocking(&self, args: BasicRejectArguments) -> Result<()> {
-        let reject = Reject::new(args.delivery_tag, args.requeue);
+        let reject = Reject::new(args.delivery_tag.into(), args.requeue);
         self.shared
             .outgoing_tx
             .blocking_send((self.shared.channel_id, reject.into_frame()))?;
+        self.shared.settle_unacked(args.delivery_tag.into(), false);
         Ok(())
     }
 
+    /// Reject the delivery and put it back to the queue to be delivered again.
+    ///
+    /// Same as [`basic_reject`] with `requeue = true`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if any failure in comunication with server.
+    ///
+    /// [`basic_reject`]: struct.Channel.html#method.basic_reject
+    pub async fn reject_requeue(&self, delivery_tag: DeliveryTag) -> Result<()> {
+        self.basic_reject(BasicRejectArguments::new(delivery_tag, true))
+            .await
+    }
+
+    /// Reject the delivery without requeue, so that server dead-letters it if the queue
+    /// has a dead letter exchange configured, or drops it otherwise.
+    ///
+    /// Same as [`basic_reject`] with `requeue = false`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if any failure in comunication with server.
+    ///
+    /// [`basic_reject`]: struct.Channel.html#method.basic_reject
+    pub async fn reject_to_deadletter(&self, delivery_tag: DeliveryTag) -> Result<()> {
+        self.basic_reject(BasicRejectArguments::new(delivery_tag, false))
+            .await
+    }
+
+    /// Reject an orphan delivery to be requeued, so that it does not hold the prefetch window.
+    ///
+    /// Deliveries acknowledged automatically by server, or already settled, are skipped.
+    pub(crate) async fn requeue_orphan(&self, delivery_tag: DeliveryTag) {
+        if !self.shared.is_unacked(delivery_tag.into()) {
+            return;
+        }
+        if let Err(_err) = self.reject_requeue(delivery_tag).await {
+            #[cfg(feature = "traces")]
+            error!(
+                "failed to requeue orphan delivery {} on channel {}, cause: {}",
+                delivery_tag, self, _err
+            );
+        }
+    }
+
     /// See [AMQP_0-9-1 Reference](https://www.rabbitmq.com/amqp-0-9-1-reference.html#basic.cancel)
     ///
     /// Returns consumer tag if succeed.
@@ -827,7 +1845,7 @@ impl Channel {
             no_wait,
         } = args;
 
-        let cancel = Cancel::new(consumer_tag.clone().try_into().unwrap(), no_wait);
+        let cancel = Cancel::new(to_short_str("consumer tag", consumer_tag.clone())?, no_wait);
 
         let consumer_tag = if args.no_wait {
             self.shared
@@ -847,6 +1865,8 @@ impl Channel {
             )?;
             cancel_ok.consumer_tag.into()
         };
+        self.connection
+            .deregister_consumer(self.shared.channel_id, &consumer_tag);
 
         let consumer_tag2 = consumer_tag.clone();
         let cmd = DeregisterContentConsumer { consumer_tag };
@@ -856,18 +1876,190 @@ impl Channel {
         Ok(consumer_tag2)
     }
 
+    /// Cancel all consumers of the channel, and wait until the consumer tasks have processed
+    /// the deliveries received before `cancel-ok`.
+    ///
+    /// Failures are only logged, because the channel is closed anyway. The consumer tasks
+    /// are not waited for if any consumer failed to be cancelled, and at most for
+    /// `CONSUMER_SHUTDOWN_TIMEOUT` otherwise.
+    pub(super) async fn cancel_consumers(&self) {
+        let (tx, rx) = oneshot::channel();
+        let consumer_tags = match self.shared.dispatcher_mgmt_tx.send(
+            DispatcherManagementCommand::ListContentConsumers(ListContentConsumers { tx }),
+        ) {
+            Ok(()) => rx.await.unwrap_or_default(),
+            Err(_) => return,
+        };
+        let mut all_cancelled = true;
+        for consumer_tag in consumer_tags {
+            if let Err(_err) = self
+                .basic_cancel(BasicCancelArguments::new(&consumer_tag))
+                .await
+            {
+                #[cfg(feature = "traces")]
+                error!(
+                    "failed to cancel consumer {} on channel {}, cause: {}",
+                    consumer_tag, self, _err
+                );
+                all_cancelled = false;
+            }
+        }
+        if !all_cancelled {
+            return;
+        }
+        // deregistered consumers drop the tx half, so their tasks exit once the rx half is drained
+        let mut consumer_tasks = self.shared.consumer_tasks.subscribe();
+        let wait_tasks = async move {
+            while *consumer_tasks.borrow_and_update() > 0 {
+                // sender is owned by `self`, it cannot be dropped while waiting
+                if consumer_tasks.changed().await.is_err() {
+                    break;
+                }
+            }
+        };
+        if time::timeout(CONSUMER_SHUTDOWN_TIMEOUT, wait_tasks)
+            .await
+            .is_err()
+        {
+            #[cfg(feature = "traces")]
+            error!(
+                "timeout to wait for consumers of channel {} to exit, close channel anyway",
+                self
+            );
+        }
+    }
+
+    /// Stop forwarding deliveries to the consumer of `consumer_tag`, without cancelling it.
+    ///
+    /// Server keeps delivering to the consumer, the deliveries are buffered in memory
+    /// until [`resume_consumer`] is called. To bound the memory used by a paused consumer,
+    /// consume with manual acknowledgement and set a prefetch count by [`basic_qos`],
+    /// then server stops delivering once prefetch count of deliveries are unacknowledged.
+    ///
+    /// For a consumer of manual acknowledgement, at most 1024 deliveries are buffered.
+    /// Further deliveries are rejected to be requeued, and server may deliver them again
+    /// right away, so keep the prefetch count below the limit. Deliveries acknowledged
+    /// automatically can not be requeued, so they are always buffered.
+    ///
+    /// Pausing an unknown consumer has no effect.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the channel has been closed.
+    ///
+    /// [`resume_consumer`]: struct.Channel.html#method.resume_consumer
+    /// [`basic_qos`]: struct.Channel.html#method.basic_qos
+    pub fn pause_consumer(&self, consumer_tag: &str) -> Result<()> {
+        self.set_consumer_paused(consumer_tag, true)
+    }
+
+    /// Resume forwarding deliveries to a consumer paused by [`pause_consumer`],
+    /// starting with the deliveries buffered while it was paused.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the channel has been closed.
+    ///
+    /// [`pause_consumer`]: struct.Channel.html#method.pause_consumer
+    pub fn resume_consumer(&self, consumer_tag: &str) -> Result<()> {
+        self.set_consumer_paused(consumer_tag, false)
+    }
+
+    fn set_consumer_paused(&self, consumer_tag: &str, paused: bool) -> Result<()> {
+        let cmd = PauseContentConsumer {
+            consumer_tag: consumer_tag.to_owned(),
+            paused,
+        };
+        self.shared
+            .dispatcher_mgmt_tx
+            .send(DispatcherManagementCommand::PauseContentConsumer(cmd))?;
+        Ok(())
+    }
+
+    /// Take up to `max` deliveries of the consumer of `consumer_tag` within `timeout`,
+    /// instead of forwarding them to the consumer.
+    ///
+    /// The `timeout` bounds the total time spent, not the wait for each delivery.
+    /// Returns early if `max` deliveries are taken or the consumer is cancelled.
+    /// Afterwards, deliveries are forwarded to the consumer again, in order.
+    ///
+    /// It works for consumers started by any of the `basic_consume` variants,
+    /// a [`ConsumerStream`] can also be drained by [`ConsumerStream::drain`].
+    /// Deliveries of a paused consumer are buffered, they are not taken.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the consumer is not registered, or the channel has been closed.
+    ///
+    /// [`ConsumerStream::drain`]: struct.ConsumerStream.html#method.drain
+    pub async fn drain(
+        &self,
+        consumer_tag: &str,
+        max: usize,
+        timeout: Duration,
+    ) -> Result<Vec<ConsumerMessage>> {
+        let (tx, rx) = consumer_channel(DeliveryChannel::Unbounded);
+        let (replaced_tx, replaced_rx) = oneshot::channel();
+        let cmd = SwapContentConsumer {
+            consumer_tag: consumer_tag.to_owned(),
+            consumer_tx: tx,
+            leftover_rx: None,
+            replaced: replaced_tx,
+        };
+        self.shared
+            .dispatcher_mgmt_tx
+            .send(DispatcherManagementCommand::SwapContentConsumer(cmd))?;
+        let consumer_tx = replaced_rx.await?.ok_or_else(|| {
+            Error::ChannelUseError(format!(
+                "consumer {} is not registered on channel {}",
+                consumer_tag, self
+            ))
+        })?;
+        let mut diverted = DivertedConsumer {
+            consumer_tag: consumer_tag.to_owned(),
+            consumer_tx: Some(consumer_tx),
+            rx: Some(rx),
+            dispatcher_mgmt_tx: self.shared.dispatcher_mgmt_tx.clone(),
+        };
+        let rx = diverted.rx.as_mut().unwrap();
+
+        let deadline = time::Instant::now() + timeout;
+        let mut messages = Vec::new();
+        while messages.len() < max {
+            match time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(message)) => messages.push(message),
+                // consumer cancelled or timed out
+                Ok(None) | Err(_) => break,
+            }
+        }
+        Ok(messages)
+    }
+
     /// See [AMQP_0-9-1 Reference](https://www.rabbitmq.com/amqp-0-9-1-reference.html#basic.get)
     ///
     /// Either returns a tuple [`GetMessage`] or [`None`] if no message available.
+    /// The `get-ok` of the tuple reports the number of messages remaining in the queue,
+    /// so a polling consumer can estimate the queue depth without an extra round trip.
+    ///
+    /// RabbitMQ does not apply the prefetch of [`basic_qos`] to `get`. Other brokers may
+    /// count the unacknowledged messages of `get` against the prefetch, then [`None`] is
+    /// returned once the prefetch is exhausted, although the queue still has messages.
+    /// Acknowledge the messages or use [`basic_get_bypass_qos`] to get regardless of it.
     ///
     /// # Errors
     ///
     /// Returns error if any failure in comunication with server.
+    ///
+    /// [`basic_qos`]: struct.Channel.html#method.basic_qos
+    /// [`basic_get_bypass_qos`]: struct.Channel.html#method.basic_get_bypass_qos
     pub async fn basic_get(&self, args: BasicGetArguments) -> Result<Option<GetMessage>> {
-        let get = Get::new(0, args.queue.try_into().unwrap(), args.no_ack);
+        let get = Get::new(0, to_short_str("queue name", args.queue)?, args.no_ack);
 
         let (tx, mut rx) = mpsc::unbounded_channel();
-        let command = RegisterGetContentResponder { tx };
+        let command = RegisterGetContentResponder {
+            tx,
+            no_ack: args.no_ack,
+        };
         self.shared.dispatcher_mgmt_tx.send(
             DispatcherManagementCommand::RegisterGetContentResponder(command),
         )?;
@@ -900,6 +2092,39 @@ impl Channel {
         Ok(Some((get_ok, basic_properties, content)))
     }
 
+    /// Same as [`basic_get`], but the prefetch of [`basic_qos`] is removed during `get`,
+    /// so it is not limited by the prefetch on brokers which apply it to `get`.
+    ///
+    /// The last [`qos`] is restored after `get`. Consumers started on the channel meanwhile
+    /// are not limited by the prefetch without `global`, and a prefetch with `global` is
+    /// removed for all consumers on the channel until it is restored.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if any failure in comunication with server. If the prefetch fails
+    /// to be restored, the message got is not returned, it is redelivered once the channel
+    /// is closed unless `no_ack` is set.
+    ///
+    /// [`basic_get`]: struct.Channel.html#method.basic_get
+    /// [`basic_qos`]: struct.Channel.html#method.basic_qos
+    /// [`qos`]: struct.Channel.html#method.qos
+    pub async fn basic_get_bypass_qos(
+        &self,
+        args: BasicGetArguments,
+    ) -> Result<Option<GetMessage>> {
+        let qos = self.qos();
+        match qos {
+            Some(qos) if qos.prefetch_count > 0 || qos.prefetch_size > 0 => {
+                self.basic_qos(BasicQosArguments::new(0, 0, qos.global))
+                    .await?;
+                let message = self.basic_get(args).await;
+                self.basic_qos(qos).await?;
+                message
+            }
+            _ => self.basic_get(args).await,
+        }
+    }
+
     /// See [AMQP_0-9-1 Reference](https://www.rabbitmq.com/amqp-0-9-1-reference.html#basic.recover)
     ///
     /// # Errors
@@ -924,219 +2149,2548 @@ impl Channel {
     ///
     /// # Errors
     ///
-    /// Returns error in case of a network I/O failure. For data safety, use
-    /// [publisher confirms](https://rabbitmq.com/publishers.html#data-safety).
+    /// Returns [`Error::PublishError`] with the exchange, routing key and body length of
+    /// the message, its `source` is the cause of the failure:
+    ///
+    /// - [`Error::ChannelClosed`] if the channel has been closed, so the message
+    ///   is not silently dropped.
+    /// - [`Error::NameTooLong`] if the exchange name or routing key is too long.
+    /// - other errors in case of a network I/O failure. For data safety, use
+    ///   [publisher confirms](https://rabbitmq.com/publishers.html#data-safety).
     pub async fn basic_publish(
         &self,
         basic_properties: BasicProperties,
         content: Vec<u8>,
         args: BasicPublishArguments,
     ) -> Result<()> {
-        let mut publish = Publish::new(
-            0,
-            args.exchange.try_into().unwrap(),
-            args.routing_key.try_into().unwrap(),
-        );
-        publish.set_mandatory(args.mandatory);
-        publish.set_immediate(args.immediate);
-
-        let content_header = ContentHeader::new(
-            ContentHeaderCommon {
-                class: 60, // basic class
-                weight: 0,
-                body_size: content.len() as u64,
-            },
-            basic_properties,
-        );
-
-        let publish_combo =
-            Frame::PublishCombo(publish, Box::new(content_header), ContentBody::new(content));
-        self.shared
-            .outgoing_tx
-            .send((self.shared.channel_id, publish_combo))
-            .await?;
+        self.publish(basic_properties, content, args, None).await?;
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::callbacks::{DefaultChannelCallback, DefaultConnectionCallback};
-    use crate::test_utils::setup_logging;
-    use crate::{
-        api::{
-            channel::{QueueBindArguments, QueueDeclareArguments},
-            connection::{Connection, OpenConnectionArguments},
-            consumer::DefaultConsumer,
-        },
-        frame::BasicProperties,
-        DELIVERY_MODE_TRANSIENT,
-    };
-    use tokio::time;
+    /// Same as [`basic_publish`], but the message properties are already encoded,
+    /// so they are not encoded again for every message.
+    ///
+    /// This is an advanced API for publishing identical messages many times, e.g. fanning out
+    /// the same message to many exchanges. The encoded properties are sent as they are, the
+    /// [default properties] and the [publish id header] of the channel are not applied.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use amqprs::{channel::{BasicPublishArguments, Channel}, BasicProperties, EncodedProperties};
+    /// # async fn fan_out(channel: &Channel, exchanges: &[&str]) -> Result<(), amqprs::error::Error> {
+    /// let basic_props = BasicProperties::default()
+    ///     .with_content_type("application/json")
+    ///     .finish();
+    /// let encoded = EncodedProperties::new(&basic_props);
+    /// for exchange in exchanges {
+    ///     channel
+    ///         .basic_publish_encoded(
+    ///             &encoded,
+    ///             b"{}".to_vec(),
+    ///             BasicPublishArguments::new(exchange, "greetings"),
+    ///         )
+    ///         .await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`basic_publish`].
+    ///
+    /// [`basic_publish`]: struct.Channel.html#method.basic_publish
+    /// [default properties]: struct.Channel.html#method.set_default_properties
+    /// [publish id header]: struct.Channel.html#method.set_publish_id_header
+    pub async fn basic_publish_encoded(
+        &self,
+        basic_properties: &EncodedProperties,
+        content: Vec<u8>,
+        args: BasicPublishArguments,
+    ) -> Result<()> {
+        let echo = args.clone();
+        let body_len = content.len();
+        self.send_publish(
+            PublishProperties::Encoded(basic_properties.clone()),
+            content,
+            args,
+            None,
+        )
+        .await
+        .map_err(|source| publish_error(&echo, body_len, source))?;
+        Ok(())
+    }
 
-    use super::{BasicConsumeArguments, BasicPublishArguments, BasicQosArguments};
+    /// Publish a [`ReturnedMessage`] again with `routing_key` to the exchange it was
+    /// originally published to, keeping its properties and body.
+    ///
+    /// The message is published as `mandatory`, so server returns it again if it is still
+    /// unroutable.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use amqprs::channel::{Channel, ReturnedMessage};
+    /// # async fn reroute(channel: &Channel, message: ReturnedMessage) -> Result<(), amqprs::error::Error> {
+    /// // route unroutable messages to a parking queue
+    /// channel.republish(message, "parking").await
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`basic_publish`].
+    ///
+    /// [`basic_publish`]: struct.Channel.html#method.basic_publish
+    pub async fn republish(&self, message: ReturnedMessage, routing_key: &str) -> Result<()> {
+        let exchange = message.exchange.clone();
+        self.republish_to(message, &exchange, routing_key).await
+    }
+
+    /// Same as [`republish`], but publish the message to `exchange`, e.g. an alternate
+    /// exchange for unroutable messages.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`basic_publish`].
+    ///
+    /// [`republish`]: struct.Channel.html#method.republish
+    /// [`basic_publish`]: struct.Channel.html#method.basic_publish
+    pub async fn republish_to(
+        &self,
+        message: ReturnedMessage,
+        exchange: &str,
+        routing_key: &str,
+    ) -> Result<()> {
+        let args = BasicPublishArguments::new(exchange, routing_key)
+            .mandatory(true)
+            .finish();
+        self.basic_publish(message.basic_properties, message.content, args)
+            .await
+    }
+
+    /// Set the properties merged into every message published on this channel, e.g. the
+    /// `app_id`, `content_type` and persistence which an application always uses.
+    ///
+    /// Properties of a published message override the defaults property by property.
+    /// Set `BasicProperties::default()` to remove the defaults.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use amqprs::{channel::{BasicPublishArguments, Channel}, BasicProperties};
+    /// # async fn publish(channel: &Channel) -> Result<(), amqprs::error::Error> {
+    /// channel.set_default_properties(
+    ///     BasicProperties::default()
+    ///         .with_app_id("app1")
+    ///         .with_content_type("application/json")
+    ///         .with_persistence(true)
+    ///         .finish(),
+    /// );
+    /// // published with `app_id` "app1", content type "text/plain" and persistent
+    /// channel
+    ///     .basic_publish(
+    ///         BasicProperties::default().with_content_type("text/plain").finish(),
+    ///         b"hello".to_vec(),
+    ///         BasicPublishArguments::new("amq.topic", "greetings"),
+    ///     )
+    ///     .await
+    /// # }
+    /// ```
+    pub fn set_default_properties(&self, basic_properties: BasicProperties) {
+        *self.shared.default_properties.lock().unwrap() = basic_properties;
+    }
+
+    /// Returns the properties merged into every message published on this channel.
+    pub fn default_properties(&self) -> BasicProperties {
+        self.shared.default_properties.lock().unwrap().clone()
+    }
+
+    /// Enable or disable strict mode of publishing to the default exchange. Default: disabled.
+    ///
+    /// Default exchange routes a message to the queue named by its routing key, and server
+    /// drops the message silently if no such queue exists, unless it is `mandatory`.
+    /// In strict mode, publishing to the default exchange checks that the queue exists by
+    /// a passive declare on a temporary channel before sending the message. Queues known to
+    /// exist are cached, so only the first publish to each queue waits for the check.
+    /// Replies to [`DIRECT_REPLY_TO_QUEUE`] are not checked.
+    ///
+    /// The cache does not notice a queue deleted by another channel or connection, use
+    /// `mandatory` publish to detect unroutable messages in that case.
+    ///
+    /// # Errors
+    ///
+    /// In strict mode, publish returns [`Error::PublishError`] caused by [`Error::QueueNotFound`]
+    /// if the queue does not exist, the message is not sent.
+    pub fn set_strict_default_exchange(&self, strict: bool) {
+        self.shared
+            .strict_default_exchange
+            .store(strict, Ordering::Relaxed);
+    }
+
+    /// Set the header injected into every message published on this channel, `None` to stop it.
+    ///
+    /// The header is not overridden if a published message already has it, e.g. republished.
+    /// Ids are generated in the order the messages are sent.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use amqprs::{channel::{BasicPublishArguments, Channel, PublishIdHeader}, BasicProperties};
+    /// # async fn publish(channel: &Channel) -> Result<(), amqprs::error::Error> {
+    /// channel.set_publish_id_header(Some(PublishIdHeader::counter("x-seq")));
+    /// // published with header "x-seq" = 1, next message gets 2
+    /// channel
+    ///     .basic_publish(
+    ///         BasicProperties::default(),
+    ///         b"hello".to_vec(),
+    ///         BasicPublishArguments::new("amq.topic", "greetings"),
+    ///     )
+    ///     .await
+    /// # }
+    /// ```
+    pub fn set_publish_id_header(&self, header: Option<PublishIdHeader>) {
+        *self.shared.publish_id_header.lock().unwrap() = header;
+    }
+
+    /// Set the generator of consumer tags on this channel, `None` to let server name them.
+    ///
+    /// It names the consumers whose arguments have an empty consumer tag, consumers
+    /// with a given tag keep it.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use amqprs::channel::{BasicConsumeArguments, Channel, ConsumerTagGenerator};
+    /// # async fn consume(channel: &Channel) -> Result<(), amqprs::error::Error> {
+    /// channel.set_consumer_tag_generator(Some(ConsumerTagGenerator::new("billing")));
+    /// // consumer tag is "ctag-billing-<channel id>-1"
+    /// let (consumer_tag, _rx) = channel
+    ///     .basic_consume_rx(BasicConsumeArguments::new("invoices", ""))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_consumer_tag_generator(&self, generator: Option<ConsumerTagGenerator>) {
+        *self.shared.consumer_tag_generator.lock().unwrap() = generator;
+    }
+
+    /// Send `publish` with content, returns the publish sequence number if in confirm mode.
+    ///
+    /// `confirm_callback` is registered on the sequence number before the message is sent.
+    /// Errors are wrapped in [`Error::PublishError`] with the arguments of the message.
+    pub(super) async fn publish(
+        &self,
+        basic_properties: BasicProperties,
+        content: Vec<u8>,
+        args: BasicPublishArguments,
+        confirm_callback: Option<ConfirmCallback>,
+    ) -> Result<Option<u64>> {
+        // arguments are consumed by sending, echoed back on failure
+        let echo = args.clone();
+        let body_len = content.len();
+        self.send_publish(
+            PublishProperties::Properties(Box::new(basic_properties)),
+            content,
+            args,
+            confirm_callback,
+        )
+        .await
+        .map_err(|source| publish_error(&echo, body_len, source))
+    }
+
+    async fn send_publish(
+        &self,
+        mut properties: PublishProperties,
+        content: Vec<u8>,
+        args: BasicPublishArguments,
+        confirm_callback: Option<ConfirmCallback>,
+    ) -> Result<Option<u64>> {
+        self.connection.check_closed_by_server()?;
+        if !self.is_open() {
+            return Err(Error::ChannelClosed(format!(
+                "failed to publish on channel {}",
+                self.channel_id()
+            )));
+        }
+        if args.exchange.is_empty()
+            && !args.routing_key.starts_with(DIRECT_REPLY_TO_QUEUE)
+            && self.shared.strict_default_exchange.load(Ordering::Relaxed)
+        {
+            self.check_queue_exists(&args.routing_key).await?;
+        }
+        // to correlate the message returned by server with its confirm
+        let mut routing = args.mandatory.then(|| PublishRouting {
+            exchange: args.exchange.clone(),
+            routing_key: args.routing_key.clone(),
+            message_id: None,
+        });
+        let mut publish = Publish::new(
+            0,
+            to_short_str("exchange name", args.exchange)?,
+            to_short_str("routing key", args.routing_key)?,
+        );
+        publish.set_mandatory(args.mandatory);
+        publish.set_immediate(args.immediate);
+
+        if let PublishProperties::Properties(basic_properties) = &mut properties {
+            let defaults = self.shared.default_properties.lock().unwrap();
+            if !defaults.is_empty() {
+                basic_properties.with_defaults(&defaults);
+            }
+        }
+        // wait for a confirm if the window of outstanding messages is full
+        let publish_window = self.shared.publish_window.lock().unwrap().clone();
+        let permit = match publish_window {
+            Some(window) => Some(window.acquire_owned().await.map_err(|_| {
+                Error::ChannelClosed(format!(
+                    "failed to publish on channel {}",
+                    self.channel_id()
+                ))
+            })?),
+            None => None,
+        };
+        // wait if the buffer limit of connection is reached
+        let buffered = self.connection.reserve_buffer(content.len()).await;
+        let mut next_seq_no = self.shared.next_publish_seq_no.lock().await;
+        // generated under the send lock, so the ids follow the send order
+        let common = ContentHeaderCommon {
+            class: 60, // basic class
+            weight: 0,
+            body_size: content.len() as u64,
+        };
+        let frames = publish_frame_count(content.len(), self.connection.frame_max());
+        let publish_combo = match properties {
+            PublishProperties::Properties(mut basic_properties) => {
+                if let Some(header) = self.shared.publish_id_header.lock().unwrap().as_mut() {
+                    header.inject(&mut basic_properties)?;
+                }
+                if let Some(routing) = routing.as_mut() {
+                    routing.message_id = basic_properties.message_id().cloned();
+                }
+                let content_header = ContentHeader::new(common, *basic_properties);
+                Frame::PublishCombo(publish, Box::new(content_header), ContentBody::new(content))
+            }
+            PublishProperties::Encoded(basic_properties) => {
+                let content_header = EncodedContentHeader::new(common, basic_properties);
+                Frame::PublishEncodedCombo(
+                    publish,
+                    Box::new(content_header),
+                    ContentBody::new(content),
+                )
+            }
+        };
+
+        if confirm_callback.is_some() && next_seq_no.is_none() {
+            return Err(Error::ChannelUseError(format!(
+                "channel {} is not in confirm mode",
+                self.channel_id()
+            )));
+        }
+        // a mandatory message is tracked even without callback, so that its return
+        // is not taken for the return of another message
+        if let (Some(delivery_tag), true) = (
+            *next_seq_no,
+            confirm_callback.is_some() || routing.is_some(),
+        ) {
+            self.shared.dispatcher_mgmt_tx.send(
+                DispatcherManagementCommand::RegisterConfirmCallback(RegisterConfirmCallback {
+                    delivery_tag,
+                    callback: confirm_callback,
+                    routing,
+                }),
+            )?;
+        }
+        // tracked before sending, so its confirm cannot arrive earlier
+        if let Some(delivery_tag) = *next_seq_no {
+            self.shared.unconfirmed.send_modify(|unconfirmed| {
+                unconfirmed.insert(delivery_tag);
+            });
+        }
+        if let Err(err) = self
+            .shared
+            .outgoing_tx
+            .send((self.shared.channel_id, publish_combo))
+            .await
+        {
+            if let Some(delivery_tag) = *next_seq_no {
+                self.shared.settle_unconfirmed(delivery_tag, false);
+            }
+            return Err(err.into());
+        }
+        self.shared.frames_sent.fetch_add(frames, Ordering::Relaxed);
+        // released by the writer once the message is written
+        buffered.forget();
+        // returned by the dispatcher once the message is confirmed
+        if let Some(permit) = permit {
+            permit.forget();
+        }
+
+        let seq_no = *next_seq_no;
+        if let Some(next) = next_seq_no.as_mut() {
+            *next += 1;
+        }
+        Ok(seq_no)
+    }
+
+    /// Same as [`basic_publish`], but if server has blocked the connection by `connection.blocked`,
+    /// wait until it is unblocked before sending the message.
+    ///
+    /// Publishing on a blocked connection fills up the socket buffer and eventually
+    /// blocks the whole connection, this avoids it by parking the publisher instead.
+    ///
+    /// If `timeout` is [`None`], wait until the connection is unblocked.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PublishError`] caused by [`Error::ConnectionBlocked`] if the connection
+    /// is still blocked after `timeout`, or caused by [`Error::ClosedByServer`] or
+    /// [`Error::ConnectionUseError`] if the connection is closed while blocked.
+    /// The message is not sent in both cases.
+    ///
+    /// Otherwise, returns the same errors as [`basic_publish`].
+    ///
+    /// [`basic_publish`]: struct.Channel.html#method.basic_publish
+    pub async fn basic_publish_unblocked(
+        &self,
+        basic_properties: BasicProperties,
+        content: Vec<u8>,
+        args: BasicPublishArguments,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let unblocked = match timeout {
+            Some(timeout) => time::timeout(timeout, self.connection.wait_unblocked())
+                .await
+                .unwrap_or_else(|_| {
+                    Err(Error::ConnectionBlocked(format!(
+                        "failed to publish on channel {} within {:?}",
+                        self.channel_id(),
+                        timeout
+                    )))
+                }),
+            None => self.connection.wait_unblocked().await,
+        };
+        if let Err(err) = unblocked {
+            return Err(publish_error(&args, content.len(), err));
+        }
+        self.basic_publish(basic_properties, content, args).await
+    }
+}
+
+/// Properties of a message to publish.
+enum PublishProperties {
+    Properties(Box<BasicProperties>),
+    /// sent as they are, channel defaults are not applied
+    Encoded(EncodedProperties),
+}
+
+/// Wrap `source` in [`Error::PublishError`] with the arguments of the failed message.
+fn publish_error(args: &BasicPublishArguments, body_len: usize, source: Error) -> Error {
+    Error::PublishError {
+        exchange: args.exchange.clone(),
+        routing_key: args.routing_key.clone(),
+        body_len,
+        source: Box::new(source),
+    }
+}
+
+/// Number of frames written for a published message: method, content header and
+/// content body frames, body frames are omitted if the body is empty.
+pub(crate) fn publish_frame_count(body_size: usize, frame_max: u32) -> u64 {
+    let payload_max = frame_max as usize - (FRAME_HEADER_SIZE + 1);
+    let body_frames = (body_size + payload_max - 1) / payload_max;
+    2 + body_frames as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::callbacks::{ChannelCallback, DefaultChannelCallback, DefaultConnectionCallback};
+    use crate::test_utils::{
+        accept_mock_connection, cancel_ok, channel_reply, close_channel, consume_ok,
+        content_header, declare_queue_ok, deliver, mock_broker, setup_logging, short_strs,
+    };
+    use crate::{
+        api::{
+            channel::{
+                Channel, ConfirmSelectArguments, ConsumerMessage, DeliveryChannel, PublishOutcome,
+                QueueBindArguments, QueueDeclareArguments, ReturnedMessage,
+            },
+            connection::{Connection, OpenConnectionArguments},
+            consumer::{AsyncConsumer, DefaultConsumer},
+            Result,
+        },
+        error::Error,
+        frame::{
+            Ack, BasicProperties, Cancel, CancelOk, CloseChannel, CloseChannelOk, ContentBody,
+            Deliver, DeliveryTag, EncodedProperties, FlowOk, Frame, GetEmpty, GetOk, Nack, QosOk,
+            Reject, Return, SelectOk, FRAME_MIN_SIZE,
+        },
+        FieldTable, FieldValue, DELIVERY_MODE_PERSISTENT, DELIVERY_MODE_TRANSIENT,
+    };
+    use async_trait::async_trait;
+    use std::collections::BTreeSet;
+    use tokio::{
+        net::TcpListener,
+        sync::{mpsc, watch},
+        time,
+    };
+
+    use super::{
+        batched_acks, publish_frame_count, AckBatchPolicy, BasicAckArguments, BasicCancelArguments,
+        BasicConsumeArguments, BasicGetArguments, BasicNackArguments, BasicPublishArguments,
+        BasicQosArguments, BasicRejectArguments, CatchUnwind, ConsumerErrorPolicy,
+        ConsumerTagGenerator, InFlightDelivery, PublishIdHeader, DIRECT_REPLY_TO_QUEUE,
+    };
+
+    #[test]
+    fn test_delivery_tag_round_trip() {
+        let tag = DeliveryTag::from(42);
+        assert_eq!(42, u64::from(tag));
+        assert_eq!("42", tag.to_string());
+
+        let args = BasicAckArguments::new(tag, true);
+        let ack = Ack::new(args.delivery_tag.into(), args.multiple);
+        assert_eq!(42, ack.delivery_tag());
+
+        let args = BasicNackArguments::new(tag, false, true);
+        assert_eq!(tag, args.delivery_tag);
+        let nack = Nack::new(args.delivery_tag.into());
+        assert_eq!(42, nack.delivery_tag());
+
+        let args = BasicRejectArguments::new(tag, false);
+        assert_eq!(tag, args.delivery_tag);
+        let _ = Reject::new(args.delivery_tag.into(), args.requeue);
+
+        assert_eq!(
+            DeliveryTag::from(0),
+            BasicNackArguments::default().delivery_tag
+        );
+    }
+
+    #[test]
+    fn test_consume_arguments() {
+        let args = BasicConsumeArguments::new("standby", "")
+            .priority(-5)
+            .cancel_on_ha_failover(true)
+            .finish();
+        let (consume, _) = args.into_consume().unwrap();
+        let arguments = consume.arguments();
+        assert_eq!(2, arguments.len());
+        assert_eq!(
+            Some(&FieldValue::I(-5)),
+            arguments.get(&"x-priority".try_into().unwrap())
+        );
+        assert_eq!(
+            Some(&FieldValue::t(true)),
+            arguments.get(&"x-cancel-on-ha-failover".try_into().unwrap())
+        );
+
+        // direct reply-to needs no-ack consumer
+        let args = BasicConsumeArguments::new(DIRECT_REPLY_TO_QUEUE, "");
+        assert!(matches!(
+            args.into_consume(),
+            Err(Error::ChannelUseError(_))
+        ));
+        let (_, consumer_tag) = BasicConsumeArguments::direct_reply_to()
+            .into_consume()
+            .unwrap();
+        assert_eq!("", consumer_tag);
+    }
+
+    #[test]
+    fn test_publish_frame_count() {
+        use crate::frame::FRAME_MIN_SIZE;
+
+        // method and header frames only
+        assert_eq!(2, publish_frame_count(0, FRAME_MIN_SIZE));
+        // each body frame carries at most `frame_max - 8` bytes
+        let payload_max = FRAME_MIN_SIZE as usize - 8;
+        assert_eq!(3, publish_frame_count(1, FRAME_MIN_SIZE));
+        assert_eq!(3, publish_frame_count(payload_max, FRAME_MIN_SIZE));
+        assert_eq!(4, publish_frame_count(payload_max + 1, FRAME_MIN_SIZE));
+        assert_eq!(12, publish_frame_count(payload_max * 10, FRAME_MIN_SIZE));
+    }
+
+    #[test]
+    fn test_consumer_tag_generator() {
+        let mut generator = ConsumerTagGenerator::new("billing");
+        let tags: Vec<String> = [1, 1, 1, 2, 2]
+            .into_iter()
+            .map(|channel_id| generator.next_tag(channel_id))
+            .collect();
+        assert_eq!(
+            vec![
+                "ctag-billing-1-1",
+                "ctag-billing-1-2",
+                "ctag-billing-1-3",
+                "ctag-billing-2-4",
+                "ctag-billing-2-5",
+            ],
+            tags
+        );
+        let unique: BTreeSet<&String> = tags.iter().collect();
+        assert_eq!(tags.len(), unique.len());
+
+        // injected generator
+        let mut fixed =
+            ConsumerTagGenerator::generator(|channel_id| format!("test-{}", channel_id));
+        assert_eq!("test-7", fixed.next_tag(7));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 5)]
+    async fn test_publish_with_default_properties() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_publish_with_default_properties")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let (queue_name, ..) = channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut stream = channel
+            .basic_consume_stream(
+                BasicConsumeArguments::new(&queue_name, "")
+                    .auto_ack(true)
+                    .finish(),
+            )
+            .await
+            .unwrap();
+
+        channel.set_default_properties(
+            BasicProperties::default()
+                .with_app_id("amqprs")
+                .with_content_type("application/json")
+                .with_persistence(true)
+                .finish(),
+        );
+        channel
+            .basic_publish(
+                BasicProperties::default()
+                    .with_content_type("text/plain")
+                    .with_message_id("101")
+                    .finish(),
+                b"merged".to_vec(),
+                BasicPublishArguments::new("", &queue_name),
+            )
+            .await
+            .unwrap();
+        let message = stream
+            .recv_timeout(time::Duration::from_secs(5))
+            .await
+            .unwrap();
+        let props = message.basic_properties.unwrap();
+        assert_eq!("text/plain", props.content_type().unwrap());
+        assert_eq!("101", props.message_id().unwrap());
+        assert_eq!("amqprs", props.app_id().unwrap());
+        assert_eq!(Some(DELIVERY_MODE_PERSISTENT), props.delivery_mode());
+
+        // defaults removed
+        channel.set_default_properties(BasicProperties::default());
+        channel
+            .basic_publish(
+                BasicProperties::default(),
+                b"plain".to_vec(),
+                BasicPublishArguments::new("", &queue_name),
+            )
+            .await
+            .unwrap();
+        let message = stream
+            .recv_timeout(time::Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(message.basic_properties.unwrap().app_id().is_none());
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 5)]
+    async fn test_frame_counters() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_frame_counters")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let (queue_name, ..) = channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut stream = channel
+            .basic_consume_stream(
+                BasicConsumeArguments::new(&queue_name, "")
+                    .auto_ack(true)
+                    .finish(),
+            )
+            .await
+            .unwrap();
+        let (sent, received) = channel.frame_counters();
+        assert_eq!(0, sent);
+
+        channel
+            .basic_publish(
+                BasicProperties::default(),
+                b"counted".to_vec(),
+                BasicPublishArguments::new("", &queue_name),
+            )
+            .await
+            .unwrap();
+        stream
+            .recv_timeout(time::Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        // publish, header and body frames sent,
+        // deliver, header and body frames received
+        let (sent_now, received_now) = channel.frame_counters();
+        assert_eq!(3, sent_now - sent);
+        assert_eq!(3, received_now - received);
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 5)]
+    async fn test_basic_consume_auto_ack() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_basic_consume_auto_ack")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+
+        {
+            let channel = connection.open_channel(None).await.unwrap();
+            let (queue_name, ..) = channel
+                .queue_declare(QueueDeclareArguments::default())
+                .await
+                .unwrap()
+                .unwrap();
+            channel
+                .queue_bind(QueueBindArguments::new(
+                    &queue_name,
+                    "amq.topic",
+                    "eiffel.#",
+                ))
+                .await
+                .unwrap();
+
+            let args = BasicConsumeArguments::new(&queue_name, "test_auto_ack")
+                .auto_ack(true)
+                .finish();
+
+            channel
+                .basic_consume(DefaultConsumer::new(args.no_ack), args)
+                .await
+                .unwrap();
+            time::sleep(time::Duration::from_secs(1)).await;
+        }
+        // channel drops, wait for close handshake done.
+        time::sleep(time::Duration::from_secs(1)).await;
+        // connection drops
+    }
+
+    #[test]
+    fn test_batched_acks() {
+        let set = |tags: &[u64]| tags.iter().copied().collect::<BTreeSet<u64>>();
+
+        // no gap, single ack by `multiple`
+        assert_eq!(
+            vec![(4, true)],
+            batched_acks(&set(&[1, 2, 3, 4]), &set(&[1, 2, 3, 4, 5]))
+        );
+        // nacked delivery is not unacked any more, so it is no gap
+        assert_eq!(
+            vec![(4, true)],
+            batched_acks(&set(&[1, 3, 4]), &set(&[1, 3, 4]))
+        );
+        // gaps of failed deliveries or deliveries of other consumers
+        assert_eq!(
+            vec![(2, true), (4, false), (6, false)],
+            batched_acks(&set(&[1, 2, 4, 6]), &set(&[1, 2, 3, 4, 5, 6]))
+        );
+        // leading gap
+        assert_eq!(
+            vec![(2, false), (3, false)],
+            batched_acks(&set(&[2, 3]), &set(&[1, 2, 3]))
+        );
+        // single delivery, and settled delivery is skipped
+        assert_eq!(vec![(2, false)], batched_acks(&set(&[1, 2]), &set(&[2])));
+        assert!(batched_acks(&set(&[]), &set(&[1])).is_empty());
+    }
+
+    #[test]
+    fn test_consumer_error_policy_nack_arguments() {
+        let tag = DeliveryTag::from(7);
+
+        let args = ConsumerErrorPolicy::NackRequeue
+            .nack_arguments(tag, false)
+            .unwrap();
+        assert_eq!(tag, args.delivery_tag);
+        assert!(!args.multiple);
+        assert!(args.requeue);
+
+        let args = ConsumerErrorPolicy::NackDeadletter
+            .nack_arguments(tag, false)
+            .unwrap();
+        assert_eq!(tag, args.delivery_tag);
+        assert!(!args.multiple);
+        assert!(!args.requeue);
+
+        assert!(ConsumerErrorPolicy::Ignore
+            .nack_arguments(tag, false)
+            .is_none());
+        assert!(ConsumerErrorPolicy::CloseChannel
+            .nack_arguments(tag, false)
+            .is_none());
+
+        // no nack for automatic acknowledgement
+        assert!(ConsumerErrorPolicy::NackRequeue
+            .nack_arguments(tag, true)
+            .is_none());
+        assert_eq!(
+            ConsumerErrorPolicy::Ignore,
+            BasicConsumeArguments::new("q", "c").error_policy
+        );
+    }
+
+    #[tokio::test]
+    async fn test_catch_unwind_of_consumer() {
+        let ok = CatchUnwind(Box::pin(async { 1 })).await;
+        assert_eq!(1, ok.unwrap());
+
+        let panicked = CatchUnwind(Box::pin(async {
+            panic!("consumer panics");
+        }))
+        .await;
+        assert!(panicked.is_err());
+    }
+
+    #[test]
+    fn test_in_flight_delivery_count() {
+        let (in_flight, rx) = watch::channel(0);
+        let first = InFlightDelivery::start(&in_flight);
+        let second = InFlightDelivery::start(&in_flight);
+        assert_eq!(2, *rx.borrow());
+        drop(first);
+        assert_eq!(1, *rx.borrow());
+        drop(second);
+        assert_eq!(0, *rx.borrow());
+    }
+
+    /// Signals when it starts processing a delivery, then waits to be released.
+    struct GatedConsumer {
+        started_tx: mpsc::UnboundedSender<()>,
+        release_rx: mpsc::UnboundedReceiver<()>,
+    }
+
+    #[async_trait]
+    impl AsyncConsumer for GatedConsumer {
+        async fn consume(&mut self, _: &Channel, _: Deliver, _: BasicProperties, _: Vec<u8>) {
+            self.started_tx.send(()).unwrap();
+            self.release_rx.recv().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_deliveries_of_no_ack_consumer() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_in_flight_deliveries_of_no_ack_consumer")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let (queue_name, ..) = channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (started_tx, mut started_rx) = mpsc::unbounded_channel();
+        let (release_tx, release_rx) = mpsc::unbounded_channel();
+        let consumer = GatedConsumer {
+            started_tx,
+            release_rx,
+        };
+        let args = BasicConsumeArguments::new(&queue_name, "test_in_flight_deliveries")
+            .manual_ack(false)
+            .finish();
+        channel.basic_consume(consumer, args).await.unwrap();
+        assert_eq!(0, channel.in_flight_deliveries());
+
+        channel
+            .basic_publish(
+                BasicProperties::default(),
+                b"in flight".to_vec(),
+                BasicPublishArguments::new("", &queue_name),
+            )
+            .await
+            .unwrap();
+        time::timeout(time::Duration::from_secs(5), started_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(1, channel.in_flight_deliveries());
+
+        release_tx.send(()).unwrap();
+        time::timeout(
+            time::Duration::from_secs(5),
+            channel.wait_in_flight_deliveries(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(0, channel.in_flight_deliveries());
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    /// Fails the first delivery of each message, then acks its redelivery.
+    struct FailOnceConsumer {
+        redelivered_tx: mpsc::UnboundedSender<bool>,
+        panics: bool,
+    }
+
+    #[async_trait]
+    impl AsyncConsumer for FailOnceConsumer {
+        async fn consume(&mut self, _: &Channel, _: Deliver, _: BasicProperties, _: Vec<u8>) {
+            // not called, `try_consume` is overridden
+        }
+
+        async fn try_consume(
+            &mut self,
+            channel: &Channel,
+            deliver: Deliver,
+            _basic_properties: BasicProperties,
+            _content: Vec<u8>,
+        ) -> Result<()> {
+            self.redelivered_tx.send(deliver.redelivered()).unwrap();
+            if deliver.redelivered() {
+                let args = BasicAckArguments::new(deliver.delivery_tag(), false);
+                return channel.basic_ack(args).await;
+            }
+            if self.panics {
+                panic!("poison message");
+            }
+            Err(Error::ConsumerError("poison message".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consumer_error_policy() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_consumer_error_policy")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+
+        // failed delivery is nacked and requeued
+        let channel = connection.open_channel(None).await.unwrap();
+        let (queue_name, ..) = channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+        let (redelivered_tx, mut redelivered_rx) = mpsc::unbounded_channel();
+        let consumer = FailOnceConsumer {
+            redelivered_tx,
+            panics: false,
+        };
+        let args = BasicConsumeArguments::new(&queue_name, "test_consumer_error_policy_requeue")
+            .error_policy(ConsumerErrorPolicy::NackRequeue)
+            .finish();
+        channel.basic_consume(consumer, args).await.unwrap();
+        channel
+            .basic_publish(
+                BasicProperties::default(),
+                b"poison".to_vec(),
+                BasicPublishArguments::new("", &queue_name),
+            )
+            .await
+            .unwrap();
+        for expected in [false, true] {
+            let redelivered = time::timeout(time::Duration::from_secs(5), redelivered_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(expected, redelivered);
+        }
+
+        // consumer panics, channel is closed
+        let (redelivered_tx, mut redelivered_rx) = mpsc::unbounded_channel();
+        let consumer = FailOnceConsumer {
+            redelivered_tx,
+            panics: true,
+        };
+        let args = BasicConsumeArguments::new(&queue_name, "test_consumer_error_policy_close")
+            .error_policy(ConsumerErrorPolicy::CloseChannel)
+            .finish();
+        let close_channel = connection.open_channel(None).await.unwrap();
+        close_channel.basic_consume(consumer, args).await.unwrap();
+        channel
+            .basic_publish(
+                BasicProperties::default(),
+                b"poison".to_vec(),
+                BasicPublishArguments::new("", &queue_name),
+            )
+            .await
+            .unwrap();
+        time::timeout(time::Duration::from_secs(5), redelivered_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        time::sleep(time::Duration::from_millis(500)).await;
+        assert!(!close_channel.is_open());
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_basic_consume_manual_ack() {
+        setup_logging();
+        {
+            let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+                .connection_name("test_basic_consume_manual_ack")
+                .finish();
+
+            let connection = Connection::open(&args).await.unwrap();
+
+            let channel = connection.open_channel(None).await.unwrap();
+            let (queue_name, ..) = channel
+                .queue_declare(QueueDeclareArguments::default())
+                .await
+                .unwrap()
+                .unwrap();
+            channel
+                .queue_bind(QueueBindArguments::new(
+                    &queue_name,
+                    "amq.topic",
+                    "eiffel.#",
+                ))
+                .await
+                .unwrap();
+
+            let args = BasicConsumeArguments::new(&queue_name, "test_manual_ack");
+            channel
+                .basic_consume(DefaultConsumer::new(args.no_ack), args)
+                .await
+                .unwrap();
+            time::sleep(time::Duration::from_secs(1)).await;
+        }
+        // channel and connection drop, wait for close task to fnish
+        time::sleep(time::Duration::from_secs(1)).await;
+    }
+
+    #[tokio::test]
+    async fn test_basic_publish() {
+        setup_logging();
+        {
+            let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+                .connection_name("test_basic_publish")
+                .finish();
+
+            let connection = Connection::open(&args).await.unwrap();
+
+            let channel = connection.open_channel(None).await.unwrap();
+
+            let args = BasicPublishArguments::new("amq.topic", "eiffel._.amqprs._.tester");
+
+            let basic_properties = BasicProperties::default()
+                .with_content_type("application/json;charset=utf-8")
+                .with_persistence(true)
+                .finish();
+
+            let content = String::from(
+            r#"
+                {
+                    "meta": {"id": "f9d42464-fceb-4282-be95-0cd98f4741b0", "type": "PublishTester", "version": "4.0.0", "time": 1640035100149},
+                    "data": { "customData": []},
+                    "links": [{"type": "BASE", "target": "fa321ff0-faa6-474e-aa1d-45edf8c99896"}]}
+            "#
+            ).into_bytes();
+
+            channel
+                .basic_publish(basic_properties, content, args)
+                .await
+                .unwrap();
+            time::sleep(time::Duration::from_secs(1)).await;
+        }
+        // channel and connection drop, wait for close task to fnish
+        time::sleep(time::Duration::from_secs(1)).await;
+    }
+
+    #[tokio::test]
+    async fn test_basic_publish_on_closed_channel() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_basic_publish_on_closed_channel")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+
+        let channel = connection.open_channel(None).await.unwrap();
+        let cloned = channel.clone();
+        channel.close().await.unwrap();
+        assert!(!cloned.is_open());
+
+        let args = BasicPublishArguments::new("amq.topic", "eiffel._.amqprs._.tester");
+        let result = cloned
+            .basic_publish(BasicProperties::default(), b"hello".to_vec(), args)
+            .await;
+        match result {
+            Err(Error::PublishError { source, .. }) => {
+                assert!(matches!(*source, Error::ChannelClosed(_)))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flush_before_close() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_flush_before_close")
+            .finish();
+
+        // queue outlives the publisher connection
+        let consumer_connection = Connection::open(&args).await.unwrap();
+        let consumer_channel = consumer_connection.open_channel(None).await.unwrap();
+        let (queue_name, ..) = consumer_channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let count = 1000;
+        for i in 0..count {
+            channel
+                .basic_publish(
+                    BasicProperties::default(),
+                    format!("message {}", i).into_bytes(),
+                    BasicPublishArguments::new("", &queue_name),
+                )
+                .await
+                .unwrap();
+        }
+        // close immediately after the batch is flushed
+        channel.flush().await.unwrap();
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+
+        let (_, message_count, _) = consumer_channel
+            .queue_declare(
+                QueueDeclareArguments::new(&queue_name)
+                    .passive(true)
+                    .finish(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(count, message_count);
+
+        consumer_channel.close().await.unwrap();
+        consumer_connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_basic_publish_unblocked_parks_while_blocked() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_basic_publish_unblocked_parks_while_blocked")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+
+        // simulate `connection.blocked` from server
+        connection.set_is_blocked(true);
+        assert!(connection.is_blocked());
+
+        let args = BasicPublishArguments::new("amq.topic", "eiffel._.amqprs._.tester");
+        let result = channel
+            .basic_publish_unblocked(
+                BasicProperties::default(),
+                b"hello".to_vec(),
+                args.clone(),
+                Some(time::Duration::from_millis(100)),
+            )
+            .await;
+        match result {
+            Err(Error::PublishError { source, .. }) => {
+                assert!(matches!(*source, Error::ConnectionBlocked(_)))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let publisher = channel.clone();
+        let parked = tokio::spawn(async move {
+            publisher
+                .basic_publish_unblocked(BasicProperties::default(), b"hello".to_vec(), args, None)
+                .await
+        });
+        time::sleep(time::Duration::from_millis(200)).await;
+        assert!(!parked.is_finished());
+
+        // simulate `connection.unblocked` from server
+        connection.set_is_blocked(false);
+        let result = time::timeout(time::Duration::from_secs(1), parked)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(result.is_ok());
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_basic_qos() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        connection
+            .register_callback(DefaultConnectionCallback)
+            .await
+            .unwrap();
+
+        let channel = connection.open_channel(None).await.unwrap();
+        channel
+            .register_callback(DefaultChannelCallback)
+            .await
+            .unwrap();
+
+        let (queue_name, ..) = channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+        channel
+            .queue_bind(QueueBindArguments::new(
+                &queue_name,
+                "amq.topic",
+                "eiffel.#",
+            ))
+            .await
+            .unwrap();
+
+        let args = BasicConsumeArguments::new(&queue_name, "test_basic_qos");
+        channel
+            .basic_consume(DefaultConsumer::new(args.no_ack), args)
+            .await
+            .unwrap();
+
+        // set qos
+        channel
+            .basic_qos(BasicQosArguments::new(0, 100, false))
+            .await
+            .unwrap();
+
+        // try recover
+        channel.basic_recover(true).await.unwrap();
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recover_qos_after_reconnection() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        assert!(channel.qos().is_none());
+
+        let qos = BasicQosArguments::new(0, 50, false);
+        channel.basic_qos(qos).await.unwrap();
+        assert_eq!(50, channel.qos().unwrap().prefetch_count);
+
+        // simulate recovery: connection is lost and a new channel is opened
+        connection.close().await.unwrap();
+        let connection = Connection::open(&args).await.unwrap();
+        let recovered = connection.open_channel(None).await.unwrap();
+        assert!(recovered.qos().is_none());
+
+        recovered.recover_qos(&channel).await.unwrap();
+        let recovered_qos = recovered.qos().unwrap();
+        assert_eq!(50, recovered_qos.prefetch_count);
+        assert_eq!(0, recovered_qos.prefetch_size);
+        assert!(!recovered_qos.global);
+
+        recovered.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_effective_prefetch() {
+        setup_logging();
+
+        // mock broker accepts qos, and consumers "c1" and "c2" in order
+        let (port, server) = mock_broker(vec!["c2", "c1"], |consumer_tags, _, frame| match frame {
+            Frame::Qos(..) => vec![QosOk.into_frame()],
+            Frame::Consume(..) => vec![consume_ok(consumer_tags.pop().unwrap())],
+            Frame::Cancel(..) => vec![CancelOk {
+                consumer_tag: "c1".try_into().unwrap(),
+            }
+            .into_frame()],
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let consume = |consumer_tag: &'static str| {
+            channel.basic_consume_rx(BasicConsumeArguments::new("q", consumer_tag))
+        };
+
+        // unlimited
+        assert_eq!(0, channel.effective_prefetch("c1"));
+
+        // per-consumer limit is captured by the consumer when it starts
+        channel
+            .basic_qos(BasicQosArguments::new(0, 10, false))
+            .await
+            .unwrap();
+        let (_, _c1_rx) = consume("c1").await.unwrap();
+        channel
+            .basic_qos(BasicQosArguments::new(0, 20, false))
+            .await
+            .unwrap();
+        let (_, _c2_rx) = consume("c2").await.unwrap();
+        assert_eq!(10, channel.effective_prefetch("c1"));
+        assert_eq!(20, channel.effective_prefetch("c2"));
+
+        // lower global limit applies to all consumers
+        channel
+            .basic_qos(BasicQosArguments::new(0, 15, true))
+            .await
+            .unwrap();
+        assert_eq!(10, channel.effective_prefetch("c1"));
+        assert_eq!(15, channel.effective_prefetch("c2"));
+        assert_eq!(15, channel.effective_prefetch("new"));
+
+        // global limit alone applies if per-consumer limit is unlimited
+        channel
+            .basic_qos(BasicQosArguments::new(0, 0, false))
+            .await
+            .unwrap();
+        assert_eq!(15, channel.effective_prefetch("new"));
+        assert_eq!(10, channel.effective_prefetch("c1"));
+
+        // cancelled consumer is forgotten
+        channel
+            .basic_cancel(BasicCancelArguments::new("c1"))
+            .await
+            .unwrap();
+        time::sleep(time::Duration::from_millis(50)).await;
+        assert_eq!(15, channel.effective_prefetch("c1"));
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain_consumer() {
+        setup_logging();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (deliver_tx, mut deliver_rx) = mpsc::unbounded_channel::<u64>();
+
+        // mock server delivers the requested number of messages after `consume-ok`
+        let server = tokio::spawn(async move {
+            let mut io_conn = accept_mock_connection(&listener).await;
+            loop {
+                let (channel_id, frame) = io_conn.read_frame().await.unwrap();
+                let responses = match frame {
+                    Frame::Consume(..) => {
+                        io_conn
+                            .write_frame(channel_id, consume_ok("ctag1"), FRAME_MIN_SIZE)
+                            .await
+                            .unwrap();
+                        let mut responses = vec![];
+                        for delivery_tag in 1..=deliver_rx.recv().await.unwrap() {
+                            responses.push(deliver("ctag1", delivery_tag).into_frame());
+                            responses.push(content_header(0).into_frame());
+                        }
+                        responses
+                    }
+                    // dropped stream cancels its consumer without waiting for reply
+                    Frame::Cancel(..) => vec![],
+                    Frame::CloseChannel(..) => {
+                        io_conn
+                            .write_frame(channel_id, CloseChannelOk.into_frame(), FRAME_MIN_SIZE)
+                            .await
+                            .unwrap();
+                        return io_conn;
+                    }
+                    other => channel_reply(other),
+                };
+                for response in responses {
+                    io_conn
+                        .write_frame(channel_id, response, FRAME_MIN_SIZE)
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let args = BasicConsumeArguments::new("q", "ctag1")
+            .auto_ack(true)
+            .finish();
+        let mut stream = channel.basic_consume_stream(args).await.unwrap();
+
+        let (drained, _) = tokio::join!(
+            channel.drain("ctag1", 3, time::Duration::from_secs(5)),
+            async {
+                // let drain divert the deliveries first
+                time::sleep(time::Duration::from_millis(100)).await;
+                deliver_tx.send(5).unwrap();
+            }
+        );
+        let delivery_tags = |messages: Vec<ConsumerMessage>| {
+            messages
+                .into_iter()
+                .map(|message| u64::from(message.deliver.unwrap().delivery_tag()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![1, 2, 3], delivery_tags(drained.unwrap()));
+
+        // deliveries not taken are given back to the consumer in order
+        let remaining = stream.drain(10, time::Duration::from_millis(200)).await;
+        assert_eq!(vec![4, 5], delivery_tags(remaining));
+
+        // returns what is received before timeout
+        let drained = channel
+            .drain("ctag1", 3, time::Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert!(drained.is_empty());
+
+        let result = channel
+            .drain("unknown", 3, time::Duration::from_millis(100))
+            .await;
+        assert!(matches!(result, Err(Error::ChannelUseError(_))));
+
+        drop(stream);
+        channel.close().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unacked_count() {
+        setup_logging();
+
+        // mock broker delivers to both consumers once the last one is registered,
+        // deliveries to the consumer with automatic ack are not counted
+        let (port, server) = mock_broker(
+            vec!["ctag-auto", "ctag-manual"],
+            |consumer_tags, _, frame| match frame {
+                Frame::Consume(..) => {
+                    let mut responses = vec![consume_ok(consumer_tags.pop().unwrap())];
+                    if consumer_tags.is_empty() {
+                        for (consumer_tag, delivery_tag) in [
+                            ("ctag-manual", 1),
+                            ("ctag-auto", 2),
+                            ("ctag-manual", 3),
+                            ("ctag-manual", 4),
+                        ] {
+                            responses.push(deliver(consumer_tag, delivery_tag).into_frame());
+                            responses.push(content_header(0).into_frame());
+                        }
+                    }
+                    responses
+                }
+                Frame::Ack(..) => vec![],
+                other => channel_reply(other),
+            },
+        )
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let (_, mut manual_rx) = channel
+            .basic_consume_rx(BasicConsumeArguments::new("q", "ctag-manual"))
+            .await
+            .unwrap();
+        let (_, mut auto_rx) = channel
+            .basic_consume_rx(
+                BasicConsumeArguments::new("q", "ctag-auto")
+                    .auto_ack(true)
+                    .finish(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(0, channel.unacked_count());
+
+        for _ in 0..3 {
+            time::timeout(time::Duration::from_secs(5), manual_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        time::timeout(time::Duration::from_secs(5), auto_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(3, channel.unacked_count());
+
+        channel
+            .basic_ack(BasicAckArguments::new(3.into(), false))
+            .await
+            .unwrap();
+        assert_eq!(2, channel.unacked_count());
+        channel
+            .basic_ack(BasicAckArguments::new(4.into(), true))
+            .await
+            .unwrap();
+        assert_eq!(0, channel.unacked_count());
+
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
+
+    /// Consumer fails to process the delivery of `fail_tag`, reports processed delivery tags.
+    struct FailingConsumer {
+        fail_tag: u64,
+        tx: mpsc::UnboundedSender<u64>,
+    }
+
+    #[async_trait]
+    impl AsyncConsumer for FailingConsumer {
+        async fn consume(&mut self, _: &Channel, _: Deliver, _: BasicProperties, _: Vec<u8>) {}
+
+        async fn try_consume(
+            &mut self,
+            _channel: &Channel,
+            deliver: Deliver,
+            _basic_properties: BasicProperties,
+            _content: Vec<u8>,
+        ) -> Result<()> {
+            let delivery_tag = u64::from(deliver.delivery_tag());
+            self.tx.send(delivery_tag).unwrap();
+            if delivery_tag == self.fail_tag {
+                return Err(Error::ConsumerError("failed".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ack_batch() {
+        setup_logging();
+
+        // mock broker delivers a burst, then records acks and nacks
+        let (port, server) = mock_broker(vec![], |received, _, frame| match frame {
+            Frame::Consume(..) => {
+                let mut responses = vec![consume_ok("ctag")];
+                for delivery_tag in 1..=10 {
+                    responses.push(deliver("ctag", delivery_tag).into_frame());
+                    responses.push(content_header(0).into_frame());
+                }
+                responses
+            }
+            Frame::Ack(_, ack) => {
+                received.push(format!("ack {} {}", ack.delivery_tag(), ack.mutiple()));
+                vec![]
+            }
+            Frame::Nack(_, nack) => {
+                received.push(format!("nack {}", nack.delivery_tag()));
+                vec![]
+            }
+            Frame::Cancel(..) => vec![cancel_ok("ctag")],
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let consumer = FailingConsumer { fail_tag: 3, tx };
+        let args = BasicConsumeArguments::new("q", "ctag")
+            .error_policy(ConsumerErrorPolicy::NackRequeue)
+            .ack_batch(AckBatchPolicy::new(4, time::Duration::from_secs(60)))
+            .finish();
+        channel.basic_consume(consumer, args).await.unwrap();
+        for delivery_tag in 1..=10 {
+            assert_eq!(Some(delivery_tag), rx.recv().await);
+        }
+
+        // the last pending ack is flushed once the consumer is cancelled
+        channel
+            .basic_cancel(BasicCancelArguments::new("ctag"))
+            .await
+            .unwrap();
+        channel.wait_in_flight_deliveries().await;
+        assert_eq!(0, channel.unacked_count());
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+
+        let received = server.await.unwrap();
+        assert_eq!(
+            vec!["nack 3", "ack 5 true", "ack 9 true", "ack 10 false"],
+            received
+        );
+    }
+
+    #[tokio::test]
+    async fn test_publish_id_header() {
+        setup_logging();
+
+        // mock broker records the id header of each published message
+        let (port, server) = mock_broker(vec![], |received, _, frame| match frame {
+            Frame::Publish(..) | Frame::ContentBody(..) => vec![],
+            Frame::ContentHeader(header) => {
+                received.push(
+                    header
+                        .basic_properties
+                        .headers()
+                        .and_then(|headers| headers.get(&"x-seq".try_into().unwrap()))
+                        .map(|id| id.to_string()),
+                );
+                vec![]
+            }
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let publish = |basic_properties: BasicProperties| {
+            channel.basic_publish(
+                basic_properties,
+                b"hello".to_vec(),
+                BasicPublishArguments::new("", "q"),
+            )
+        };
+
+        channel.set_publish_id_header(Some(PublishIdHeader::counter("x-seq")));
+        for _ in 0..3 {
+            publish(BasicProperties::default()).await.unwrap();
+        }
+        // header set by publisher is kept, counter is not consumed
+        let mut headers = FieldTable::new();
+        headers.insert("x-seq".try_into().unwrap(), "republished".into());
+        publish(BasicProperties::default().with_headers(headers).finish())
+            .await
+            .unwrap();
+        publish(BasicProperties::default()).await.unwrap();
+
+        let mut next = 0;
+        channel.set_publish_id_header(Some(PublishIdHeader::generator("x-seq", move || {
+            next += 1;
+            format!("trace-{}", next).into()
+        })));
+        publish(BasicProperties::default()).await.unwrap();
+        publish(BasicProperties::default()).await.unwrap();
+
+        channel.set_publish_id_header(None);
+        publish(BasicProperties::default()).await.unwrap();
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+
+        let received = server.await.unwrap();
+        let expected = ["1", "2", "3", "republished", "4", "trace-1", "trace-2"];
+        let mut expected: Vec<Option<String>> =
+            expected.iter().map(|id| Some(id.to_string())).collect();
+        expected.push(None);
+        assert_eq!(expected, received);
+    }
+
+    #[tokio::test]
+    async fn test_bounded_delivery_channel_backpressure() {
+        setup_logging();
+
+        // mock broker delivers a burst once the consumer is registered, it forwards the rejects
+        let (reject_tx, mut reject_rx) = mpsc::unbounded_channel();
+        let (port, server) = mock_broker((), move |_, _, frame| match frame {
+            Frame::Consume(..) => vec![consume_ok("ctag")],
+            Frame::Flow(_, flow) => {
+                let mut responses = vec![FlowOk::new(flow.active).into_frame()];
+                for delivery_tag in 1..=5 {
+                    responses.push(deliver("ctag", delivery_tag).into_frame());
+                    responses.push(content_header(0).into_frame());
+                }
+                responses
+            }
+            Frame::Reject(_, reject) => {
+                // delivery tag, then requeue bit
+                let bytes = amqp_serde::to_bytes(&reject).unwrap();
+                let delivery_tag = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+                reject_tx
+                    .send(format!("{} requeue={}", delivery_tag, bytes[8] != 0))
+                    .unwrap();
+                vec![]
+            }
+            Frame::Cancel(..) => vec![cancel_ok("ctag")],
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        assert_eq!(
+            DeliveryChannel::Unbounded,
+            BasicConsumeArguments::new("q", "ctag").delivery_channel
+        );
+        let args = BasicConsumeArguments::new("q", "ctag")
+            .manual_ack(true)
+            .delivery_channel(DeliveryChannel::Bounded(2))
+            .finish();
+        // receiver of `basic_consume_rx` can not be bounded
+        assert!(matches!(
+            channel.basic_consume_rx(args.clone()).await,
+            Err(Error::ChannelUseError(_))
+        ));
+        let mut stream = channel.basic_consume_stream(args).await.unwrap();
+        channel.flow(true).await.unwrap();
+
+        // the consumer takes nothing, deliveries beyond the capacity are requeued
+        for delivery_tag in 3..=5 {
+            let reject = time::timeout(time::Duration::from_secs(5), reject_rx.recv())
+                .await
+                .unwrap();
+            assert_eq!(Some(format!("{} requeue=true", delivery_tag)), reject);
+        }
+        assert_eq!(2, channel.unacked_count());
+
+        // the buffered deliveries are handed over in order
+        for delivery_tag in 1..=2 {
+            let message = stream
+                .recv_timeout(time::Duration::from_secs(5))
+                .await
+                .unwrap();
+            assert_eq!(
+                delivery_tag,
+                u64::from(message.deliver.unwrap().delivery_tag())
+            );
+        }
+        assert!(stream
+            .recv_timeout(time::Duration::from_millis(100))
+            .await
+            .is_none());
+
+        channel
+            .basic_cancel(BasicCancelArguments::new("ctag"))
+            .await
+            .unwrap();
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        server.await.unwrap();
+        assert!(reject_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_strict_default_exchange() {
+        setup_logging();
+
+        // mock broker has only queue "orders", it records declared queues and published messages
+        let (port, server) = mock_broker(vec![], |received, channel_id, frame| match frame {
+            Frame::DeclareQueue(_, declare) => {
+                let bytes = amqp_serde::to_bytes(&declare).unwrap();
+                let queue = short_strs(&bytes, 1).0.remove(0);
+                received.push(format!("declare {}", queue));
+                if queue == "orders" {
+                    vec![declare_queue_ok(&queue)]
+                } else {
+                    // 404 NOT_FOUND, class queue, method declare
+                    let text = format!("NOT_FOUND - no queue '{}' in vhost '/'", queue);
+                    vec![close_channel(404, &text, 50, 10)]
+                }
+            }
+            Frame::Publish(..) => {
+                received.push(format!("publish on channel {}", channel_id));
+                vec![]
+            }
+            Frame::ContentHeader(..) | Frame::ContentBody(..) => vec![],
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(Some(1)).await.unwrap();
+        let publish = |routing_key: &'static str| {
+            channel.basic_publish(
+                BasicProperties::default(),
+                b"hello".to_vec(),
+                BasicPublishArguments::new("", routing_key),
+            )
+        };
+
+        // not checked unless strict
+        publish("order").await.unwrap();
+
+        channel.set_strict_default_exchange(true);
+        let err = publish("order").await.unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::PublishError { source, .. } if matches!(**source, Error::QueueNotFound(_))
+        ));
+        assert!(err.to_string().contains("no queue 'order'"));
+        // check runs on a temporary channel, the publishing channel stays open
+        assert!(channel.is_open());
+
+        // existing queue is checked only once
+        publish("orders").await.unwrap();
+        publish("orders").await.unwrap();
+        // other exchanges are not checked
+        channel
+            .basic_publish(
+                BasicProperties::default(),
+                b"hello".to_vec(),
+                BasicPublishArguments::new("amq.direct", "order"),
+            )
+            .await
+            .unwrap();
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        let received = server.await.unwrap();
+        assert_eq!(
+            vec![
+                "publish on channel 1",
+                "declare order",
+                "declare orders",
+                "publish on channel 1",
+                "publish on channel 1",
+                "publish on channel 1",
+            ],
+            received
+        );
+    }
+
+    #[tokio::test]
+    async fn test_basic_get_with_qos() {
+        setup_logging();
+
+        // mock broker applies prefetch to `get`, its queue is never empty,
+        // but it returns `get-empty` once the prefetch of unacked messages is exhausted
+        let (port, server) = mock_broker(
+            (vec![], 0, 0),
+            |(received, prefetch_count, unacked), _, frame| match frame {
+                Frame::Qos(_, qos) => {
+                    // prefetch size, then prefetch count
+                    let bytes = amqp_serde::to_bytes(&qos).unwrap();
+                    *prefetch_count = u16::from_be_bytes([bytes[4], bytes[5]]);
+                    received.push(format!("qos {}", prefetch_count));
+                    vec![QosOk.into_frame()]
+                }
+                Frame::Get(..) => {
+                    received.push("get".to_owned());
+                    if *prefetch_count > 0 && *unacked >= *prefetch_count {
+                        vec![amqp_serde::from_bytes::<GetEmpty>(&[0])
+                            .unwrap()
+                            .into_frame()]
+                    } else {
+                        *unacked += 1;
+                        // delivery tag, redelivered, exchange, routing key, message count
+                        let mut bytes = u64::from(*unacked).to_be_bytes().to_vec();
+                        bytes.extend_from_slice(&[0, 0, 1, b'q', 0, 0, 0, 9]);
+                        vec![
+                            amqp_serde::from_bytes::<GetOk>(&bytes)
+                                .unwrap()
+                                .into_frame(),
+                            content_header(5).into_frame(),
+                            ContentBody::new(b"hello".to_vec()).into_frame(),
+                        ]
+                    }
+                }
+                other => channel_reply(other),
+            },
+        )
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        channel
+            .basic_qos(BasicQosArguments::new(0, 1, false))
+            .await
+            .unwrap();
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 5)]
-    async fn test_basic_consume_auto_ack() {
+        let (get_ok, _, content) = channel
+            .basic_get(BasicGetArguments::new("q"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(9, get_ok.message_count());
+        assert_eq!(b"hello".to_vec(), content);
+        // empty although the queue has messages, the prefetch is exhausted
+        assert!(channel
+            .basic_get(BasicGetArguments::new("q"))
+            .await
+            .unwrap()
+            .is_none());
+
+        // the prefetch is removed during `get`, then restored
+        assert!(channel
+            .basic_get_bypass_qos(BasicGetArguments::new("q"))
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(1, channel.qos().unwrap().prefetch_count);
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        assert_eq!(
+            vec!["qos 1", "get", "get", "qos 0", "get", "qos 1"],
+            server.await.unwrap().0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consume_all_basic_properties() {
         setup_logging();
 
-        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
-            .connection_name("test_basic_consume_auto_ack")
-            .finish();
+        // mock broker delivers each published message back to the consumer
+        let (port, server) = mock_broker(None, |published_header, _, frame| match frame {
+            Frame::Consume(..) => vec![consume_ok("ctag")],
+            Frame::Cancel(..) => vec![cancel_ok("ctag")],
+            Frame::Publish(..) => vec![],
+            Frame::ContentHeader(header) => {
+                *published_header = Some(header);
+                vec![]
+            }
+            Frame::ContentBody(body) => vec![
+                deliver("ctag", 1).into_frame(),
+                published_header.take().unwrap().into_frame(),
+                body.into_frame(),
+            ],
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
         let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let args = BasicConsumeArguments::new("q", "ctag")
+            .auto_ack(true)
+            .finish();
+        let (_, mut rx) = channel.basic_consume_rx(args).await.unwrap();
 
-        {
-            let channel = connection.open_channel(None).await.unwrap();
-            let (queue_name, ..) = channel
-                .queue_declare(QueueDeclareArguments::default())
-                .await
-                .unwrap()
-                .unwrap();
-            channel
-                .queue_bind(QueueBindArguments::new(
-                    &queue_name,
-                    "amq.topic",
-                    "eiffel.#",
-                ))
-                .await
-                .unwrap();
+        let mut headers = FieldTable::new();
+        headers.insert("k".try_into().unwrap(), FieldValue::I(1));
+        let properties = BasicProperties::new(
+            Some("application/json".to_owned()),
+            Some("gzip".to_owned()),
+            Some(headers.clone()),
+            Some(2),
+            Some(9),
+            Some("correlation".to_owned()),
+            Some("reply".to_owned()),
+            Some("60000".to_owned()),
+            Some("message".to_owned()),
+            Some(1674404425),
+            Some("type".to_owned()),
+            Some("user".to_owned()),
+            Some("app".to_owned()),
+            Some("cluster".to_owned()),
+        );
+        channel
+            .basic_publish(
+                properties,
+                b"hello".to_vec(),
+                BasicPublishArguments::new("amq.direct", "key"),
+            )
+            .await
+            .unwrap();
 
-            let args = BasicConsumeArguments::new(&queue_name, "test_auto_ack")
-                .auto_ack(true)
-                .finish();
+        let message = time::timeout(time::Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let properties = message.basic_properties.unwrap();
+        assert_eq!([0xff, 0xfc], properties.property_flags());
+        assert_eq!("application/json", properties.content_type().unwrap());
+        assert_eq!("gzip", properties.content_encoding().unwrap());
+        assert_eq!(&headers, properties.headers().unwrap());
+        assert_eq!(Some(2), properties.delivery_mode());
+        assert_eq!(Some(9), properties.priority());
+        assert_eq!("correlation", properties.correlation_id().unwrap());
+        assert_eq!("reply", properties.reply_to().unwrap());
+        assert_eq!("60000", properties.expiration().unwrap());
+        assert_eq!("message", properties.message_id().unwrap());
+        assert_eq!(Some(1674404425), properties.timestamp());
+        assert_eq!("type", properties.message_type().unwrap());
+        assert_eq!("user", properties.user_id().unwrap());
+        assert_eq!("app", properties.app_id().unwrap());
+        assert_eq!("cluster", properties.cluster_id().unwrap());
+        assert_eq!(b"hello".to_vec(), message.content.unwrap());
 
-            channel
-                .basic_consume(DefaultConsumer::new(args.no_ack), args)
-                .await
-                .unwrap();
-            time::sleep(time::Duration::from_secs(1)).await;
-        }
-        // channel drops, wait for close handshake done.
-        time::sleep(time::Duration::from_secs(1)).await;
-        // connection drops
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        server.await.unwrap();
     }
 
     #[tokio::test]
-    async fn test_basic_consume_manual_ack() {
+    async fn test_publish_routed() {
         setup_logging();
-        {
-            let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
-                .connection_name("test_basic_consume_manual_ack")
-                .finish();
 
-            let connection = Connection::open(&args).await.unwrap();
+        // mock server routes messages with routing key "orders", returns the others
+        // before acking them, and records whether they are published as mandatory
+        let (port, server) = mock_broker(
+            (vec![], None, None, 0),
+            |(published, route, header, delivery_tag), _, frame| match frame {
+                Frame::Select(..) => vec![SelectOk.into_frame()],
+                Frame::Publish(_, publish) => {
+                    let bytes = amqp_serde::to_bytes(&publish).unwrap();
+                    let (strs, flags) = short_strs(&bytes, 2);
+                    let mandatory = bytes[flags] & 1 != 0;
+                    published.push(format!("{} {}", strs[1], mandatory));
+                    *route = Some((strs[0].clone(), strs[1].clone()));
+                    vec![]
+                }
+                Frame::ContentHeader(content_header) => {
+                    *header = Some(content_header);
+                    vec![]
+                }
+                Frame::ContentBody(body) => {
+                    *delivery_tag += 1;
+                    let (exchange, routing_key) = route.take().unwrap();
+                    let header = header.take().unwrap();
+                    let ack = Ack::new(*delivery_tag, false).into_frame();
+                    if routing_key == "orders" {
+                        vec![ack]
+                    } else {
+                        let ret = Return::new(
+                            312,
+                            "NO_ROUTE".try_into().unwrap(),
+                            exchange.try_into().unwrap(),
+                            routing_key.try_into().unwrap(),
+                        );
+                        vec![
+                            ret.into_frame(),
+                            header.into_frame(),
+                            body.into_frame(),
+                            ack,
+                        ]
+                    }
+                }
+                other => channel_reply(other),
+            },
+        )
+        .await;
 
-            let channel = connection.open_channel(None).await.unwrap();
-            let (queue_name, ..) = channel
-                .queue_declare(QueueDeclareArguments::default())
-                .await
-                .unwrap()
-                .unwrap();
-            channel
-                .queue_bind(QueueBindArguments::new(
-                    &queue_name,
-                    "amq.topic",
-                    "eiffel.#",
-                ))
-                .await
-                .unwrap();
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
 
-            let args = BasicConsumeArguments::new(&queue_name, "test_manual_ack");
+        // confirm mode is required
+        let publish_args = BasicPublishArguments::new("amq.direct", "orders");
+        assert!(matches!(
             channel
-                .basic_consume(DefaultConsumer::new(args.no_ack), args)
-                .await
-                .unwrap();
-            time::sleep(time::Duration::from_secs(1)).await;
+                .basic_publish_routed(BasicProperties::default(), vec![], publish_args.clone())
+                .await,
+            Err(Error::PublishError { source, .. }) if matches!(*source, Error::ChannelUseError(_))
+        ));
+        channel
+            .confirm_select(ConfirmSelectArguments::default())
+            .await
+            .unwrap();
+
+        let outcome = channel
+            .basic_publish_routed(
+                BasicProperties::default(),
+                b"lost".to_vec(),
+                BasicPublishArguments::new("amq.direct", "nowhere"),
+            )
+            .await
+            .unwrap();
+        match outcome {
+            PublishOutcome::Unroutable(message) => {
+                assert_eq!(312, message.reply_code);
+                assert_eq!("nowhere", message.routing_key);
+                assert_eq!(b"lost".to_vec(), message.content);
+                // message is published untouched
+                assert!(message.basic_properties.headers().is_none());
+            }
+            other => panic!("unexpected outcome {:?}", other),
         }
-        // channel and connection drop, wait for close task to fnish
-        time::sleep(time::Duration::from_secs(1)).await;
+        let outcome = channel
+            .basic_publish_routed(BasicProperties::default(), b"order".to_vec(), publish_args)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, PublishOutcome::Routed));
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        assert_eq!(vec!["nowhere true", "orders true"], server.await.unwrap().0);
     }
 
     #[tokio::test]
-    async fn test_basic_publish() {
+    async fn test_publish_routed_multiple_ack() {
         setup_logging();
-        {
-            let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
-                .connection_name("test_basic_publish")
-                .finish();
 
-            let connection = Connection::open(&args).await.unwrap();
+        // mock server returns the message with routing key "nowhere", and acks all three
+        // messages by one confirm with `multiple` after the third one
+        let (published_tx, mut published_rx) = mpsc::unbounded_channel();
+        let (port, server) = mock_broker(
+            (None, None, 0, vec![]),
+            move |(route, header, delivery_tag, returned), _, frame| match frame {
+                Frame::Select(..) => vec![SelectOk.into_frame()],
+                Frame::Publish(_, publish) => {
+                    *route = Some((
+                        publish.exchange().to_owned(),
+                        publish.routing_key().to_owned(),
+                    ));
+                    vec![]
+                }
+                Frame::ContentHeader(content_header) => {
+                    *header = Some(content_header);
+                    vec![]
+                }
+                Frame::ContentBody(body) => {
+                    *delivery_tag += 1;
+                    let (exchange, routing_key) = route.take().unwrap();
+                    let header = header.take().unwrap();
+                    published_tx.send(routing_key.clone()).unwrap();
+                    if routing_key == "nowhere" {
+                        let ret = Return::new(
+                            312,
+                            "NO_ROUTE".try_into().unwrap(),
+                            exchange.try_into().unwrap(),
+                            routing_key.try_into().unwrap(),
+                        );
+                        returned.extend([ret.into_frame(), header.into_frame(), body.into_frame()]);
+                    }
+                    if *delivery_tag == 3 {
+                        returned.push(Ack::new(3, true).into_frame());
+                        std::mem::take(returned)
+                    } else {
+                        vec![]
+                    }
+                }
+                other => channel_reply(other),
+            },
+        )
+        .await;
 
-            let channel = connection.open_channel(None).await.unwrap();
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        channel
+            .confirm_select(ConfirmSelectArguments::default())
+            .await
+            .unwrap();
 
-            let args = BasicPublishArguments::new("amq.topic", "eiffel._.amqprs._.tester");
+        // the unroutable message is the second of the three messages acked at once
+        let timeout = time::Duration::from_secs(5);
+        let mut publishes = vec![];
+        for routing_key in ["orders", "nowhere", "orders"] {
+            let channel = channel.clone();
+            publishes.push(tokio::spawn(async move {
+                channel
+                    .basic_publish_routed(
+                        BasicProperties::default(),
+                        routing_key.as_bytes().to_vec(),
+                        BasicPublishArguments::new("amq.direct", routing_key),
+                    )
+                    .await
+                    .unwrap()
+            }));
+            let published = time::timeout(timeout, published_rx.recv()).await.unwrap();
+            assert_eq!(Some(routing_key.to_owned()), published);
+        }
 
-            let basic_properties = BasicProperties::default()
-                .with_content_type("application/json;charset=utf-8")
-                .with_persistence(true)
-                .finish();
+        let mut outcomes = vec![];
+        for publish in publishes {
+            outcomes.push(time::timeout(timeout, publish).await.unwrap().unwrap());
+        }
+        assert!(matches!(outcomes[0], PublishOutcome::Routed));
+        match &outcomes[1] {
+            PublishOutcome::Unroutable(message) => {
+                assert_eq!("nowhere", message.routing_key);
+                assert_eq!(b"nowhere".to_vec(), message.content);
+            }
+            other => panic!("unexpected outcome {:?}", other),
+        }
+        assert!(matches!(outcomes[2], PublishOutcome::Routed));
 
-            let content = String::from(
-            r#"
-                {
-                    "meta": {"id": "f9d42464-fceb-4282-be95-0cd98f4741b0", "type": "PublishTester", "version": "4.0.0", "time": 1640035100149},
-                    "data": { "customData": []},
-                    "links": [{"type": "BASE", "target": "fa321ff0-faa6-474e-aa1d-45edf8c99896"}]}
-            "#
-            ).into_bytes();
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_publish_encoded() {
+        setup_logging();
+
+        // mock server records the exchange, properties and body frames of published messages
+        let (port, server) = mock_broker(vec![], |received, _, frame| {
+            match frame {
+                Frame::Publish(_, publish) => received.push(publish.exchange().to_owned()),
+                Frame::ContentHeader(header) => {
+                    let properties = &header.basic_properties;
+                    received.push(format!(
+                        "{:?} {:?} {:?} {}",
+                        properties.content_type(),
+                        properties.app_id(),
+                        properties.headers().map(|headers| headers.len()),
+                        header.common.body_size
+                    ));
+                }
+                Frame::ContentBody(body) => received.push(body.inner.len().to_string()),
+                other => return channel_reply(other),
+            }
+            vec![]
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        channel.set_default_properties(BasicProperties::default().with_app_id("app1").finish());
 
+        let mut headers = FieldTable::new();
+        headers.insert("x-fan-out".try_into().unwrap(), true.into());
+        let basic_properties = BasicProperties::default()
+            .with_content_type("application/json")
+            .with_headers(headers)
+            .finish();
+        let encoded = EncodedProperties::new(&basic_properties);
+        for exchange in ["e1", "e2"] {
             channel
-                .basic_publish(basic_properties, content, args)
+                .basic_publish_encoded(
+                    &encoded,
+                    vec![0; 5000],
+                    BasicPublishArguments::new(exchange, "k"),
+                )
                 .await
                 .unwrap();
-            time::sleep(time::Duration::from_secs(1)).await;
         }
-        // channel and connection drop, wait for close task to fnish
-        time::sleep(time::Duration::from_secs(1)).await;
+        channel
+            .basic_publish(
+                basic_properties,
+                b"hello".to_vec(),
+                BasicPublishArguments::new("e3", "k"),
+            )
+            .await
+            .unwrap();
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+
+        // encoded properties are sent as they are, without channel defaults,
+        // body is split by `frame_max`
+        let encoded_message = |exchange: &str| {
+            vec![
+                exchange.to_owned(),
+                r#"Some("application/json") None Some(1) 5000"#.to_owned(),
+                (FRAME_MIN_SIZE as usize - 8).to_string(),
+                (5000 - (FRAME_MIN_SIZE as usize - 8)).to_string(),
+            ]
+        };
+        let mut expected = encoded_message("e1");
+        expected.extend(encoded_message("e2"));
+        expected.extend([
+            "e3".to_owned(),
+            r#"Some("application/json") Some("app1") Some(1) 5"#.to_owned(),
+            "5".to_owned(),
+        ]);
+        assert_eq!(expected, server.await.unwrap());
+    }
+
+    /// Forwards returned messages.
+    struct ReturnForwarder(mpsc::UnboundedSender<ReturnedMessage>);
+
+    #[async_trait]
+    impl ChannelCallback for ReturnForwarder {
+        async fn close(&mut self, _: &Channel, _: CloseChannel) -> Result<()> {
+            Ok(())
+        }
+        async fn cancel(&mut self, _: &Channel, _: Cancel) -> Result<()> {
+            Ok(())
+        }
+        async fn flow(&mut self, _: &Channel, active: bool) -> Result<bool> {
+            Ok(active)
+        }
+        async fn publish_ack(&mut self, _: &Channel, _: Ack) {}
+        async fn publish_nack(&mut self, _: &Channel, _: Nack) {}
+        async fn publish_return(&mut self, _: &Channel, _: Return, _: BasicProperties, _: Vec<u8>) {
+        }
+        async fn publish_returned(&mut self, _: &Channel, message: ReturnedMessage) {
+            self.0.send(message).unwrap();
+        }
     }
 
     #[tokio::test]
-    async fn test_basic_qos() {
+    async fn test_republish_returned_message() {
         setup_logging();
 
-        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami");
+        // mock server routes messages with routing key "orders" to the consumer,
+        // and returns the other mandatory messages, it records the published routes
+        let (port, server) = mock_broker(
+            (vec![], None, None),
+            |(published, route, header), _, frame| match frame {
+                Frame::Consume(..) => vec![consume_ok("ctag")],
+                Frame::Cancel(..) => vec![cancel_ok("ctag")],
+                Frame::Publish(_, publish) => {
+                    // ticket, exchange, routing key, then mandatory bit
+                    let bytes = amqp_serde::to_bytes(&publish).unwrap();
+                    let (strs, flags) = short_strs(&bytes, 2);
+                    let mandatory = bytes[flags] & 1 != 0;
+                    published.push(format!("{} {} {}", strs[0], strs[1], mandatory));
+                    *route = Some((strs[0].clone(), strs[1].clone()));
+                    vec![]
+                }
+                Frame::ContentHeader(content_header) => {
+                    *header = Some(content_header);
+                    vec![]
+                }
+                Frame::ContentBody(body) => {
+                    let (exchange, routing_key) = route.take().unwrap();
+                    let first = if routing_key == "orders" {
+                        deliver("ctag", 1).into_frame()
+                    } else {
+                        Return::new(
+                            312,
+                            "NO_ROUTE".try_into().unwrap(),
+                            exchange.try_into().unwrap(),
+                            routing_key.try_into().unwrap(),
+                        )
+                        .into_frame()
+                    };
+                    vec![
+                        first,
+                        header.take().unwrap().into_frame(),
+                        body.into_frame(),
+                    ]
+                }
+                other => channel_reply(other),
+            },
+        )
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
         let connection = Connection::open(&args).await.unwrap();
-        connection
-            .register_callback(DefaultConnectionCallback)
+        let channel = connection.open_channel(None).await.unwrap();
+        let (tx, mut returned) = mpsc::unbounded_channel();
+        channel
+            .register_callback(ReturnForwarder(tx))
             .await
             .unwrap();
+        let args = BasicConsumeArguments::new("q", "ctag")
+            .auto_ack(true)
+            .finish();
+        let (_, mut rx) = channel.basic_consume_rx(args).await.unwrap();
+        let timeout = time::Duration::from_secs(5);
 
-        let channel = connection.open_channel(None).await.unwrap();
+        let properties = BasicProperties::default().with_message_id("m1").finish();
         channel
-            .register_callback(DefaultChannelCallback)
+            .basic_publish(
+                properties,
+                b"hello".to_vec(),
+                BasicPublishArguments::new("amq.direct", "order")
+                    .mandatory(true)
+                    .finish(),
+            )
             .await
             .unwrap();
-
-        let (queue_name, ..) = channel
-            .queue_declare(QueueDeclareArguments::default())
+        let message = time::timeout(timeout, returned.recv())
             .await
             .unwrap()
             .unwrap();
-        channel
-            .queue_bind(QueueBindArguments::new(
-                &queue_name,
-                "amq.topic",
-                "eiffel.#",
-            ))
+        assert_eq!("order", message.routing_key);
+
+        // still unroutable, returned again
+        channel.republish(message, "orderz").await.unwrap();
+        let message = time::timeout(timeout, returned.recv())
             .await
+            .unwrap()
             .unwrap();
+        assert_eq!("orderz", message.routing_key);
 
-        let args = BasicConsumeArguments::new(&queue_name, "test_basic_qos");
-        channel
-            .basic_consume(DefaultConsumer::new(args.no_ack), args)
+        // delivered with the properties and body kept
+        channel.republish(message, "orders").await.unwrap();
+        let delivered = time::timeout(timeout, rx.recv()).await.unwrap().unwrap();
+        assert_eq!(
+            "m1",
+            delivered.basic_properties.unwrap().message_id().unwrap()
+        );
+        assert_eq!(b"hello".to_vec(), delivered.content.unwrap());
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        assert_eq!(
+            vec![
+                "amq.direct order true",
+                "amq.direct orderz true",
+                "amq.direct orders true"
+            ],
+            server.await.unwrap().0
+        );
+        assert!(returned.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reject_requeue_flags() {
+        setup_logging();
+
+        // mock server records the rejects
+        let (port, server) = mock_broker(vec![], |rejects, _, frame| match frame {
+            Frame::Reject(_, reject) => {
+                // delivery tag, then requeue bit
+                let bytes = amqp_serde::to_bytes(&reject).unwrap();
+                let delivery_tag = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+                rejects.push(format!("{} requeue={}", delivery_tag, bytes[8] != 0));
+                vec![]
+            }
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        channel.reject_requeue(1.into()).await.unwrap();
+        channel.reject_to_deadletter(2.into()).await.unwrap();
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        assert_eq!(
+            vec!["1 requeue=true", "2 requeue=false"],
+            server.await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consume_with_consumer_tag_generator() {
+        setup_logging();
+
+        // mock server confirms consumers with the tags given by client
+        let (port, server) = mock_broker((), |_, _, frame| match frame {
+            Frame::Consume(_, consume) => {
+                // ticket, queue, then consumer tag
+                let bytes = amqp_serde::to_bytes(&consume).unwrap();
+                let (strs, _) = short_strs(&bytes, 2);
+                vec![consume_ok(&strs[1])]
+            }
+            Frame::Cancel(_, cancel) => vec![cancel_ok(cancel.consumer_tag())],
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(Some(3)).await.unwrap();
+        channel.set_consumer_tag_generator(Some(ConsumerTagGenerator::new("billing")));
+
+        let mut tags = vec![];
+        for _ in 0..2 {
+            let (tag, _rx) = channel
+                .basic_consume_rx(BasicConsumeArguments::new("invoices", ""))
+                .await
+                .unwrap();
+            tags.push(tag);
+        }
+        // given tag is kept
+        let (tag, _rx) = channel
+            .basic_consume_rx(BasicConsumeArguments::new("invoices", "given"))
             .await
             .unwrap();
+        tags.push(tag);
+        assert_eq!(vec!["ctag-billing-3-1", "ctag-billing-3-2", "given"], tags);
 
-        // set qos
-        channel
-            .basic_qos(BasicQosArguments::new(0, 100, false))
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_publish_error_echoes_arguments() {
+        setup_logging();
+
+        // mock server accepts the channel, nothing is published successfully
+        let (port, server) = mock_broker((), |_, _, frame| channel_reply(frame)).await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let assert_echoed = |err: Error, routing_key: &str, body_len: usize| match err {
+            Error::PublishError {
+                exchange,
+                routing_key: echoed_key,
+                body_len: echoed_len,
+                source,
+            } => {
+                assert_eq!("amq.topic", exchange);
+                assert_eq!(routing_key, echoed_key);
+                assert_eq!(body_len, echoed_len);
+                source
+            }
+            other => panic!("unexpected error {}", other),
+        };
+
+        // name too long
+        let long_key = "k".repeat(256);
+        let err = channel
+            .basic_publish(
+                BasicProperties::default(),
+                b"hello".to_vec(),
+                BasicPublishArguments::new("amq.topic", &long_key),
+            )
             .await
-            .unwrap();
+            .unwrap_err();
+        let source = assert_echoed(err, &long_key, 5);
+        assert!(matches!(*source, Error::NameTooLong(_)));
 
-        // try recover
-        channel.basic_recover(true).await.unwrap();
+        // connection blocked
+        connection.set_is_blocked(true);
+        let err = channel
+            .basic_publish_unblocked(
+                BasicProperties::default(),
+                vec![0; 64],
+                BasicPublishArguments::new("amq.topic", "orders.blocked"),
+                Some(time::Duration::from_millis(10)),
+            )
+            .await
+            .unwrap_err();
+        let source = assert_echoed(err, "orders.blocked", 64);
+        assert!(matches!(*source, Error::ConnectionBlocked(_)));
+        connection.set_is_blocked(false);
 
+        // channel closed
+        let closed = channel.clone();
         channel.close().await.unwrap();
+        let err = closed
+            .basic_publish(
+                BasicProperties::default(),
+                vec![],
+                BasicPublishArguments::new("amq.topic", "orders.closed"),
+            )
+            .await
+            .unwrap_err();
+        let message = err.to_string();
+        let source = assert_echoed(err, "orders.closed", 0);
+        assert!(matches!(*source, Error::ChannelClosed(_)));
+        assert!(message.contains("exchange 'amq.topic', routing key 'orders.closed'"));
+
         connection.close().await.unwrap();
+        server.await.unwrap();
     }
 }
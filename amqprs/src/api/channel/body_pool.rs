@@ -0,0 +1,94 @@
+//! Pool of content body buffers, enabled by feature "body_pool".
+//!
+//! Buffers given back by [`ConsumerMessage::recycle`] are reused to assemble the
+//! content body of subsequent deliveries, instead of allocating a new `Vec<u8>`
+//! for every message.
+//!
+//! [`ConsumerMessage::recycle`]: struct.ConsumerMessage.html#method.recycle
+use std::sync::Mutex;
+
+/// Maximum number of buffers kept in the pool.
+const MAX_POOLED_BUFFERS: usize = 1024;
+
+/// Buffers with larger capacity are dropped instead of being pooled,
+/// so that a few large messages do not pin a lot of memory.
+const MAX_POOLED_CAPACITY: usize = 1024 * 1024;
+
+/// Pool shared by all channels.
+pub(crate) static BODY_POOL: BodyPool = BodyPool::new();
+
+pub(crate) struct BodyPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BodyPool {
+    // const `Mutex::new` requires Rust 1.63, as documented for the feature
+    #[allow(clippy::incompatible_msrv)]
+    const fn new() -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Take a buffer from the pool with at least `size` capacity,
+    /// or allocate a new one if the pool is empty.
+    pub(crate) fn take(&self, size: usize) -> Vec<u8> {
+        let buf = self.buffers.lock().unwrap().pop();
+        match buf {
+            Some(mut buf) => {
+                buf.reserve(size);
+                buf
+            }
+            None => Vec::with_capacity(size),
+        }
+    }
+
+    /// Give back a buffer to the pool.
+    pub(crate) fn put(&self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        buf.clear();
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < MAX_POOLED_BUFFERS {
+            buffers.push(buf);
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BodyPool, MAX_POOLED_BUFFERS, MAX_POOLED_CAPACITY};
+
+    #[test]
+    fn test_recycled_buffer_is_reused() {
+        let pool = BodyPool::new();
+        let mut buf = pool.take(64);
+        buf.extend_from_slice(&[1; 64]);
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+
+        let reused = pool.take(32);
+        assert!(reused.is_empty());
+        assert!(reused.capacity() >= 64);
+        assert_eq!(ptr, reused.as_ptr());
+    }
+
+    #[test]
+    fn test_pool_is_bounded() {
+        let pool = BodyPool::new();
+        pool.put(vec![0u8; MAX_POOLED_CAPACITY + 1]);
+        pool.put(Vec::new());
+        assert_eq!(0, pool.len());
+
+        for _ in 0..MAX_POOLED_BUFFERS + 1 {
+            pool.put(vec![0u8; 16]);
+        }
+        assert_eq!(MAX_POOLED_BUFFERS, pool.len());
+    }
+}
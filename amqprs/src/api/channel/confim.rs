@@ -1,9 +1,19 @@
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+use tokio::{
+    sync::{mpsc, oneshot, Semaphore},
+    time,
+};
+
 use crate::{
     api::error::Error,
-    frame::{Frame, Select, SelectOk},
+    frame::{BasicProperties, DeliveryTag, Frame, Nack, Select, SelectOk},
 };
 
-use super::{Channel, Result};
+use super::{
+    BasicPublishArguments, Channel, ChannelMode, DispatcherManagementCommand,
+    RegisterConfirmListener, Result, ReturnedMessage,
+};
 
 /// Arguments for [`confirm_select`]
 ///
@@ -23,21 +33,95 @@ impl ConfirmSelectArguments {
     }
 }
 
+/// Outcome of a published message confirmed by server, yielded by [`ConfirmStream`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Confirm {
+    /// Delivery tag of the message, i.e. its publish sequence number on the channel.
+    pub delivery_tag: u64,
+    /// `true` if server acks the message, `false` if server nacks it.
+    pub ack: bool,
+}
+
+/// Outcome of a message published by [`Channel::basic_publish_routed`].
+///
+/// [`Channel::basic_publish_routed`]: struct.Channel.html#method.basic_publish_routed
+#[derive(Debug)]
+pub enum PublishOutcome {
+    /// Server routed the message to at least one queue and acked it.
+    Routed,
+    /// Server could not route the message to any queue, and returned it before acking it.
+    Unroutable(Box<ReturnedMessage>),
+    /// Server nacked the message, it may or may not have been routed.
+    Nacked(Nack),
+}
+
+/// Stream of confirms of published messages, returned by [`Channel::confirm_stream`].
+///
+/// A confirm with the `multiple` flag is expanded into one [`Confirm`] per message, so
+/// each published message is yielded exactly once. Confirms are yielded in the order
+/// server sends them, which is not necessarily the order of delivery tags.
+///
+/// The stream ends when the channel is closed, or another stream is created on the channel.
+///
+/// [`Channel::confirm_stream`]: struct.Channel.html#method.confirm_stream
+pub struct ConfirmStream {
+    rx: mpsc::UnboundedReceiver<Confirm>,
+}
+
+impl ConfirmStream {
+    /// Receive next confirm.
+    ///
+    /// Returns `None` if the stream has ended.
+    pub async fn recv(&mut self) -> Option<Confirm> {
+        self.rx.recv().await
+    }
+
+    /// Receive next confirm, waiting at most `timeout`.
+    ///
+    /// Returns `None` if the stream has ended or no confirm arrives in time.
+    pub async fn recv_timeout(&mut self, timeout: Duration) -> Option<Confirm> {
+        time::timeout(timeout, self.rx.recv()).await.ok().flatten()
+    }
+
+    /// Collect up to `max` confirms within `timeout`, e.g. to await a window of publishes.
+    ///
+    /// The `timeout` bounds the total time spent, not the wait for each confirm.
+    /// Returns early if `max` confirms are received or the stream has ended.
+    pub async fn drain(&mut self, max: usize, timeout: Duration) -> Vec<Confirm> {
+        let deadline = time::Instant::now() + timeout;
+        let mut confirms = Vec::new();
+        while confirms.len() < max {
+            match time::timeout_at(deadline, self.rx.recv()).await {
+                Ok(Some(confirm)) => confirms.push(confirm),
+                // stream ended or timed out
+                Ok(None) | Err(_) => break,
+            }
+        }
+        confirms
+    }
+}
+
 /// APIs for AMQP confirm class.
 impl Channel {
     /// See [AMQP_0-9-1 Reference](https://www.rabbitmq.com/amqp-0-9-1-reference.html#confirm.select).
     ///
     /// # Errors
     ///
+    /// Returns [`Error::ModeConflict`] if the channel is in transaction mode.
+    ///
     /// Returns error if any failure in comunication with server.
+    ///
+    /// [`Error::ModeConflict`]: ../error/enum.Error.html#variant.ModeConflict
     pub async fn confirm_select(&self, args: ConfirmSelectArguments) -> Result<()> {
+        self.check_mode(ChannelMode::Confirm)?;
         let select = Select::new(args.no_wait);
+        // publishes are numbered from 1 once in confirm mode
+        let mut next_seq_no = self.shared.next_publish_seq_no.lock().await;
         if args.no_wait {
             self.shared
                 .outgoing_tx
                 .send((self.shared.channel_id, select.into_frame()))
                 .await?;
-            Ok(())
         } else {
             let responder_rx = self.register_responder(SelectOk::header()).await?;
 
@@ -48,23 +132,234 @@ impl Channel {
                 Frame::SelectOk,
                 Error::ChannelUseError
             )?;
-            Ok(())
         }
+        next_seq_no.get_or_insert(1);
+        self.set_mode(ChannelMode::Confirm);
+        Ok(())
+    }
+
+    /// Same as [`confirm_select`], but at most `max_in_flight` published messages can be
+    /// unconfirmed at a time.
+    ///
+    /// Once `max_in_flight` messages are outstanding, [`basic_publish`] waits until server
+    /// confirms some of them, either ack or nack, before sending the message. This bounds
+    /// the memory of tracking outstanding messages on both client and server.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelUseError`] if `max_in_flight` is zero, or the channel is already
+    /// in confirm mode, because the messages published before are not counted.
+    ///
+    /// Otherwise, returns the same errors as [`confirm_select`].
+    ///
+    /// [`basic_publish`]: struct.Channel.html#method.basic_publish
+    /// [`confirm_select`]: struct.Channel.html#method.confirm_select
+    pub async fn confirm_select_with(&self, max_in_flight: usize) -> Result<()> {
+        if max_in_flight == 0 {
+            return Err(Error::ChannelUseError(
+                "max in-flight publishes must be greater than zero".to_string(),
+            ));
+        }
+        if self.mode() == ChannelMode::Confirm {
+            return Err(Error::ChannelUseError(format!(
+                "channel {} is already in confirm mode",
+                self.channel_id()
+            )));
+        }
+        self.confirm_select(ConfirmSelectArguments::default())
+            .await?;
+        self.shared
+            .publish_window
+            .lock()
+            .unwrap()
+            .replace(Arc::new(Semaphore::new(max_in_flight)));
+        Ok(())
+    }
+
+    /// Publish a message in confirm mode without awaiting its confirm, `callback` is
+    /// invoked with the outcome once server confirms the message.
+    ///
+    /// `callback` receives `Ok(())` if server acks the message, or the [`Nack`] of the message
+    /// if server nacks it. It runs on the channel's dispatcher task, so it should return quickly.
+    /// Callbacks of messages which are not confirmed before the channel is closed are dropped
+    /// without being invoked.
+    ///
+    /// Messages published by [`basic_publish`] share the same sequence of delivery tags,
+    /// so both can be mixed on the channel. Confirms are also passed to the channel's
+    /// [`publish_ack`] and [`publish_nack`] callbacks, if registered.
+    ///
+    /// Returns the delivery tag which server confirms the message with.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PublishError`] caused by [`Error::ChannelUseError`] if the channel is not
+    /// in confirm mode by [`confirm_select`].
+    ///
+    /// Otherwise, returns the same errors as [`basic_publish`].
+    ///
+    /// [`basic_publish`]: struct.Channel.html#method.basic_publish
+    /// [`confirm_select`]: struct.Channel.html#method.confirm_select
+    /// [`publish_ack`]: ../callbacks/trait.ChannelCallback.html#tymethod.publish_ack
+    /// [`publish_nack`]: ../callbacks/trait.ChannelCallback.html#tymethod.publish_nack
+    pub async fn basic_publish_with_confirm_callback<F>(
+        &self,
+        basic_properties: BasicProperties,
+        content: Vec<u8>,
+        args: BasicPublishArguments,
+        callback: F,
+    ) -> Result<DeliveryTag>
+    where
+        F: FnOnce(std::result::Result<(), Nack>) + Send + 'static,
+    {
+        let callback = Box::new(move |outcome: std::result::Result<_, Nack>| {
+            callback(outcome.map(|_returned| ()))
+        });
+        let delivery_tag = self
+            .publish(basic_properties, content, args, Some(callback))
+            .await?;
+        // callback is registered only in confirm mode
+        Ok(delivery_tag.unwrap().into())
+    }
+
+    /// Publish a message as mandatory in confirm mode, and wait until server confirms it
+    /// to learn whether it was routed to any queue.
+    ///
+    /// The message returned by server is correlated with its confirm by the order of
+    /// returns, which server sends in publish order, and by the exchange, routing key and
+    /// message id of the message, even if a confirm with the `multiple` flag covers several
+    /// messages. The message itself is left untouched. The returned message is also passed
+    /// to the channel's [`publish_returned`] callback, if registered.
+    ///
+    /// If messages with the same exchange and routing key are published concurrently on
+    /// the channel and only some of them are unroutable, e.g. to a headers exchange, set
+    /// message ids unique among them, otherwise a return may be taken for an earlier message.
+    ///
+    /// The `mandatory` flag of `args` is always set.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PublishError`] caused by [`Error::ChannelUseError`] if the channel is not
+    /// in confirm mode by [`confirm_select`].
+    ///
+    /// Returns [`Error::ChannelClosed`] if the channel is closed before the message is confirmed.
+    ///
+    /// Otherwise, returns the same errors as [`basic_publish`].
+    ///
+    /// [`basic_publish`]: struct.Channel.html#method.basic_publish
+    /// [`confirm_select`]: struct.Channel.html#method.confirm_select
+    /// [`publish_returned`]: ../callbacks/trait.ChannelCallback.html#method.publish_returned
+    /// [`Error::ChannelUseError`]: ../error/enum.Error.html#variant.ChannelUseError
+    /// [`Error::ChannelClosed`]: ../error/enum.Error.html#variant.ChannelClosed
+    /// [`Error::PublishError`]: ../error/enum.Error.html#variant.PublishError
+    pub async fn basic_publish_routed(
+        &self,
+        basic_properties: BasicProperties,
+        content: Vec<u8>,
+        mut args: BasicPublishArguments,
+    ) -> Result<PublishOutcome> {
+        args.mandatory = true;
+        let (tx, rx) = oneshot::channel();
+        let callback = Box::new(move |outcome: std::result::Result<_, Nack>| {
+            let _ = tx.send(outcome);
+        });
+        let delivery_tag = self
+            .publish(basic_properties, content, args, Some(callback))
+            .await?;
+        match rx.await {
+            Ok(Ok(None)) => Ok(PublishOutcome::Routed),
+            Ok(Ok(Some(returned))) => Ok(PublishOutcome::Unroutable(Box::new(returned))),
+            Ok(Err(nack)) => Ok(PublishOutcome::Nacked(nack)),
+            // callback is dropped if the channel is closed before the confirm
+            Err(_) => Err(Error::ChannelClosed(format!(
+                "message {} is not confirmed on channel {}",
+                delivery_tag.unwrap(),
+                self.channel_id()
+            ))),
+        }
+    }
+
+    /// Returns a stream of confirms of messages published on the channel, so that
+    /// confirms can be processed as they arrive instead of awaiting each publish.
+    ///
+    /// Only confirms received after the stream is created are yielded. Confirms are also
+    /// passed to confirm callbacks and the channel's [`publish_ack`] and [`publish_nack`]
+    /// callbacks, if registered.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelUseError`] if the channel is not in confirm mode by [`confirm_select`].
+    ///
+    /// Returns error if the channel is closed.
+    ///
+    /// [`confirm_select`]: struct.Channel.html#method.confirm_select
+    /// [`publish_ack`]: ../callbacks/trait.ChannelCallback.html#tymethod.publish_ack
+    /// [`publish_nack`]: ../callbacks/trait.ChannelCallback.html#tymethod.publish_nack
+    pub async fn confirm_stream(&self) -> Result<ConfirmStream> {
+        // hold the lock, so that no publish is confirmed before the stream is registered
+        let next_seq_no = self.shared.next_publish_seq_no.lock().await;
+        if next_seq_no.is_none() {
+            return Err(Error::ChannelUseError(format!(
+                "channel {} is not in confirm mode",
+                self.channel_id()
+            )));
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.shared.dispatcher_mgmt_tx.send(
+            DispatcherManagementCommand::RegisterConfirmListener(RegisterConfirmListener { tx }),
+        )?;
+        Ok(ConfirmStream { rx })
+    }
+
+    /// Wait until server confirms all messages published on the channel, then close it.
+    ///
+    /// Returns the delivery tags of the messages which are still unconfirmed when `timeout`
+    /// elapses or server closes the channel, the channel is closed in either case. Returns
+    /// an empty set once all messages are confirmed, or if the channel is not in confirm mode.
+    ///
+    /// Messages being published by other tasks while waiting are waited for as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`close`].
+    ///
+    /// [`close`]: struct.Channel.html#method.close
+    pub async fn close_after_confirms(self, timeout: Duration) -> Result<BTreeSet<u64>> {
+        let mut unconfirmed = self.shared.unconfirmed.subscribe();
+        let all_confirmed = async {
+            while !unconfirmed.borrow_and_update().is_empty() {
+                // sender is owned by `self`, it cannot be dropped while waiting
+                if unconfirmed.changed().await.is_err() {
+                    break;
+                }
+            }
+        };
+        // no more confirms arrive once the channel is closed
+        tokio::select! {
+            _ = time::timeout(timeout, all_confirmed) => {}
+            _ = self.closed() => {}
+        }
+        let unconfirmed = self.shared.unconfirmed.borrow().clone();
+        self.close().await?;
+        Ok(unconfirmed)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{collections::HashMap, time::Duration};
 
     use tokio::time;
 
+    use tokio::{net::TcpListener, sync::mpsc};
+
     use crate::{
         callbacks::{DefaultChannelCallback, DefaultConnectionCallback},
-        channel::BasicPublishArguments,
+        channel::{BasicPublishArguments, QueueDeclareArguments},
         connection::{Connection, OpenConnectionArguments},
-        test_utils::setup_logging,
-        BasicProperties, DELIVERY_MODE_TRANSIENT,
+        error::Error,
+        frame::{Ack, CloseOk, Frame, SelectOk, FRAME_MIN_SIZE},
+        test_utils::{accept_mock_connection, channel_reply, setup_logging, ReturnRecorder},
+        BasicProperties, FieldTable, FieldValue, DELIVERY_MODE_TRANSIENT,
     };
 
     use super::ConfirmSelectArguments;
@@ -95,9 +390,7 @@ mod tests {
 
         let args = BasicPublishArguments::new("amq.topic", "amqprs.test.transaction");
 
-        let basic_properties = BasicProperties::default()
-            .with_persistence(true)
-            .finish();
+        let basic_properties = BasicProperties::default().with_persistence(true).finish();
 
         let content = String::from("AMQPRS test publish confirm").into_bytes();
 
@@ -110,4 +403,318 @@ mod tests {
         channel.close().await.unwrap();
         connection.close().await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_confirm_stream() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+
+        // not in confirm mode yet
+        assert!(matches!(
+            channel.confirm_stream().await,
+            Err(Error::ChannelUseError(_))
+        ));
+
+        channel
+            .confirm_select(ConfirmSelectArguments::default())
+            .await
+            .unwrap();
+        let mut confirms = channel.confirm_stream().await.unwrap();
+
+        let args = BasicPublishArguments::new("amq.topic", "amqprs.test.confirm_stream");
+        for i in 0..100 {
+            channel
+                .basic_publish(
+                    BasicProperties::default(),
+                    format!("message {}", i).into_bytes(),
+                    args.clone(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let mut received = confirms.drain(100, Duration::from_secs(10)).await;
+        assert_eq!(100, received.len());
+        assert!(received.iter().all(|confirm| confirm.ack));
+        received.sort_by_key(|confirm| confirm.delivery_tag);
+        let delivery_tags: Vec<u64> = received.iter().map(|c| c.delivery_tag).collect();
+        assert_eq!((1..=100).collect::<Vec<u64>>(), delivery_tags);
+
+        channel.close().await.unwrap();
+        // stream ends once channel is closed
+        assert!(confirms.recv().await.is_none());
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_publish_with_confirm_callback() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+
+        // queue of one message, further publishes are nacked
+        let mut arguments = FieldTable::new();
+        arguments.insert("x-max-length".try_into().unwrap(), FieldValue::I(1));
+        arguments.insert(
+            "x-overflow".try_into().unwrap(),
+            FieldValue::S("reject-publish".try_into().unwrap()),
+        );
+        let (queue_name, ..) = channel
+            .queue_declare(
+                QueueDeclareArguments::default()
+                    .exclusive(true)
+                    .arguments(arguments)
+                    .finish(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        // not in confirm mode yet
+        let result = channel
+            .basic_publish_with_confirm_callback(
+                BasicProperties::default(),
+                b"unconfirmed".to_vec(),
+                BasicPublishArguments::new("", &queue_name),
+                |_| {},
+            )
+            .await;
+        match result {
+            Err(Error::PublishError { source, .. }) => {
+                assert!(matches!(*source, Error::ChannelUseError(_)))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        channel
+            .confirm_select(ConfirmSelectArguments::default())
+            .await
+            .unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for i in 1..=3u64 {
+            let tx = tx.clone();
+            let delivery_tag = channel
+                .basic_publish_with_confirm_callback(
+                    BasicProperties::default(),
+                    format!("message {}", i).into_bytes(),
+                    BasicPublishArguments::new("", &queue_name),
+                    move |outcome| tx.send((i, outcome.is_ok())).unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(i, u64::from(delivery_tag));
+        }
+
+        let mut outcomes = Vec::new();
+        for _ in 0..3 {
+            let outcome = time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            outcomes.push(outcome);
+        }
+        outcomes.sort_unstable();
+        assert_eq!(vec![(1, true), (2, false), (3, false)], outcomes);
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_confirm_select_with_max_in_flight() {
+        setup_logging();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // mock server acks all outstanding messages once publishing pauses,
+        // returns the max number of outstanding messages
+        let server = tokio::spawn(async move {
+            let mut io_conn = accept_mock_connection(&listener).await;
+            let (mut published, mut acked, mut max_outstanding) = (0u64, 0u64, 0u64);
+            loop {
+                let (channel_id, frame) =
+                    match time::timeout(Duration::from_millis(50), io_conn.read_frame()).await {
+                        Ok(result) => result.unwrap(),
+                        Err(_) => {
+                            if published > acked {
+                                acked = published;
+                                io_conn
+                                    .write_frame(
+                                        1,
+                                        Ack::new(acked, true).into_frame(),
+                                        FRAME_MIN_SIZE,
+                                    )
+                                    .await
+                                    .unwrap();
+                            }
+                            continue;
+                        }
+                    };
+                let responses = match frame {
+                    Frame::Select(..) => vec![SelectOk.into_frame()],
+                    Frame::Publish(..) => {
+                        published += 1;
+                        max_outstanding = max_outstanding.max(published - acked);
+                        vec![]
+                    }
+                    Frame::ContentHeader(..) | Frame::ContentBody(..) => vec![],
+                    Frame::Close(..) => {
+                        io_conn
+                            .write_frame(channel_id, CloseOk.into_frame(), FRAME_MIN_SIZE)
+                            .await
+                            .unwrap();
+                        return (published, max_outstanding);
+                    }
+                    other => channel_reply(other),
+                };
+                for response in responses {
+                    io_conn
+                        .write_frame(channel_id, response, FRAME_MIN_SIZE)
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(Some(1)).await.unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        channel.register_callback(ReturnRecorder(tx)).await.unwrap();
+        assert!(matches!(
+            channel.confirm_select_with(0).await,
+            Err(Error::ChannelUseError(_))
+        ));
+        channel.confirm_select_with(10).await.unwrap();
+        assert!(matches!(
+            channel.confirm_select_with(10).await,
+            Err(Error::ChannelUseError(_))
+        ));
+
+        for i in 0..100 {
+            channel
+                .basic_publish(
+                    BasicProperties::default(),
+                    format!("message {}", i).into_bytes(),
+                    BasicPublishArguments::new("amq.topic", "window"),
+                )
+                .await
+                .unwrap();
+        }
+
+        // each window is acked as a whole
+        for i in 1..=10 {
+            assert_eq!(format!("ack {}", i * 10), rx.recv().await.unwrap());
+        }
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        let (published, max_outstanding) = server.await.unwrap();
+        assert_eq!(100, published);
+        assert_eq!(10, max_outstanding);
+    }
+
+    #[tokio::test]
+    async fn test_close_after_confirms() {
+        setup_logging();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // mock server acks all 3 messages on channel 1 after a delay,
+        // but only the first of 2 messages on channel 2
+        let server = tokio::spawn(async move {
+            let mut io_conn = accept_mock_connection(&listener).await;
+            let mut published = HashMap::new();
+            loop {
+                let (channel_id, frame) = io_conn.read_frame().await.unwrap();
+                let responses = match frame {
+                    Frame::Select(..) => vec![SelectOk.into_frame()],
+                    Frame::Publish(..) | Frame::ContentHeader(..) => vec![],
+                    Frame::ContentBody(..) => {
+                        let count = published.entry(channel_id).or_insert(0);
+                        *count += 1;
+                        match (channel_id, *count) {
+                            (1, 3) => {
+                                time::sleep(time::Duration::from_millis(50)).await;
+                                vec![
+                                    Ack::new(2, true).into_frame(),
+                                    Ack::new(3, false).into_frame(),
+                                ]
+                            }
+                            (2, 2) => vec![Ack::new(1, false).into_frame()],
+                            _ => vec![],
+                        }
+                    }
+                    Frame::Close(..) => {
+                        io_conn
+                            .write_frame(channel_id, CloseOk.into_frame(), FRAME_MIN_SIZE)
+                            .await
+                            .unwrap();
+                        return;
+                    }
+                    other => channel_reply(other),
+                };
+                for response in responses {
+                    io_conn
+                        .write_frame(channel_id, response, FRAME_MIN_SIZE)
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut channels = vec![];
+        for (channel_id, count) in [(1, 3), (2, 2)] {
+            let channel = connection.open_channel(Some(channel_id)).await.unwrap();
+            channel
+                .confirm_select(ConfirmSelectArguments::default())
+                .await
+                .unwrap();
+            for _ in 0..count {
+                let tx = tx.clone();
+                channel
+                    .basic_publish_with_confirm_callback(
+                        BasicProperties::default(),
+                        b"message".to_vec(),
+                        BasicPublishArguments::new("amq.topic", "confirms"),
+                        move |outcome| tx.send((channel_id, outcome.is_ok())).unwrap(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            channels.push(channel);
+        }
+        let timeout = time::Duration::from_millis(200);
+
+        // all confirms are resolved before the channel is closed
+        let channel1 = channels.remove(0);
+        assert!(channel1
+            .close_after_confirms(timeout)
+            .await
+            .unwrap()
+            .is_empty());
+        for _ in 0..3 {
+            assert_eq!((1, true), rx.try_recv().unwrap());
+        }
+
+        // the channel is closed with the unconfirmed messages once timeout elapses
+        let channel2 = channels.remove(0);
+        let unconfirmed = channel2.close_after_confirms(timeout).await.unwrap();
+        assert_eq!(vec![2], unconfirmed.into_iter().collect::<Vec<_>>());
+        assert_eq!((2, true), rx.try_recv().unwrap());
+        assert!(rx.try_recv().is_err());
+
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
 }
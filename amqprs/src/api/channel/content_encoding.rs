@@ -0,0 +1,212 @@
+use std::{borrow::Cow, io::Read};
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+
+use super::ConsumerMessage;
+use crate::api::{error::Error, Result};
+
+/// Default maximum size of decompressed content, see [`ConsumerMessage::body_decoded`].
+pub const DEFAULT_MAX_DECODED_SIZE: usize = 64 * 1024 * 1024;
+
+impl ConsumerMessage {
+    /// Returns the message content decompressed according to its `content_encoding`.
+    ///
+    /// `gzip`, `deflate` and `zstd` encodings are decompressed, `deflate` content may be in
+    /// zlib format or raw DEFLATE. The content is returned as is if it has no or an unknown
+    /// encoding, e.g. `identity`, so consumers can call it for every message.
+    ///
+    /// The decompressed content is limited to [`DEFAULT_MAX_DECODED_SIZE`] bytes,
+    /// use [`body_decoded_with_limit`] to change it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ContentEncodingError`] if the content is corrupted, or it exceeds
+    /// the maximum size after decompression.
+    ///
+    /// [`body_decoded_with_limit`]: #method.body_decoded_with_limit
+    pub fn body_decoded(&self) -> Result<Cow<'_, [u8]>> {
+        self.body_decoded_with_limit(DEFAULT_MAX_DECODED_SIZE)
+    }
+
+    /// Same as [`body_decoded`], but the decompressed content is limited to `max_size` bytes.
+    ///
+    /// [`body_decoded`]: #method.body_decoded
+    pub fn body_decoded_with_limit(&self, max_size: usize) -> Result<Cow<'_, [u8]>> {
+        let content = self.content.as_deref().unwrap_or_default();
+        let encoding = self
+            .basic_properties
+            .as_ref()
+            .and_then(|props| props.content_encoding())
+            .map(|encoding| encoding.trim().to_ascii_lowercase());
+        let decoded = match encoding.as_deref() {
+            Some("gzip") | Some("x-gzip") => decode("gzip", MultiGzDecoder::new(content), max_size),
+            Some("deflate") if is_zlib(content) => {
+                decode("deflate", ZlibDecoder::new(content), max_size)
+            }
+            Some("deflate") => decode("deflate", DeflateDecoder::new(content), max_size),
+            Some("zstd") => {
+                let decoder = zstd::stream::read::Decoder::with_buffer(content)
+                    .map_err(|err| corrupted("zstd", err))?;
+                decode("zstd", decoder, max_size)
+            }
+            _ => return Ok(Cow::Borrowed(content)),
+        };
+        decoded.map(Cow::Owned)
+    }
+}
+
+/// zlib header: compression method 8 and a check value, otherwise raw DEFLATE.
+fn is_zlib(content: &[u8]) -> bool {
+    match content {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (*cmf as u16 * 256 + *flg as u16) % 31 == 0,
+        _ => false,
+    }
+}
+
+fn corrupted(encoding: &str, err: std::io::Error) -> Error {
+    Error::ContentEncodingError(format!("corrupted {} content: {}", encoding, err))
+}
+
+fn decode<R: Read>(encoding: &str, decoder: R, max_size: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    // read one more byte to know if the limit is exceeded, without decoding everything
+    decoder
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|err| corrupted(encoding, err))?;
+    if output.len() > max_size {
+        return Err(Error::ContentEncodingError(format!(
+            "decoded {} content exceeds {} bytes",
+            encoding, max_size
+        )));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use crate::{api::error::Error, channel::ConsumerMessage, BasicProperties};
+
+    const TEXT: &[u8] = b"hello amqprs";
+    /// `TEXT` compressed in a fixed Huffman block
+    const GZIP_FIXED: [u8; 32] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0x48, 0xcc, 0x2d, 0x2c, 0x28, 0x2a, 0x06, 0x00, 0x7e, 0x00, 0x8f, 0x32, 0x0c, 0x00,
+        0x00, 0x00,
+    ];
+    /// `TEXT` in a stored block
+    const GZIP_STORED: [u8; 35] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x0c, 0x00, 0xf3, 0xff,
+        0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x61, 0x6d, 0x71, 0x70, 0x72, 0x73, 0x7e, 0x00, 0x8f,
+        0x32, 0x0c, 0x00, 0x00, 0x00,
+    ];
+    const ZLIB: [u8; 20] = [
+        0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x48, 0xcc, 0x2d, 0x2c, 0x28, 0x2a, 0x06,
+        0x00, 0x1e, 0x71, 0x04, 0xc9,
+    ];
+    const RAW_DEFLATE: [u8; 14] = [
+        0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x48, 0xcc, 0x2d, 0x2c, 0x28, 0x2a, 0x06, 0x00,
+    ];
+    /// `TEXT` compressed by `zstd` command line
+    const ZSTD: [u8; 25] = [
+        0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58, 0x61, 0x00, 0x00, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x20,
+        0x61, 0x6d, 0x71, 0x70, 0x72, 0x73, 0x4c, 0x11, 0x1d, 0xf2,
+    ];
+
+    fn message(encoding: Option<&str>, content: &[u8]) -> ConsumerMessage {
+        let mut props = BasicProperties::default();
+        if let Some(encoding) = encoding {
+            props.with_content_encoding(encoding);
+        }
+        ConsumerMessage {
+            deliver: None,
+            basic_properties: Some(props),
+            content: Some(content.to_vec()),
+            remaining: 0,
+            buffered: None,
+        }
+    }
+
+    #[test]
+    fn test_body_decoded_gzip() {
+        for content in [&GZIP_FIXED[..], &GZIP_STORED[..]] {
+            let msg = message(Some("gzip"), content);
+            assert_eq!(TEXT, &*msg.body_decoded().unwrap());
+        }
+        let msg = message(Some("X-GZIP"), &GZIP_FIXED);
+        assert_eq!(TEXT, &*msg.body_decoded().unwrap());
+    }
+
+    #[test]
+    fn test_body_decoded_deflate() {
+        for content in [&ZLIB[..], &RAW_DEFLATE[..]] {
+            let msg = message(Some("deflate"), content);
+            assert_eq!(TEXT, &*msg.body_decoded().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_body_decoded_zstd() {
+        let msg = message(Some("zstd"), &ZSTD);
+        assert_eq!(TEXT, &*msg.body_decoded().unwrap());
+
+        let content = zstd::encode_all(&[b'a'; 4096][..], 3).unwrap();
+        let msg = message(Some("zstd"), &content);
+        assert_eq!(&[b'a'; 4096][..], &*msg.body_decoded().unwrap());
+    }
+
+    #[test]
+    fn test_body_decoded_raw() {
+        for encoding in [None, Some("identity"), Some("br")] {
+            let msg = message(encoding, TEXT);
+            assert_eq!(TEXT, &*msg.body_decoded().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_body_decoded_max_size() {
+        // 16 MB of zeros compressed to a few KB
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        let zeros = vec![0; 1024 * 1024];
+        for _ in 0..16 {
+            encoder.write_all(&zeros).unwrap();
+        }
+        let content = encoder.finish().unwrap();
+        let msg = message(Some("gzip"), &content);
+
+        let err = msg.body_decoded_with_limit(1024 * 1024).unwrap_err();
+        assert!(matches!(err, Error::ContentEncodingError(_)), "{}", err);
+        assert!(err.to_string().contains("exceeds 1048576 bytes"), "{}", err);
+        assert_eq!(16 * 1024 * 1024, msg.body_decoded().unwrap().len());
+
+        let content = zstd::encode_all(&[0; 4096][..], 3).unwrap();
+        let msg = message(Some("zstd"), &content);
+        assert!(msg.body_decoded_with_limit(4095).is_err());
+        assert_eq!(4096, msg.body_decoded_with_limit(4096).unwrap().len());
+    }
+
+    #[test]
+    fn test_body_decoded_errors() {
+        // checksum of trailer is corrupted
+        let mut content = GZIP_FIXED;
+        content[24] ^= 0xff;
+        let err = message(Some("gzip"), &content).body_decoded().unwrap_err();
+        assert!(matches!(err, Error::ContentEncodingError(_)), "{}", err);
+
+        for (encoding, content) in [
+            ("gzip", TEXT),
+            ("gzip", &GZIP_FIXED[..20]),
+            ("deflate", &ZLIB[..10]),
+            ("zstd", TEXT),
+            ("zstd", &ZSTD[..20]),
+        ] {
+            let err = message(Some(encoding), content).body_decoded().unwrap_err();
+            assert!(matches!(err, Error::ContentEncodingError(_)), "{}", err);
+            assert!(err.to_string().contains("corrupted"), "{}", err);
+        }
+    }
+}
@@ -1,22 +1,38 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    sync::atomic::Ordering,
+};
 
 use tokio::{
-    sync::{mpsc, oneshot},
-    task::yield_now,
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
     time,
 };
 
+#[cfg(feature = "traces")]
+use crate::frame::trace_server_close;
 use crate::{
-    api::{callbacks::ChannelCallback, channel::ReturnMessage},
+    api::{
+        callbacks::ChannelCallback, channel::ReturnMessage,
+        connection::DEFAULT_BODY_PREALLOC_LIMIT, error::Error, Result,
+    },
     channel::GetOkMessage,
-    frame::{CancelOk, CloseChannelOk, ContentBody, FlowOk, Frame, MethodHeader},
+    frame::{
+        Cancel, CancelOk, CloseChannelOk, ContentBody, ContentHeader, DeclareQueueOk, Deliver,
+        FlowOk, Frame, MethodHeader, Nack,
+    },
     net::IncomingMessage,
     BasicProperties, Return,
 };
 #[cfg(feature = "traces")]
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
-use super::{Channel, ConsumerMessage, DispatcherManagementCommand};
+use super::{
+    Channel, Confirm, ConfirmCallback, ConsumerMessage, ConsumerTx, DispatcherManagementCommand,
+    ForwardBufferedDeliveries, PublishRouting, ReturnedMessage,
+};
 
 /// Assumption:
 /// Depends on total number of consumers per channel, a reasonable value
@@ -30,15 +46,25 @@ const CONSUMER_PURGE_INTERVAL: time::Duration = time::Duration::from_secs(10);
 /// After consumer is canceled, all on-the-fly messages should be received within `5` seconds
 const CONSUMER_EXPIRY_PERIOD: time::Duration = time::Duration::from_secs(5);
 
+/// Maximum deliveries buffered for a paused consumer of manual acknowledgement,
+/// further deliveries are rejected to be requeued.
+const MAX_PAUSED_DELIVERIES: usize = 1024;
+
 /// Resource for handling consumer messages.
 struct ConsumerResource {
     /// FIFO buffer for a delivery = `deliver + content`.
     fifo: VecDeque<ConsumerMessage>,
     /// tx channel to forward a delivery to a consumer task.
     /// dispatcher task holds the tx half, and the consumer task holds the rx half.
-    tx: Option<mpsc::UnboundedSender<ConsumerMessage>>,
+    tx: Option<ConsumerTx>,
     /// expiry time of fifo buffer
     expiration: Option<time::Instant>,
+    /// deliveries are buffered instead of forwarded while consumer is paused
+    paused: bool,
+    /// deliveries are buffered until the full bounded channel of consumer has capacity
+    waiting_capacity: bool,
+    /// deliveries are acknowledged automatically by server
+    no_ack: bool,
 }
 
 impl ConsumerResource {
@@ -47,22 +73,99 @@ impl ConsumerResource {
             fifo: VecDeque::new(),
             tx: None,
             expiration: Some(time::Instant::now() + CONSUMER_EXPIRY_PERIOD),
+            paused: false,
+            waiting_capacity: false,
+            no_ack: false,
         }
     }
 
-    fn register_tx(
-        &mut self,
-        tx: mpsc::UnboundedSender<ConsumerMessage>,
-    ) -> Option<mpsc::UnboundedSender<ConsumerMessage>> {
+    fn register_tx(&mut self, tx: ConsumerTx) -> Option<ConsumerTx> {
         // once consumer's tx half is registered, clear the expiry timer
         self.expiration.take();
+        self.waiting_capacity = false;
         self.tx.replace(tx)
     }
 
-    fn get_tx(&self) -> Option<&mpsc::UnboundedSender<ConsumerMessage>> {
+    fn get_tx(&self) -> Option<&ConsumerTx> {
         self.tx.as_ref()
     }
 
+    /// Returns tx half to forward a delivery to, `None` if deliveries should be buffered.
+    fn get_forward_tx(&self) -> Option<&ConsumerTx> {
+        if self.paused {
+            None
+        } else {
+            self.get_tx()
+        }
+    }
+
+    /// Returns `true` if a new delivery is not buffered but rejected to be requeued.
+    ///
+    /// A paused consumer buffers at most `MAX_PAUSED_DELIVERIES`, and a bounded channel
+    /// holds at most its capacity, so its deliveries are not buffered once it is full.
+    /// Deliveries acknowledged automatically can not be requeued, so they are always buffered.
+    fn is_buffer_full(&self) -> bool {
+        if self.no_ack {
+            false
+        } else if self.paused {
+            self.fifo.len() >= MAX_PAUSED_DELIVERIES
+        } else {
+            match self.tx {
+                Some(ConsumerTx::Bounded(ref tx)) => self.waiting_capacity || tx.capacity() == 0,
+                _ => false,
+            }
+        }
+    }
+
+    /// Forward buffered deliveries if consumer is registered and not paused.
+    ///
+    /// Never waits for the consumer. If its bounded channel is full, the remaining deliveries
+    /// stay buffered, and dispatcher is told to forward them once the consumer takes a message.
+    fn forward_buffered(
+        &mut self,
+        consumer_tag: &str,
+        mgmt_tx: &mpsc::UnboundedSender<DispatcherManagementCommand>,
+    ) {
+        if self.waiting_capacity || self.get_forward_tx().is_none() {
+            return;
+        }
+        let tx = self.tx.as_ref().unwrap();
+        while let Some(msg) = self.fifo.pop_front() {
+            #[cfg(feature = "traces")]
+            trace!(
+                "consumer {} total buffered messages: {}",
+                consumer_tag,
+                self.fifo.len() + 1
+            );
+            match tx.try_send(msg) {
+                Ok(()) => {}
+                Err(TrySendError::Full(msg)) => {
+                    self.fifo.push_front(msg);
+                    if let ConsumerTx::Bounded(tx) = tx {
+                        self.waiting_capacity = true;
+                        let tx = tx.clone();
+                        let mgmt_tx = mgmt_tx.clone();
+                        let consumer_tag = consumer_tag.to_owned();
+                        tokio::spawn(async move {
+                            // dispatcher is the only sender, so the capacity is left for it,
+                            // consumer is gone if the channel is closed
+                            drop(tx.reserve().await);
+                            let cmd = ForwardBufferedDeliveries { consumer_tag };
+                            mgmt_tx
+                                .send(DispatcherManagementCommand::ForwardBufferedDeliveries(cmd))
+                                .ok();
+                        });
+                    }
+                    return;
+                }
+                Err(TrySendError::Closed(_)) => {
+                    #[cfg(feature = "traces")]
+                    error!("failed to forward message to consumer {}", consumer_tag);
+                }
+            }
+        }
+    }
+
     fn get_expiration(&self) -> Option<&time::Instant> {
         self.expiration.as_ref()
     }
@@ -70,9 +173,134 @@ impl ConsumerResource {
     fn push_message(&mut self, message: ConsumerMessage) {
         self.fifo.push_back(message);
     }
+}
+
+/// Allocate the buffer to assemble content body of a message.
+///
+/// The buffer is allocated for the body size declared in content header up front, so it is
+/// not reallocated as content body frames arrive, each of which is at most `frame_max` bytes.
+/// The declared size is not trusted beyond `prealloc_limit`, see [`extend_content_buffer`].
+fn new_content_buffer(body_size: usize, prealloc_limit: usize) -> Vec<u8> {
+    let capacity = body_size.min(prealloc_limit);
+    #[cfg(feature = "body_pool")]
+    return super::body_pool::BODY_POOL.take(capacity);
+    #[cfg(not(feature = "body_pool"))]
+    Vec::with_capacity(capacity)
+}
+
+/// Append a content body frame to the buffer, `remaining` is the number of bytes
+/// still expected by the content header, including the frame.
+///
+/// Beyond its pre-allocation, the buffer at least doubles each time it grows, but never
+/// beyond the declared body size, so it is reallocated only a few times for a large body,
+/// while a bogus body size cannot allocate more than twice the bytes received.
+fn extend_content_buffer(content: &mut Vec<u8>, body: &[u8], remaining: usize) {
+    let len = content.len();
+    if content.capacity() - len < body.len() {
+        let capacity = (content.capacity() * 2)
+            .max(len + body.len())
+            .min(len + remaining.max(body.len()));
+        content.reserve_exact(capacity - len);
+    }
+    content.extend_from_slice(body);
+}
 
-    fn pop_message(&mut self) -> Option<ConsumerMessage> {
-        self.fifo.pop_front()
+/// Buffer to assemble a delivery from `deliver`, content header and content body frames.
+///
+/// A delivery is taken out of the buffer as soon as it is complete, and an incomplete
+/// delivery is discarded once the next one starts or an unexpected frame is received,
+/// so a failure while assembling one delivery never leaks into the following one.
+struct DeliveryBuffer {
+    message: Option<ConsumerMessage>,
+    /// max bytes allocated up front for a content body
+    prealloc_limit: usize,
+}
+
+impl Default for DeliveryBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_BODY_PREALLOC_LIMIT)
+    }
+}
+
+impl DeliveryBuffer {
+    fn new(prealloc_limit: usize) -> Self {
+        Self {
+            message: None,
+            prealloc_limit,
+        }
+    }
+
+    /// Start assembling a new delivery.
+    ///
+    /// Returns the incomplete delivery which is discarded, if any.
+    fn start(&mut self, deliver: Deliver) -> Option<ConsumerMessage> {
+        self.message.replace(ConsumerMessage {
+            deliver: Some(deliver),
+            basic_properties: None,
+            content: None,
+            remaining: 0,
+            buffered: None,
+        })
+    }
+
+    /// Returns the delivery if it is complete, i.e. its content body is empty.
+    ///
+    /// Returns error with the reason if the frame is unexpected, the incomplete delivery
+    /// is discarded.
+    fn header(
+        &mut self,
+        header: ContentHeader,
+    ) -> std::result::Result<Option<ConsumerMessage>, &'static str> {
+        let message = match self.message.as_mut() {
+            Some(message) if message.basic_properties.is_none() => message,
+            _ => {
+                self.message = None;
+                return Err("content header is not preceded by deliver");
+            }
+        };
+        let body_size: usize = header.common.body_size.try_into().unwrap();
+        message.basic_properties = Some(header.basic_properties);
+        message.remaining = body_size;
+        if body_size == 0 {
+            // do not wait for content body frame if content body size is zero
+            message.content = Some(Vec::new());
+            Ok(self.message.take())
+        } else {
+            message.content = Some(new_content_buffer(body_size, self.prealloc_limit));
+            Ok(None)
+        }
+    }
+
+    /// Returns the delivery once its content body is complete.
+    ///
+    /// Returns error with the reason if the frame is unexpected, the incomplete delivery
+    /// is discarded.
+    fn body(
+        &mut self,
+        body: ContentBody,
+    ) -> std::result::Result<Option<ConsumerMessage>, &'static str> {
+        let message = match self.message.as_mut() {
+            Some(message) if message.content.is_some() => message,
+            _ => {
+                self.message = None;
+                return Err("content body is not preceded by content header");
+            }
+        };
+        if body.inner.len() > message.remaining {
+            self.message = None;
+            return Err("content body exceeds the size in content header");
+        }
+        extend_content_buffer(
+            message.content.as_mut().unwrap(),
+            &body.inner,
+            message.remaining,
+        );
+        message.remaining -= body.inner.len();
+        if message.remaining == 0 {
+            Ok(self.message.take())
+        } else {
+            Ok(None)
+        }
     }
 }
 
@@ -80,7 +308,6 @@ enum State {
     Initial,
     Deliver,
     GetOk,
-    GetEmpty,
     Return,
 }
 
@@ -95,10 +322,170 @@ pub(crate) struct ChannelDispatcher {
     dispatcher_mgmt_rx: mpsc::UnboundedReceiver<DispatcherManagementCommand>,
     consumer_resources: HashMap<String, ConsumerResource>,
     get_content_responder: Option<mpsc::UnboundedSender<IncomingMessage>>,
-    responders: HashMap<&'static MethodHeader, oneshot::Sender<IncomingMessage>>,
+    /// acknowledgement mode of the pending `get`
+    get_no_ack: bool,
+    /// acknowledgement mode of the consumer confirmed by the pending `consume-ok`
+    consume_no_ack: bool,
+    /// expiry time of removed consumers whose deliveries are acknowledged automatically,
+    /// their on-the-fly deliveries are orphan but must not be rejected
+    removed_no_ack: HashMap<String, time::Instant>,
+    responders: HashMap<&'static MethodHeader, Responder>,
+    /// number of late responses to discard by method, their requests have timed out
+    late_responses: HashMap<&'static MethodHeader, usize>,
     callback: Option<Box<dyn ChannelCallback + Send + 'static>>,
+    confirm_callbacks: ConfirmCallbacks,
+    confirm_listener: ConfirmListener,
     state: State,
 }
+
+/// Responder of a synchronous request waiting for the response from server.
+struct Responder {
+    tx: oneshot::Sender<Result<IncomingMessage>>,
+    /// `None` if waiting without timeout
+    deadline: Option<time::Instant>,
+}
+
+/// Callbacks of published messages waiting for confirm, by publish sequence number.
+#[derive(Default)]
+struct ConfirmCallbacks {
+    callbacks: BTreeMap<u64, ConfirmCallback>,
+    /// routing of mandatory messages waiting for confirm, by publish sequence number
+    routings: BTreeMap<u64, PublishRouting>,
+    /// returned messages waiting for their confirm, by publish sequence number
+    returned: HashMap<u64, ReturnedMessage>,
+}
+
+impl ConfirmCallbacks {
+    fn insert(
+        &mut self,
+        delivery_tag: u64,
+        callback: Option<ConfirmCallback>,
+        routing: Option<PublishRouting>,
+    ) {
+        if let Some(callback) = callback {
+            self.callbacks.insert(delivery_tag, callback);
+        }
+        if let Some(routing) = routing {
+            self.routings.insert(delivery_tag, routing);
+        }
+    }
+
+    /// Keep a returned message for the callback of the message it belongs to.
+    ///
+    /// A return is not correlated with the following confirm, because a confirm with
+    /// `multiple` confirms messages returned before it, and several messages may be returned
+    /// in a row. Server sends returns in publish order, and the return of a message before
+    /// its confirm, so the message is the first mandatory one with the same routing, among
+    /// the messages waiting for confirm and published after the previously returned one.
+    ///
+    /// Returns `true` if the message is kept.
+    fn returned(&mut self, message: &ReturnedMessage) -> bool {
+        let delivery_tag = match self
+            .routings
+            .iter()
+            .find(|(_, routing)| routing.matches(message))
+        {
+            Some((delivery_tag, _)) => *delivery_tag,
+            None => return false,
+        };
+        // messages published before are either routed or returned already
+        self.routings = self.routings.split_off(&(delivery_tag + 1));
+        if self.callbacks.contains_key(&delivery_tag) {
+            self.returned.insert(delivery_tag, message.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Invoke callbacks of the confirmed messages, `nack` is `None` if server acks them.
+    ///
+    /// If `multiple`, all messages up to and including `delivery_tag` are confirmed.
+    /// Acked messages which are returned before are passed to their callbacks.
+    ///
+    /// Returns the number of invoked callbacks.
+    fn confirm(&mut self, delivery_tag: u64, multiple: bool, nack: Option<&Nack>) -> usize {
+        let confirmed = if multiple {
+            let pending = self.callbacks.split_off(&(delivery_tag + 1));
+            std::mem::replace(&mut self.callbacks, pending)
+        } else {
+            self.callbacks
+                .remove_entry(&delivery_tag)
+                .into_iter()
+                .collect()
+        };
+        if multiple {
+            self.routings = self.routings.split_off(&(delivery_tag + 1));
+        } else {
+            self.routings.remove(&delivery_tag);
+        }
+        let count = confirmed.len();
+        for (tag, callback) in confirmed {
+            let returned = self.returned.remove(&tag);
+            let outcome = match nack {
+                None => Ok(returned),
+                Some(nack) => {
+                    let mut message_nack = Nack::new(tag);
+                    message_nack.set_requeue(nack.requeue());
+                    Err(message_nack)
+                }
+            };
+            callback(outcome);
+        }
+        count
+    }
+}
+/// Tracks confirmed delivery tags to expand confirms with the `multiple` flag for
+/// a `ConfirmStream`, without knowing which messages have been published.
+#[derive(Default)]
+struct ConfirmListener {
+    tx: Option<mpsc::UnboundedSender<Confirm>>,
+    /// all messages up to and including it are confirmed
+    confirmed_up_to: u64,
+    /// messages confirmed individually after `confirmed_up_to`
+    confirmed: BTreeSet<u64>,
+}
+
+impl ConfirmListener {
+    fn register(&mut self, tx: mpsc::UnboundedSender<Confirm>) {
+        self.tx.replace(tx);
+    }
+
+    /// Forward confirms of the messages which are not confirmed yet to the listener.
+    ///
+    /// If `multiple`, all messages up to and including `delivery_tag` are confirmed.
+    ///
+    /// Returns the number of confirmed messages.
+    fn confirm(&mut self, delivery_tag: u64, multiple: bool, ack: bool) -> usize {
+        let delivery_tags: Vec<u64> = if multiple {
+            let tags = (self.confirmed_up_to + 1..=delivery_tag)
+                .filter(|tag| !self.confirmed.contains(tag))
+                .collect();
+            self.confirmed_up_to = self.confirmed_up_to.max(delivery_tag);
+            self.confirmed = self.confirmed.split_off(&(delivery_tag + 1));
+            tags
+        } else if delivery_tag > self.confirmed_up_to && self.confirmed.insert(delivery_tag) {
+            vec![delivery_tag]
+        } else {
+            vec![]
+        };
+        // compact contiguous individual confirms
+        while self.confirmed.remove(&(self.confirmed_up_to + 1)) {
+            self.confirmed_up_to += 1;
+        }
+
+        if let Some(ref tx) = self.tx {
+            for &delivery_tag in &delivery_tags {
+                if tx.send(Confirm { delivery_tag, ack }).is_err() {
+                    // stream is dropped
+                    self.tx.take();
+                    break;
+                }
+            }
+        }
+        delivery_tags.len()
+    }
+}
 /////////////////////////////////////////////////////////////////////////////
 impl ChannelDispatcher {
     pub(crate) fn new(
@@ -112,8 +499,14 @@ impl ChannelDispatcher {
             dispatcher_mgmt_rx,
             consumer_resources: HashMap::new(),
             get_content_responder: None,
+            get_no_ack: false,
+            consume_no_ack: false,
+            removed_no_ack: HashMap::new(),
             responders: HashMap::new(),
+            late_responses: HashMap::new(),
             callback: None,
+            confirm_callbacks: ConfirmCallbacks::default(),
+            confirm_listener: ConfirmListener::default(),
             state: State::Initial,
         }
     }
@@ -153,41 +546,174 @@ impl ChannelDispatcher {
                 key, self.channel
             );
         }
+        let now = time::Instant::now();
+        self.removed_no_ack
+            .retain(|_, expiration| *expiration >= now);
+    }
+    /// Allow publishing as many messages as `confirmed`, if the channel has a publish window.
+    fn release_publish_window(&self, confirmed: usize) {
+        if let Some(window) = self.channel.shared.publish_window.lock().unwrap().as_ref() {
+            window.add_permits(confirmed);
+        }
+    }
+
+    /// Earliest deadline of the responders waiting with timeout.
+    fn next_responder_deadline(&self) -> Option<time::Instant> {
+        self.responders.values().filter_map(|r| r.deadline).min()
+    }
+
+    /// Resolve the responders which wait longer than the rpc timeout with error, so that
+    /// a response which never arrives does not leave the request pending forever.
+    ///
+    /// Responses are matched to requests by method only, so the late response of an expired
+    /// request is discarded when it arrives, instead of being taken as the response of
+    /// a later request of the same method.
+    fn expire_responders(&mut self) {
+        let now = time::Instant::now();
+        let expired: Vec<&'static MethodHeader> = self
+            .responders
+            .iter()
+            .filter(|(_, r)| matches!(r.deadline, Some(deadline) if deadline <= now))
+            .map(|(method_header, _)| *method_header)
+            .collect();
+        for method_header in expired {
+            #[cfg(feature = "traces")]
+            warn!(
+                "no response of class {} method {} within rpc timeout on channel {}",
+                method_header.class_id(),
+                method_header.method_id(),
+                self.channel
+            );
+            let responder = self.responders.remove(method_header).unwrap();
+            let _ = responder.tx.send(Err(Error::RpcTimeout(format!(
+                "no response of class {} method {} on channel {}",
+                method_header.class_id(),
+                method_header.method_id(),
+                self.channel
+            ))));
+            *self.late_responses.entry(method_header).or_default() += 1;
+        }
+    }
+
+    /// Whether the response is the late one of an expired request, which is discarded.
+    fn is_late_response(&mut self, method_header: &'static MethodHeader) -> bool {
+        match self.late_responses.get_mut(method_header) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    self.late_responses.remove(method_header);
+                }
+                true
+            }
+            None => false,
+        }
     }
     /// Remove the consumer resource.
     ///
     /// Becuase the tx channel will drop, the consumer task will also exit.
     fn remove_consumer_resource(&mut self, consumer_tag: &String) -> Option<ConsumerResource> {
-        self.consumer_resources.remove(consumer_tag)
+        self.channel
+            .connection
+            .deregister_consumer(self.channel.channel_id(), consumer_tag);
+        self.channel
+            .shared
+            .prefetch
+            .lock()
+            .unwrap()
+            .remove_consumer(consumer_tag);
+        let resource = self.consumer_resources.remove(consumer_tag);
+        if matches!(resource, Some(ref resource) if resource.no_ack) {
+            self.removed_no_ack.insert(
+                consumer_tag.clone(),
+                time::Instant::now() + CONSUMER_EXPIRY_PERIOD,
+            );
+        }
+        resource
     }
 
-    async fn forward_deliver(&mut self, consumer_message: ConsumerMessage) {
+    async fn forward_deliver(&mut self, mut consumer_message: ConsumerMessage) {
         let consumer_tag = consumer_message
             .deliver
             .as_ref()
             .unwrap()
             .consumer_tag()
             .clone();
-        let consumer = self.get_or_new_consumer_resource(&consumer_tag);
-        match consumer.get_tx() {
-            Some(consumer_tx) => {
-                if (consumer_tx.send(consumer_message)).is_err() {
+        // consumer resource is created once the consumer tag is known from `consume-ok`,
+        // or expected by `consume` with `no_wait`, so never buffer for an unknown tag
+        match self.consumer_resources.get(&consumer_tag) {
+            Some(consumer) if !consumer.is_buffer_full() => {}
+            Some(_) => {
+                let delivery_tag = consumer_message.deliver.as_ref().unwrap().delivery_tag();
+                #[cfg(feature = "traces")]
+                warn!(
+                    "requeue delivery {} of consumer {} on channel {}, buffer is full",
+                    delivery_tag, consumer_tag, self.channel
+                );
+                if let Err(_err) = self.channel.reject_requeue(delivery_tag).await {
                     #[cfg(feature = "traces")]
                     error!(
-                        "failed to dispatch message to consumer {} on channel {}",
-                        consumer_tag, self.channel
+                        "failed to requeue delivery {} on channel {}, cause: {}",
+                        delivery_tag, self.channel, _err
                     );
                 }
+                return;
             }
             None => {
-                #[cfg(feature = "traces")]
-                debug!("can't find consumer {}, message is buffered", consumer_tag);
-                consumer.push_message(consumer_message);
-                // try to yield for expected consumer registration command,
-                // it might reduceas buffering
-                yield_now().await;
+                let no_ack = self.removed_no_ack.contains_key(&consumer_tag);
+                self.handle_orphan_delivery(consumer_message, no_ack).await;
+                return;
             }
         };
+        // counted until the consumer takes the message, dispatching never waits for the limit
+        let bytes = consumer_message.content.as_ref().map_or(0, Vec::len);
+        consumer_message.buffered = Some(self.channel.connection.count_buffer(bytes));
+        let consumer = self.consumer_resources.get_mut(&consumer_tag).unwrap();
+        if !consumer.no_ack {
+            let delivery_tag = consumer_message.deliver.as_ref().unwrap().delivery_tag();
+            self.channel.shared.track_unacked(delivery_tag.into());
+        }
+        #[cfg(feature = "traces")]
+        if consumer.get_forward_tx().is_none() {
+            debug!(
+                "consumer {} is not registered or paused, message is buffered",
+                consumer_tag
+            );
+        }
+        // registration, resume or free capacity forwards the buffered messages in order
+        consumer.push_message(consumer_message);
+        consumer.forward_buffered(&consumer_tag, &self.channel.shared.dispatcher_mgmt_tx);
+    }
+
+    /// Hand a delivery which no consumer takes to the callback.
+    ///
+    /// Unless acknowledged automatically, the delivery is tracked as unacked, and requeued
+    /// if no callback is registered, so that it never holds the prefetch window.
+    async fn handle_orphan_delivery(&mut self, consumer_message: ConsumerMessage, no_ack: bool) {
+        let deliver = consumer_message.deliver.unwrap();
+        #[cfg(feature = "traces")]
+        warn!(
+            "orphan delivery for consumer {} on channel {}, delivery tag: {}",
+            deliver.consumer_tag(),
+            self.channel,
+            deliver.delivery_tag()
+        );
+        if !no_ack {
+            self.channel
+                .shared
+                .track_unacked(deliver.delivery_tag().into());
+        }
+        match self.callback {
+            Some(ref mut cb) => {
+                cb.orphan_delivery(
+                    &self.channel,
+                    deliver,
+                    consumer_message.basic_properties.unwrap(),
+                    consumer_message.content.unwrap(),
+                )
+                .await
+            }
+            None => self.channel.requeue_orphan(deliver.delivery_tag()).await,
+        }
     }
 
     async fn handle_return(
@@ -196,10 +722,12 @@ impl ChannelDispatcher {
         basic_properties: BasicProperties,
         content: Vec<u8>,
     ) {
+        let message = ReturnedMessage::new(ret, basic_properties, content);
+        // kept only if a publish awaits its outcome
+        let kept = self.confirm_callbacks.returned(&message);
         if let Some(ref mut cb) = self.callback {
-            cb.publish_return(&self.channel, ret, basic_properties, content)
-                .await;
-        } else {
+            cb.publish_returned(&self.channel, message).await;
+        } else if !kept {
             #[cfg(feature = "traces")]
             error!("callback not registered on channel {}", self.channel);
         }
@@ -208,12 +736,8 @@ impl ChannelDispatcher {
     pub(in crate::api) async fn spawn(mut self) {
         tokio::spawn(async move {
             // aggregation buffer for `deliver + content` messages to a consumer
-            let mut message_buffer = ConsumerMessage {
-                deliver: None,
-                basic_properties: None,
-                content: None,
-                remaining: 0,
-            };
+            let prealloc_limit = self.channel.connection.body_prealloc_limit();
+            let mut delivery_buffer = DeliveryBuffer::new(prealloc_limit);
             // buffer for `return + content` messages due to publish failure.
             let mut return_buffer = ReturnMessage {
                 ret: None,
@@ -232,8 +756,11 @@ impl ChannelDispatcher {
 
             let mut purge_timer = time::interval(CONSUMER_PURGE_INTERVAL);
             purge_timer.tick().await;
+            // `close` request from server, reported by `Channel::closed`
+            let mut close_reason = None;
             // main loop of dispatcher
             loop {
+                let responder_deadline = self.next_responder_deadline();
                 tokio::select! {
                     biased;
 
@@ -252,18 +779,10 @@ impl ChannelDispatcher {
                             DispatcherManagementCommand::RegisterContentConsumer(cmd) => {
                                 #[cfg(feature="traces")]
                                 info!("register consumer {}", cmd.consumer_tag);
+                                let mgmt_tx = self.channel.shared.dispatcher_mgmt_tx.clone();
                                 let consumer = self.get_or_new_consumer_resource(&cmd.consumer_tag);
                                 consumer.register_tx(cmd.consumer_tx);
-                                // forward buffered messages
-                                while !consumer.fifo.is_empty() {
-                                    #[cfg(feature="traces")]
-                                    trace!("consumer {} total buffered messages: {}", cmd.consumer_tag, consumer.fifo.len());
-                                    let msg = consumer.pop_message().unwrap();
-                                    if let Err(_err) = consumer.get_tx().unwrap().send(msg) {
-                                        #[cfg(feature="traces")]
-                                        error!("failed to forward message to consumer {}", cmd.consumer_tag);
-                                    }
-                                }
+                                consumer.forward_buffered(&cmd.consumer_tag, &mgmt_tx);
                             },
                             DispatcherManagementCommand::DeregisterContentConsumer(cmd) => {
                                 if let Some(consumer) = self.remove_consumer_resource(&cmd.consumer_tag) {
@@ -273,11 +792,87 @@ impl ChannelDispatcher {
                                     );
                                 }
                             },
+                            DispatcherManagementCommand::CancelContentConsumer(cmd) => {
+                                // skip if consumer is already deregistered, e.g. by `basic_cancel`
+                                if self.remove_consumer_resource(&cmd.consumer_tag).is_some() && self.channel.is_open() {
+                                    #[cfg(feature="traces")]
+                                    info!("cancel dropped consumer {} on channel {}", cmd.consumer_tag, self.channel);
+                                    // consumer tag is given by server, it never exceeds limit of short string
+                                    let cancel = Cancel::new(cmd.consumer_tag.try_into().unwrap(), true);
+                                    if let Err(_err) = self.channel.shared.outgoing_tx
+                                        .send((self.channel.channel_id(), cancel.into_frame()))
+                                        .await
+                                    {
+                                        #[cfg(feature="traces")]
+                                        error!("failed to cancel dropped consumer on channel {}, cause: {}", self.channel, _err);
+                                    }
+                                }
+                            }
+                            DispatcherManagementCommand::ListContentConsumers(cmd) => {
+                                cmd.tx.send(self.consumer_resources.keys().cloned().collect()).ok();
+                            }
+                            DispatcherManagementCommand::PauseContentConsumer(cmd) => {
+                                match self.consumer_resources.get_mut(&cmd.consumer_tag) {
+                                    Some(consumer) => {
+                                        #[cfg(feature="traces")]
+                                        info!("{} consumer {} on channel {}",
+                                            if cmd.paused { "pause" } else { "resume" }, cmd.consumer_tag, self.channel
+                                        );
+                                        consumer.paused = cmd.paused;
+                                        // resumed consumer receives the buffered messages first
+                                        consumer.forward_buffered(&cmd.consumer_tag, &self.channel.shared.dispatcher_mgmt_tx);
+                                    }
+                                    None => {
+                                        #[cfg(feature="traces")]
+                                        warn!("can't find consumer {} to pause or resume on channel {}", cmd.consumer_tag, self.channel);
+                                    }
+                                }
+                            }
+                            DispatcherManagementCommand::SwapContentConsumer(cmd) => {
+                                let replaced = match self.consumer_resources.get_mut(&cmd.consumer_tag) {
+                                    Some(consumer) if consumer.get_tx().is_some() => {
+                                        // messages not taken from the replaced tx half go first to keep the order
+                                        if let Some(mut leftover_rx) = cmd.leftover_rx {
+                                            let mut leftovers = VecDeque::new();
+                                            while let Some(msg) = leftover_rx.try_recv() {
+                                                leftovers.push_back(msg);
+                                            }
+                                            leftovers.append(&mut consumer.fifo);
+                                            consumer.fifo = leftovers;
+                                        }
+                                        let replaced = consumer.register_tx(cmd.consumer_tx);
+                                        consumer.forward_buffered(&cmd.consumer_tag, &self.channel.shared.dispatcher_mgmt_tx);
+                                        replaced
+                                    }
+                                    _ => {
+                                        #[cfg(feature="traces")]
+                                        warn!("can't find registered consumer {} on channel {}", cmd.consumer_tag, self.channel);
+                                        None
+                                    }
+                                };
+                                cmd.replaced.send(replaced).ok();
+                            }
+                            DispatcherManagementCommand::ForwardBufferedDeliveries(cmd) => {
+                                // consumer may have been deregistered meanwhile
+                                if let Some(consumer) = self.consumer_resources.get_mut(&cmd.consumer_tag) {
+                                    consumer.waiting_capacity = false;
+                                    consumer.forward_buffered(&cmd.consumer_tag, &self.channel.shared.dispatcher_mgmt_tx);
+                                }
+                            }
+                            DispatcherManagementCommand::ExpectContentConsumer(cmd) => {
+                                // buffer deliveries until consumer is registered or resource expires
+                                self.get_or_new_consumer_resource(&cmd.consumer_tag).no_ack = cmd.no_ack;
+                            }
+                            DispatcherManagementCommand::ExpectConsumeOk(cmd) => {
+                                self.consume_no_ack = cmd.no_ack;
+                            }
                             DispatcherManagementCommand::RegisterGetContentResponder(cmd) => {
                                 self.get_content_responder.replace(cmd.tx);
+                                self.get_no_ack = cmd.no_ack;
                             }
                             DispatcherManagementCommand::RegisterOneshotResponder(cmd) => {
-                                self.responders.insert(cmd.method_header, cmd.responder);
+                                let deadline = self.channel.rpc_timeout().map(|timeout| time::Instant::now() + timeout);
+                                self.responders.insert(cmd.method_header, Responder { tx: cmd.responder, deadline });
                                 cmd.acker.send(()).unwrap();
                             }
                             DispatcherManagementCommand::RegisterChannelCallback(cmd) => {
@@ -285,6 +880,12 @@ impl ChannelDispatcher {
                                 #[cfg(feature="traces")]
                                 debug!("callback registered on channel {}", self.channel);
                             }
+                            DispatcherManagementCommand::RegisterConfirmCallback(cmd) => {
+                                self.confirm_callbacks.insert(cmd.delivery_tag, cmd.callback, cmd.routing);
+                            }
+                            DispatcherManagementCommand::RegisterConfirmListener(cmd) => {
+                                self.confirm_listener.register(cmd.tx);
+                            }
                         }
                     }
                     // only one tx half held by connection handler, once the tx half dorp
@@ -300,6 +901,19 @@ impl ChannelDispatcher {
                             },
                             Some(v) => v,
                         };
+                        self.channel.shared.frames_received.fetch_add(1, Ordering::Relaxed);
+                        // content must follow `return` immediately, reset if another method arrives
+                        // instead, otherwise the dispatcher waits for content which never comes
+                        if matches!(self.state, State::Return)
+                            && !matches!(frame, Frame::ContentHeader(_) | Frame::ContentBody(_))
+                        {
+                            self.state = State::Initial;
+                            let _ret = return_buffer.ret.take();
+                            return_buffer.basic_properties = None;
+                            return_buffer.content = None;
+                            #[cfg(feature="traces")]
+                            warn!("discard return without content on channel {}, {}, followed by {}", self.channel, _ret.unwrap(), frame);
+                        }
                         // handle frames
                         match frame {
                             ////////////////////////////////////////////////
@@ -307,17 +921,43 @@ impl ChannelDispatcher {
                             // channel.close-ok response from server
                             Frame::CloseChannelOk(method_header, close_channel_ok) => {
                                 self.channel.set_is_open(false);
+                                self.channel.connection.deregister_channel(self.channel.channel_id());
 
                                 match self.responders.remove(method_header) {
-                                    Some(responder) => responder.send(close_channel_ok.into_frame()).unwrap(),
-                                    None => unreachable!("responder must be registered for {} on channel {}",
-                                    close_channel_ok.into_frame(), self.channel),
+                                    Some(responder) => responder.tx.send(Ok(close_channel_ok.into_frame())).unwrap(),
+                                    None => {
+                                        // responder is expired after rpc timeout
+                                        #[cfg(feature="traces")]
+                                        warn!("discard late response {} on channel {}", close_channel_ok.into_frame(), self.channel);
+                                    }
                                 }
                                 // exit
                                 break;
                             }
                             // channel.close request from server
                             Frame::CloseChannel(_, close_channel) => {
+                                #[cfg(feature="traces")]
+                                trace_server_close(
+                                    close_channel.reply_code(),
+                                    close_channel.reply_text(),
+                                    close_channel.class_id(),
+                                    close_channel.method_id(),
+                                    format_args!("channel {}", self.channel),
+                                );
+                                close_reason = Some(close_channel.clone());
+                                self.channel.connection.deregister_channel(self.channel.channel_id());
+                                // a pending `queue.declare` fails with the reason of closing,
+                                // e.g. `PRECONDITION_FAILED` due to inequivalent arguments
+                                if let Some(responder) = self.responders.remove(DeclareQueueOk::header()) {
+                                    let _ = responder.tx.send(Ok(close_channel.clone().into_frame()));
+                                }
+                                // any other pending request fails with the reason too, it may be
+                                // caused by an earlier request sent with `no_wait`
+                                for (_, responder) in self.responders.drain() {
+                                    let _ = responder
+                                        .tx
+                                        .send(Err(close_channel.clone().into()));
+                                }
                                 // callback
                                 if let Some(ref mut cb) = self.callback {
                                     if let Err(err) = cb.close(&self.channel, close_channel).await {
@@ -342,18 +982,35 @@ impl ChannelDispatcher {
                             ////////////////////////////////////////////////
                             // the method frames followed by content frames
                             Frame::GetEmpty(_, get_empty) => {
-                                self.state = State::GetEmpty;
+                                // no content follows
+                                self.state = State::Initial;
 
-                                self.get_content_responder.take()
-                                .expect("get responder must be registered")
-                                .send(get_empty.into_frame()).unwrap();
+                                match self.get_content_responder.take() {
+                                    Some(responder) => {
+                                        let _ = responder.send(get_empty.into_frame());
+                                    }
+                                    None => {
+                                        #[cfg(feature="traces")]
+                                        warn!("drop unexpected get-empty on channel {}", self.channel);
+                                    }
+                                }
                             }
                             Frame::GetOk(_, get_ok) => {
-                                self.state = State::GetOk;
-
-                                self.get_content_responder.as_ref()
-                                .expect("get responder must be registered")
-                                .send(get_ok.into_frame()).unwrap();
+                                match self.get_content_responder.as_ref() {
+                                    Some(responder) => {
+                                        self.state = State::GetOk;
+                                        if !self.get_no_ack {
+                                            self.channel.shared.track_unacked(get_ok.delivery_tag().into());
+                                        }
+                                        let _ = responder.send(get_ok.into_frame());
+                                    }
+                                    None => {
+                                        // its content is dropped as unexpected content
+                                        self.state = State::Initial;
+                                        #[cfg(feature="traces")]
+                                        warn!("drop unexpected get-ok on channel {}", self.channel);
+                                    }
+                                }
                             }
                             Frame::Return(_, ret) => {
                                 self.state = State::Return;
@@ -361,36 +1018,39 @@ impl ChannelDispatcher {
                             }
                             Frame::Deliver(_, deliver) => {
                                 self.state = State::Deliver;
-                                message_buffer.deliver = Some(deliver);
+                                if let Some(_incomplete) = delivery_buffer.start(deliver) {
+                                    #[cfg(feature="traces")]
+                                    warn!("discard incomplete delivery on channel {}, {}", self.channel, _incomplete.deliver.unwrap());
+                                }
                             }
                             Frame::ContentHeader(header) => {
                                 match self.state {
                                     State::Deliver => {
-                                        message_buffer.remaining = header.common.body_size.try_into().unwrap();
-                                        // do not wait for content body frame if content body size is zero
-                                        if message_buffer.remaining == 0 {
-                                            let consumer_message  = ConsumerMessage {
-                                                deliver: message_buffer.deliver.take(),
-                                                basic_properties: Some(header.basic_properties),
-                                                content: Some(Vec::new()),
-                                                remaining: 0,
-                                            };
-                                            self.forward_deliver(consumer_message).await;
-                                        } else {
-                                            message_buffer.basic_properties = Some(header.basic_properties);
-                                            message_buffer.content = Some(Vec::new());
+                                        match delivery_buffer.header(*header) {
+                                            Ok(Some(consumer_message)) => {
+                                                self.state = State::Initial;
+                                                self.forward_deliver(consumer_message).await;
+                                            }
+                                            Ok(None) => {}
+                                            Err(_reason) => {
+                                                self.state = State::Initial;
+                                                #[cfg(feature="traces")]
+                                                warn!("discard incomplete delivery on channel {}, {}", self.channel, _reason);
+                                            }
                                         }
                                     },
                                     State::GetOk => {
                                         getok_content_buffer.remaining = header.common.body_size.try_into().unwrap();
 
+                                        // state is `GetOk` only if the responder is registered
                                         let responder = self.get_content_responder.as_ref().expect("get responder must be registered");
-                                        responder.send(header.into_frame()).unwrap();
+                                        let _ = responder.send(header.into_frame());
                                         // do not wait for content body frame if content body size is zero
                                         if getok_content_buffer.remaining  == 0 {
-                                            responder.send(ContentBody::new(Vec::new()).into_frame()).unwrap();
+                                            let _ = responder.send(ContentBody::new(Vec::new()).into_frame());
+                                            self.state = State::Initial;
                                         } else {
-                                            getok_content_buffer.content = Some(Vec::new());
+                                            getok_content_buffer.content = Some(new_content_buffer(getok_content_buffer.remaining, prealloc_limit));
                                         }
                                     },
                                     State::Return => {
@@ -398,60 +1058,89 @@ impl ChannelDispatcher {
 
                                         if return_buffer.remaining == 0 {
                                             // do not wait for content body frame if content body size is zero
+                                            self.state = State::Initial;
                                             self.handle_return(return_buffer.ret.take().unwrap(), header.basic_properties, Vec::new()).await;
                                         } else {
                                             return_buffer.basic_properties = Some(header.basic_properties);
-                                            return_buffer.content = Some(Vec::new());
+                                            return_buffer.content = Some(new_content_buffer(return_buffer.remaining, prealloc_limit));
                                         }
                                     },
-                                    _  => unreachable!("invalid dispatcher state"),
+                                    State::Initial => {
+                                        #[cfg(feature="traces")]
+                                        warn!("drop unexpected content header on channel {}", self.channel);
+                                    }
                                 }
                             }
                             Frame::ContentBody(body) => {
                                 match self.state {
                                     State::Deliver => {
-                                        let mut content_buffer = message_buffer.content.take().unwrap();
-                                        content_buffer.extend_from_slice(&body.inner);
-                                        message_buffer.content.replace(content_buffer);
-                                        // calculate remaining size of content body
-                                        message_buffer.remaining = message_buffer.remaining.checked_sub(body.inner.len()).expect("should never overflow");
-
-                                        if message_buffer.remaining == 0 {
-                                            let consumer_message  = ConsumerMessage {
-                                                deliver: message_buffer.deliver.take(),
-                                                basic_properties: message_buffer.basic_properties.take(),
-                                                content: message_buffer.content.take(),
-                                                remaining: message_buffer.remaining,
-                                            };
-                                            self.forward_deliver(consumer_message).await;
+                                        match delivery_buffer.body(body) {
+                                            Ok(Some(consumer_message)) => {
+                                                self.state = State::Initial;
+                                                self.forward_deliver(consumer_message).await;
+                                            }
+                                            Ok(None) => {}
+                                            Err(_reason) => {
+                                                self.state = State::Initial;
+                                                #[cfg(feature="traces")]
+                                                warn!("discard incomplete delivery on channel {}, {}", self.channel, _reason);
+                                            }
                                         }
                                     }
                                     State::GetOk => {
-                                        let mut content_buffer = getok_content_buffer.content.take().unwrap();
-                                        content_buffer.extend_from_slice(&body.inner);
+                                        let remaining = getok_content_buffer.remaining.checked_sub(body.inner.len());
+                                        let (mut content_buffer, remaining) = match (getok_content_buffer.content.take(), remaining) {
+                                            (Some(content_buffer), Some(remaining)) => (content_buffer, remaining),
+                                            _ => {
+                                                // body without header or exceeding its size, fail the pending `get`
+                                                self.state = State::Initial;
+                                                self.get_content_responder.take();
+                                                #[cfg(feature="traces")]
+                                                error!("discard get content out of sync on channel {}", self.channel);
+                                                continue;
+                                            }
+                                        };
+                                        extend_content_buffer(&mut content_buffer, &body.inner, getok_content_buffer.remaining);
                                         getok_content_buffer.content.replace(content_buffer);
-                                        getok_content_buffer.remaining = getok_content_buffer.remaining.checked_sub(body.inner.len()).expect("should never overflow");
+                                        getok_content_buffer.remaining = remaining;
                                         if getok_content_buffer.remaining == 0 {
+                                            self.state = State::Initial;
                                             let content = getok_content_buffer.content.take().unwrap();
-                                            self.get_content_responder.take()
-                                            .expect("get responder must be registered")
-                                            .send(ContentBody::new(content).into_frame()).unwrap();
+                                            if let Some(responder) = self.get_content_responder.take() {
+                                                let _ = responder.send(ContentBody::new(content).into_frame());
+                                            }
                                         }
                                     },
                                     State::Return => {
-                                        let mut content_buffer = return_buffer.content.take().unwrap();
-                                        content_buffer.extend_from_slice(&body.inner);
+                                        let remaining = return_buffer.remaining.checked_sub(body.inner.len());
+                                        let (mut content_buffer, remaining) = match (return_buffer.content.take(), remaining) {
+                                            (Some(content_buffer), Some(remaining)) => (content_buffer, remaining),
+                                            _ => {
+                                                // body without header or exceeding its size
+                                                self.state = State::Initial;
+                                                return_buffer.ret.take();
+                                                return_buffer.basic_properties.take();
+                                                #[cfg(feature="traces")]
+                                                error!("discard returned message out of sync on channel {}", self.channel);
+                                                continue;
+                                            }
+                                        };
+                                        extend_content_buffer(&mut content_buffer, &body.inner, return_buffer.remaining);
                                         return_buffer.content.replace(content_buffer);
-                                        return_buffer.remaining = return_buffer.remaining.checked_sub(body.inner.len()).expect("should never overflow");
+                                        return_buffer.remaining = remaining;
 
                                         if return_buffer.remaining == 0 {
+                                            self.state = State::Initial;
                                             self.handle_return(
                                                 return_buffer.ret.take().unwrap(),
                                                 return_buffer.basic_properties.take().unwrap(),
                                                 return_buffer.content.take().unwrap()).await;
                                         }
                                     },
-                                    State::Initial | State::GetEmpty  => unreachable!("invalid dispatcher state on channel {}", self.channel),
+                                    State::Initial => {
+                                        #[cfg(feature="traces")]
+                                        warn!("drop unexpected content body on channel {}", self.channel);
+                                    }
                                 }
                             }
                             ////////////////////////////////////////////////
@@ -475,22 +1164,35 @@ impl ChannelDispatcher {
                             | Frame::TxSelectOk(method_header, _)
                             | Frame::TxCommitOk(method_header, _)
                             | Frame::TxRollbackOk(method_header, _) => {
+                                // consumer tag is known from `consume-ok`, buffer deliveries
+                                // until consumer is registered
+                                if let Frame::ConsumeOk(_, consume_ok) = &frame {
+                                    let consumer_tag = consume_ok.consumer_tag.to_string();
+                                    let no_ack = self.consume_no_ack;
+                                    self.get_or_new_consumer_resource(&consumer_tag).no_ack = no_ack;
+                                }
                                 // handle synchronous response
+                                if self.is_late_response(method_header) {
+                                    #[cfg(feature="traces")]
+                                    warn!("discard late response {} on channel {}", frame, self.channel);
+                                    continue;
+                                }
                                 match self.responders.remove(method_header)
                                 {
                                     Some(responder) => {
-                                        if let Err(response) = responder.send(frame) {
+                                        if let Err(Ok(_response)) = responder.tx.send(Ok(frame)) {
                                             #[cfg(feature="traces")]
                                             error!(
                                                 "failed to dispatch {} to channel {}",
-                                                response, self.channel
+                                                _response, self.channel
                                             );
                                         }
                                     }
-                                    None => unreachable!(
-                                        "responder must be registered for {} on channel {}",
-                                        frame, self.channel
-                                    ),
+                                    None => {
+                                        // responder is expired after rpc timeout
+                                        #[cfg(feature="traces")]
+                                        warn!("discard late response {} on channel {}", frame, self.channel);
+                                    }
                                 }
                             }
                             //////////////////////////////////////////////////////////
@@ -543,20 +1245,29 @@ impl ChannelDispatcher {
                             }
                             // in confirmed mode
                             Frame::Ack(_, ack) => {
+                                self.channel.shared.settle_unconfirmed(ack.delivery_tag(), ack.mutiple());
+                                let newly_confirmed = self.confirm_listener.confirm(ack.delivery_tag(), ack.mutiple(), true);
+                                self.release_publish_window(newly_confirmed);
+                                let confirmed = self.confirm_callbacks.confirm(ack.delivery_tag(), ack.mutiple(), None);
                                 if let Some(ref mut cb) = self.callback {
                                     cb.publish_ack(&self.channel, ack).await;
-                                } else {
+                                } else if confirmed == 0 {
                                     #[cfg(feature="traces")]
                                     error!("callback not registered on channel {}", self.channel);
                                 }
                             }
                             Frame::Nack(_, nack) => {
+                                self.channel.shared.settle_unconfirmed(nack.delivery_tag(), nack.multiple());
+                                let newly_confirmed = self.confirm_listener.confirm(nack.delivery_tag(), nack.multiple(), false);
+                                self.release_publish_window(newly_confirmed);
+                                let confirmed = self.confirm_callbacks.confirm(nack.delivery_tag(), nack.multiple(), Some(&nack));
                                 if let Some(ref mut cb) = self.callback {
                                     cb.publish_nack(&self.channel, nack).await;
-                                } else {
+                                } else if confirmed == 0 {
                                     #[cfg(feature="traces")]
                                     error!("callback not registered on channel {}", self.channel);
-                                }                            }
+                                }
+                            }
                             _ => unreachable!("dispatcher of channel {} receive unexpected frame {}", self.channel, frame),
                         }
                     }
@@ -564,12 +1275,27 @@ impl ChannelDispatcher {
                     _ = purge_timer.tick() => {
                         self.purge_consumer_resource();
                     }
+                    // resolve responders waiting longer than rpc timeout
+                    _ = time::sleep_until(responder_deadline.unwrap_or_else(time::Instant::now)), if responder_deadline.is_some() => {
+                        self.expire_responders();
+                    }
                     else => {
                         break;
                     }
                 }
             }
             self.channel.set_is_open(false);
+            // pending requests fail with the reason if server has closed the connection
+            if let Some(close) = self.channel.connection.close_reason() {
+                for (_, responder) in self.responders.drain() {
+                    let _ = responder.tx.send(Err(close.clone().into()));
+                }
+            }
+            // publishers waiting for confirms fail with channel closed
+            if let Some(window) = self.channel.shared.publish_window.lock().unwrap().as_ref() {
+                window.close();
+            }
+            self.channel.shared.closed.send_replace(Some(close_reason));
 
             #[cfg(feature = "traces")]
             info!("exit dispatcher of channel {}", self.channel);
@@ -579,20 +1305,454 @@ impl ChannelDispatcher {
 
 #[cfg(test)]
 mod tests {
-    use tokio::time;
+    use async_trait::async_trait;
+    use tokio::{sync::mpsc, time};
 
     use crate::{
+        api::{
+            callbacks::ChannelCallback,
+            channel::{BasicGetArguments, DeregisterContentConsumer, DispatcherManagementCommand},
+            error::Error,
+            Result,
+        },
         channel::{
-            BasicCancelArguments, BasicConsumeArguments, BasicPublishArguments, QueueBindArguments,
-            QueueDeclareArguments,
+            BasicCancelArguments, BasicConsumeArguments, BasicPublishArguments, Channel,
+            QueueBindArguments, QueueDeclareArguments, ReturnedMessage,
         },
         connection::{Connection, OpenConnectionArguments},
         consumer::DefaultConsumer,
-        test_utils::setup_logging,
-        BasicProperties,
+        frame::{ContentBody, ContentHeader, ContentHeaderCommon, Frame, GetOk},
+        test_utils::{
+            cancel_ok, channel_reply, consume_ok, declare_queue_ok, deliver, mock_broker,
+            setup_logging, short_strs, ReturnRecorder,
+        },
+        Ack, BasicProperties, Cancel, CloseChannel, Deliver, Nack, Return,
+    };
+
+    use super::{
+        ConfirmCallbacks, ConfirmListener, ConsumerResource, DeliveryBuffer,
+        CONSUMER_EXPIRY_PERIOD, CONSUMER_PURGE_INTERVAL, DEFAULT_BODY_PREALLOC_LIMIT,
+        MAX_PAUSED_DELIVERIES,
+    };
+    use crate::api::channel::{
+        consumer_channel, ConfirmCallback, ConsumerMessage, DeliveryChannel, PublishRouting,
     };
 
-    use super::{CONSUMER_EXPIRY_PERIOD, CONSUMER_PURGE_INTERVAL};
+    fn consumer_message() -> ConsumerMessage {
+        ConsumerMessage {
+            deliver: None,
+            basic_properties: Some(BasicProperties::default()),
+            content: Some(Vec::new()),
+            remaining: 0,
+            buffered: None,
+        }
+    }
+
+    fn content_header(body_size: u64, message_id: &str) -> ContentHeader {
+        ContentHeader::new(
+            ContentHeaderCommon {
+                class: 60,
+                weight: 0,
+                body_size,
+            },
+            BasicProperties::default()
+                .with_message_id(message_id)
+                .finish(),
+        )
+    }
+
+    #[test]
+    fn test_delivery_buffer_reset_after_failure() {
+        let mut buffer = DeliveryBuffer::default();
+
+        // delivery is interrupted in the middle of its content body
+        assert!(buffer.start(deliver("ctag", 1)).is_none());
+        assert!(buffer
+            .header(content_header(10, "first"))
+            .unwrap()
+            .is_none());
+        assert!(buffer
+            .body(ContentBody::new(b"abcd".to_vec()))
+            .unwrap()
+            .is_none());
+        let incomplete = buffer.start(deliver("ctag", 2)).unwrap();
+        assert_eq!(1, u64::from(incomplete.deliver.unwrap().delivery_tag()));
+
+        // the following delivery is assembled without leftovers
+        assert!(buffer
+            .header(content_header(6, "second"))
+            .unwrap()
+            .is_none());
+        assert!(buffer
+            .body(ContentBody::new(b"xyz".to_vec()))
+            .unwrap()
+            .is_none());
+        let message = buffer
+            .body(ContentBody::new(b"uvw".to_vec()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(2, u64::from(message.deliver.unwrap().delivery_tag()));
+        assert_eq!(
+            Some(&"second".to_string()),
+            message.basic_properties.unwrap().message_id()
+        );
+        assert_eq!(b"xyzuvw".to_vec(), message.content.unwrap());
+
+        // content body beyond the announced size discards the delivery
+        buffer.start(deliver("ctag", 3));
+        buffer.header(content_header(2, "third")).unwrap();
+        assert!(buffer.body(ContentBody::new(b"too long".to_vec())).is_err());
+        assert!(buffer.body(ContentBody::new(b"ab".to_vec())).is_err());
+        assert!(buffer.header(content_header(0, "orphan")).is_err());
+
+        // empty content body completes on header
+        assert!(buffer.start(deliver("ctag", 4)).is_none());
+        let message = buffer.header(content_header(0, "fourth")).unwrap().unwrap();
+        assert_eq!(4, u64::from(message.deliver.unwrap().delivery_tag()));
+        assert_eq!(Vec::<u8>::new(), message.content.unwrap());
+        assert!(buffer.start(deliver("ctag", 5)).is_none());
+    }
+
+    #[test]
+    fn test_content_buffer_preallocated() {
+        let mut buffer = DeliveryBuffer::default();
+        buffer.start(deliver("ctag", 1));
+        assert!(buffer
+            .header(content_header(10, "first"))
+            .unwrap()
+            .is_none());
+        let capacity = buffer
+            .message
+            .as_ref()
+            .unwrap()
+            .content
+            .as_ref()
+            .unwrap()
+            .capacity();
+        #[cfg(not(feature = "body_pool"))]
+        assert_eq!(10, capacity);
+        // a pooled buffer may be larger
+        assert!(capacity >= 10);
+
+        assert!(buffer
+            .body(ContentBody::new(b"abcd".to_vec()))
+            .unwrap()
+            .is_none());
+        let message = buffer
+            .body(ContentBody::new(b"efghij".to_vec()))
+            .unwrap()
+            .unwrap();
+        let content = message.content.unwrap();
+        assert_eq!(b"abcdefghij".to_vec(), content);
+        // not reallocated while assembling
+        assert_eq!(capacity, content.capacity());
+    }
+
+    #[test]
+    fn test_content_buffer_prealloc_capped() {
+        let capacity = |buffer: &DeliveryBuffer| {
+            let message = buffer.message.as_ref().unwrap();
+            message.content.as_ref().unwrap().capacity()
+        };
+
+        // allocation is capped for a header claiming a huge body
+        let mut buffer = DeliveryBuffer::new(16);
+        buffer.start(deliver("ctag", 1));
+        assert!(buffer
+            .header(content_header(1 << 40, "huge"))
+            .unwrap()
+            .is_none());
+        #[cfg(not(feature = "body_pool"))]
+        assert_eq!(16, capacity(&buffer));
+        // a pooled buffer may be larger, but never near the claimed size
+        assert!(capacity(&buffer) <= DEFAULT_BODY_PREALLOC_LIMIT);
+        for _ in 0..2 {
+            assert!(buffer
+                .body(ContentBody::new(vec![0; 10]))
+                .unwrap()
+                .is_none());
+        }
+        #[cfg(not(feature = "body_pool"))]
+        assert_eq!(32, capacity(&buffer));
+
+        // growth beyond the cap stops at the declared size
+        buffer.start(deliver("ctag", 2));
+        buffer.header(content_header(40, "large")).unwrap();
+        for _ in 0..3 {
+            assert!(buffer
+                .body(ContentBody::new(vec![0; 10]))
+                .unwrap()
+                .is_none());
+        }
+        let message = buffer.body(ContentBody::new(vec![0; 10])).unwrap().unwrap();
+        let content = message.content.unwrap();
+        assert_eq!(40, content.len());
+        #[cfg(not(feature = "body_pool"))]
+        assert_eq!(40, content.capacity());
+    }
+
+    #[test]
+    fn test_confirm_callbacks() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let callback = |tag: u64| -> Option<ConfirmCallback> {
+            let tx = tx.clone();
+            Some(Box::new(
+                move |outcome: std::result::Result<Option<ReturnedMessage>, Nack>| {
+                    let outcome = outcome
+                        .map(|returned| returned.map(|message| message.routing_key))
+                        .map_err(|nack| nack.delivery_tag());
+                    tx.send((tag, outcome)).unwrap();
+                },
+            ))
+        };
+        let routing = |routing_key: &str, message_id: Option<&str>| {
+            Some(PublishRouting {
+                exchange: "amq.topic".to_string(),
+                routing_key: routing_key.to_string(),
+                message_id: message_id.map(str::to_string),
+            })
+        };
+        let mut callbacks = ConfirmCallbacks::default();
+        for tag in 1..=4 {
+            callbacks.insert(tag, callback(tag), None);
+        }
+        callbacks.insert(5, callback(5), routing("nowhere", None));
+        callbacks.insert(6, callback(6), routing("orders", None));
+        callbacks.insert(7, callback(7), routing("nowhere", Some("order-7")));
+
+        // single ack
+        assert_eq!(1, callbacks.confirm(2, false, None));
+        assert_eq!((2, Ok(None)), rx.try_recv().unwrap());
+        // multiple nack confirms the remaining messages up to the tag
+        let mut nack = Nack::new(4);
+        nack.set_multiple(true);
+        assert_eq!(3, callbacks.confirm(4, true, Some(&nack)));
+        assert_eq!((1, Err(1)), rx.try_recv().unwrap());
+        assert_eq!((3, Err(3)), rx.try_recv().unwrap());
+        assert_eq!((4, Err(4)), rx.try_recv().unwrap());
+        // already confirmed
+        assert_eq!(0, callbacks.confirm(3, false, None));
+
+        // returned message belongs to the first message of its routing in publish order,
+        // not to the tag of the following confirm
+        let returned = |routing_key: &str, message_id: Option<&str>| {
+            let ret = Return::new(
+                312,
+                "NO_ROUTE".try_into().unwrap(),
+                "amq.topic".try_into().unwrap(),
+                routing_key.try_into().unwrap(),
+            );
+            let mut basic_properties = BasicProperties::default();
+            if let Some(message_id) = message_id {
+                basic_properties.with_message_id(message_id);
+            }
+            ReturnedMessage::new(ret, basic_properties, vec![])
+        };
+        assert!(!callbacks.returned(&returned("elsewhere", None)));
+        assert!(callbacks.returned(&returned("nowhere", None)));
+        assert!(!callbacks.returned(&returned("nowhere", Some("order-9"))));
+        assert!(callbacks.returned(&returned("nowhere", Some("order-7"))));
+        assert!(callbacks.routings.is_empty());
+        assert_eq!(3, callbacks.confirm(7, true, None));
+        assert_eq!((5, Ok(Some("nowhere".to_string()))), rx.try_recv().unwrap());
+        assert_eq!((6, Ok(None)), rx.try_recv().unwrap());
+        assert_eq!((7, Ok(Some("nowhere".to_string()))), rx.try_recv().unwrap());
+
+        // mandatory message without callback takes its return, confirm drops its routing
+        callbacks.insert(8, None, routing("nowhere", None));
+        callbacks.insert(9, callback(9), routing("nowhere", None));
+        callbacks.insert(10, None, routing("nowhere", None));
+        assert!(!callbacks.returned(&returned("nowhere", None)));
+        assert!(callbacks.returned(&returned("nowhere", None)));
+        assert_eq!(1, callbacks.confirm(9, true, None));
+        assert_eq!((9, Ok(Some("nowhere".to_string()))), rx.try_recv().unwrap());
+        assert_eq!(0, callbacks.confirm(10, false, None));
+        assert!(callbacks.routings.is_empty());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_confirm_listener() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut listener = ConfirmListener::default();
+        // confirms before a stream is registered are only tracked
+        assert_eq!(1, listener.confirm(1, false, true));
+        listener.register(tx);
+
+        assert_eq!(1, listener.confirm(3, false, true));
+        assert_eq!(1, listener.confirm(5, false, false));
+        // multiple expands to the messages not confirmed yet
+        assert_eq!(2, listener.confirm(4, true, true));
+        assert_eq!(2, listener.confirm(7, true, false));
+        // already confirmed
+        assert_eq!(0, listener.confirm(6, false, true));
+        assert_eq!(0, listener.confirm(7, true, true));
+
+        let mut received = Vec::new();
+        while let Ok(confirm) = rx.try_recv() {
+            received.push((confirm.delivery_tag, confirm.ack));
+        }
+        assert_eq!(
+            vec![
+                (3, true),
+                (5, false),
+                (2, true),
+                (4, true),
+                (6, false),
+                (7, false)
+            ],
+            received
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bounded_consumer_channel_backpressure() {
+        let (tx, mut rx) = consumer_channel(DeliveryChannel::Bounded(2));
+        let (mgmt_tx, mut mgmt_rx) = mpsc::unbounded_channel();
+        let mut resource = ConsumerResource::new();
+        resource.register_tx(tx);
+
+        // channel fills up, further deliveries are rejected by dispatcher
+        assert!(!resource.is_buffer_full());
+        for _ in 0..2 {
+            resource.push_message(consumer_message());
+            resource.forward_buffered("bounded-tester", &mgmt_tx);
+        }
+        assert!(resource.fifo.is_empty());
+        assert!(resource.is_buffer_full());
+        resource.no_ack = true;
+        assert!(!resource.is_buffer_full());
+        resource.no_ack = false;
+
+        // buffered deliveries, e.g. of a resumed consumer, are held without waiting for it
+        for _ in 0..3 {
+            resource.push_message(consumer_message());
+        }
+        resource.forward_buffered("bounded-tester", &mgmt_tx);
+        assert_eq!(3, resource.fifo.len());
+        assert!(resource.waiting_capacity);
+        assert!(resource.is_buffer_full());
+        let notified = time::timeout(time::Duration::from_millis(100), mgmt_rx.recv()).await;
+        assert!(notified.is_err());
+
+        // dispatcher is told to forward the held deliveries once consumer takes one
+        rx.recv().await.unwrap();
+        let consumer_tag = match mgmt_rx.recv().await {
+            Some(DispatcherManagementCommand::ForwardBufferedDeliveries(cmd)) => cmd.consumer_tag,
+            _ => panic!("expect command to forward buffered deliveries"),
+        };
+        assert_eq!("bounded-tester", consumer_tag);
+        resource.waiting_capacity = false;
+        resource.forward_buffered("bounded-tester", &mgmt_tx);
+        assert_eq!(2, resource.fifo.len());
+        assert!(resource.waiting_capacity);
+
+        // all deliveries are received in the end
+        for _ in 0..2 {
+            rx.recv().await.unwrap();
+            assert!(mgmt_rx.recv().await.is_some());
+            resource.waiting_capacity = false;
+            resource.forward_buffered("bounded-tester", &mgmt_tx);
+        }
+        assert!(resource.fifo.is_empty());
+        for _ in 0..2 {
+            assert!(rx.recv().await.is_some());
+        }
+    }
+
+    #[test]
+    fn test_unbounded_consumer_channel_never_blocks() {
+        let (tx, _rx) = consumer_channel(DeliveryChannel::Unbounded);
+        for _ in 0..1000 {
+            assert!(tx.try_send(consumer_message()).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paused_consumer_resource() {
+        let (tx, mut rx) = consumer_channel(DeliveryChannel::Unbounded);
+        let (mgmt_tx, _mgmt_rx) = mpsc::unbounded_channel();
+        let mut resource = ConsumerResource::new();
+        resource.register_tx(tx);
+        resource.paused = true;
+        assert!(resource.get_forward_tx().is_none());
+
+        for _ in 0..MAX_PAUSED_DELIVERIES {
+            assert!(!resource.is_buffer_full());
+            resource.push_message(consumer_message());
+        }
+        assert!(resource.is_buffer_full());
+        // still paused, nothing is forwarded
+        resource.forward_buffered("pause-tester", &mgmt_tx);
+        let received = time::timeout(time::Duration::from_millis(10), rx.recv()).await;
+        assert!(received.is_err());
+
+        resource.paused = false;
+        resource.forward_buffered("pause-tester", &mgmt_tx);
+        assert!(!resource.is_buffer_full());
+        assert!(resource.get_forward_tx().is_some());
+        for _ in 0..MAX_PAUSED_DELIVERIES {
+            assert!(rx.recv().await.is_some());
+        }
+        let received = time::timeout(time::Duration::from_millis(10), rx.recv()).await;
+        assert!(received.is_err());
+
+        // deliveries acknowledged automatically are always buffered
+        resource.paused = true;
+        resource.no_ack = true;
+        for _ in 0..=MAX_PAUSED_DELIVERIES {
+            resource.push_message(consumer_message());
+        }
+        assert!(!resource.is_buffer_full());
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_consumer() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_pause_and_resume_consumer")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let (queue_name, ..) = channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+        let args = BasicConsumeArguments::new(&queue_name, "pause-tester")
+            .auto_ack(true)
+            .finish();
+        let (consumer_tag, mut rx) = channel.basic_consume_rx(args).await.unwrap();
+
+        channel.pause_consumer(&consumer_tag).unwrap();
+        for i in 0..3u8 {
+            channel
+                .basic_publish(
+                    BasicProperties::default(),
+                    vec![i],
+                    BasicPublishArguments::new("", &queue_name),
+                )
+                .await
+                .unwrap();
+        }
+        // paused consumer receives nothing
+        let received = time::timeout(time::Duration::from_secs(1), rx.recv()).await;
+        assert!(received.is_err());
+
+        // buffered messages flow in order once resumed
+        channel.resume_consumer(&consumer_tag).unwrap();
+        for i in 0..3u8 {
+            let message = time::timeout(time::Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(Some(vec![i]), message.content);
+        }
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
 
     #[tokio::test]
     async fn test_purge_consumer_resource() {
@@ -660,4 +1820,392 @@ mod tests {
         // the consumer resource should be purged within `CONSUMER_PURGE_INTERVAL + CONSUMER_EXPIRY_PERIOD`
         time::sleep(CONSUMER_PURGE_INTERVAL + CONSUMER_EXPIRY_PERIOD).await;
     }
+
+    /// Forward consumer tag of orphan deliveries to test
+    struct OrphanDeliveryRecorder(mpsc::UnboundedSender<String>);
+
+    #[async_trait]
+    impl ChannelCallback for OrphanDeliveryRecorder {
+        async fn close(&mut self, _: &Channel, _: CloseChannel) -> Result<()> {
+            Ok(())
+        }
+        async fn cancel(&mut self, _: &Channel, _: Cancel) -> Result<()> {
+            Ok(())
+        }
+        async fn flow(&mut self, _: &Channel, active: bool) -> Result<bool> {
+            Ok(active)
+        }
+        async fn publish_ack(&mut self, _: &Channel, _: Ack) {}
+        async fn publish_nack(&mut self, _: &Channel, _: Nack) {}
+        async fn publish_return(&mut self, _: &Channel, _: Return, _: BasicProperties, _: Vec<u8>) {
+        }
+        async fn orphan_delivery(
+            &mut self,
+            _: &Channel,
+            deliver: Deliver,
+            _: BasicProperties,
+            _: Vec<u8>,
+        ) {
+            self.0.send(deliver.consumer_tag().clone()).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delivery_for_unknown_consumer() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_delivery_for_unknown_consumer")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let (orphan_tx, mut orphan_rx) = mpsc::unbounded_channel();
+        channel
+            .register_callback(OrphanDeliveryRecorder(orphan_tx))
+            .await
+            .unwrap();
+
+        let (queue_name, ..) = channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+        let args = BasicConsumeArguments::new(&queue_name, "orphan-tester")
+            .auto_ack(true)
+            .finish();
+        let (consumer_tag, _rx) = channel.basic_consume_rx(args).await.unwrap();
+
+        // consumer is unknown to dispatcher, but server still delivers to it
+        let deregistered = channel.shared.dispatcher_mgmt_tx.send(
+            DispatcherManagementCommand::DeregisterContentConsumer(DeregisterContentConsumer {
+                consumer_tag: consumer_tag.clone(),
+            }),
+        );
+        assert!(deregistered.is_ok());
+        channel
+            .basic_publish(
+                BasicProperties::default(),
+                b"orphan".to_vec(),
+                BasicPublishArguments::new("", &queue_name),
+            )
+            .await
+            .unwrap();
+
+        let orphan = time::timeout(time::Duration::from_secs(5), orphan_rx.recv())
+            .await
+            .unwrap();
+        assert_eq!(Some(consumer_tag), orphan);
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_buffered_before_consumer_registered() {
+        setup_logging();
+
+        const CONSUMERS: usize = 3;
+        const DELIVERIES: u64 = 1000;
+
+        // mock broker delivers right after `consume-ok`, before the consumer is registered
+        let (port, server) = mock_broker(0, |consumers, _, frame| match frame {
+            Frame::Consume(..) => {
+                *consumers += 1;
+                let consumer_tag = format!("ctag{}", consumers);
+                let mut responses = vec![consume_ok(&consumer_tag)];
+                for delivery_tag in 1..=DELIVERIES {
+                    responses.push(deliver(&consumer_tag, delivery_tag).into_frame());
+                    responses.push(content_header(0, "").into_frame());
+                }
+                responses
+            }
+            // dropped stream cancels its consumer without waiting for reply
+            Frame::Cancel(..) => vec![],
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        for consumer in 1..=CONSUMERS {
+            let args = BasicConsumeArguments::new("q", &format!("ctag{}", consumer))
+                .auto_ack(true)
+                .finish();
+            let mut stream = channel.basic_consume_stream(args).await.unwrap();
+
+            // buffered deliveries are forwarded first, then the later ones, all in order
+            for delivery_tag in 1..=DELIVERIES {
+                let message = stream
+                    .recv_timeout(time::Duration::from_secs(5))
+                    .await
+                    .unwrap();
+                let deliver = message.deliver.unwrap();
+                assert_eq!(stream.consumer_tag(), deliver.consumer_tag());
+                assert_eq!(delivery_tag, u64::from(deliver.delivery_tag()));
+            }
+        }
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rpc_timeout() {
+        setup_logging();
+
+        // mock broker never responds to `queue.declare` of "slow" in time, it responds late
+        // before the response of the next `queue.declare`, and records the declared queues
+        let (port, server) = mock_broker(
+            (vec![], false),
+            |(declared, pending_declare), _, frame| match frame {
+                Frame::DeclareQueue(_, declare) => {
+                    let bytes = amqp_serde::to_bytes(&declare).unwrap();
+                    let queue = short_strs(&bytes, 1).0.remove(0);
+                    declared.push(queue.clone());
+                    if queue == "slow" {
+                        *pending_declare = true;
+                        vec![]
+                    } else if *pending_declare {
+                        *pending_declare = false;
+                        vec![declare_queue_ok("slow"), declare_queue_ok(&queue)]
+                    } else {
+                        vec![declare_queue_ok(&queue)]
+                    }
+                }
+                other => channel_reply(other),
+            },
+        )
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        assert_eq!(None, channel.rpc_timeout());
+        channel.set_rpc_timeout(Some(time::Duration::from_millis(200)));
+
+        let start = time::Instant::now();
+        let result = channel
+            .queue_declare(QueueDeclareArguments::new("slow"))
+            .await;
+        assert!(matches!(result, Err(Error::RpcTimeout(_))));
+        assert!(start.elapsed() >= time::Duration::from_millis(200));
+
+        // the channel is kept open, the late response of "slow" is not taken as
+        // the response of "fast"
+        assert!(channel.is_open());
+        for _ in 0..2 {
+            let (queue, _, _) = channel
+                .queue_declare(QueueDeclareArguments::new("fast"))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!("fast", queue);
+        }
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        let (declared, _) = server.await.unwrap();
+        assert_eq!(vec!["slow", "fast", "fast"], declared);
+    }
+
+    #[tokio::test]
+    async fn test_orphan_delivery_requeued() {
+        setup_logging();
+
+        // mock server delivers to the cancelled consumer with `no_ack` and to an unknown
+        // consumer, it forwards the rejects
+        let (reject_tx, mut reject_rx) = mpsc::unbounded_channel();
+        let (port, server) = mock_broker((), move |_, _, frame| match frame {
+            Frame::Consume(..) => vec![consume_ok("auto")],
+            Frame::Cancel(..) => vec![
+                deliver("auto", 1).into_frame(),
+                content_header(0, "").into_frame(),
+                cancel_ok("auto"),
+                deliver("ghost", 2).into_frame(),
+                content_header(0, "").into_frame(),
+            ],
+            Frame::Reject(_, reject) => {
+                // delivery tag, then requeue bit
+                let bytes = amqp_serde::to_bytes(&reject).unwrap();
+                let delivery_tag = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+                reject_tx
+                    .send(format!("{} requeue={}", delivery_tag, bytes[8] != 0))
+                    .unwrap();
+                vec![]
+            }
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let args = BasicConsumeArguments::new("q", "auto")
+            .auto_ack(true)
+            .finish();
+        let (_, _rx) = channel.basic_consume_rx(args).await.unwrap();
+        channel
+            .basic_cancel(BasicCancelArguments::new("auto"))
+            .await
+            .unwrap();
+
+        // without callback, only the delivery to be acknowledged manually is requeued
+        let reject = time::timeout(time::Duration::from_secs(5), reject_rx.recv())
+            .await
+            .unwrap();
+        assert_eq!(Some("2 requeue=true".to_owned()), reject);
+
+        channel.clone().close().await.unwrap();
+        assert_eq!(0, channel.unacked_count());
+        connection.close().await.unwrap();
+        server.await.unwrap();
+        assert!(reject_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_return_without_content() {
+        setup_logging();
+
+        // mock server returns the published message, but the first return has no content
+        let ret = |reply_text: &str| {
+            Return::new(
+                312,
+                reply_text.try_into().unwrap(),
+                "".try_into().unwrap(),
+                "q".try_into().unwrap(),
+            )
+            .into_frame()
+        };
+        let (port, server) = mock_broker((), move |_, _, frame| match frame {
+            Frame::Publish(..) | Frame::ContentHeader(..) => vec![],
+            Frame::ContentBody(..) => vec![
+                ret("no content"),
+                Ack::new(1, false).into_frame(),
+                // stray content must not be taken as content of the first return
+                content_header(5, "").into_frame(),
+                ContentBody::new(b"stray".to_vec()).into_frame(),
+                ret("complete"),
+                content_header(5, "").into_frame(),
+                ContentBody::new(b"hello".to_vec()).into_frame(),
+            ],
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        channel.register_callback(ReturnRecorder(tx)).await.unwrap();
+        channel
+            .basic_publish(
+                BasicProperties::default(),
+                b"hello".to_vec(),
+                BasicPublishArguments::new("", "q").mandatory(true).finish(),
+            )
+            .await
+            .unwrap();
+
+        // dispatcher recovers from the return without content
+        let mut received = vec![];
+        for _ in 0..2 {
+            let event = time::timeout(time::Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            received.push(event);
+        }
+        assert_eq!(vec!["ack 1", "return complete hello"], received);
+
+        channel.close().await.unwrap();
+        assert!(rx.try_recv().is_err());
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher_survives_content_desync() {
+        setup_logging();
+
+        // mock server sends content frames out of sync before a complete delivery,
+        // and fails the first `get` by omitting the content header
+        let get_ok = || {
+            // delivery tag, redelivered, exchange, routing key, message count
+            let mut bytes = 1u64.to_be_bytes().to_vec();
+            bytes.extend_from_slice(&[0, 0, 1, b'q', 0, 0, 0, 0]);
+            amqp_serde::from_bytes::<GetOk>(&bytes)
+                .unwrap()
+                .into_frame()
+        };
+        let (port, server) = mock_broker(0, move |gets, _, frame| match frame {
+            Frame::Consume(..) => vec![
+                consume_ok("ctag"),
+                // content body without any method
+                ContentBody::new(b"stray".to_vec()).into_frame(),
+                // get-ok without pending `get`
+                get_ok(),
+                content_header(5, "").into_frame(),
+                ContentBody::new(b"stray".to_vec()).into_frame(),
+                // return without content header
+                Return::new(
+                    312,
+                    "NO_ROUTE".try_into().unwrap(),
+                    "".try_into().unwrap(),
+                    "q".try_into().unwrap(),
+                )
+                .into_frame(),
+                ContentBody::new(b"stray".to_vec()).into_frame(),
+                deliver("ctag", 1).into_frame(),
+                content_header(5, "").into_frame(),
+                ContentBody::new(b"hello".to_vec()).into_frame(),
+            ],
+            Frame::Get(..) => {
+                *gets += 1;
+                if *gets == 1 {
+                    vec![get_ok(), ContentBody::new(b"stray".to_vec()).into_frame()]
+                } else {
+                    vec![
+                        get_ok(),
+                        content_header(5, "").into_frame(),
+                        ContentBody::new(b"hello".to_vec()).into_frame(),
+                    ]
+                }
+            }
+            Frame::Cancel(..) => vec![cancel_ok("ctag")],
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let args = BasicConsumeArguments::new("q", "ctag")
+            .auto_ack(true)
+            .finish();
+        let (_, mut rx) = channel.basic_consume_rx(args).await.unwrap();
+        let timeout = time::Duration::from_secs(5);
+
+        // stray content is dropped, the following delivery is intact
+        let message = time::timeout(timeout, rx.recv()).await.unwrap().unwrap();
+        assert_eq!(b"hello".to_vec(), message.content.unwrap());
+
+        // the pending `get` fails, the channel keeps working
+        assert!(channel
+            .basic_get(BasicGetArguments::new("q"))
+            .await
+            .is_err());
+        let (_, _, content) = channel
+            .basic_get(BasicGetArguments::new("q"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(b"hello".to_vec(), content);
+        assert!(channel.is_open());
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
 }
@@ -1,11 +1,11 @@
-use std::borrow::ToOwned;
-use std::fmt::{Debug, Display, Formatter};
 use crate::{
     api::{error::Error, FieldTable},
     frame::{Bind, BindOk, Declare, DeclareOk, Delete, DeleteOk, Frame, Unbind, UnbindOk},
 };
+use std::borrow::ToOwned;
+use std::fmt::{Debug, Display, Formatter};
 
-use super::{Channel, Result};
+use super::{check_reserved_name, to_short_str, Channel, Result};
 
 #[cfg(feature = "compliance_assert")]
 use crate::api::compliance_asserts::assert_exchange_name;
@@ -33,18 +33,18 @@ pub enum ExchangeType {
     /// Recent history exchange
     RecentHistory,
     /// All other x-* exchange types, for example, those provided by plugins
-    Plugin(String)
+    Plugin(String),
 }
 
 const EXCHANGE_TYPE_FANOUT: &str = "fanout";
-const EXCHANGE_TYPE_TOPIC:  &str = "topic";
-const EXCHANGE_TYPE_DIRECT:  &str = "direct";
-const EXCHANGE_TYPE_HEADERS:  &str = "headers";
-const EXCHANGE_TYPE_CONSISTENT_HASHING:  &str = "x-consistent-hash";
-const EXCHANGE_TYPE_MODULUS_HASH:  &str = "x-modulus-hash";
-const EXCHANGE_TYPE_RANDOM:  &str = "x-random";
-const EXCHANGE_TYPE_JMS_TOPIC:  &str = "x-jms-topic";
-const EXCHANGE_TYPE_RECENT_HISTORY:  &str = "x-recent-history";
+const EXCHANGE_TYPE_TOPIC: &str = "topic";
+const EXCHANGE_TYPE_DIRECT: &str = "direct";
+const EXCHANGE_TYPE_HEADERS: &str = "headers";
+const EXCHANGE_TYPE_CONSISTENT_HASHING: &str = "x-consistent-hash";
+const EXCHANGE_TYPE_MODULUS_HASH: &str = "x-modulus-hash";
+const EXCHANGE_TYPE_RANDOM: &str = "x-random";
+const EXCHANGE_TYPE_JMS_TOPIC: &str = "x-jms-topic";
+const EXCHANGE_TYPE_RECENT_HISTORY: &str = "x-recent-history";
 
 impl From<&str> for ExchangeType {
     fn from(value: &str) -> Self {
@@ -58,7 +58,7 @@ impl From<&str> for ExchangeType {
             EXCHANGE_TYPE_RANDOM => ExchangeType::Random,
             EXCHANGE_TYPE_JMS_TOPIC => ExchangeType::JmsTopic,
             EXCHANGE_TYPE_RECENT_HISTORY => ExchangeType::RecentHistory,
-            other => ExchangeType::Plugin(other.to_owned())
+            other => ExchangeType::Plugin(other.to_owned()),
         }
     }
 }
@@ -81,7 +81,7 @@ impl From<ExchangeType> for String {
             ExchangeType::Random => EXCHANGE_TYPE_RANDOM.to_owned(),
             ExchangeType::JmsTopic => EXCHANGE_TYPE_JMS_TOPIC.to_owned(),
             ExchangeType::RecentHistory => EXCHANGE_TYPE_RECENT_HISTORY.to_owned(),
-            ExchangeType::Plugin(exchange_type) => exchange_type
+            ExchangeType::Plugin(exchange_type) => exchange_type,
         }
     }
 }
@@ -97,8 +97,8 @@ impl Display for ExchangeType {
             ExchangeType::ModulusHash => Display::fmt(&EXCHANGE_TYPE_MODULUS_HASH, f),
             ExchangeType::Random => Display::fmt(&EXCHANGE_TYPE_RANDOM, f),
             ExchangeType::JmsTopic => Display::fmt(&EXCHANGE_TYPE_JMS_TOPIC, f),
-            ExchangeType::RecentHistory => Display::fmt(&EXCHANGE_TYPE_RECENT_HISTORY,f),
-            ExchangeType::Plugin(exchange_type) => Display::fmt(&exchange_type, f)
+            ExchangeType::RecentHistory => Display::fmt(&EXCHANGE_TYPE_RECENT_HISTORY, f),
+            ExchangeType::Plugin(exchange_type) => Display::fmt(&exchange_type, f),
         }
     }
 }
@@ -109,7 +109,7 @@ impl Display for ExchangeType {
 /// ```
 /// # use amqprs::channel::{ExchangeDeclareArguments, ExchangeType};
 ///
-/// let x = ExchangeDeclareArguments::of_type("amq.direct", ExchangeType::Direct)
+/// let x = ExchangeDeclareArguments::of_type("orders", ExchangeType::Direct)
 ///     .durable(true)
 ///     .finish();
 /// ```
@@ -214,6 +214,22 @@ impl ExchangeDeclareArguments {
 
         self.clone()
     }
+
+    /// Check for arguments which server rejects.
+    ///
+    /// [`exchange_declare`] validates its arguments, call this to catch a configuration
+    /// mistake earlier, e.g. when loading the configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ReservedName`] if the exchange name starts with `amq.` and the declare
+    /// is not passive. Built-in exchanges, e.g. `amq.topic`, can be declared passively to
+    /// check that they exist.
+    ///
+    /// [`exchange_declare`]: struct.Channel.html#method.exchange_declare
+    pub fn validate(&self) -> Result<()> {
+        check_reserved_name("exchange name", &self.exchange, self.passive)
+    }
 }
 
 /// Arguments for [`exchange_delete`]
@@ -402,12 +418,16 @@ impl Channel {
     ///
     /// # Errors
     ///
+    /// Returns [`Error::ReservedName`] if the exchange name is reserved by server and the
+    /// declare is not passive, see [`ExchangeDeclareArguments::validate`].
+    ///
     /// Returns error if any failure in comunication with server.
     pub async fn exchange_declare(&self, args: ExchangeDeclareArguments) -> Result<()> {
+        args.validate()?;
         let mut declare = Declare::new(
             0,
-            args.exchange.try_into().unwrap(),
-            args.exchange_type.try_into().unwrap(),
+            to_short_str("exchange name", args.exchange)?,
+            to_short_str("exchange type", args.exchange_type)?,
             args.arguments,
         );
 
@@ -442,7 +462,7 @@ impl Channel {
     ///
     /// Returns error if any failure in comunication with server.
     pub async fn exchange_delete(&self, args: ExchangeDeleteArguments) -> Result<()> {
-        let mut delete = Delete::new(0, args.exchange.try_into().unwrap());
+        let mut delete = Delete::new(0, to_short_str("exchange name", args.exchange)?);
         delete.set_if_unused(args.if_unused);
         delete.set_no_wait(args.no_wait);
         if args.no_wait {
@@ -472,9 +492,9 @@ impl Channel {
     pub async fn exchange_bind(&self, args: ExchangeBindArguments) -> Result<()> {
         let bind = Bind::new(
             0,
-            args.destination.try_into().unwrap(),
-            args.source.try_into().unwrap(),
-            args.routing_key.try_into().unwrap(),
+            to_short_str("destination exchange name", args.destination)?,
+            to_short_str("source exchange name", args.source)?,
+            to_short_str("routing key", args.routing_key)?,
             args.no_wait,
             args.arguments,
         );
@@ -505,9 +525,9 @@ impl Channel {
     pub async fn exchange_unbind(&self, args: ExchangeUnbindArguments) -> Result<()> {
         let unbind = Unbind::new(
             0,
-            args.destination.try_into().unwrap(),
-            args.source.try_into().unwrap(),
-            args.routing_key.try_into().unwrap(),
+            to_short_str("destination exchange name", args.destination)?,
+            to_short_str("source exchange name", args.source)?,
+            to_short_str("routing key", args.routing_key)?,
             args.no_wait,
             args.arguments,
         );
@@ -535,11 +555,14 @@ impl Channel {
 #[cfg(test)]
 mod tests {
     use super::{
-        ExchangeBindArguments, ExchangeDeclareArguments, ExchangeDeleteArguments,
-        ExchangeUnbindArguments, ExchangeType,
+        ExchangeBindArguments, ExchangeDeclareArguments, ExchangeDeleteArguments, ExchangeType,
+        ExchangeUnbindArguments,
     };
     use crate::{
-        api::connection::{Connection, OpenConnectionArguments},
+        api::{
+            connection::{Connection, OpenConnectionArguments},
+            error::Error,
+        },
         callbacks::{DefaultChannelCallback, DefaultConnectionCallback},
         test_utils,
     };
@@ -550,34 +573,71 @@ mod tests {
         assert_eq!(ExchangeType::Topic.to_string(), "topic");
         assert_eq!(ExchangeType::Direct.to_string(), "direct");
         assert_eq!(ExchangeType::Headers.to_string(), "headers");
-        assert_eq!(ExchangeType::ConsistentHashing.to_string(), "x-consistent-hash");
+        assert_eq!(
+            ExchangeType::ConsistentHashing.to_string(),
+            "x-consistent-hash"
+        );
         assert_eq!(ExchangeType::Random.to_string(), "x-random");
         assert_eq!(ExchangeType::JmsTopic.to_string(), "x-jms-topic");
         assert_eq!(ExchangeType::RecentHistory.to_string(), "x-recent-history");
         assert_eq!(ExchangeType::ModulusHash.to_string(), "x-modulus-hash");
-        assert_eq!(ExchangeType::Plugin(String::from("x-custom-exchange-2")).to_string(), "x-custom-exchange-2");
+        assert_eq!(
+            ExchangeType::Plugin(String::from("x-custom-exchange-2")).to_string(),
+            "x-custom-exchange-2"
+        );
 
         assert_eq!(ExchangeType::from("fanout"), ExchangeType::Fanout);
         assert_eq!(ExchangeType::from("topic"), ExchangeType::Topic);
         assert_eq!(ExchangeType::from("direct"), ExchangeType::Direct);
         assert_eq!(ExchangeType::from("headers"), ExchangeType::Headers);
-        assert_eq!(ExchangeType::from("x-consistent-hash"), ExchangeType::ConsistentHashing);
+        assert_eq!(
+            ExchangeType::from("x-consistent-hash"),
+            ExchangeType::ConsistentHashing
+        );
         assert_eq!(ExchangeType::from("x-random"), ExchangeType::Random);
         assert_eq!(ExchangeType::from("x-jms-topic"), ExchangeType::JmsTopic);
-        assert_eq!(ExchangeType::from("x-modulus-hash"), ExchangeType::ModulusHash);
-        assert_eq!(ExchangeType::from("x-custom-exchange-2"), ExchangeType::Plugin(String::from("x-custom-exchange-2")));
+        assert_eq!(
+            ExchangeType::from("x-modulus-hash"),
+            ExchangeType::ModulusHash
+        );
+        assert_eq!(
+            ExchangeType::from("x-custom-exchange-2"),
+            ExchangeType::Plugin(String::from("x-custom-exchange-2"))
+        );
 
         assert_eq!(String::from(ExchangeType::Fanout), "fanout");
         assert_eq!(String::from(ExchangeType::Topic), "topic");
         assert_eq!(String::from(ExchangeType::Direct), "direct");
         assert_eq!(String::from(ExchangeType::Headers), "headers");
         assert_eq!(String::from(ExchangeType::ModulusHash), "x-modulus-hash");
-        assert_eq!(String::from(ExchangeType::ConsistentHashing), "x-consistent-hash");
-        assert_eq!(String::from(ExchangeType::RecentHistory), "x-recent-history");
+        assert_eq!(
+            String::from(ExchangeType::ConsistentHashing),
+            "x-consistent-hash"
+        );
+        assert_eq!(
+            String::from(ExchangeType::RecentHistory),
+            "x-recent-history"
+        );
         assert_eq!(String::from(ExchangeType::Random), "x-random");
         assert_eq!(String::from(ExchangeType::JmsTopic), "x-jms-topic");
-        assert_eq!(String::from(ExchangeType::Plugin(String::from("x-custom-exchange-3"))), "x-custom-exchange-3");
+        assert_eq!(
+            String::from(ExchangeType::Plugin(String::from("x-custom-exchange-3"))),
+            "x-custom-exchange-3"
+        );
+    }
 
+    #[test]
+    fn test_exchange_declare_arguments_validation() {
+        let args = ExchangeDeclareArguments::of_type("amq.foo", ExchangeType::Topic);
+        assert!(matches!(args.validate(), Err(Error::ReservedName(_))));
+
+        let args = ExchangeDeclareArguments::of_type("amq.topic", ExchangeType::Topic)
+            .passive(true)
+            .finish();
+        assert!(args.validate().is_ok());
+
+        let args = ExchangeDeclareArguments::of_type("orders.amq.topic", ExchangeType::Topic);
+        assert!(args.validate().is_ok());
     }
 
     #[tokio::test]
@@ -601,6 +661,12 @@ mod tests {
             .finish();
         channel.exchange_declare(args).await.unwrap();
 
+        // rejected locally, channel is still open
+        let args = ExchangeDeclareArguments::of_type("amq.foo", ExchangeType::Topic);
+        let result = channel.exchange_declare(args).await;
+        assert!(matches!(result, Err(Error::ReservedName(_))));
+        assert!(channel.is_open());
+
         channel.close().await.unwrap();
         connection.close().await.unwrap();
     }
@@ -11,6 +11,13 @@
 //! Almost all methods of [`Channel`] accepts arguments, this module also contains
 //! all argument types for each method.
 //!
+//! # No-wait requests
+//! Arguments of the declare, bind, unbind, purge and delete methods have a `no_wait` setter.
+//! With `no_wait` set, the request is sent without waiting for server's ok response, so the
+//! method returns as soon as the frame is queued. Server reports a failed request by closing
+//! the channel asynchronously, see [`closed`] for the reason, after which any pending or
+//! later request on the channel returns error.
+//!
 //! # Example
 //! See [`crate`] documentation for quick start.
 //! See details in documentation of each method.
@@ -18,25 +25,38 @@
 //! [`APIs`]: struct.Channel.html#implementations
 //! [`Channel`]: struct.Channel.html
 //! [`close`]: struct.Channel.html#method.close
+//! [`closed`]: struct.Channel.html#method.closed
 //!
 use std::{
+    collections::{BTreeSet, HashSet},
     fmt,
+    future::Future,
+    pin::Pin,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, Weak,
     },
+    task::{Context, Poll},
 };
 
-use amqp_serde::types::AmqpChannelId;
-use tokio::sync::{mpsc, oneshot};
+use amqp_serde::types::{AmqpChannelId, ShortStr};
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot, watch, Semaphore,
+    },
+    time::{self, Duration},
+};
 
 use super::callbacks::ChannelCallback;
 use crate::{
     api::{error::Error, Result},
-    connection::Connection,
-    frame::{CloseChannel, CloseChannelOk, Deliver, Flow, FlowOk, Frame, MethodHeader, Return},
+    connection::{BufferedBytes, Connection},
+    frame::{
+        CloseChannel, CloseChannelOk, Deliver, Flow, FlowOk, Frame, MethodHeader, Nack, Return,
+    },
     net::{ConnManagementCommand, IncomingMessage, OutgoingMessage},
-    BasicProperties,
+    BasicProperties, FieldTable, RedactionPolicy,
 };
 #[cfg(feature = "traces")]
 use tracing::{error, info, trace};
@@ -50,7 +70,240 @@ pub struct ConsumerMessage {
     pub deliver: Option<Deliver>,
     pub basic_properties: Option<BasicProperties>,
     pub content: Option<Vec<u8>>,
-    remaining: usize,
+    pub(crate) remaining: usize,
+    /// counts the content in the buffered bytes of the connection until dropped
+    pub(crate) buffered: Option<BufferedBytes>,
+}
+
+impl ConsumerMessage {
+    /// Returns the underlying `deliver` method received from server.
+    pub fn raw_deliver(&self) -> Option<&Deliver> {
+        self.deliver.as_ref()
+    }
+
+    /// Returns the underlying message properties, including the property flags.
+    pub fn raw_properties(&self) -> Option<&BasicProperties> {
+        self.basic_properties.as_ref()
+    }
+
+    /// Returns a copy of the message headers with the values of the keys matching `policy`
+    /// redacted, so that they can be logged safely, or `None` if the message has no headers.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use amqprs::{channel::ConsumerMessage, RedactionPolicy};
+    /// # fn log(message: ConsumerMessage) {
+    /// let policy = RedactionPolicy::new(["authorization", "*token*"]);
+    /// if let Some(headers) = message.redacted_headers(&policy) {
+    ///     println!("received message with headers {}", headers);
+    /// }
+    /// # }
+    /// ```
+    pub fn redacted_headers(&self, policy: &RedactionPolicy) -> Option<FieldTable> {
+        let headers = self.basic_properties.as_ref()?.headers()?;
+        Some(headers.redacted(policy))
+    }
+
+    /// Returns arguments and properties to publish the message again, e.g. to retry it.
+    ///
+    /// The arguments have the exchange and routing key the message was delivered with,
+    /// and the properties are copied from the message, so only what changes needs to be
+    /// updated, such as a retry counter in the headers.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use amqprs::{channel::{Channel, ConsumerMessage}, FieldTable, FieldValue};
+    /// # async fn retry(channel: &Channel, message: ConsumerMessage) -> Result<(), amqprs::error::Error> {
+    /// let (args, mut basic_properties) = message.republish_builder().unwrap();
+    /// let mut headers = basic_properties.headers().cloned().unwrap_or_default();
+    /// headers.insert("x-retry".try_into().unwrap(), FieldValue::l(1));
+    /// basic_properties.with_headers(headers);
+    /// channel
+    ///     .basic_publish(basic_properties, message.content.unwrap(), args)
+    ///     .await
+    /// # }
+    /// ```
+    pub fn republish_builder(&self) -> Option<(BasicPublishArguments, BasicProperties)> {
+        let deliver = self.deliver.as_ref()?;
+        let args = BasicPublishArguments::new(deliver.exchange(), deliver.routing_key());
+        Some((args, self.basic_properties.clone()?))
+    }
+
+    /// Give back a content buffer once it is processed, so that it can be reused
+    /// for the content of subsequent deliveries instead of allocating a new one.
+    ///
+    /// Recycling is optional, a buffer which is not given back is simply dropped.
+    #[cfg(feature = "body_pool")]
+    pub fn recycle(buf: Vec<u8>) {
+        body_pool::BODY_POOL.put(buf);
+    }
+}
+
+/// Prefix of exchange and queue names reserved by server, e.g. `amq.topic`.
+const RESERVED_NAME_PREFIX: &str = "amq.";
+
+/// Check that a name to declare is not reserved by server, which refuses to declare it
+/// with `ACCESS_REFUSED`. Reserved names are allowed in passive declare, e.g. to check
+/// that a built-in exchange exists.
+///
+/// Returns [`Error::ReservedName`] if the name is reserved and `passive` is `false`.
+pub(crate) fn check_reserved_name(field: &str, name: &str, passive: bool) -> Result<()> {
+    if !passive && name.starts_with(RESERVED_NAME_PREFIX) {
+        return Err(Error::ReservedName(format!(
+            "{} '{}' starts with '{}', which is reserved by server and only allowed in passive declare",
+            field, name, RESERVED_NAME_PREFIX
+        )));
+    }
+    Ok(())
+}
+
+/// Convert a user supplied name to `ShortStr`.
+///
+/// Returns [`Error::NameTooLong`] instead of panic if it is longer than 255 bytes.
+pub(crate) fn to_short_str(field: &str, name: String) -> Result<ShortStr> {
+    let len = name.len();
+    name.try_into().map_err(|_| {
+        Error::NameTooLong(format!(
+            "{} has {} bytes, maximum is {}",
+            field,
+            len,
+            u8::MAX
+        ))
+    })
+}
+
+/// Stream of messages delivered to a consumer, returned by [`Channel::basic_consume_stream`].
+///
+/// The stream ends when the consumer is cancelled or the channel is closed.
+///
+/// When the stream is dropped, the consumer is cancelled with best effort, so that server
+/// stops delivering messages to it. Messages already delivered but not yet acknowledged
+/// are requeued by server only when the channel is closed.
+///
+/// [`Channel::basic_consume_stream`]: struct.Channel.html#method.basic_consume_stream
+pub struct ConsumerStream {
+    consumer_tag: String,
+    rx: ConsumerRx,
+    /// `None` if the consumer should not be cancelled on drop.
+    dispatcher_mgmt_tx: Option<mpsc::UnboundedSender<DispatcherManagementCommand>>,
+}
+
+impl ConsumerStream {
+    pub(crate) fn new(
+        consumer_tag: String,
+        rx: ConsumerRx,
+        dispatcher_mgmt_tx: Option<mpsc::UnboundedSender<DispatcherManagementCommand>>,
+    ) -> Self {
+        Self {
+            consumer_tag,
+            rx,
+            dispatcher_mgmt_tx,
+        }
+    }
+
+    /// Returns the consumer tag of the stream.
+    pub fn consumer_tag(&self) -> &str {
+        &self.consumer_tag
+    }
+
+    /// Receive next message.
+    ///
+    /// Returns `None` if the stream has ended.
+    pub async fn recv(&mut self) -> Option<ConsumerMessage> {
+        self.rx.recv().await
+    }
+
+    /// Receive next message, waiting at most `timeout`.
+    ///
+    /// Returns `None` if the stream has ended or no message arrives in time.
+    pub async fn recv_timeout(&mut self, timeout: Duration) -> Option<ConsumerMessage> {
+        time::timeout(timeout, self.rx.recv()).await.ok().flatten()
+    }
+
+    /// Collect up to `max` messages within `timeout`.
+    ///
+    /// The `timeout` bounds the total time spent, not the wait for each message.
+    /// Returns early if `max` messages are received or the stream has ended.
+    pub async fn drain(&mut self, max: usize, timeout: Duration) -> Vec<ConsumerMessage> {
+        let deadline = time::Instant::now() + timeout;
+        let mut messages = Vec::new();
+        while messages.len() < max {
+            match time::timeout_at(deadline, self.rx.recv()).await {
+                Ok(Some(message)) => messages.push(message),
+                // stream ended or timed out
+                Ok(None) | Err(_) => break,
+            }
+        }
+        messages
+    }
+}
+
+impl Drop for ConsumerStream {
+    /// Ask the dispatcher to cancel the consumer, no error if the channel is already closed.
+    fn drop(&mut self) {
+        if let Some(tx) = self.dispatcher_mgmt_tx.take() {
+            let cmd = CancelContentConsumer {
+                consumer_tag: self.consumer_tag.clone(),
+            };
+            if tx
+                .send(DispatcherManagementCommand::CancelContentConsumer(cmd))
+                .is_err()
+            {
+                #[cfg(feature = "traces")]
+                trace!(
+                    "channel closed, no need to cancel consumer {}",
+                    self.consumer_tag
+                );
+            }
+        }
+    }
+}
+
+/// Undeliverable message returned by server, delivered to [`ChannelCallback::publish_returned`].
+///
+/// It contains the reason why the message is returned, and the original
+/// exchange and routing key used to publish it, so that publisher can
+/// decide whether to republish it elsewhere.
+///
+/// [`ChannelCallback::publish_returned`]: ../callbacks/trait.ChannelCallback.html#method.publish_returned
+#[derive(Debug, Clone)]
+pub struct ReturnedMessage {
+    pub reply_code: u16,
+    pub reply_text: String,
+    /// exchange the message was originally published to
+    pub exchange: String,
+    /// routing key the message was originally published with
+    pub routing_key: String,
+    pub basic_properties: BasicProperties,
+    pub content: Vec<u8>,
+}
+
+impl ReturnedMessage {
+    /// Assemble the returned message from `return` method and its content.
+    pub fn new(ret: Return, basic_properties: BasicProperties, content: Vec<u8>) -> Self {
+        Self {
+            reply_code: ret.reply_code(),
+            reply_text: ret.reply_text().clone(),
+            exchange: ret.exchange().clone(),
+            routing_key: ret.routing_key().clone(),
+            basic_properties,
+            content,
+        }
+    }
+
+    /// Split the returned message into `return` method and its content.
+    pub fn into_parts(self) -> (Return, BasicProperties, Vec<u8>) {
+        // values are received in short strings, so conversions never fail
+        let ret = Return::new(
+            self.reply_code,
+            self.reply_text.try_into().unwrap(),
+            self.exchange.try_into().unwrap(),
+            self.routing_key.try_into().unwrap(),
+        );
+        (ret, self.basic_properties, self.content)
+    }
 }
 
 /// Message buffer for a `Return + content` sequence from server.
@@ -70,7 +323,76 @@ pub(crate) struct GetOkMessage {
 /// Command to register consumer of asynchronous delivered contents.
 pub(crate) struct RegisterContentConsumer {
     consumer_tag: String,
-    consumer_tx: mpsc::UnboundedSender<ConsumerMessage>,
+    consumer_tx: ConsumerTx,
+}
+
+/// tx half used by dispatcher to forward deliveries to a consumer task.
+pub(crate) enum ConsumerTx {
+    Unbounded(mpsc::UnboundedSender<ConsumerMessage>),
+    /// Sending fails once it is full, dispatcher buffers the message until the consumer
+    /// catches up, see [`ForwardBufferedDeliveries`].
+    Bounded(mpsc::Sender<ConsumerMessage>),
+}
+
+impl ConsumerTx {
+    /// Send without waiting, an unbounded channel is never full.
+    // same as tokio's `try_send`, the error gives the message back
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn try_send(
+        &self,
+        message: ConsumerMessage,
+    ) -> std::result::Result<(), TrySendError<ConsumerMessage>> {
+        match self {
+            ConsumerTx::Unbounded(tx) => {
+                tx.send(message).map_err(|err| TrySendError::Closed(err.0))
+            }
+            ConsumerTx::Bounded(tx) => tx.try_send(message),
+        }
+    }
+}
+
+/// rx half held by a consumer task.
+pub(crate) enum ConsumerRx {
+    Unbounded(mpsc::UnboundedReceiver<ConsumerMessage>),
+    Bounded(mpsc::Receiver<ConsumerMessage>),
+}
+
+impl ConsumerRx {
+    pub(crate) async fn recv(&mut self) -> Option<ConsumerMessage> {
+        match self {
+            ConsumerRx::Unbounded(rx) => rx.recv().await,
+            ConsumerRx::Bounded(rx) => rx.recv().await,
+        }
+    }
+
+    pub(crate) fn blocking_recv(&mut self) -> Option<ConsumerMessage> {
+        match self {
+            ConsumerRx::Unbounded(rx) => rx.blocking_recv(),
+            ConsumerRx::Bounded(rx) => rx.blocking_recv(),
+        }
+    }
+
+    /// Receive a message already in the channel, `None` if there is none.
+    pub(crate) fn try_recv(&mut self) -> Option<ConsumerMessage> {
+        match self {
+            ConsumerRx::Unbounded(rx) => rx.try_recv().ok(),
+            ConsumerRx::Bounded(rx) => rx.try_recv().ok(),
+        }
+    }
+}
+
+/// Create the channel between dispatcher and a consumer task.
+pub(crate) fn consumer_channel(delivery_channel: DeliveryChannel) -> (ConsumerTx, ConsumerRx) {
+    match delivery_channel {
+        DeliveryChannel::Unbounded => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (ConsumerTx::Unbounded(tx), ConsumerRx::Unbounded(rx))
+        }
+        DeliveryChannel::Bounded(size) => {
+            let (tx, rx) = mpsc::channel(size);
+            (ConsumerTx::Bounded(tx), ConsumerRx::Bounded(rx))
+        }
+    }
 }
 
 /// Command to deregister consumer of asynchronous delivered contents.
@@ -80,19 +402,84 @@ pub(crate) struct DeregisterContentConsumer {
     consumer_tag: String,
 }
 
+/// Command to cancel a consumer whose receiver is dropped, e.g. a dropped [`ConsumerStream`].
+///
+/// Unlike [`DeregisterContentConsumer`], dispatcher also sends `cancel` to server.
+pub(crate) struct CancelContentConsumer {
+    consumer_tag: String,
+}
+
+/// Command to list the tags of consumers known by dispatcher.
+pub(crate) struct ListContentConsumers {
+    tx: oneshot::Sender<Vec<String>>,
+}
+
+/// Command to pause or resume forwarding deliveries to a consumer.
+pub(crate) struct PauseContentConsumer {
+    consumer_tag: String,
+    paused: bool,
+}
+
+/// Command to replace the tx half which deliveries of a consumer are forwarded to.
+///
+/// See [`Channel::drain`].
+pub(crate) struct SwapContentConsumer {
+    consumer_tag: String,
+    consumer_tx: ConsumerTx,
+    /// rx half of the replaced tx half, messages left in it are forwarded to `consumer_tx` first.
+    leftover_rx: Option<ConsumerRx>,
+    /// oneshot sender to return the replaced tx half, `None` if consumer is not registered.
+    replaced: oneshot::Sender<Option<ConsumerTx>>,
+}
+
+/// Command to forward the deliveries buffered for a consumer whose bounded channel was full,
+/// sent once the consumer has taken a message from it.
+pub(crate) struct ForwardBufferedDeliveries {
+    consumer_tag: String,
+}
+
+/// Command to expect deliveries for a consumer which is not registered yet.
+///
+/// Used when `consume` is sent with `no_wait`, in which case there is no `consume-ok`
+/// for dispatcher to learn the consumer tag.
+pub(crate) struct ExpectContentConsumer {
+    consumer_tag: String,
+    no_ack: bool,
+}
+
+/// Command to tell the acknowledgement mode of the consumer confirmed by next `consume-ok`.
+pub(crate) struct ExpectConsumeOk {
+    no_ack: bool,
+}
+
 /// Command to register sender to forward server's response to `get` request.
 ///
 /// Server will respond `get-ok` + `message propertities` + `content body` in sequence,
 /// so the sender should be mpsc instead of oneshot.
 pub(crate) struct RegisterGetContentResponder {
     tx: mpsc::UnboundedSender<IncomingMessage>,
+    no_ack: bool,
+}
+
+/// Receiver of the response to a synchronous request.
+///
+/// Resolves to [`Error::RpcTimeout`] if the dispatcher discards the responder after the
+/// rpc timeout of the channel.
+pub(crate) struct ResponseReceiver(oneshot::Receiver<Result<IncomingMessage>>);
+
+impl Future for ResponseReceiver {
+    type Output = Result<IncomingMessage>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx).map(|response| response?)
+    }
 }
 
 /// Command to register oneshot sender for response from server.
 pub(crate) struct RegisterOneshotResponder {
     pub method_header: &'static MethodHeader,
-    /// oneshot sender to forward response message from server.
-    pub responder: oneshot::Sender<IncomingMessage>,
+    /// oneshot sender to forward response message from server, or the rpc timeout error.
+    pub responder: oneshot::Sender<Result<IncomingMessage>>,
     // oneshot sender to acknowledge registration is done.
     pub acker: oneshot::Sender<()>,
 }
@@ -102,13 +489,80 @@ pub(crate) struct RegisterChannelCallback {
     pub callback: Box<dyn ChannelCallback + Send + 'static>,
 }
 
+/// Callback invoked with the outcome once server confirms a published message,
+/// along with the message if server returned it before the ack.
+pub(crate) type ConfirmCallback =
+    Box<dyn FnOnce(std::result::Result<Option<ReturnedMessage>, Nack>) + Send + 'static>;
+
+/// Command to register callback for confirm of a published message.
+pub(crate) struct RegisterConfirmCallback {
+    /// publish sequence number of the message
+    delivery_tag: u64,
+    callback: Option<ConfirmCallback>,
+    /// routing of a mandatory message, to correlate the message returned by server
+    /// with its confirm
+    routing: Option<PublishRouting>,
+}
+
+/// Routing of a mandatory message published in confirm mode.
+pub(crate) struct PublishRouting {
+    exchange: String,
+    routing_key: String,
+    message_id: Option<String>,
+}
+
+impl PublishRouting {
+    /// Returns `true` if `message` is returned with the routing of the published message.
+    ///
+    /// Message id is not compared if it is unknown, e.g. of encoded properties.
+    fn matches(&self, message: &ReturnedMessage) -> bool {
+        self.exchange == message.exchange
+            && self.routing_key == message.routing_key
+            && (self.message_id.is_none()
+                || self.message_id.as_ref() == message.basic_properties.message_id())
+    }
+}
+
+/// Command to forward confirms of published messages to a [`ConfirmStream`].
+pub(crate) struct RegisterConfirmListener {
+    tx: mpsc::UnboundedSender<Confirm>,
+}
+
 /// List of management commands for channel dispatcher.
 pub(crate) enum DispatcherManagementCommand {
     RegisterContentConsumer(RegisterContentConsumer),
     DeregisterContentConsumer(DeregisterContentConsumer),
+    CancelContentConsumer(CancelContentConsumer),
+    ListContentConsumers(ListContentConsumers),
+    PauseContentConsumer(PauseContentConsumer),
+    SwapContentConsumer(SwapContentConsumer),
+    ForwardBufferedDeliveries(ForwardBufferedDeliveries),
+    ExpectContentConsumer(ExpectContentConsumer),
+    ExpectConsumeOk(ExpectConsumeOk),
     RegisterGetContentResponder(RegisterGetContentResponder),
     RegisterOneshotResponder(RegisterOneshotResponder),
     RegisterChannelCallback(RegisterChannelCallback),
+    RegisterConfirmCallback(RegisterConfirmCallback),
+    RegisterConfirmListener(RegisterConfirmListener),
+}
+
+/// Mode of a channel, confirm mode and transaction mode are mutually exclusive.
+///
+/// See [`Channel::mode`].
+///
+/// [`Channel::mode`]: struct.Channel.html#method.mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    /// Neither confirm mode nor transaction mode.
+    Normal,
+    /// Publisher confirms enabled by [`confirm_select`].
+    ///
+    /// [`confirm_select`]: struct.Channel.html#method.confirm_select
+    Confirm,
+    /// Transactions enabled by [`tx_select`].
+    ///
+    /// [`tx_select`]: struct.Channel.html#method.tx_select
+    Transaction,
 }
 
 /// Type represents an AMQP Channel.
@@ -152,6 +606,47 @@ pub(crate) struct SharedChannelInner {
     conn_mgmt_tx: mpsc::Sender<ConnManagementCommand>,
     /// tx half to send management command to `ChannelDispatcher` task
     dispatcher_mgmt_tx: mpsc::UnboundedSender<DispatcherManagementCommand>,
+    /// last `basic_qos` accepted by server, to be re-applied after reconnection
+    qos: Mutex<Option<BasicQosArguments>>,
+    /// prefetch counts accepted by server and captured by consumers
+    prefetch: Mutex<PrefetchCounts>,
+    /// properties merged into every published message
+    default_properties: Mutex<BasicProperties>,
+    /// header injected into every published message
+    publish_id_header: Mutex<Option<PublishIdHeader>>,
+    /// names consumers which are not given a consumer tag
+    consumer_tag_generator: Mutex<Option<ConsumerTagGenerator>>,
+    /// check that the queue exists before publishing to the default exchange
+    strict_default_exchange: AtomicBool,
+    /// queues known to exist by the checks of strict default exchange
+    known_queues: Mutex<HashSet<String>>,
+    /// time to wait for the response to a synchronous request, `None` to wait forever
+    rpc_timeout: Mutex<Option<Duration>>,
+    /// confirm or transaction mode selected on the channel
+    mode: Mutex<ChannelMode>,
+    /// number of deliveries being processed by async consumers
+    in_flight: watch::Sender<usize>,
+    /// number of running tasks of async and blocking consumers
+    consumer_tasks: watch::Sender<usize>,
+    /// number of frames sent by `basic_publish`
+    frames_sent: AtomicU64,
+    /// number of frames received by `ChannelDispatcher`
+    frames_received: AtomicU64,
+    /// delivery tags of received messages which are not acked, nacked or rejected yet,
+    /// only messages to be acknowledged manually are tracked
+    unacked: Mutex<BTreeSet<u64>>,
+    /// sequence number of next published message in confirm mode, `None` if not in confirm mode.
+    ///
+    /// Async lock is held until the message is sent, so sequence numbers follow the send order.
+    next_publish_seq_no: tokio::sync::Mutex<Option<u64>>,
+    /// delivery tags of published messages which are not confirmed yet, only tracked
+    /// in confirm mode
+    unconfirmed: watch::Sender<BTreeSet<u64>>,
+    /// permits of messages which can be published before the outstanding ones are confirmed,
+    /// `None` if unlimited
+    publish_window: Mutex<Option<Arc<Semaphore>>>,
+    /// `None` while `ChannelDispatcher` runs, then `Some` with the `close` from server if any
+    closed: watch::Sender<Option<Option<CloseChannel>>>,
 }
 
 impl SharedChannelInner {
@@ -161,7 +656,7 @@ impl SharedChannelInner {
     async fn register_responder(
         &self,
         method_header: &'static MethodHeader,
-    ) -> Result<oneshot::Receiver<IncomingMessage>> {
+    ) -> Result<ResponseReceiver> {
         let (responder, responder_rx) = oneshot::channel();
         let (acker, acker_rx) = oneshot::channel();
         let cmd = RegisterOneshotResponder {
@@ -172,8 +667,65 @@ impl SharedChannelInner {
         self.dispatcher_mgmt_tx
             .send(DispatcherManagementCommand::RegisterOneshotResponder(cmd))?;
         acker_rx.await?;
-        Ok(responder_rx)
+        Ok(ResponseReceiver(responder_rx))
+    }
+    /// Track a received message to be acknowledged manually.
+    pub(crate) fn track_unacked(&self, delivery_tag: u64) {
+        self.unacked.lock().unwrap().insert(delivery_tag);
+    }
+
+    /// Whether a received message is still to be acknowledged manually.
+    pub(crate) fn is_unacked(&self, delivery_tag: u64) -> bool {
+        self.unacked.lock().unwrap().contains(&delivery_tag)
+    }
+
+    /// Stop tracking the messages which are acked, nacked or rejected.
+    ///
+    /// If `multiple`, all messages up to and including `delivery_tag` are settled,
+    /// delivery tag zero settles all outstanding messages.
+    pub(crate) fn settle_unacked(&self, delivery_tag: u64, multiple: bool) {
+        let mut unacked = self.unacked.lock().unwrap();
+        if !multiple {
+            unacked.remove(&delivery_tag);
+        } else if delivery_tag == 0 {
+            unacked.clear();
+        } else {
+            *unacked = unacked.split_off(&(delivery_tag + 1));
+        }
+    }
+
+    /// Stop tracking the published messages which are acked or nacked by server.
+    ///
+    /// If `multiple`, all messages up to and including `delivery_tag` are confirmed.
+    pub(crate) fn settle_unconfirmed(&self, delivery_tag: u64, multiple: bool) {
+        self.unconfirmed.send_if_modified(|unconfirmed| {
+            if multiple {
+                let len = unconfirmed.len();
+                *unconfirmed = unconfirmed.split_off(&(delivery_tag + 1));
+                unconfirmed.len() != len
+            } else {
+                unconfirmed.remove(&delivery_tag)
+            }
+        });
     }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.is_open.load(Ordering::Relaxed)
+    }
+
+    /// Close the channel if it is still open.
+    pub(crate) async fn close(&self) -> Result<()> {
+        if let Ok(true) =
+            self.is_open
+                .compare_exchange(true, false, Ordering::Acquire, Ordering::Relaxed)
+        {
+            #[cfg(feature = "traces")]
+            info!("close channel {}", self.channel_id);
+            self.close_handshake().await?;
+        }
+        Ok(())
+    }
+
     async fn close_handshake(&self) -> Result<()> {
         let responder_rx = self.register_responder(CloseChannelOk::header()).await?;
         synchronous_request!(
@@ -250,7 +802,15 @@ impl Channel {
     async fn register_responder(
         &self,
         method_header: &'static MethodHeader,
-    ) -> Result<oneshot::Receiver<IncomingMessage>> {
+    ) -> Result<ResponseReceiver> {
+        self.connection.check_closed_by_server()?;
+        // a closing channel discards requests, e.g. after a request timed out
+        if !self.is_open() {
+            return Err(Error::ChannelClosed(format!(
+                "failed to send request on channel {}",
+                self.channel_id()
+            )));
+        }
         let (responder, responder_rx) = oneshot::channel();
         let (acker, acker_rx) = oneshot::channel();
         let cmd = RegisterOneshotResponder {
@@ -262,7 +822,7 @@ impl Channel {
             .dispatcher_mgmt_tx
             .send(DispatcherManagementCommand::RegisterOneshotResponder(cmd))?;
         acker_rx.await?;
-        Ok(responder_rx)
+        Ok(ResponseReceiver(responder_rx))
     }
 
     pub fn channel_id(&self) -> AmqpChannelId {
@@ -274,6 +834,13 @@ impl Channel {
     pub fn is_connection_open(&self) -> bool {
         self.connection.is_open()
     }
+    /// Returns the max frame size negotiated by the connection.
+    ///
+    /// Content bodies are split into frames of at most this size, and the connection is
+    /// closed with `FRAME_ERROR` if server sends a larger frame.
+    pub fn frame_max(&self) -> u32 {
+        self.connection.frame_max()
+    }
     /// Returns `true` if channel is open.
     pub fn is_open(&self) -> bool {
         self.shared.is_open.load(Ordering::Relaxed)
@@ -282,6 +849,48 @@ impl Channel {
         self.shared.is_open.store(is_open, Ordering::Relaxed);
     }
 
+    /// Set the time to wait for the response to a synchronous request, e.g. `queue.declare`.
+    /// Default: `None`, waits until the response arrives or the channel is closed.
+    ///
+    /// If server does not respond in time, the request returns [`Error::RpcTimeout`].
+    /// Other pending requests and the channel are not affected. The late response is
+    /// discarded when it arrives, since server responds to the requests in order.
+    ///
+    /// The timeout applies to the requests sent after it is set.
+    pub fn set_rpc_timeout(&self, timeout: Option<Duration>) {
+        *self.shared.rpc_timeout.lock().unwrap() = timeout;
+    }
+
+    /// Returns the time to wait for the response to a synchronous request.
+    pub fn rpc_timeout(&self) -> Option<Duration> {
+        *self.shared.rpc_timeout.lock().unwrap()
+    }
+
+    /// Returns whether the channel is in confirm mode, transaction mode, or neither.
+    pub fn mode(&self) -> ChannelMode {
+        *self.shared.mode.lock().unwrap()
+    }
+
+    /// Check the channel is not in the other mode before selecting `mode`,
+    /// server closes the channel otherwise.
+    pub(crate) fn check_mode(&self, mode: ChannelMode) -> Result<()> {
+        let current = self.mode();
+        if current == ChannelMode::Normal || current == mode {
+            Ok(())
+        } else {
+            Err(Error::ModeConflict(format!(
+                "can't select {:?} mode on channel {} in {:?} mode",
+                mode,
+                self.channel_id(),
+                current
+            )))
+        }
+    }
+
+    pub(crate) fn set_mode(&self, mode: ChannelMode) {
+        *self.shared.mode.lock().unwrap() = mode;
+    }
+
     /// Asks the server to pause or restart the flow of content data.
     ///
     /// Ask to start the flow if input `active` = `true`, otherwise to pause.
@@ -312,6 +921,13 @@ impl Channel {
     /// This method consume the channel, so even it may return error,
     /// channel will anyway be dropped.
     ///
+    /// Consumers of the channel are shut down in order before the channel is closed:
+    /// they are cancelled, then their tasks process the deliveries received before
+    /// `cancel-ok`, so in-flight deliveries can still be acknowledged. It waits for
+    /// the handlers, so it must not be called by a consumer of the channel. The handlers are
+    /// waited for at most 30 seconds, and failures to cancel the consumers are only logged,
+    /// the channel is closed anyway.
+    ///
     /// # Errors
     ///
     /// Returns error if any failure in communication with server.
@@ -319,21 +935,107 @@ impl Channel {
     pub async fn close(self) -> Result<()> {
         // if connection closed, no need to close channel
         if self.is_connection_open() {
-            // check if channel is open
-            if let Ok(true) = self.shared.is_open.compare_exchange(
-                true,
-                false,
-                Ordering::Acquire,
-                Ordering::Relaxed,
-            ) {
-                #[cfg(feature = "traces")]
-                info!("close channel {}", self);
-                self.shared.close_handshake().await?;
+            if self.is_open() {
+                self.cancel_consumers().await;
             }
+            self.shared.close().await?;
         }
         Ok(())
     }
 
+    /// Wait until all messages published so far are written to the network connection.
+    ///
+    /// Publishing only enqueues messages to be sent by the connection, call this method
+    /// after a batch of publishes to make sure the tail of the batch is not lost if
+    /// the channel or connection is closed immediately after.
+    ///
+    /// It does not mean the messages have been received by server, for data safety, use
+    /// [publisher confirms](https://rabbitmq.com/publishers.html#data-safety).
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the connection has been closed.
+    pub async fn flush(&self) -> Result<()> {
+        self.connection.flush().await
+    }
+
+    /// Returns the number of deliveries being processed by async consumers of the channel.
+    ///
+    /// A delivery is in flight from the moment it is passed to the consumer until its handler
+    /// has returned and the [`ConsumerErrorPolicy`] is applied, also if the consumer is in
+    /// `no_ack` mode. Deliveries of [`ConsumerStream`] are not counted.
+    pub fn in_flight_deliveries(&self) -> usize {
+        *self.shared.in_flight.borrow()
+    }
+
+    /// Wait until no delivery is being processed by async consumers of the channel.
+    ///
+    /// For graceful shutdown, cancel the consumers first, then wait for their
+    /// in-flight deliveries to complete before closing the channel.
+    pub async fn wait_in_flight_deliveries(&self) {
+        let mut in_flight = self.shared.in_flight.subscribe();
+        while *in_flight.borrow_and_update() > 0 {
+            // sender is owned by `self`, it cannot be dropped while waiting
+            if in_flight.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Wait until the channel's dispatcher task exits, i.e. the channel is closed
+    /// by client or by server, or its connection is lost.
+    ///
+    /// Returns the `close` request if server closed the channel, e.g. due to a
+    /// `NOT_FOUND` or `PRECONDITION_FAILED` error, otherwise returns `None`.
+    ///
+    /// Resolves immediately if the dispatcher has already exited.
+    pub async fn closed(&self) -> Option<CloseChannel> {
+        let mut closed = self.shared.closed.subscribe();
+        loop {
+            if let Some(reason) = &*closed.borrow_and_update() {
+                return reason.clone();
+            }
+            // sender is owned by `self`, it cannot be dropped while waiting
+            if closed.changed().await.is_err() {
+                return None;
+            }
+        }
+    }
+
+    /// Returns the number of frames `(sent, received)` on the channel.
+    ///
+    /// Sent frames are counted by [`basic_publish`], a message counts as its method,
+    /// content header and content body frames, the body split by the connection's `frame_max`.
+    /// Received frames are all frames dispatched to the channel, including deliveries.
+    ///
+    /// Counters only increase, sample them periodically to compute the rates of the channel.
+    ///
+    /// [`basic_publish`]: struct.Channel.html#method.basic_publish
+    pub fn frame_counters(&self) -> (u64, u64) {
+        (
+            self.shared.frames_sent.load(Ordering::Relaxed),
+            self.shared.frames_received.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Returns the number of received messages which are not acked, nacked or rejected yet.
+    ///
+    /// Only messages to be acknowledged manually are counted, i.e. deliveries to consumers
+    /// and messages from [`basic_get`] without `no_ack`. A count which keeps growing
+    /// indicates the application leaks deliveries, which eventually stalls the consumers
+    /// once the prefetch limit set by [`basic_qos`] is reached.
+    ///
+    /// [`basic_get`]: struct.Channel.html#method.basic_get
+    /// [`basic_qos`]: struct.Channel.html#method.basic_qos
+    pub fn unacked_count(&self) -> usize {
+        self.shared.unacked.lock().unwrap().len()
+    }
+
+    /// Returns a weak reference to the shared state, which does not keep the channel alive.
+    pub(in crate::api) fn downgrade(&self) -> Weak<SharedChannelInner> {
+        Arc::downgrade(&self.shared)
+    }
+
     pub(crate) fn clone_as_secondary(&self) -> Self {
         Self {
             shared: self.shared.clone(),
@@ -407,21 +1109,261 @@ impl SharedChannelInner {
             outgoing_tx,
             conn_mgmt_tx,
             dispatcher_mgmt_tx,
+            qos: Mutex::new(None),
+            prefetch: Mutex::new(PrefetchCounts::default()),
+            default_properties: Mutex::new(BasicProperties::default()),
+            publish_id_header: Mutex::new(None),
+            consumer_tag_generator: Mutex::new(None),
+            strict_default_exchange: AtomicBool::new(false),
+            known_queues: Mutex::new(HashSet::new()),
+            rpc_timeout: Mutex::new(None),
+            mode: Mutex::new(ChannelMode::Normal),
+            in_flight: watch::channel(0).0,
+            consumer_tasks: watch::channel(0).0,
+            frames_sent: AtomicU64::new(0),
+            frames_received: AtomicU64::new(0),
+            unacked: Mutex::new(BTreeSet::new()),
+            next_publish_seq_no: tokio::sync::Mutex::new(None),
+            unconfirmed: watch::channel(BTreeSet::new()).0,
+            publish_window: Mutex::new(None),
+            closed: watch::channel(None).0,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use tokio::time;
+    use amqp_serde::from_bytes;
+    use async_trait::async_trait;
+    use tokio::{sync::mpsc, time};
 
+    use super::{
+        consumer_channel, to_short_str, BasicAckArguments, BasicConsumeArguments, ChannelMode,
+        ConfirmSelectArguments, ConsumerMessage, ConsumerStream, DeliveryChannel,
+        DispatcherManagementCommand, ReturnedMessage,
+    };
     use crate::{
+        api::error::Error,
         channel::Channel,
         connection::{Connection, OpenConnectionArguments},
-        test_utils::setup_logging,
+        consumer::AsyncConsumer,
+        frame::{CloseChannelOk, Frame, SelectOk, TxSelectOk},
+        test_utils::{
+            cancel_ok, channel_reply, close_channel, consume_ok, content_header, deliver,
+            mock_broker, setup_logging,
+        },
+        BasicProperties, Deliver, FieldTable, FieldValue, RedactionPolicy, Return,
+        REDACTED_PLACEHOLDER,
     };
     use std::marker::PhantomData;
 
+    #[test]
+    fn test_consumer_message_raw_accessors() {
+        // consumer tag, delivery tag, redelivered, exchange, routing key
+        let raw = [
+            4, b'c', b't', b'a', b'g', 0, 0, 0, 0, 0, 0, 0, 7, 1, 0, 2, b'r', b'k',
+        ];
+        let deliver: Deliver = from_bytes(&raw).unwrap();
+        let basic_properties = BasicProperties::default()
+            .with_content_type("text/plain")
+            .finish();
+        let message = ConsumerMessage {
+            deliver: Some(deliver),
+            basic_properties: Some(basic_properties.clone()),
+            content: Some(Vec::new()),
+            remaining: 0,
+            buffered: None,
+        };
+
+        let raw_deliver = message.raw_deliver().unwrap();
+        assert_eq!(7, u64::from(raw_deliver.delivery_tag()));
+        assert!(raw_deliver.redelivered());
+        assert_eq!("ctag", raw_deliver.consumer_tag());
+        let raw_properties = message.raw_properties().unwrap();
+        assert_eq!("text/plain", raw_properties.content_type().unwrap());
+        assert_eq!(
+            basic_properties.property_flags(),
+            raw_properties.property_flags()
+        );
+        assert_eq!([0b1000_0000, 0], raw_properties.property_flags());
+    }
+
+    #[test]
+    fn test_consumer_message_republish_builder() {
+        // consumer tag, delivery tag, redelivered, exchange, routing key
+        let mut raw = vec![4, b'c', b't', b'a', b'g', 0, 0, 0, 0, 0, 0, 0, 7, 1, 9];
+        raw.extend_from_slice(b"amq.topic");
+        raw.push(9);
+        raw.extend_from_slice(b"orders.eu");
+        let deliver: Deliver = from_bytes(&raw).unwrap();
+        let mut headers = FieldTable::new();
+        headers.insert("x-retry".try_into().unwrap(), FieldValue::l(1));
+        let basic_properties = BasicProperties::default()
+            .with_message_id("msg-1")
+            .with_headers(headers)
+            .finish();
+        let message = ConsumerMessage {
+            deliver: Some(deliver),
+            basic_properties: Some(basic_properties),
+            content: Some(b"hello".to_vec()),
+            remaining: 0,
+            buffered: None,
+        };
+
+        let (args, mut basic_properties) = message.republish_builder().unwrap();
+        assert_eq!("amq.topic", args.exchange);
+        assert_eq!("orders.eu", args.routing_key);
+        assert_eq!("msg-1", basic_properties.message_id().unwrap());
+
+        let mut headers = basic_properties.headers().unwrap().clone();
+        headers.insert("x-retry".try_into().unwrap(), FieldValue::l(2));
+        basic_properties.with_headers(headers);
+        let retry = basic_properties
+            .headers()
+            .unwrap()
+            .get(&"x-retry".try_into().unwrap());
+        assert_eq!(Some(&FieldValue::l(2)), retry);
+        // original message is not changed
+        let original = message.raw_properties().unwrap().headers().unwrap();
+        assert_eq!(
+            Some(&FieldValue::l(1)),
+            original.get(&"x-retry".try_into().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_consumer_message_redacted_headers() {
+        let mut headers = FieldTable::new();
+        headers.insert("authorization".try_into().unwrap(), "Bearer abc".into());
+        headers.insert("x-retry".try_into().unwrap(), FieldValue::l(1));
+        let message = |basic_properties: BasicProperties| ConsumerMessage {
+            deliver: None,
+            basic_properties: Some(basic_properties),
+            content: Some(Vec::new()),
+            remaining: 0,
+            buffered: None,
+        };
+        let policy = RedactionPolicy::new(["Authorization"]);
+
+        let redacted = message(BasicProperties::default().with_headers(headers).finish())
+            .redacted_headers(&policy)
+            .unwrap();
+        assert_eq!(
+            Some(&FieldValue::S(REDACTED_PLACEHOLDER.try_into().unwrap())),
+            redacted.get(&"authorization".try_into().unwrap())
+        );
+        assert_eq!(
+            Some(&FieldValue::l(1)),
+            redacted.get(&"x-retry".try_into().unwrap())
+        );
+
+        assert!(message(BasicProperties::default())
+            .redacted_headers(&policy)
+            .is_none());
+    }
+
+    #[test]
+    fn test_returned_message_carries_routing_metadata() {
+        // reply code, reply text, exchange, routing key
+        let mut raw = vec![0x01, 0x38, 8];
+        raw.extend_from_slice(b"NO_ROUTE");
+        raw.push(9);
+        raw.extend_from_slice(b"amq.topic");
+        raw.push(7);
+        raw.extend_from_slice(b"nowhere");
+        let ret: Return = from_bytes(&raw).unwrap();
+        let basic_properties = BasicProperties::default().with_message_id("msg-1").finish();
+
+        let message = ReturnedMessage::new(ret, basic_properties, b"hello".to_vec());
+        assert_eq!(312, message.reply_code);
+        assert_eq!("NO_ROUTE", message.reply_text);
+        assert_eq!("amq.topic", message.exchange);
+        assert_eq!("nowhere", message.routing_key);
+        assert_eq!("msg-1", message.basic_properties.message_id().unwrap());
+        assert_eq!(b"hello".to_vec(), message.content);
+
+        let (ret, _, content) = message.into_parts();
+        assert_eq!("amq.topic", ret.exchange());
+        assert_eq!("nowhere", ret.routing_key());
+        assert_eq!(b"hello".to_vec(), content);
+    }
+
+    #[test]
+    fn test_to_short_str_rejects_long_name() {
+        let routing_key = "k".repeat(300);
+        match to_short_str("routing key", routing_key) {
+            Err(Error::NameTooLong(msg)) => {
+                assert_eq!("routing key has 300 bytes, maximum is 255", msg)
+            }
+            _ => panic!("expected NameTooLong error"),
+        }
+        assert!(to_short_str("routing key", "k".repeat(255)).is_ok());
+    }
+
+    fn consumer_message() -> ConsumerMessage {
+        ConsumerMessage {
+            deliver: None,
+            basic_properties: Some(BasicProperties::default()),
+            content: Some(Vec::new()),
+            remaining: 0,
+            buffered: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consumer_stream_drain_returns_on_timeout() {
+        let (tx, rx) = consumer_channel(DeliveryChannel::Unbounded);
+        let mut stream = ConsumerStream::new("drain-tester".to_owned(), rx, None);
+        for _ in 0..3 {
+            assert!(tx.try_send(consumer_message()).is_ok());
+        }
+
+        let timeout = time::Duration::from_millis(100);
+        let start = time::Instant::now();
+        let messages = stream.drain(10, timeout).await;
+        assert_eq!(3, messages.len());
+        assert!(start.elapsed() >= timeout);
+
+        // nothing left
+        assert!(stream.recv_timeout(timeout).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_consumer_stream_drain_returns_at_max() {
+        let (tx, rx) = consumer_channel(DeliveryChannel::Bounded(10));
+        let mut stream = ConsumerStream::new("drain-tester".to_owned(), rx, None);
+        for _ in 0..5 {
+            assert!(tx.try_send(consumer_message()).is_ok());
+        }
+
+        let messages = stream.drain(2, time::Duration::from_secs(60)).await;
+        assert_eq!(2, messages.len());
+        assert!(stream
+            .recv_timeout(time::Duration::from_millis(100))
+            .await
+            .is_some());
+    }
+
+    #[test]
+    fn test_consumer_stream_cancel_on_drop() {
+        let (_tx, rx) = consumer_channel(DeliveryChannel::Unbounded);
+        let (mgmt_tx, mut mgmt_rx) = mpsc::unbounded_channel();
+        let stream = ConsumerStream::new("drop-tester".to_owned(), rx, Some(mgmt_tx));
+        drop(stream);
+
+        match mgmt_rx.try_recv() {
+            Ok(DispatcherManagementCommand::CancelContentConsumer(cmd)) => {
+                assert_eq!("drop-tester", cmd.consumer_tag)
+            }
+            _ => panic!("expect cancel command for dropped stream"),
+        }
+        assert!(mgmt_rx.try_recv().is_err());
+
+        // no command for a stream not bound to a channel
+        let (_tx, rx) = consumer_channel(DeliveryChannel::Unbounded);
+        drop(ConsumerStream::new("drop-tester".to_owned(), rx, None));
+    }
+
     #[ignore = "https://github.com/gftea/amqprs/issues/69"]
     #[tokio::test]
     async fn test_channel_is_not_cloneable() {
@@ -463,21 +1405,255 @@ mod tests {
         conn.close().await.unwrap();
         time::sleep(time::Duration::from_millis(100)).await;
     }
+
+    #[tokio::test]
+    async fn test_channel_mode_conflict() {
+        setup_logging();
+
+        // mock broker accepts any mode, conflicts must be detected by client
+        let (port, server) = mock_broker((), |_, _, frame| match frame {
+            Frame::Select(..) => vec![SelectOk.into_frame()],
+            Frame::TxSelect(..) => vec![TxSelectOk.into_frame()],
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+
+        let confirm_channel = connection.open_channel(None).await.unwrap();
+        assert_eq!(ChannelMode::Normal, confirm_channel.mode());
+        confirm_channel
+            .confirm_select(ConfirmSelectArguments::default())
+            .await
+            .unwrap();
+        assert_eq!(ChannelMode::Confirm, confirm_channel.mode());
+        assert!(matches!(
+            confirm_channel.tx_select().await,
+            Err(Error::ModeConflict(_))
+        ));
+        assert_eq!(ChannelMode::Confirm, confirm_channel.mode());
+
+        let tx_channel = connection.open_channel(None).await.unwrap();
+        tx_channel.tx_select().await.unwrap();
+        assert_eq!(ChannelMode::Transaction, tx_channel.mode());
+        assert!(matches!(
+            tx_channel
+                .confirm_select(ConfirmSelectArguments::default())
+                .await,
+            Err(Error::ModeConflict(_))
+        ));
+        assert_eq!(ChannelMode::Transaction, tx_channel.mode());
+
+        // selecting the same mode again is fine
+        tx_channel.tx_select().await.unwrap();
+
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
+
+    /// Acknowledges each delivery after a delay, recording when handling starts and ends.
+    struct SlowConsumer {
+        tx: mpsc::UnboundedSender<String>,
+    }
+
+    #[async_trait]
+    impl AsyncConsumer for SlowConsumer {
+        async fn consume(
+            &mut self,
+            channel: &Channel,
+            deliver: Deliver,
+            _basic_properties: BasicProperties,
+            _content: Vec<u8>,
+        ) {
+            let delivery_tag = deliver.delivery_tag();
+            self.tx.send(format!("start {}", delivery_tag)).unwrap();
+            time::sleep(time::Duration::from_millis(200)).await;
+            channel
+                .basic_ack(BasicAckArguments::new(delivery_tag, false))
+                .await
+                .unwrap();
+            self.tx.send(format!("end {}", delivery_tag)).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_channel_with_active_consumer() {
+        setup_logging();
+
+        // mock broker delivers two messages on `consume`, one more before `cancel-ok`,
+        // and records the frames it receives
+        let (port, server) = mock_broker(vec![], |received, _, frame| match frame {
+            Frame::Consume(..) => vec![
+                consume_ok("ctag"),
+                deliver("ctag", 1).into_frame(),
+                content_header(0).into_frame(),
+                deliver("ctag", 2).into_frame(),
+                content_header(0).into_frame(),
+            ],
+            Frame::Cancel(..) => {
+                received.push("cancel".to_owned());
+                vec![
+                    deliver("ctag", 3).into_frame(),
+                    content_header(0).into_frame(),
+                    cancel_ok("ctag"),
+                ]
+            }
+            Frame::Ack(_, ack) => {
+                received.push(format!("ack {}", ack.delivery_tag()));
+                vec![]
+            }
+            Frame::CloseChannel(..) => {
+                received.push("close".to_owned());
+                vec![CloseChannelOk.into_frame()]
+            }
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        channel
+            .basic_consume(SlowConsumer { tx }, BasicConsumeArguments::new("q", "ctag"))
+            .await
+            .unwrap();
+
+        // close while the first delivery is being handled
+        let timeout = time::Duration::from_secs(5);
+        let started = time::timeout(timeout, rx.recv()).await.unwrap().unwrap();
+        assert_eq!("start 1", started);
+        time::timeout(timeout, channel.close())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // in-flight and buffered deliveries complete before the channel is closed
+        let mut handled = vec![];
+        while let Ok(event) = rx.try_recv() {
+            handled.push(event);
+        }
+        assert_eq!(
+            vec!["end 1", "start 2", "end 2", "start 3", "end 3"],
+            handled
+        );
+        connection.close().await.unwrap();
+        assert_eq!(
+            vec!["cancel", "ack 1", "ack 2", "ack 3", "close"],
+            server.await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close_channel_cancel_failure() {
+        setup_logging();
+
+        // mock broker closes the channel instead of replying `cancel-ok`,
+        // and records the frames it receives
+        let (port, server) = mock_broker(vec![], |received, _, frame| match frame {
+            Frame::Consume(..) => vec![consume_ok("ctag")],
+            Frame::Cancel(..) => {
+                received.push("cancel");
+                // 541 INTERNAL_ERROR, class basic, method cancel
+                vec![close_channel(541, "INTERNAL_ERROR", 60, 30)]
+            }
+            Frame::CloseChannelOk(..) => {
+                received.push("close-ok");
+                vec![]
+            }
+            Frame::CloseChannel(..) => {
+                received.push("close");
+                vec![CloseChannelOk.into_frame()]
+            }
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        channel
+            .basic_consume(SlowConsumer { tx }, BasicConsumeArguments::new("q", "ctag"))
+            .await
+            .unwrap();
+
+        // the failure to cancel the consumer does not fail the close, nor wait for the consumer
+        time::timeout(time::Duration::from_secs(5), channel.close())
+            .await
+            .unwrap()
+            .unwrap();
+
+        connection.close().await.unwrap();
+        assert_eq!(vec!["cancel", "close-ok"], server.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_channel_closed_by_server() {
+        setup_logging();
+
+        // mock server closes the channel once it is opened,
+        // 404 NOT_FOUND, closed by `queue.declare`
+        let text = "NOT_FOUND - no queue 'orders' in vhost '/'";
+        let (port, server) = mock_broker((), move |_, _, frame| match frame {
+            Frame::OpenChannel(..) => {
+                let mut responses = channel_reply(frame);
+                responses.push(close_channel(404, text, 50, 10));
+                responses
+            }
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+
+        let reason = time::timeout(time::Duration::from_secs(5), channel.closed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(404, reason.reply_code());
+        assert_eq!(text, reason.reply_text());
+        assert!(!channel.is_open());
+        // resolves immediately once closed
+        let reason = time::timeout(time::Duration::from_millis(100), channel.closed())
+            .await
+            .unwrap();
+        assert_eq!(404, reason.unwrap().reply_code());
+
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
 }
 
 /////////////////////////////////////////////////////////////////////////////
 mod dispatcher;
 pub(crate) use dispatcher::*;
 
+#[cfg(feature = "body_pool")]
+mod body_pool;
+
 mod basic;
 mod confim;
+#[cfg(feature = "compression")]
+mod content_encoding;
 mod exchange;
+mod publish_buffer;
 mod queue;
+mod setup;
 mod tx;
+mod x_death;
 
 // public APIs
 pub use basic::*;
 pub use confim::*;
+#[cfg(feature = "compression")]
+pub use content_encoding::*;
 pub use exchange::*;
+pub use publish_buffer::*;
 pub use queue::*;
+pub use setup::*;
 pub use tx::*;
+pub use x_death::*;
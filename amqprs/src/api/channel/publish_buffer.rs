@@ -0,0 +1,303 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use super::{BasicPublishArguments, Channel};
+use crate::{
+    api::{error::Error, Result},
+    BasicProperties,
+};
+
+/// A message held by [`PublishBuffer`] until it can be published.
+#[derive(Clone)]
+struct BufferedPublish {
+    basic_properties: BasicProperties,
+    content: Vec<u8>,
+    args: BasicPublishArguments,
+}
+
+/// Bounded store-and-forward buffer of publishes.
+///
+/// While the channel or its connection is closed, e.g. during a brief network outage,
+/// [`publish`] holds the message in the buffer instead of failing. After the application
+/// has reconnected, opened a new channel and re-declared its topology, call [`flush`]
+/// to send the buffered messages in the order they were published.
+///
+/// As long as the buffer is not empty, new messages are also buffered to keep the order.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use amqprs::{
+/// #     channel::{BasicPublishArguments, PublishBuffer},
+/// #     connection::{Connection, OpenConnectionArguments},
+/// #     BasicProperties,
+/// # };
+/// # async fn example(args: OpenConnectionArguments) -> Result<(), amqprs::error::Error> {
+/// let buffer = PublishBuffer::new(1000);
+///
+/// let connection = Connection::open(&args).await?;
+/// let channel = connection.open_channel(None).await?;
+/// let publish_args = BasicPublishArguments::new("amq.topic", "amqprs.example");
+/// buffer
+///     .publish(&channel, BasicProperties::default(), b"hello".to_vec(), publish_args)
+///     .await?;
+///
+/// // ...connection is lost, messages are buffered until reconnected
+///
+/// let connection = Connection::open(&args).await?;
+/// let channel = connection.open_channel(None).await?;
+/// // re-declare topology, then send buffered messages
+/// buffer.flush(&channel).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`publish`]: struct.PublishBuffer.html#method.publish
+/// [`flush`]: struct.PublishBuffer.html#method.flush
+pub struct PublishBuffer {
+    capacity: usize,
+    messages: Mutex<VecDeque<BufferedPublish>>,
+    /// held while flushing, so that concurrent flushes do not send a message twice
+    flushing: tokio::sync::Mutex<()>,
+}
+
+impl PublishBuffer {
+    /// Create a buffer which holds at most `capacity` messages.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            messages: Mutex::new(VecDeque::new()),
+            flushing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Maximum number of messages the buffer holds.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of messages currently buffered.
+    pub fn len(&self) -> usize {
+        self.messages.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no message is buffered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Publish the message on `channel` if it is usable, otherwise hold it in the buffer.
+    ///
+    /// See [`Channel::basic_publish`](struct.Channel.html#method.basic_publish).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BufferFull`] if the message needs to be buffered but the buffer
+    /// has reached its capacity.
+    ///
+    /// Returns error of [`Channel::basic_publish`](struct.Channel.html#method.basic_publish)
+    /// if the channel is open but publishing fails.
+    pub async fn publish(
+        &self,
+        channel: &Channel,
+        basic_properties: BasicProperties,
+        content: Vec<u8>,
+        args: BasicPublishArguments,
+    ) -> Result<()> {
+        {
+            let mut messages = self.messages.lock().unwrap();
+            if !messages.is_empty() || !channel.is_open() || !channel.is_connection_open() {
+                if messages.len() >= self.capacity {
+                    return Err(Error::BufferFull(format!(
+                        "{} messages are buffered, capacity is {}",
+                        messages.len(),
+                        self.capacity
+                    )));
+                }
+                messages.push_back(BufferedPublish {
+                    basic_properties,
+                    content,
+                    args,
+                });
+                return Ok(());
+            }
+        }
+        channel.basic_publish(basic_properties, content, args).await
+    }
+
+    /// Send all buffered messages on `channel` in the order they were published.
+    ///
+    /// Returns the number of messages sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if publishing fails, the failed message and all messages after it
+    /// are kept in the buffer to be flushed again.
+    pub async fn flush(&self, channel: &Channel) -> Result<usize> {
+        let _flushing = self.flushing.lock().await;
+        let mut count = 0;
+        loop {
+            // a copy is published, the message is removed from the buffer once it is sent
+            let message = match self.messages.lock().unwrap().front() {
+                Some(message) => message.clone(),
+                None => return Ok(count),
+            };
+            if !channel.is_open() || !channel.is_connection_open() {
+                return Err(Error::ChannelClosed(format!(
+                    "failed to flush buffered messages on channel {}",
+                    channel.channel_id()
+                )));
+            }
+            channel
+                .basic_publish(message.basic_properties, message.content, message.args)
+                .await?;
+            self.messages.lock().unwrap().pop_front();
+            count += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PublishBuffer;
+    use crate::{
+        api::error::Error,
+        channel::{BasicGetArguments, BasicPublishArguments, QueueDeclareArguments},
+        connection::{Connection, OpenConnectionArguments},
+        frame::Frame,
+        test_utils::{channel_reply, mock_broker, setup_logging},
+        BasicProperties,
+    };
+
+    #[tokio::test]
+    async fn test_publish_buffer_store_and_forward() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_publish_buffer_store_and_forward")
+            .finish();
+
+        // queue outlives the producer connection
+        let consumer_connection = Connection::open(&args).await.unwrap();
+        let consumer_channel = consumer_connection.open_channel(None).await.unwrap();
+        let (queue_name, ..) = consumer_channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let buffer = PublishBuffer::new(3);
+        let publish_args = BasicPublishArguments::new("", &queue_name);
+
+        // simulate disconnect
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        connection.clone().close().await.unwrap();
+        assert!(!channel.is_connection_open());
+
+        for i in 0..3u8 {
+            buffer
+                .publish(
+                    &channel,
+                    BasicProperties::default(),
+                    vec![i],
+                    publish_args.clone(),
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(3, buffer.len());
+        let result = buffer
+            .publish(
+                &channel,
+                BasicProperties::default(),
+                vec![3],
+                publish_args.clone(),
+            )
+            .await;
+        assert!(matches!(result, Err(Error::BufferFull(_))));
+
+        // reconnect and flush
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        assert_eq!(3, buffer.flush(&channel).await.unwrap());
+        assert!(buffer.is_empty());
+
+        for i in 0..3u8 {
+            let mut message = None;
+            for _ in 0..50 {
+                message = consumer_channel
+                    .basic_get(BasicGetArguments::new(&queue_name).no_ack(true).finish())
+                    .await
+                    .unwrap();
+                if message.is_some() {
+                    break;
+                }
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            }
+            let (_, _, content) = message.expect("buffered message must be sent");
+            assert_eq!(vec![i], content);
+        }
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        consumer_channel.close().await.unwrap();
+        consumer_connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_publish_buffer_flush_failure() {
+        setup_logging();
+
+        // mock server records the content of published messages
+        let (port, server) = mock_broker(vec![], |published, _, frame| match frame {
+            Frame::Publish(..) | Frame::ContentHeader(..) => vec![],
+            Frame::ContentBody(body) => {
+                published.push(body.inner);
+                vec![]
+            }
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        channel.clone().close().await.unwrap();
+
+        // buffered while the channel is closed, the second message can never be published
+        let buffer = PublishBuffer::new(3);
+        let long_key = "k".repeat(256);
+        for (i, routing_key) in ["key", &long_key, "key"].iter().enumerate() {
+            buffer
+                .publish(
+                    &channel,
+                    BasicProperties::default(),
+                    vec![i as u8],
+                    BasicPublishArguments::new("amq.direct", routing_key),
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(3, buffer.len());
+
+        // flushing into a closed channel keeps all messages
+        let result = buffer.flush(&channel).await;
+        assert!(
+            matches!(result, Err(Error::ChannelClosed(_))),
+            "{:?}",
+            result
+        );
+        assert_eq!(3, buffer.len());
+
+        // the failed message and the messages after it are kept
+        let channel = connection.open_channel(None).await.unwrap();
+        assert!(buffer.flush(&channel).await.is_err());
+        assert_eq!(2, buffer.len());
+        assert!(buffer.flush(&channel).await.is_err());
+        assert_eq!(2, buffer.len());
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+        assert_eq!(vec![vec![0]], server.await.unwrap());
+    }
+}
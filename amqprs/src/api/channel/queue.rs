@@ -1,17 +1,47 @@
 use amqp_serde::types::AmqpMessageCount;
 
-use super::Channel;
+use super::{check_reserved_name, to_short_str, Channel};
 use crate::{
     api::{error::Error, FieldTable, Result},
     frame::{
-        BindQueue, BindQueueOk, DeclareQueue, DeclareQueueOk, DeleteQueue, DeleteQueueOk, Frame,
-        PurgeQueue, PurgeQueueOk, UnbindQueue, UnbindQueueOk,
+        BindQueue, BindQueueOk, CloseChannel, DeclareQueue, DeclareQueueOk, DeleteQueue,
+        DeleteQueueOk, Frame, PurgeQueue, PurgeQueueOk, UnbindQueue, UnbindQueueOk, NOT_FOUND,
+        PRECONDITION_FAILED, RESOURCE_LOCKED,
     },
+    FieldValue,
 };
+#[cfg(feature = "traces")]
+use tracing::warn;
 
 #[cfg(feature = "compliance_assert")]
 use crate::api::compliance_asserts::{assert_exchange_name, assert_queue_name};
 
+/// Quorum queue argument of the number of initial replicas.
+const X_QUORUM_INITIAL_GROUP_SIZE: &str = "x-quorum-initial-group-size";
+/// Quorum queue argument of the strategy to place the leader replica.
+const X_QUEUE_LEADER_LOCATOR: &str = "x-queue-leader-locator";
+
+/// Strategy to place the leader replica of a quorum queue among the cluster nodes,
+/// see [`QueueDeclareArguments::queue_leader_locator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaderLocator {
+    /// Place the leader on the node the declaring client is connected to.
+    ClientLocal,
+    /// Place the leader on the node hosting the fewest leaders,
+    /// or on a random node if there are many queues.
+    Balanced,
+}
+
+impl LeaderLocator {
+    /// Returns the value of `x-queue-leader-locator` argument.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LeaderLocator::ClientLocal => "client-local",
+            LeaderLocator::Balanced => "balanced",
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 /// Arguments for [`queue_declare`]
 ///
@@ -80,6 +110,15 @@ impl QueueDeclareArguments {
         }
     }
 
+    /// Arguments of a transient, non-exclusive, non-autodelete queue named by server.
+    ///
+    /// The generated name is returned by [`queue_declare`], so `no_wait` must stay `false`.
+    ///
+    /// [`queue_declare`]: struct.Channel.html#method.queue_declare
+    pub fn server_named() -> Self {
+        Self::new("")
+    }
+
     /// Arguments of an exclusive, transient, server-named
     // queue. Usually a good fit for queues that store client-specific transient state.
     pub fn exclusive_server_named() -> Self {
@@ -138,6 +177,37 @@ impl QueueDeclareArguments {
         /// Chainable setter method.
         arguments, FieldTable
     }
+
+    /// Chainable setter method to set the number of initial replicas of a quorum queue
+    /// by `x-quorum-initial-group-size` argument.
+    ///
+    /// Server uses the cluster size if it is larger, and rejects `0`.
+    /// It only applies to a queue declared with `x-queue-type` of `quorum`.
+    ///
+    /// See [RabbitMQ quorum queues](https://www.rabbitmq.com/quorum-queues.html#replication-factor).
+    pub fn quorum_initial_group_size(&mut self, size: u8) -> &mut Self {
+        self.arguments.insert(
+            X_QUORUM_INITIAL_GROUP_SIZE.try_into().unwrap(),
+            FieldValue::I(size.into()),
+        );
+        self
+    }
+
+    /// Chainable setter method to set the placement of the leader replica of a quorum queue
+    /// by `x-queue-leader-locator` argument.
+    ///
+    /// It only applies to a queue declared with `x-queue-type` of `quorum`,
+    /// and overrides the `queue_leader_locator` setting of server.
+    ///
+    /// See [RabbitMQ quorum queues](https://www.rabbitmq.com/quorum-queues.html#leader-placement).
+    pub fn queue_leader_locator(&mut self, locator: LeaderLocator) -> &mut Self {
+        self.arguments.insert(
+            X_QUEUE_LEADER_LOCATOR.try_into().unwrap(),
+            FieldValue::S(locator.as_str().try_into().unwrap()),
+        );
+        self
+    }
+
     /// Finish chained configuration and return new arguments.
     pub fn finish(&mut self) -> Self {
         #[cfg(feature = "compliance_assert")]
@@ -145,6 +215,58 @@ impl QueueDeclareArguments {
 
         self.clone()
     }
+
+    /// Returns the queue name, empty if it is to be named by server.
+    pub(super) fn queue_name(&self) -> &str {
+        &self.queue
+    }
+
+    pub(super) fn is_passive(&self) -> bool {
+        self.passive
+    }
+
+    /// Check for flag combinations which server rejects, or which cannot work as intended.
+    ///
+    /// [`queue_declare`] validates its arguments, call this to catch a configuration
+    /// mistake earlier, e.g. when loading the configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelUseError`] if the queue is to be named by server and `no_wait`
+    /// is `true`, because the generated name would never be known.
+    ///
+    /// Returns [`Error::ReservedName`] if the queue name starts with `amq.` and the declare
+    /// is not passive.
+    ///
+    /// [`queue_declare`]: struct.Channel.html#method.queue_declare
+    pub fn validate(&self) -> Result<()> {
+        if self.queue.is_empty() && self.no_wait && !self.passive {
+            return Err(Error::ChannelUseError(
+                "server-named queue can't be declared with no_wait".to_string(),
+            ));
+        }
+        check_reserved_name("queue name", &self.queue, self.passive)
+    }
+
+    /// Flag combinations accepted by server which do not have the intended effect.
+    #[cfg(any(feature = "traces", test))]
+    fn warnings(&self) -> Vec<&'static str> {
+        let mut warnings = Vec::new();
+        if self.passive
+            && (self.durable || self.exclusive || self.auto_delete || !self.arguments.is_empty())
+        {
+            warnings.push(
+                "passive declare only checks that the queue exists, \
+                durable, exclusive, auto_delete and arguments are ignored",
+            );
+        }
+        if !self.passive && self.exclusive && self.durable {
+            warnings.push(
+                "exclusive queue is deleted when its connection closes, durable has no effect",
+            );
+        }
+        warnings
+    }
 }
 ////////////////////////////////////////////////////////////////////////////////
 /// Arguments for [`queue_bind`]
@@ -240,6 +362,21 @@ impl QueuePurgeArguments {
             no_wait: false,
         }
     }
+    impl_chainable_setter! {
+        /// Chainable setter method.
+        queue, String
+    }
+    impl_chainable_setter! {
+        /// Chainable setter method.
+        no_wait, bool
+    }
+    /// Finish chained configuration and return new arguments.
+    pub fn finish(&mut self) -> Self {
+        #[cfg(feature = "compliance_assert")]
+        assert_queue_name(&self.queue);
+
+        self.clone()
+    }
 }
 ////////////////////////////////////////////////////////////////////////////////
 /// Arguments for [`queue_delete`]
@@ -358,6 +495,20 @@ impl QueueUnbindArguments {
     }
 }
 
+/// Map `channel.close` from server in response to `queue.declare` to error.
+fn queue_declare_error(queue: &str, close_channel: CloseChannel) -> Error {
+    if close_channel.reply_code() == PRECONDITION_FAILED {
+        Error::QueueArgumentMismatch(format!("queue '{}': {}", queue, close_channel.reply_text()))
+    } else if close_channel.reply_code() == RESOURCE_LOCKED {
+        Error::ChannelClosed(format!(
+            "queue '{}' is exclusive to another connection: {}",
+            queue, close_channel
+        ))
+    } else {
+        close_channel.into()
+    }
+}
+
 /////////////////////////////////////////////////////////////////////////////
 /// APIs for AMQP queue class.
 impl Channel {
@@ -367,15 +518,37 @@ impl Channel {
     ///
     /// Returns a tuple `(queue_name, message_count, consumer_count)`
     /// if `no_wait` argument is `false`, otherwise returns [`None`].
+    /// If the queue name in `args` is empty, `queue_name` is the name generated by server.
     ///
     /// # Errors
     ///
+    /// Returns [`Error::ChannelUseError`] if `args` fails [`QueueDeclareArguments::validate`].
+    /// Flag combinations which server accepts but ignores are logged as warnings.
+    ///
+    /// Returns [`Error::QueueArgumentMismatch`] if the queue already exists with
+    /// inequivalent arguments, e.g. different durability, and server closes the channel
+    /// with `PRECONDITION_FAILED`. Returns [`Error::ChannelClosed`] with `RESOURCE_LOCKED` if
+    /// the queue is exclusive to another connection, or [`Error::ClosedByServer`] if server
+    /// closes the channel for other reasons.
+    ///
     /// Returns error if any failure in comunication with server.
     pub async fn queue_declare(
         &self,
         args: QueueDeclareArguments,
     ) -> Result<Option<(String, AmqpMessageCount, u32)>> {
-        let mut declare = DeclareQueue::new(0, args.queue.try_into().unwrap(), args.arguments);
+        args.validate()?;
+        #[cfg(feature = "traces")]
+        for warning in args.warnings() {
+            warn!(
+                "declare queue '{}' on channel {}: {}",
+                args.queue, self, warning
+            );
+        }
+        let queue = args.queue.clone();
+        // exclusive queues are recorded to be re-declared on recovery
+        let exclusive = (args.exclusive && !args.passive).then(|| args.clone());
+        let mut declare =
+            DeclareQueue::new(0, to_short_str("queue name", args.queue)?, args.arguments);
         declare.set_passive(args.passive);
         declare.set_durable(args.durable);
         declare.set_exclusive(args.exclusive);
@@ -386,18 +559,30 @@ impl Channel {
                 .outgoing_tx
                 .send((self.channel_id(), declare.into_frame()))
                 .await?;
+            if let Some(exclusive) = exclusive {
+                self.connection.record_exclusive_queue(queue, exclusive);
+            }
             Ok(None)
         } else {
             let responder_rx = self.register_responder(DeclareQueueOk::header()).await?;
-            let declare_ok = synchronous_request!(
-                self.shared.outgoing_tx,
-                (self.channel_id(), declare.into_frame()),
-                responder_rx,
-                Frame::DeclareQueueOk,
-                Error::ChannelUseError
-            )?;
+            self.shared
+                .outgoing_tx
+                .send((self.channel_id(), declare.into_frame()))
+                .await?;
+            let declare_ok = match responder_rx.await? {
+                Frame::DeclareQueueOk(_, declare_ok) => declare_ok,
+                Frame::CloseChannel(_, close_channel) => {
+                    return Err(queue_declare_error(&queue, close_channel))
+                }
+                unexpected => return Err(Error::ChannelUseError(unexpected.to_string())),
+            };
+            let queue_name: String = declare_ok.queue.into();
+            if let Some(exclusive) = exclusive {
+                self.connection
+                    .record_exclusive_queue(queue_name.clone(), exclusive);
+            }
             Ok(Some((
-                declare_ok.queue.into(),
+                queue_name,
                 declare_ok.message_count,
                 declare_ok.consumer_count,
             )))
@@ -410,11 +595,12 @@ impl Channel {
     ///
     /// Returns error if any failure in comunication with server.
     pub async fn queue_bind(&self, args: QueueBindArguments) -> Result<()> {
+        let binding = args.clone();
         let bind = BindQueue::new(
             0,
-            args.queue.try_into().unwrap(),
-            args.exchange.try_into().unwrap(),
-            args.routing_key.try_into().unwrap(),
+            to_short_str("queue name", args.queue)?,
+            to_short_str("exchange name", args.exchange)?,
+            to_short_str("routing key", args.routing_key)?,
             args.no_wait,
             args.arguments,
         );
@@ -435,6 +621,36 @@ impl Channel {
                 Error::ChannelUseError
             )?;
         }
+        self.connection.record_queue_binding(binding);
+        Ok(())
+    }
+
+    /// Bind queues in a batch, without waiting for each `bind-ok`.
+    ///
+    /// All bindings except the last one are sent with `no_wait`, then it waits for the
+    /// `bind-ok` of the last one. Server handles methods of a channel in order, so all
+    /// bindings are done once it returns. This is much faster than [`queue_bind`] one by one
+    /// when setting up a large topology.
+    ///
+    /// # Errors
+    ///
+    /// If a binding fails, server closes the channel and [`Error::ClosedByServer`] is returned
+    /// with the reason, e.g. `NOT_FOUND` if the exchange does not exist. Bindings before the
+    /// failed one are done.
+    ///
+    /// Returns error if any failure in comunication with server.
+    ///
+    /// [`queue_bind`]: struct.Channel.html#method.queue_bind
+    pub async fn queue_bind_batch(&self, bindings: Vec<QueueBindArguments>) -> Result<()> {
+        let count = bindings.len();
+        for (i, mut args) in bindings.into_iter().enumerate() {
+            args.no_wait = i + 1 < count;
+            if let Err(err) = self.queue_bind(args).await {
+                // channel may be closed by a former binding before the last one is sent
+                let reason = self.shared.closed.borrow().clone().flatten();
+                return Err(reason.map_or(err, Error::from));
+            }
+        }
         Ok(())
     }
 
@@ -448,7 +664,7 @@ impl Channel {
     ///
     /// Returns error if any failure in comunication with server.
     pub async fn queue_purge(&self, args: QueuePurgeArguments) -> Result<Option<AmqpMessageCount>> {
-        let purge = PurgeQueue::new(0, args.queue.try_into().unwrap(), args.no_wait);
+        let purge = PurgeQueue::new(0, to_short_str("queue name", args.queue)?, args.no_wait);
 
         if args.no_wait {
             self.shared
@@ -482,7 +698,9 @@ impl Channel {
         &self,
         args: QueueDeleteArguments,
     ) -> Result<Option<AmqpMessageCount>> {
-        let mut delete = DeleteQueue::new(0, args.queue.try_into().unwrap());
+        self.shared.known_queues.lock().unwrap().remove(&args.queue);
+        self.connection.forget_exclusive_queue(&args.queue);
+        let mut delete = DeleteQueue::new(0, to_short_str("queue name", args.queue)?);
         delete.set_if_unused(args.if_unused);
         delete.set_if_empty(args.if_empty);
         delete.set_no_wait(args.no_wait);
@@ -505,17 +723,65 @@ impl Channel {
             Ok(Some(delete_ok.message_count))
         }
     }
+
+    /// Check that `queue` exists for strict default exchange, queues known to exist are cached.
+    pub(super) async fn check_queue_exists(&self, queue: &str) -> Result<()> {
+        if self.shared.known_queues.lock().unwrap().contains(queue) {
+            return Ok(());
+        }
+        self.probe_queue(queue).await?;
+        self.shared
+            .known_queues
+            .lock()
+            .unwrap()
+            .insert(queue.to_owned());
+        Ok(())
+    }
+
+    /// Check that `queue` exists by a passive declare, returns [`Error::QueueNotFound`] if not.
+    ///
+    /// Server closes the channel if a passive declare finds no queue,
+    /// so it is declared on a temporary channel.
+    pub(crate) async fn probe_queue(&self, queue: &str) -> Result<()> {
+        let probe = self.connection.open_channel(None).await?;
+        let mut declare = DeclareQueue::new(
+            0,
+            to_short_str("queue name", queue.to_owned())?,
+            FieldTable::new(),
+        );
+        declare.set_passive(true);
+        let responder_rx = probe.register_responder(DeclareQueueOk::header()).await?;
+        probe
+            .shared
+            .outgoing_tx
+            .send((probe.channel_id(), declare.into_frame()))
+            .await?;
+        match responder_rx.await? {
+            Frame::DeclareQueueOk(..) => probe.close().await,
+            Frame::CloseChannel(_, close_channel) if close_channel.reply_code() == NOT_FOUND => {
+                Err(Error::QueueNotFound(format!(
+                    "queue '{}': {}",
+                    queue,
+                    close_channel.reply_text()
+                )))
+            }
+            Frame::CloseChannel(_, close_channel) => Err(queue_declare_error(queue, close_channel)),
+            unexpected => Err(Error::ChannelUseError(unexpected.to_string())),
+        }
+    }
+
     /// See [AMQP_0-9-1 Reference](https://www.rabbitmq.com/amqp-0-9-1-reference.html#queue.unbind)
     ///
     /// # Errors
     ///
     /// Returns error if any failure in comunication with server.
     pub async fn queue_unbind(&self, args: QueueUnbindArguments) -> Result<()> {
+        let unbinding = args.clone();
         let unbind = UnbindQueue::new(
             0,
-            args.queue.try_into().unwrap(),
-            args.exchange.try_into().unwrap(),
-            args.routing_key.try_into().unwrap(),
+            to_short_str("queue name", args.queue)?,
+            to_short_str("exchange name", args.exchange)?,
+            to_short_str("routing key", args.routing_key)?,
             args.arguments,
         );
 
@@ -528,6 +794,7 @@ impl Channel {
             Frame::UnbindQueueOk,
             Error::ChannelUseError
         )?;
+        self.connection.forget_queue_binding(&unbinding);
         Ok(())
     }
 }
@@ -536,13 +803,25 @@ impl Channel {
 mod tests {
 
     use crate::{
+        api::error::Error,
         callbacks::{DefaultChannelCallback, DefaultConnectionCallback},
+        channel::{
+            BasicGetArguments, BasicPublishArguments, ExchangeDeclareArguments,
+            ExchangeDeleteArguments,
+        },
         connection::{Connection, OpenConnectionArguments},
+        frame::{BindQueueOk, CloseChannel, CloseKind, Frame},
+        test_utils::{
+            channel_reply, close_channel, mock_broker, setup_logging, short_strs, ReturnRecorder,
+        },
+        BasicProperties, FieldValue,
     };
+    use std::collections::HashSet;
+    use tokio::{sync::mpsc, time};
 
     use super::{
-        QueueBindArguments, QueueDeclareArguments, QueueDeleteArguments, QueuePurgeArguments,
-        QueueUnbindArguments,
+        queue_declare_error, LeaderLocator, QueueBindArguments, QueueDeclareArguments,
+        QueueDeleteArguments, QueuePurgeArguments, QueueUnbindArguments,
     };
 
     #[tokio::test]
@@ -600,4 +879,509 @@ mod tests {
         channel.close().await.unwrap();
         connection.close().await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_declare_server_named_queue() {
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_declare_server_named_queue")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+
+        let (queue_name, message_count, consumer_count) = channel
+            .queue_declare(
+                QueueDeclareArguments::server_named()
+                    .exclusive(true)
+                    .finish(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!queue_name.is_empty());
+        assert_eq!(0, message_count);
+        assert_eq!(0, consumer_count);
+        // generated name can be used to refer to the queue
+        channel
+            .queue_bind(QueueBindArguments::new(
+                &queue_name,
+                "amq.topic",
+                "amqprs.server.named",
+            ))
+            .await
+            .unwrap();
+
+        let result = channel
+            .queue_declare(QueueDeclareArguments::server_named().no_wait(true).finish())
+            .await;
+        assert!(matches!(result, Err(Error::ChannelUseError(_))));
+        assert!(channel.is_open());
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_queue_bind_batch() {
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_queue_bind_batch")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+
+        let mut queue_names = vec![];
+        for _ in 0..50 {
+            let (queue_name, ..) = channel
+                .queue_declare(QueueDeclareArguments::exclusive_server_named())
+                .await
+                .unwrap()
+                .unwrap();
+            queue_names.push(queue_name);
+        }
+        let bindings = queue_names
+            .iter()
+            .map(|name| QueueBindArguments::new(name, "amq.direct", name))
+            .collect();
+        channel.queue_bind_batch(bindings).await.unwrap();
+
+        // each queue receives the message routed by its binding
+        for name in &queue_names {
+            channel
+                .basic_publish(
+                    BasicProperties::default(),
+                    name.as_bytes().to_vec(),
+                    BasicPublishArguments::new("amq.direct", name),
+                )
+                .await
+                .unwrap();
+        }
+        for name in &queue_names {
+            let (_, _, content) = channel
+                .basic_get(BasicGetArguments::new(name).no_ack(true).finish())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(name.as_bytes(), content);
+        }
+
+        // a failed binding closes the channel
+        let bindings = vec![
+            QueueBindArguments::new(&queue_names[0], "amq.direct", "batch"),
+            QueueBindArguments::new(&queue_names[1], "amqprs.batch.missing", "batch"),
+            QueueBindArguments::new(&queue_names[2], "amq.direct", "batch"),
+        ];
+        let err = channel.queue_bind_batch(bindings).await.unwrap_err();
+        assert!(matches!(err, Error::ClosedByServer { code: 404, .. }));
+        assert!(!channel.is_open());
+
+        connection.close().await.unwrap();
+    }
+
+    #[test]
+    fn test_queue_declare_error_mapping() {
+        let text = "PRECONDITION_FAILED - inequivalent arg 'durable' for queue 'orders'";
+        let mut bytes = vec![0x01, 0x96, text.len() as u8];
+        bytes.extend_from_slice(text.as_bytes());
+        bytes.extend_from_slice(&[0, 50, 0, 10]);
+        let close_channel: CloseChannel = amqp_serde::from_bytes(&bytes).unwrap();
+
+        match queue_declare_error("orders", close_channel) {
+            Error::QueueArgumentMismatch(msg) => {
+                assert_eq!(format!("queue 'orders': {}", text), msg)
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+
+        let close_channel: CloseChannel =
+            amqp_serde::from_bytes(&[0x01, 0x94, 0, 0, 50, 0, 10]).unwrap();
+        let err = queue_declare_error("orders", close_channel);
+        assert!(matches!(err, Error::ClosedByServer { code: 404, .. }));
+        assert_eq!(Some(CloseKind::Fatal), err.close_kind());
+
+        // 405 RESOURCE_LOCKED
+        let close_channel: CloseChannel =
+            amqp_serde::from_bytes(&[0x01, 0x95, 0, 0, 50, 0, 10]).unwrap();
+        match queue_declare_error("orders", close_channel) {
+            Error::ChannelClosed(msg) => {
+                assert!(msg.starts_with("queue 'orders' is exclusive to another connection"))
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_quorum_queue_arguments() {
+        let args = QueueDeclareArguments::durable_client_named("orders")
+            .quorum_initial_group_size(3)
+            .queue_leader_locator(LeaderLocator::ClientLocal)
+            .finish();
+        assert_eq!(2, args.arguments.len());
+        assert_eq!(
+            Some(&FieldValue::I(3)),
+            args.arguments
+                .get(&"x-quorum-initial-group-size".try_into().unwrap())
+        );
+        assert_eq!(
+            Some(&FieldValue::S("client-local".try_into().unwrap())),
+            args.arguments
+                .get(&"x-queue-leader-locator".try_into().unwrap())
+        );
+
+        // setting again replaces the value
+        let args = QueueDeclareArguments::new("orders")
+            .queue_leader_locator(LeaderLocator::ClientLocal)
+            .queue_leader_locator(LeaderLocator::Balanced)
+            .finish();
+        assert_eq!(1, args.arguments.len());
+        assert_eq!(
+            Some(&FieldValue::S("balanced".try_into().unwrap())),
+            args.arguments
+                .get(&"x-queue-leader-locator".try_into().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_queue_declare_arguments_validation() {
+        // server-named queue with no_wait
+        let args = QueueDeclareArguments::server_named().no_wait(true).finish();
+        assert!(matches!(args.validate(), Err(Error::ChannelUseError(_))));
+        assert!(QueueDeclareArguments::server_named()
+            .no_wait(true)
+            .passive(true)
+            .finish()
+            .validate()
+            .is_ok());
+
+        // passive declare ignores other flags
+        let args = QueueDeclareArguments::durable_client_named("orders")
+            .passive(true)
+            .finish();
+        assert!(args.validate().is_ok());
+        assert_eq!(1, args.warnings().len());
+        assert!(args.warnings()[0].starts_with("passive declare"));
+
+        // exclusive queue dies with its connection
+        let args = QueueDeclareArguments::exclusive_server_named()
+            .durable(true)
+            .finish();
+        assert_eq!(1, args.warnings().len());
+        assert!(args.warnings()[0].starts_with("exclusive queue"));
+
+        // reserved names can only be declared passively
+        let args = QueueDeclareArguments::new("amq.orders");
+        assert!(matches!(args.validate(), Err(Error::ReservedName(_))));
+        let args = QueueDeclareArguments::new("amq.orders")
+            .passive(true)
+            .finish();
+        assert!(args.validate().is_ok());
+
+        // sensible combinations
+        for args in [
+            QueueDeclareArguments::default(),
+            QueueDeclareArguments::durable_client_named("orders"),
+            QueueDeclareArguments::exclusive_server_named(),
+            QueueDeclareArguments::transient_autodelete("orders"),
+            QueueDeclareArguments::new("orders").passive(true).finish(),
+        ] {
+            assert!(args.validate().is_ok());
+            assert!(args.warnings().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queue_declare_argument_mismatch() {
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami");
+
+        let connection = Connection::open(&args).await.unwrap();
+        connection
+            .register_callback(DefaultConnectionCallback)
+            .await
+            .unwrap();
+
+        let queue_name = "amqprs.test.queue_declare_argument_mismatch";
+        let channel = connection.open_channel(None).await.unwrap();
+        channel
+            .queue_declare(
+                QueueDeclareArguments::new(queue_name)
+                    .durable(false)
+                    .finish(),
+            )
+            .await
+            .unwrap();
+
+        // re-declare with conflicting durability
+        let result = channel
+            .queue_declare(
+                QueueDeclareArguments::new(queue_name)
+                    .durable(true)
+                    .finish(),
+            )
+            .await;
+        match result {
+            Err(Error::QueueArgumentMismatch(msg)) => assert!(msg.contains(queue_name)),
+            other => panic!("expected queue argument mismatch, got {:?}", other),
+        }
+
+        // channel has been closed by server, clean up on a new channel
+        let channel = connection.open_channel(None).await.unwrap();
+        channel
+            .queue_delete(QueueDeleteArguments::new(queue_name))
+            .await
+            .unwrap();
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_no_wait_requests() {
+        setup_logging();
+
+        // mock broker never responds to `no_wait` requests, it closes the channel on the
+        // first `queue.purge` that waits for response, as if the earlier bind failed
+        let (port, server) = mock_broker(vec![], |received, _, frame| match frame {
+            Frame::Declare(..) => {
+                received.push("exchange.declare");
+                vec![]
+            }
+            Frame::Delete(..) => {
+                received.push("exchange.delete");
+                vec![]
+            }
+            Frame::BindQueue(..) => {
+                received.push("queue.bind");
+                vec![]
+            }
+            Frame::DeleteQueue(..) => {
+                received.push("queue.delete");
+                vec![]
+            }
+            Frame::PurgeQueue(_, purge) => {
+                received.push("queue.purge");
+                // `no_wait` is the last octet
+                let bytes = amqp_serde::to_bytes(&purge).unwrap();
+                if bytes[bytes.len() - 1] == 1 {
+                    vec![]
+                } else {
+                    // 404 NOT_FOUND, class queue, method bind
+                    let text = "NOT_FOUND - no exchange 'missing' in vhost '/'";
+                    vec![close_channel(404, text, 50, 20)]
+                }
+            }
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(Some(1)).await.unwrap();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        channel.register_callback(ReturnRecorder(tx)).await.unwrap();
+
+        let timeout = time::Duration::from_secs(5);
+        time::timeout(timeout, async {
+            channel
+                .exchange_declare(
+                    ExchangeDeclareArguments::new("logs", "fanout")
+                        .no_wait(true)
+                        .finish(),
+                )
+                .await
+                .unwrap();
+            channel
+                .queue_bind(
+                    QueueBindArguments::new("orders", "missing", "")
+                        .no_wait(true)
+                        .finish(),
+                )
+                .await
+                .unwrap();
+            let purged = channel
+                .queue_purge(QueuePurgeArguments::new("orders").no_wait(true).finish())
+                .await
+                .unwrap();
+            assert_eq!(None, purged);
+            let deleted = channel
+                .queue_delete(QueueDeleteArguments::new("orders").no_wait(true).finish())
+                .await
+                .unwrap();
+            assert_eq!(None, deleted);
+            channel
+                .exchange_delete(ExchangeDeleteArguments::new("logs").no_wait(true).finish())
+                .await
+                .unwrap();
+        })
+        .await
+        .unwrap();
+
+        // the pending request fails with the reason of closing
+        let err = time::timeout(
+            timeout,
+            channel.queue_purge(QueuePurgeArguments::new("orders")),
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+        assert!(matches!(err, Error::ClosedByServer { code: 404, .. }));
+        assert_eq!(Some(CloseKind::Fatal), err.close_kind());
+        assert!(err.to_string().contains("no exchange 'missing'"));
+
+        let reason = time::timeout(timeout, channel.closed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(404, reason.reply_code());
+        assert!(!channel.is_open());
+        assert!(channel
+            .queue_purge(QueuePurgeArguments::new("orders"))
+            .await
+            .is_err());
+
+        connection.close().await.unwrap();
+        let received = server.await.unwrap();
+        assert_eq!(
+            vec![
+                "exchange.declare",
+                "queue.bind",
+                "queue.purge",
+                "queue.delete",
+                "exchange.delete",
+                "queue.purge",
+            ],
+            received
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_bind_batch_no_wait() {
+        setup_logging();
+
+        // mock server records bindings, it has no exchange "missing"
+        let (port, server) = mock_broker(
+            (vec![], HashSet::new()),
+            |(bindings, closed), channel_id, frame| {
+                if closed.contains(&channel_id) && !matches!(frame, Frame::CloseChannelOk(..)) {
+                    // server ignores frames on a closing channel
+                    return vec![];
+                }
+                match frame {
+                    Frame::BindQueue(_, bind) => {
+                        // ticket, queue, exchange, routing key, then `no_wait`
+                        let bytes = amqp_serde::to_bytes(&bind).unwrap();
+                        let (strs, flags) = short_strs(&bytes, 3);
+                        let no_wait = bytes[flags] == 1;
+                        bindings.push(format!("{} {} {}", channel_id, strs.join(" "), no_wait));
+                        if strs[1] == "missing" {
+                            closed.insert(channel_id);
+                            // 404 NOT_FOUND, class queue, method bind
+                            let text = "NOT_FOUND - no exchange 'missing' in vhost '/'";
+                            vec![close_channel(404, text, 50, 20)]
+                        } else if no_wait {
+                            vec![]
+                        } else {
+                            vec![BindQueueOk.into_frame()]
+                        }
+                    }
+                    Frame::CloseChannelOk(..) => {
+                        closed.remove(&channel_id);
+                        vec![]
+                    }
+                    other => channel_reply(other),
+                }
+            },
+        )
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let timeout = time::Duration::from_secs(5);
+
+        // only the last binding waits for `bind-ok`
+        let channel = connection.open_channel(None).await.unwrap();
+        let bindings = (0..50)
+            .map(|i| QueueBindArguments::new(&format!("q{}", i), "amq.direct", "key"))
+            .collect();
+        time::timeout(timeout, channel.queue_bind_batch(bindings))
+            .await
+            .unwrap()
+            .unwrap();
+        channel.close().await.unwrap();
+
+        // a failed binding closes the channel, the batch fails with the reason
+        let channel = connection.open_channel(None).await.unwrap();
+        let bindings = vec![
+            QueueBindArguments::new("q0", "amq.direct", "key"),
+            QueueBindArguments::new("q1", "missing", "key"),
+            QueueBindArguments::new("q2", "amq.direct", "key"),
+        ];
+        let err = time::timeout(timeout, channel.queue_bind_batch(bindings))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::ClosedByServer { code: 404, .. }),
+            "{}",
+            err
+        );
+        assert!(err.to_string().contains("no exchange 'missing'"));
+        assert!(!channel.is_open());
+
+        connection.close().await.unwrap();
+        let mut expected: Vec<String> = (0..50)
+            .map(|i| format!("1 q{} amq.direct key {}", i, i < 49))
+            .collect();
+        // channel id is reused after the first channel closes
+        expected.push("1 q0 amq.direct key true".to_owned());
+        expected.push("1 q1 missing key true".to_owned());
+        assert_eq!(expected, server.await.unwrap().0);
+    }
+
+    #[tokio::test]
+    async fn test_queue_argument_mismatch_fails_pending_requests() {
+        setup_logging();
+
+        // mock server closes the channel with `PRECONDITION_FAILED` once both
+        // `queue.declare` and `queue.bind` are pending
+        let (port, server) = mock_broker(0, |pending, _, frame| match frame {
+            Frame::DeclareQueue(..) | Frame::BindQueue(..) => {
+                *pending += 1;
+                if *pending < 2 {
+                    return vec![];
+                }
+                // 406 PRECONDITION_FAILED, class queue, method declare
+                let text = "PRECONDITION_FAILED - inequivalent arg 'durable' \
+                    for queue 'orders' in vhost '/'";
+                vec![close_channel(406, text, 50, 10)]
+            }
+            other => channel_reply(other),
+        })
+        .await;
+
+        let args = OpenConnectionArguments::new("127.0.0.1", port, "user", "bitnami");
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+        let declare = channel.queue_declare(QueueDeclareArguments::durable_client_named("orders"));
+        let bind = channel.queue_bind(QueueBindArguments::new("orders", "amq.topic", "orders.#"));
+        let (declared, bound) = time::timeout(time::Duration::from_secs(5), async {
+            tokio::join!(declare, bind)
+        })
+        .await
+        .unwrap();
+
+        // the declare fails with the mismatch, the other pending request with the reason
+        match declared {
+            Err(Error::QueueArgumentMismatch(msg)) => assert!(msg.contains("'orders'"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match bound {
+            Err(Error::ClosedByServer {
+                code: 406, text, ..
+            }) => {
+                assert!(text.contains("inequivalent arg 'durable'"), "{}", text)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        connection.close().await.unwrap();
+        server.await.unwrap();
+    }
 }
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use super::{
+    BasicConsumeArguments, BasicQosArguments, Channel, ConsumerStream, QueueBindArguments,
+    QueueDeclareArguments, QueueDeleteArguments,
+};
+use crate::api::{
+    connection::{Connection, RegisteredConsumer},
+    error::Error,
+    Result,
+};
+#[cfg(feature = "traces")]
+use tracing::error;
+
+////////////////////////////////////////////////////////////////////////////////
+/// Arguments for [`setup_consumer`]
+///
+/// # Support chainable methods to build arguments
+/// ```
+/// # use amqprs::channel::{
+/// #     BasicConsumeArguments, BasicQosArguments, QueueDeclareArguments, SetupConsumerArguments,
+/// # };
+///
+/// let x = SetupConsumerArguments::new(QueueDeclareArguments::durable_client_named("orders"))
+///     .bind("amq.topic", "orders.#")
+///     .qos(BasicQosArguments::new(0, 100, false))
+///     .consume(
+///         BasicConsumeArguments::default()
+///             .consumer_tag("orders-consumer".to_owned())
+///             .finish(),
+///     )
+///     .finish();
+/// ```
+///
+/// [`setup_consumer`]: struct.Channel.html#method.setup_consumer
+#[derive(Debug, Clone, Default)]
+pub struct SetupConsumerArguments {
+    /// Arguments to declare the queue. `no_wait` is ignored, because the declared
+    /// queue name is needed by the other steps.
+    pub queue: QueueDeclareArguments,
+    /// Bindings of the queue as `(exchange, routing_key)`. Default: empty.
+    pub bindings: Vec<(String, String)>,
+    /// Qos of the channel, not changed if `None`. Default: `None`.
+    pub qos: Option<BasicQosArguments>,
+    /// Arguments to consume the queue. The `queue` field is replaced by the
+    /// declared queue name. Default: server-generated consumer tag.
+    pub consume: BasicConsumeArguments,
+}
+
+impl SetupConsumerArguments {
+    /// Create new arguments to declare queue by `queue`, without bindings and qos.
+    pub fn new(queue: QueueDeclareArguments) -> Self {
+        Self {
+            queue,
+            bindings: Vec::new(),
+            qos: None,
+            consume: BasicConsumeArguments::default(),
+        }
+    }
+
+    /// Chainable setter method to add a binding of the queue to `exchange` with `routing_key`.
+    pub fn bind(&mut self, exchange: &str, routing_key: &str) -> &mut Self {
+        self.bindings
+            .push((exchange.to_owned(), routing_key.to_owned()));
+        self
+    }
+
+    /// Chainable setter method.
+    pub fn qos(&mut self, qos: BasicQosArguments) -> &mut Self {
+        self.qos = Some(qos);
+        self
+    }
+
+    impl_chainable_setter! {
+        /// Chainable setter method.
+        consume, BasicConsumeArguments
+    }
+
+    /// Finish chained configuration and return new arguments.
+    pub fn finish(&mut self) -> Self {
+        self.clone()
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+/// APIs to set up a consumer in one call.
+impl Channel {
+    /// Declare a queue, bind it to exchanges, set qos, and start consuming it.
+    ///
+    /// It combines [`queue_declare`], [`queue_bind`], [`basic_qos`] and [`basic_consume_stream`].
+    ///
+    /// If succeed, returns the queue name and the [`ConsumerStream`].
+    ///
+    /// If any step after declaring the queue fails, the queue is deleted again if it is
+    /// created by this call, i.e. it is server-named or did not exist before.
+    /// A queue which already existed is never deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the failed step.
+    ///
+    /// [`queue_declare`]: struct.Channel.html#method.queue_declare
+    /// [`queue_bind`]: struct.Channel.html#method.queue_bind
+    /// [`basic_qos`]: struct.Channel.html#method.basic_qos
+    /// [`basic_consume_stream`]: struct.Channel.html#method.basic_consume_stream
+    pub async fn setup_consumer(
+        &self,
+        args: SetupConsumerArguments,
+    ) -> Result<(String, ConsumerStream)> {
+        let SetupConsumerArguments {
+            mut queue,
+            bindings,
+            qos,
+            mut consume,
+        } = args;
+
+        let creates_queue = if queue.is_passive() {
+            false
+        } else if queue.queue_name().is_empty() {
+            true
+        } else {
+            match self.probe_queue(queue.queue_name()).await {
+                Ok(()) => false,
+                Err(Error::QueueNotFound(_)) => true,
+                Err(err) => return Err(err),
+            }
+        };
+
+        let (queue_name, ..) = self
+            .queue_declare(queue.no_wait(false).finish())
+            .await?
+            .expect("queue.declare-ok is expected if no_wait is false");
+
+        let result = async {
+            for (exchange, routing_key) in bindings {
+                self.queue_bind(QueueBindArguments::new(
+                    &queue_name,
+                    &exchange,
+                    &routing_key,
+                ))
+                .await?;
+            }
+            if let Some(qos) = qos {
+                self.basic_qos(qos).await?;
+            }
+            self.basic_consume_stream(consume.queue(queue_name.clone()).finish())
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(stream) => Ok((queue_name, stream)),
+            Err(err) => {
+                if creates_queue {
+                    if let Err(_cleanup_err) = self.delete_queue_on_open_channel(&queue_name).await
+                    {
+                        #[cfg(feature = "traces")]
+                        error!(
+                            "failed to delete queue {} after failed setup on channel {}, cause: {}",
+                            queue_name, self, _cleanup_err
+                        );
+                    }
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Prepare the queues of `consumers` recorded by [`Connection::consumers`] of `previous`
+    /// connection, so that they can be consumed again on this channel after `previous` has
+    /// been lost and this channel is opened on a new connection.
+    ///
+    /// Server deletes an exclusive queue when the connection which declared it closes, so
+    /// each exclusive queue declared on `previous` connection and consumed by any of
+    /// `consumers` is re-declared and re-bound once on this channel. A server-named queue
+    /// gets a new name, which is used by all consumers of the queue.
+    /// Other queues are expected to survive, they are not declared again.
+    ///
+    /// Returns the arguments to consume the queues with, in the order of `consumers`, the same
+    /// as those of each consumer but for the queue name. Pass them to the consume method which
+    /// matches [`RegisteredConsumer::handler`] to re-attach the consumers.
+    /// [`Connection::recover`] does all of it for each channel of `previous` connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of re-declaring or re-binding a queue.
+    ///
+    /// [`Connection::consumers`]: ../connection/struct.Connection.html#method.consumers
+    /// [`Connection::recover`]: ../connection/struct.Connection.html#method.recover
+    /// [`RegisteredConsumer::handler`]: ../connection/struct.RegisteredConsumer.html#method.handler
+    pub async fn recover_consumer_queues(
+        &self,
+        previous: &Connection,
+        consumers: &[RegisteredConsumer],
+    ) -> Result<Vec<BasicConsumeArguments>> {
+        // names of the queues on `previous` connection to their names on this channel
+        let mut recovered: HashMap<String, String> = HashMap::new();
+        let mut consume_args = Vec::with_capacity(consumers.len());
+        for consumer in consumers {
+            let mut args = consumer.args().clone();
+            if let Some(queue_name) = recovered.get(&args.queue) {
+                args.queue = queue_name.clone();
+            } else if let Some(exclusive_queue) = previous.exclusive_queue(&args.queue) {
+                let (queue_name, ..) = self
+                    .queue_declare(exclusive_queue.declare.clone().no_wait(false).finish())
+                    .await?
+                    .ok_or_else(|| {
+                        Error::ChannelUseError(format!(
+                            "no queue.declare-ok to re-declare exclusive queue {}",
+                            args.queue
+                        ))
+                    })?;
+                for mut binding in exclusive_queue.bindings {
+                    binding.queue = queue_name.clone();
+                    self.queue_bind(binding).await?;
+                }
+                recovered.insert(args.queue.clone(), queue_name.clone());
+                args.queue = queue_name;
+            }
+            consume_args.push(args);
+        }
+        Ok(consume_args)
+    }
+
+    /// Delete the queue on this channel, or on a short-lived channel if this channel
+    /// has been closed by the failure.
+    async fn delete_queue_on_open_channel(&self, queue: &str) -> Result<()> {
+        if self.is_open() {
+            self.queue_delete(QueueDeleteArguments::new(queue)).await?;
+        } else {
+            let channel = self.connection.open_channel(None).await?;
+            channel
+                .queue_delete(QueueDeleteArguments::new(queue))
+                .await?;
+            channel.close().await?;
+        }
+        Ok(())
+    }
+}
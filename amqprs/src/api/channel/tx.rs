@@ -14,7 +14,7 @@ use crate::{
     frame::{Frame, TxCommit, TxCommitOk, TxRollback, TxRollbackOk, TxSelect, TxSelectOk},
 };
 
-use super::Channel;
+use super::{Channel, ChannelMode};
 
 /// APIs for AMQP transaction class.
 impl Channel {
@@ -24,11 +24,15 @@ impl Channel {
     /// Also see [AMQP_0-9-1 Reference](https://www.rabbitmq.com/amqp-0-9-1-reference.html#tx.select).
     /// # Errors
     ///
+    /// Returns [`Error::ModeConflict`] if the channel is in confirm mode.
+    ///
     /// Returns error if any failure in communication with server.
     ///
     /// [`tx_commit`]: struct.Channel.html#method.tx_commit
     /// [`tx_rollback`]: struct.Channel.html#method.tx_rollback
+    /// [`Error::ModeConflict`]: ../error/enum.Error.html#variant.ModeConflict
     pub async fn tx_select(&self) -> Result<()> {
+        self.check_mode(ChannelMode::Transaction)?;
         let select = TxSelect;
 
         let responder_rx = self.register_responder(TxSelectOk::header()).await?;
@@ -40,6 +44,7 @@ impl Channel {
             Frame::TxSelectOk,
             Error::ChannelUseError
         )?;
+        self.set_mode(ChannelMode::Transaction);
         Ok(())
     }
     /// This method commits all message publications and acknowledgments performed in
@@ -120,9 +125,7 @@ mod tests {
 
         let args = BasicPublishArguments::new("amq.topic", "amqprs.test.transaction");
 
-        let basic_properties = BasicProperties::default()
-            .with_persistence(true)
-            .finish();
+        let basic_properties = BasicProperties::default().with_persistence(true).finish();
 
         let content = String::from("AMQPRS test transactions").into_bytes();
 
@@ -0,0 +1,190 @@
+use amqp_serde::types::{FieldTable, FieldValue, TimeStamp};
+
+use super::ConsumerMessage;
+
+/// Header added by server when a message is dead-lettered.
+const X_DEATH: &str = "x-death";
+
+/// One entry of the `x-death` header, describing how often and why a message was
+/// dead-lettered from a queue.
+///
+/// Server keeps one entry per `(queue, reason)`, the most recent one first.
+/// See [RabbitMQ dead lettering](https://www.rabbitmq.com/dlx.html#effects).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct XDeathEntry {
+    /// How many times the message was dead-lettered from `queue` for `reason`.
+    pub count: u64,
+    /// Why the message was dead-lettered, e.g. `rejected`, `expired`, `maxlen`.
+    pub reason: String,
+    /// The queue the message was dead-lettered from.
+    pub queue: String,
+    /// When the message was dead-lettered for the first time, seconds since epoch.
+    pub time: Option<TimeStamp>,
+    /// The exchange the message was published to.
+    pub exchange: String,
+    /// The routing keys the message was published with, including `CC` keys.
+    pub routing_keys: Vec<String>,
+}
+
+impl XDeathEntry {
+    /// Parse the `x-death` header of message `headers`.
+    ///
+    /// Returns `None` if the header does not exist or is not an array.
+    /// Array items which are not tables are skipped, missing fields are left default.
+    pub fn from_headers(headers: &FieldTable) -> Option<Vec<Self>> {
+        let key = X_DEATH.try_into().unwrap();
+        let entries = headers.get_array(&key)?;
+        Some(
+            entries
+                .as_ref()
+                .iter()
+                .filter_map(|entry| match entry {
+                    FieldValue::F(table) => Some(Self::from_table(table)),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    fn from_table(table: &FieldTable) -> Self {
+        let mut entry = Self::default();
+        for (key, value) in table.iter() {
+            match (key.as_ref().as_str(), value) {
+                ("count", value) => entry.count = to_u64(value).unwrap_or_default(),
+                ("reason", value) => entry.reason = to_string(value).unwrap_or_default(),
+                ("queue", value) => entry.queue = to_string(value).unwrap_or_default(),
+                ("time", FieldValue::T(time)) => entry.time = Some(*time),
+                ("exchange", value) => entry.exchange = to_string(value).unwrap_or_default(),
+                ("routing-keys", FieldValue::A(keys)) => {
+                    entry.routing_keys = keys.as_ref().iter().filter_map(to_string).collect()
+                }
+                _ => {}
+            }
+        }
+        entry
+    }
+}
+
+/// Server encodes `count` as signed long, but accept any non-negative integer.
+fn to_u64(value: &FieldValue) -> Option<u64> {
+    match *value {
+        FieldValue::b(v) => u64::try_from(v).ok(),
+        FieldValue::B(v) => Some(v.into()),
+        FieldValue::s(v) => u64::try_from(v).ok(),
+        FieldValue::u(v) => Some(v.into()),
+        FieldValue::I(v) => u64::try_from(v).ok(),
+        FieldValue::i(v) => Some(v.into()),
+        FieldValue::l(v) => u64::try_from(v).ok(),
+        _ => None,
+    }
+}
+
+fn to_string(value: &FieldValue) -> Option<String> {
+    match value {
+        FieldValue::S(v) => Some(v.as_ref().clone()),
+        _ => None,
+    }
+}
+
+impl ConsumerMessage {
+    /// Returns the death history of a dead-lettered message, parsed from its `x-death` header.
+    ///
+    /// Returns `None` if the message has never been dead-lettered.
+    ///
+    /// See [`XDeathEntry::from_headers`].
+    pub fn x_death(&self) -> Option<Vec<XDeathEntry>> {
+        XDeathEntry::from_headers(self.basic_properties.as_ref()?.headers()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amqp_serde::types::{FieldArray, FieldTable, FieldValue};
+
+    use super::XDeathEntry;
+
+    fn table(fields: Vec<(&str, FieldValue)>) -> FieldTable {
+        let mut table = FieldTable::new();
+        for (key, value) in fields {
+            table.insert(key.try_into().unwrap(), value);
+        }
+        table
+    }
+
+    fn array(values: Vec<FieldValue>) -> FieldValue {
+        FieldValue::A(FieldArray::try_from(values).unwrap())
+    }
+
+    #[test]
+    fn test_parse_x_death() {
+        let rejected = table(vec![
+            ("count", FieldValue::l(3)),
+            ("reason", "rejected".into()),
+            ("queue", "orders".into()),
+            ("time", FieldValue::T(1_700_000_000)),
+            ("exchange", "amq.topic".into()),
+            (
+                "routing-keys",
+                array(vec!["orders.eu".into(), "orders.audit".into()]),
+            ),
+        ]);
+        let expired = table(vec![
+            ("count", FieldValue::l(1)),
+            ("reason", "expired".into()),
+            ("queue", "orders.retry".into()),
+            ("time", FieldValue::T(1_700_000_010)),
+            ("exchange", "".into()),
+            ("routing-keys", array(vec!["orders.retry".into()])),
+            ("original-expiration", "5000".into()),
+        ]);
+        let headers = table(vec![
+            ("x-death", array(vec![expired.into(), rejected.into()])),
+            ("x-first-death-reason", "rejected".into()),
+        ]);
+
+        let entries = XDeathEntry::from_headers(&headers).unwrap();
+        assert_eq!(
+            vec![
+                XDeathEntry {
+                    count: 1,
+                    reason: "expired".to_string(),
+                    queue: "orders.retry".to_string(),
+                    time: Some(1_700_000_010),
+                    exchange: "".to_string(),
+                    routing_keys: vec!["orders.retry".to_string()],
+                },
+                XDeathEntry {
+                    count: 3,
+                    reason: "rejected".to_string(),
+                    queue: "orders".to_string(),
+                    time: Some(1_700_000_000),
+                    exchange: "amq.topic".to_string(),
+                    routing_keys: vec!["orders.eu".to_string(), "orders.audit".to_string()],
+                },
+            ],
+            entries
+        );
+        let total: u64 = entries.iter().map(|entry| entry.count).sum();
+        assert_eq!(4, total);
+    }
+
+    #[test]
+    fn test_parse_x_death_absent_or_malformed() {
+        assert_eq!(None, XDeathEntry::from_headers(&FieldTable::new()));
+
+        let headers = table(vec![("x-death", "rejected".into())]);
+        assert_eq!(None, XDeathEntry::from_headers(&headers));
+
+        let headers = table(vec![(
+            "x-death",
+            array(vec![
+                "rejected".into(),
+                table(vec![("count", FieldValue::I(2))]).into(),
+            ]),
+        )]);
+        let entries = XDeathEntry::from_headers(&headers).unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(2, entries[0].count);
+        assert_eq!("", entries[0].reason);
+    }
+}
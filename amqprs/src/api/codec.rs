@@ -0,0 +1,423 @@
+//! Typed message payloads, enabled by feature "codec".
+//!
+//! A [`Codec`] serializes a value into message content on publish, and deserializes
+//! the content of a [`ConsumerMessage`] back into a typed value on consume.
+//!
+//! With feature "json", [`JsonCodec`] is provided together with the convenient methods
+//! [`Channel::publish_json`] and [`ConsumerMessage::deserialize_json`].
+//! Other formats, e.g. MessagePack or CBOR, can be supported by implementing [`Codec`].
+//!
+//! To consume messages of different formats from the same queue, register a decoder per
+//! content type in a [`DecoderRegistry`] and call [`ConsumerMessage::decode`].
+//!
+//! [`ConsumerMessage`]: ../channel/struct.ConsumerMessage.html
+//! [`Channel::publish_json`]: ../channel/struct.Channel.html#method.publish_json
+//! [`ConsumerMessage::deserialize_json`]: ../channel/struct.ConsumerMessage.html#method.deserialize_json
+//! [`ConsumerMessage::decode`]: ../channel/struct.ConsumerMessage.html#method.decode
+use std::collections::HashMap;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    channel::{BasicPublishArguments, Channel, ConsumerMessage},
+    error::Error,
+    Result,
+};
+use crate::BasicProperties;
+
+/// Serialization format of typed message payloads.
+///
+/// # Example
+///
+/// ```ignore
+/// struct MsgPackCodec;
+///
+/// impl Codec for MsgPackCodec {
+///     fn content_type(&self) -> &str {
+///         "application/msgpack"
+///     }
+///     fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, Error> {
+///         rmp_serde::to_vec(value).map_err(|err| Error::CodecError(err.to_string()))
+///     }
+///     fn decode<T: DeserializeOwned>(&self, content: &[u8]) -> Result<T, Error> {
+///         rmp_serde::from_slice(content).map_err(|err| Error::CodecError(err.to_string()))
+///     }
+/// }
+/// ```
+pub trait Codec {
+    /// MIME type set as `content_type` of published messages.
+    fn content_type(&self) -> &str;
+
+    /// Serialize `value` into message content.
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> std::result::Result<Vec<u8>, Error>;
+
+    /// Deserialize message content into a value of type `T`.
+    fn decode<T: DeserializeOwned>(&self, content: &[u8]) -> std::result::Result<T, Error>;
+}
+
+/// JSON codec, enabled by feature "json".
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl Codec for JsonCodec {
+    fn content_type(&self) -> &str {
+        "application/json"
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> std::result::Result<Vec<u8>, Error> {
+        serde_json::to_vec(value).map_err(|err| Error::CodecError(err.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, content: &[u8]) -> std::result::Result<T, Error> {
+        serde_json::from_slice(content).map_err(|err| Error::CodecError(err.to_string()))
+    }
+}
+
+type Decoder<T> = Box<dyn Fn(&[u8]) -> std::result::Result<T, Error> + Send + Sync>;
+
+/// Decoders of message content into a value of type `T`, selected by `content_type`.
+///
+/// The content type is matched by its media type only, case insensitively, so that
+/// parameters such as `charset` are ignored, e.g. `application/json; charset=utf-8`
+/// is decoded by the decoder registered for `application/json`.
+///
+/// # Example
+///
+/// ```ignore
+/// enum Event {
+///     Order(Order),
+///     Refund(Refund),
+/// }
+///
+/// let mut registry = DecoderRegistry::new();
+/// registry
+///     .register_codec(JsonCodec, Event::Order)
+///     .register("application/cbor", |content| {
+///         ciborium::de::from_reader(content)
+///             .map(Event::Refund)
+///             .map_err(|err| Error::CodecError(err.to_string()))
+///     });
+///
+/// let event: Event = message.decode(&registry)?;
+/// ```
+pub struct DecoderRegistry<T> {
+    decoders: HashMap<String, Decoder<T>>,
+}
+
+impl<T> Default for DecoderRegistry<T> {
+    fn default() -> Self {
+        Self {
+            decoders: HashMap::new(),
+        }
+    }
+}
+
+impl<T> DecoderRegistry<T> {
+    /// Returns an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `decoder` for messages of `content_type`.
+    ///
+    /// A decoder registered before for the same content type is replaced.
+    pub fn register<F>(&mut self, content_type: &str, decoder: F) -> &mut Self
+    where
+        F: Fn(&[u8]) -> std::result::Result<T, Error> + Send + Sync + 'static,
+    {
+        self.decoders
+            .insert(media_type(content_type), Box::new(decoder));
+        self
+    }
+
+    /// Register `codec` for messages of [`Codec::content_type`], the decoded value
+    /// is converted into `T` by `map`.
+    pub fn register_codec<C, V, M>(&mut self, codec: C, map: M) -> &mut Self
+    where
+        C: Codec + Send + Sync + 'static,
+        V: DeserializeOwned,
+        M: Fn(V) -> T + Send + Sync + 'static,
+    {
+        let content_type = codec.content_type().to_owned();
+        self.register(&content_type, move |content| {
+            codec.decode(content).map(&map)
+        })
+    }
+
+    /// Returns `true` if a decoder is registered for `content_type`.
+    pub fn contains(&self, content_type: &str) -> bool {
+        self.decoders.contains_key(&media_type(content_type))
+    }
+
+    /// Decode `content` by the decoder registered for `content_type`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if no decoder is registered for `content_type`, or decoding fails.
+    pub fn decode(&self, content_type: &str, content: &[u8]) -> Result<T> {
+        match self.decoders.get(&media_type(content_type)) {
+            Some(decoder) => decoder(content),
+            None => Err(Error::CodecError(format!(
+                "no decoder registered for content type '{}'",
+                content_type
+            ))),
+        }
+    }
+}
+
+/// Strip the parameters of a MIME type and normalize its case.
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+impl Channel {
+    /// Serialize `value` by `codec` and publish it as message content.
+    ///
+    /// The `content_type` of `basic_properties` is set to [`Codec::content_type`].
+    ///
+    /// # Errors
+    ///
+    /// Returns error if serialization fails, or any failure in comunication with server.
+    pub async fn publish_with_codec<C: Codec, T: Serialize + ?Sized>(
+        &self,
+        codec: &C,
+        mut basic_properties: BasicProperties,
+        args: BasicPublishArguments,
+        value: &T,
+    ) -> Result<()> {
+        let content = codec.encode(value)?;
+        basic_properties.with_content_type(codec.content_type());
+        self.basic_publish(basic_properties, content, args).await
+    }
+
+    /// Serialize `value` to JSON and publish it to `exchange` with `routing_key`.
+    ///
+    /// The `content_type` of message is set to `application/json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if serialization fails, or any failure in comunication with server.
+    #[cfg(feature = "json")]
+    pub async fn publish_json<T: Serialize + ?Sized>(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        value: &T,
+    ) -> Result<()> {
+        self.publish_with_codec(
+            &JsonCodec,
+            BasicProperties::default(),
+            BasicPublishArguments::new(exchange, routing_key),
+            value,
+        )
+        .await
+    }
+}
+
+impl ConsumerMessage {
+    /// Deserialize the message content by `codec`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the message has no content, or deserialization fails.
+    pub fn deserialize_with<C: Codec, T: DeserializeOwned>(&self, codec: &C) -> Result<T> {
+        match self.content.as_ref() {
+            Some(content) => codec.decode(content),
+            None => Err(Error::CodecError("message has no content".to_owned())),
+        }
+    }
+
+    /// Deserialize the message content from JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the message has no content, or deserialization fails.
+    #[cfg(feature = "json")]
+    pub fn deserialize_json<T: DeserializeOwned>(&self) -> Result<T> {
+        self.deserialize_with(&JsonCodec)
+    }
+
+    /// Decode the message content by the decoder in `registry` for its `content_type`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the message has no content or content type, no decoder is
+    /// registered for the content type, or decoding fails.
+    pub fn decode<T>(&self, registry: &DecoderRegistry<T>) -> Result<T> {
+        let content_type = self
+            .basic_properties
+            .as_ref()
+            .and_then(|props| props.content_type())
+            .ok_or_else(|| Error::CodecError("message has no content type".to_owned()))?;
+        match self.content.as_ref() {
+            Some(content) => registry.decode(content_type, content),
+            None => Err(Error::CodecError("message has no content".to_owned())),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use tokio::time;
+
+    use super::{Codec, DecoderRegistry, JsonCodec};
+    use crate::{
+        channel::{BasicConsumeArguments, ConsumerMessage, QueueDeclareArguments},
+        connection::{Connection, OpenConnectionArguments},
+        error::Error,
+        test_utils::setup_logging,
+        BasicProperties,
+    };
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u64,
+        item: String,
+        quantity: u32,
+    }
+
+    fn order() -> Order {
+        Order {
+            id: 42,
+            item: "amqprs".to_owned(),
+            quantity: 3,
+        }
+    }
+
+    #[test]
+    fn test_json_codec_round_trip() {
+        let content = JsonCodec.encode(&order()).unwrap();
+        let decoded: Order = JsonCodec.decode(&content).unwrap();
+        assert_eq!(order(), decoded);
+
+        assert!(JsonCodec.decode::<Order>(b"not json").is_err());
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Payload {
+        Json(Order),
+        Cbor(String),
+    }
+
+    /// Decode a CBOR text string of less than 24 bytes, enough to tell the decoders apart.
+    fn decode_cbor_text(content: &[u8]) -> Result<String, Error> {
+        match content.split_first() {
+            Some((&header, text))
+                if header & 0xe0 == 0x60 && (header & 0x1f) as usize == text.len() =>
+            {
+                String::from_utf8(text.to_vec()).map_err(|err| Error::CodecError(err.to_string()))
+            }
+            _ => Err(Error::CodecError("not a short CBOR text string".to_owned())),
+        }
+    }
+
+    fn message(content_type: Option<&str>, content: &[u8]) -> ConsumerMessage {
+        let mut basic_properties = BasicProperties::default();
+        if let Some(content_type) = content_type {
+            basic_properties.with_content_type(content_type);
+        }
+        ConsumerMessage {
+            deliver: None,
+            basic_properties: Some(basic_properties),
+            content: Some(content.to_vec()),
+            remaining: 0,
+            buffered: None,
+        }
+    }
+
+    #[test]
+    fn test_decoder_registry() {
+        let mut registry = DecoderRegistry::new();
+        registry
+            .register_codec(JsonCodec, Payload::Json)
+            .register("application/cbor", |content| {
+                decode_cbor_text(content).map(Payload::Cbor)
+            });
+        assert!(registry.contains("Application/JSON"));
+        assert!(!registry.contains("text/plain"));
+
+        let json = message(
+            Some("application/json; charset=utf-8"),
+            &JsonCodec.encode(&order()).unwrap(),
+        );
+        assert_eq!(Payload::Json(order()), json.decode(&registry).unwrap());
+
+        let cbor = message(Some("application/cbor"), b"\x66amqprs");
+        assert_eq!(
+            Payload::Cbor("amqprs".to_owned()),
+            cbor.decode(&registry).unwrap()
+        );
+
+        // each decoder only accepts its own format
+        let mislabeled = message(Some("application/json"), b"\x66amqprs");
+        assert!(matches!(
+            mislabeled.decode(&registry),
+            Err(Error::CodecError(_))
+        ));
+
+        let unknown = message(Some("text/plain"), b"amqprs");
+        assert!(matches!(
+            unknown.decode(&registry),
+            Err(Error::CodecError(_))
+        ));
+
+        let untyped = message(None, b"amqprs");
+        assert!(matches!(
+            untyped.decode(&registry),
+            Err(Error::CodecError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_publish_json_and_consume() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_publish_json_and_consume")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+
+        let (queue_name, ..) = channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+        let args = BasicConsumeArguments::new(&queue_name, "test_publish_json_and_consume")
+            .auto_ack(true)
+            .finish();
+        let (_ctag, mut rx) = channel.basic_consume_rx(args).await.unwrap();
+
+        channel
+            .publish_json("", &queue_name, &order())
+            .await
+            .unwrap();
+
+        let message = time::timeout(time::Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            "application/json",
+            message
+                .basic_properties
+                .as_ref()
+                .unwrap()
+                .content_type()
+                .unwrap()
+        );
+        let received: Order = message.deserialize_json().unwrap();
+        assert_eq!(order(), received);
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+}
@@ -10,7 +10,10 @@
 //! [`Channel::basic_consume`]: ../channel/struct.Channel.html#method.basic_consume
 //! [`Channel::basic_consume_blocking`]: ../channel/struct.Channel.html#method.basic_consume_blocking
 //!
-use super::channel::{BasicAckArguments, Channel};
+use super::{
+    channel::{BasicAckArguments, Channel},
+    Result,
+};
 use crate::frame::{BasicProperties, Deliver};
 
 use async_trait::async_trait;
@@ -38,6 +41,13 @@ pub trait AsyncConsumer {
     ///
     /// `content`: the content body
     ///
+    /// # Ordering
+    ///
+    /// Deliveries to a consumer are handled one at a time, in the order they are received.
+    /// The returned future is awaited to completion before the next delivery is passed in,
+    /// so the implementation can await IO (e.g. database writes or HTTP calls) without
+    /// reordering messages.
+    ///
     /// # Non-blocking and blocking consumer
     ///
     /// This method is invoked in a async task context, so its implementation should NOT be CPU bound, otherwise it will starving the async runtime.
@@ -62,6 +72,32 @@ pub trait AsyncConsumer {
         basic_properties: BasicProperties,
         content: Vec<u8>,
     );
+
+    /// Same as [`consume`], but the consumer can signal that it fails to process the delivery
+    /// by returning an error, e.g. [`Error::ConsumerError`].
+    ///
+    /// On error, or if the consumer panics, the [`ConsumerErrorPolicy`] given by
+    /// [`BasicConsumeArguments::error_policy`] is applied to the delivery.
+    ///
+    /// The default implementation calls [`consume`] and returns `Ok`.
+    /// Override this method instead of [`consume`] to signal failures, then [`consume`]
+    /// is not called by the library.
+    ///
+    /// [`consume`]: trait.AsyncConsumer.html#tymethod.consume
+    /// [`Error::ConsumerError`]: ../error/enum.Error.html#variant.ConsumerError
+    /// [`ConsumerErrorPolicy`]: ../channel/enum.ConsumerErrorPolicy.html
+    /// [`BasicConsumeArguments::error_policy`]: ../channel/struct.BasicConsumeArguments.html#structfield.error_policy
+    async fn try_consume(
+        &mut self,
+        channel: &Channel,
+        deliver: Deliver,
+        basic_properties: BasicProperties,
+        content: Vec<u8>,
+    ) -> Result<()> {
+        self.consume(channel, deliver, basic_properties, content)
+            .await;
+        Ok(())
+    }
 }
 
 /// Default type implements the [`AsyncConsumer`].
@@ -111,6 +147,36 @@ impl AsyncConsumer for DefaultConsumer {
     }
 }
 
+/// Boxed consumer created by the factory of [`Channel::basic_consume_recoverable`].
+///
+/// [`Channel::basic_consume_recoverable`]: ../channel/struct.Channel.html#method.basic_consume_recoverable
+#[async_trait]
+impl AsyncConsumer for Box<dyn AsyncConsumer + Send> {
+    async fn consume(
+        &mut self,
+        channel: &Channel,
+        deliver: Deliver,
+        basic_properties: BasicProperties,
+        content: Vec<u8>,
+    ) {
+        (**self)
+            .consume(channel, deliver, basic_properties, content)
+            .await
+    }
+
+    async fn try_consume(
+        &mut self,
+        channel: &Channel,
+        deliver: Deliver,
+        basic_properties: BasicProperties,
+        content: Vec<u8>,
+    ) -> Result<()> {
+        (**self)
+            .try_consume(channel, deliver, basic_properties, content)
+            .await
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////
 /// Similar as [`AsyncConsumer`] but run in a blocking context, aiming for CPU bound task.
 pub trait BlockingConsumer {
@@ -176,3 +242,18 @@ impl BlockingConsumer for DefaultBlockingConsumer {
         }
     }
 }
+
+/// Boxed consumer created by the factory of [`Channel::basic_consume_blocking_recoverable`].
+///
+/// [`Channel::basic_consume_blocking_recoverable`]: ../channel/struct.Channel.html#method.basic_consume_blocking_recoverable
+impl BlockingConsumer for Box<dyn BlockingConsumer + Send> {
+    fn consume(
+        &mut self,
+        channel: &Channel,
+        deliver: Deliver,
+        basic_properties: BasicProperties,
+        content: Vec<u8>,
+    ) {
+        (**self).consume(channel, deliver, basic_properties, content)
+    }
+}
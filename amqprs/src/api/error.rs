@@ -1,6 +1,9 @@
 //! Error type can be returned by the APIs.
 
-use crate::net;
+use crate::{
+    frame::{Close, CloseChannel, CloseKind, ACCESS_REFUSED, NOT_ALLOWED},
+    net,
+};
 
 use std::fmt;
 use tokio::sync::{mpsc::error::SendError, oneshot::error::RecvError};
@@ -15,16 +18,83 @@ pub enum Error {
     UriError(String),
     /// Error during openning a connection.
     ConnectionOpenError(String),
+    /// Error when server refuses to open a connection by `Connection.Close` during handshake,
+    /// e.g. `403 ACCESS_REFUSED` for bad credentials, or `530 NOT_ALLOWED` for a virtual host
+    /// which the user is not allowed to access.
+    ConnectionRefused {
+        /// Reply code of server.
+        code: u16,
+        /// Reply text of server.
+        text: String,
+    },
+    /// Error when server closes the connection or channel, e.g. by `404 NOT_FOUND` in
+    /// response to a request. See [`Error::close_kind`] to decide whether to retry.
+    ClosedByServer {
+        /// Reply code of server.
+        code: u16,
+        /// Reply text of server.
+        text: String,
+        /// Class id of the method which caused the close, zero if none.
+        class_id: u16,
+        /// Method id of the method which caused the close, zero if none.
+        method_id: u16,
+    },
     /// Error during closing a connection.
     ConnectionCloseError(String),
     /// Error when using the connection. Usually due to incorrect usage by user.
     ConnectionUseError(String),
     /// Error during openning a channel.
     ChannelOpenError(String),
+    /// Error when opening a channel with an id which is already used by an open channel.
+    ChannelIdInUse(String),
     /// Error during closing a channel.
     ChannelCloseError(String),
     /// Error when using the channel. Usually due to incorrect usage by user.
     ChannelUseError(String),
+    /// Error when using a channel which has already been closed, either by
+    /// client or by server.
+    ChannelClosed(String),
+    /// Error when server does not respond to a synchronous request within the rpc timeout
+    /// of the channel.
+    RpcTimeout(String),
+    /// Error when a queue is re-declared with arguments inequivalent to the existing queue,
+    /// i.e. server closes the channel with `PRECONDITION_FAILED`.
+    QueueArgumentMismatch(String),
+    /// Error when a queue does not exist, i.e. server closes the channel with `NOT_FOUND`.
+    QueueNotFound(String),
+    /// Error when a name, e.g. exchange name, queue name, routing key or consumer tag,
+    /// exceeds the maximum length of 255 bytes.
+    NameTooLong(String),
+    /// Error when an exchange or queue name starts with the prefix `amq.` reserved by server,
+    /// and it is not declared passively.
+    ReservedName(String),
+    /// Error when selecting confirm mode on a channel in transaction mode, or vice versa.
+    ModeConflict(String),
+    /// Error when connection arguments can't be created from environment variables.
+    EnvError(String),
+    /// Error when serializing or deserializing typed message content.
+    CodecError(String),
+    /// Error when decompressing message content according to its `content_encoding`.
+    ContentEncodingError(String),
+    /// Error when the connection is still blocked by server after waiting for the given timeout.
+    ConnectionBlocked(String),
+    /// Error returned by a consumer which fails to process a delivery.
+    ConsumerError(String),
+    /// Error when a publish needs to be buffered but the publish buffer has reached its capacity.
+    BufferFull(String),
+    /// Error when a message fails to be published, e.g. a name is too long or the channel
+    /// is closed. It echoes the arguments of the message, so that it can be logged or
+    /// published again without keeping them.
+    PublishError {
+        /// Exchange the message is published to.
+        exchange: String,
+        /// Routing key the message is published with.
+        routing_key: String,
+        /// Length of the message body in bytes.
+        body_len: usize,
+        /// The error which fails the publish.
+        source: Box<Error>,
+    },
     /// Error occurs in network layer.
     NetworkError(String),
     /// Error in sending or receiving messages via internal communication channel.
@@ -32,6 +102,51 @@ pub enum Error {
     InternalChannelError(String),
 }
 
+impl Error {
+    /// Returns whether the error is retryable or fatal, if it is caused by server closing
+    /// the connection or channel, otherwise returns [`None`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use amqprs::{error::Error, CloseKind};
+    /// fn should_reconnect(err: &Error) -> bool {
+    ///     err.close_kind() == Some(CloseKind::Retryable)
+    /// }
+    /// ```
+    pub fn close_kind(&self) -> Option<CloseKind> {
+        match self {
+            Error::ClosedByServer { code, .. } | Error::ConnectionRefused { code, .. } => {
+                Some(CloseKind::from_reply_code(*code))
+            }
+            Error::PublishError { source, .. } => source.close_kind(),
+            _ => None,
+        }
+    }
+}
+
+impl From<Close> for Error {
+    fn from(close: Close) -> Self {
+        Self::ClosedByServer {
+            code: close.reply_code(),
+            text: close.reply_text().clone(),
+            class_id: close.class_id(),
+            method_id: close.method_id(),
+        }
+    }
+}
+
+impl From<CloseChannel> for Error {
+    fn from(close_channel: CloseChannel) -> Self {
+        Self::ClosedByServer {
+            code: close_channel.reply_code(),
+            text: close_channel.reply_text().clone(),
+            class_id: close_channel.class_id(),
+            method_id: close_channel.method_id(),
+        }
+    }
+}
+
 #[cfg(feature = "urispec")]
 impl From<URIReferenceError> for Error {
     fn from(err: URIReferenceError) -> Self {
@@ -41,7 +156,11 @@ impl From<URIReferenceError> for Error {
 
 impl From<net::Error> for Error {
     fn from(err: net::Error) -> Self {
-        Self::NetworkError(err.to_string())
+        match err {
+            // server is reachable, but does not accept the protocol version
+            net::Error::UnsupportedProtocol(_) => Self::ConnectionOpenError(err.to_string()),
+            _ => Self::NetworkError(err.to_string()),
+        }
     }
 }
 impl<T> From<SendError<T>> for Error {
@@ -63,11 +182,61 @@ impl fmt::Display for Error {
             }
             Error::NetworkError(msg) => write!(f, "AMQP network error: {}", msg),
             Error::ConnectionOpenError(msg) => write!(f, "AMQP connection open error: {}", msg),
+            Error::ConnectionRefused { code, text } => {
+                let reason = match *code {
+                    ACCESS_REFUSED => "access refused, check username and password",
+                    NOT_ALLOWED => "not allowed, check virtual host and user permissions",
+                    _ => "refused by server",
+                };
+                write!(f, "AMQP connection {}: {} {}", reason, code, text)
+            }
+            Error::ClosedByServer {
+                code,
+                text,
+                class_id,
+                method_id,
+            } => write!(
+                f,
+                "AMQP closed by server, {}: {} {} (class_id = {}, method_id = {})",
+                CloseKind::from_reply_code(*code),
+                code,
+                text,
+                class_id,
+                method_id
+            ),
             Error::ConnectionCloseError(msg) => write!(f, "AMQP connection close error: {}", msg),
             Error::ConnectionUseError(msg) => write!(f, "AMQP connection usage error: {}", msg),
+            Error::ConnectionBlocked(msg) => write!(f, "AMQP connection blocked: {}", msg),
             Error::ChannelOpenError(msg) => write!(f, "AMQP channel open error: {}", msg),
+            Error::ChannelIdInUse(msg) => write!(f, "AMQP channel id in use: {}", msg),
             Error::ChannelUseError(msg) => write!(f, "AMQP channel usage error: {}", msg),
             Error::ChannelCloseError(msg) => write!(f, "AMQP channel close error: {}", msg),
+            Error::ChannelClosed(msg) => write!(f, "AMQP channel closed: {}", msg),
+            Error::RpcTimeout(msg) => write!(f, "AMQP rpc timeout: {}", msg),
+            Error::QueueArgumentMismatch(msg) => {
+                write!(f, "AMQP queue argument mismatch: {}", msg)
+            }
+            Error::QueueNotFound(msg) => write!(f, "AMQP queue not found: {}", msg),
+            Error::NameTooLong(msg) => write!(f, "AMQP name too long: {}", msg),
+            Error::ReservedName(msg) => write!(f, "AMQP reserved name: {}", msg),
+            Error::EnvError(msg) => write!(f, "AMQP environment config error: {}", msg),
+            Error::ModeConflict(msg) => write!(f, "AMQP channel mode conflict: {}", msg),
+            Error::ConsumerError(msg) => write!(f, "AMQP consumer error: {}", msg),
+            Error::BufferFull(msg) => write!(f, "AMQP publish buffer full: {}", msg),
+            Error::PublishError {
+                exchange,
+                routing_key,
+                body_len,
+                source,
+            } => write!(
+                f,
+                "AMQP publish error: exchange '{}', routing key '{}', {} bytes body: {}",
+                exchange, routing_key, body_len, source
+            ),
+            Error::CodecError(msg) => write!(f, "AMQP message codec error: {}", msg),
+            Error::ContentEncodingError(msg) => {
+                write!(f, "AMQP message content encoding error: {}", msg)
+            }
             Error::InternalChannelError(msg) => {
                 write!(f, "AMQP internal communication error: {}", msg)
             }
@@ -75,4 +244,11 @@ impl fmt::Display for Error {
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::PublishError { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Interceptors of outgoing frames, a.k.a. middleware.
+//!
+//! Middleware registered by [`Connection::add_outgoing_middleware`] is applied to every frame
+//! of the connection before it is written to the network, e.g. to record metrics, inject
+//! headers or sign published messages without wrapping every call site.
+//!
+//! # Ordering
+//!
+//! Middleware runs in the order it is registered, each one sees the changes made by the
+//! ones before it. Frames are intercepted in the order they are written, which is the
+//! order they are sent by a channel.
+//!
+//! # Performance
+//!
+//! Middleware runs on the task writing to the network, so it delays every frame of the
+//! connection, and it must not block. Keep it cheap, e.g. a counter or a header insert,
+//! and hand off expensive work to another task. When any middleware is registered, each
+//! frame also takes an uncontended lock.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use amqprs::connection::{Connection, OpenConnectionArguments};
+//! # #[tokio::main]
+//! # async fn main() {
+//! # let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami");
+//! let connection = Connection::open(&args).await.unwrap();
+//! connection.add_outgoing_middleware(|frame| {
+//!     if let Some(properties) = frame.properties_mut() {
+//!         properties.with_app_id("billing");
+//!     }
+//! });
+//! # }
+//! ```
+//!
+//! [`Connection::add_outgoing_middleware`]: ../connection/struct.Connection.html#method.add_outgoing_middleware
+use std::fmt;
+
+use amqp_serde::types::AmqpChannelId;
+
+use crate::{frame::Frame, BasicProperties};
+
+type OutgoingMiddleware = Box<dyn FnMut(&mut OutgoingFrame<'_>) + Send>;
+
+/// Middleware applied to outgoing frames, in the order of registration.
+#[derive(Default)]
+pub(crate) struct MiddlewareChain {
+    middleware: Vec<OutgoingMiddleware>,
+}
+
+impl MiddlewareChain {
+    pub(crate) fn push<F>(&mut self, middleware: F)
+    where
+        F: FnMut(&mut OutgoingFrame<'_>) + Send + 'static,
+    {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    pub(crate) fn apply(&mut self, channel_id: AmqpChannelId, frame: &mut Frame) {
+        let mut outgoing_frame = OutgoingFrame::new(channel_id, frame);
+        for middleware in self.middleware.iter_mut() {
+            middleware(&mut outgoing_frame);
+        }
+    }
+}
+
+impl fmt::Debug for MiddlewareChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MiddlewareChain({})", self.middleware.len())
+    }
+}
+
+/// An outgoing frame intercepted by middleware.
+///
+/// A published message is intercepted as one frame, including its properties and body.
+pub struct OutgoingFrame<'a> {
+    channel_id: AmqpChannelId,
+    frame: &'a mut Frame,
+}
+
+impl<'a> OutgoingFrame<'a> {
+    pub(crate) fn new(channel_id: AmqpChannelId, frame: &'a mut Frame) -> Self {
+        Self { channel_id, frame }
+    }
+
+    /// Returns the id of the channel which sends the frame, `0` for connection-level frames.
+    pub fn channel_id(&self) -> AmqpChannelId {
+        self.channel_id
+    }
+
+    /// Returns `true` if the frame is a published message.
+    pub fn is_publish(&self) -> bool {
+        matches!(
+            self.frame,
+            Frame::PublishCombo(..) | Frame::PublishEncodedCombo(..)
+        )
+    }
+
+    /// Returns the exchange of a published message, `None` for other frames.
+    pub fn exchange(&self) -> Option<&str> {
+        match &*self.frame {
+            Frame::PublishCombo(publish, ..) | Frame::PublishEncodedCombo(publish, ..) => {
+                Some(publish.exchange())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the routing key of a published message, `None` for other frames.
+    pub fn routing_key(&self) -> Option<&str> {
+        match &*self.frame {
+            Frame::PublishCombo(publish, ..) | Frame::PublishEncodedCombo(publish, ..) => {
+                Some(publish.routing_key())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the properties of a published message.
+    ///
+    /// Returns `None` for other frames, and for messages published by
+    /// [`basic_publish_encoded`] since their properties are already encoded.
+    ///
+    /// [`basic_publish_encoded`]: ../channel/struct.Channel.html#method.basic_publish_encoded
+    pub fn properties(&self) -> Option<&BasicProperties> {
+        match &*self.frame {
+            Frame::PublishCombo(_, header, _) => Some(&header.basic_properties),
+            _ => None,
+        }
+    }
+
+    /// Returns the mutable properties of a published message, e.g. to inject headers.
+    ///
+    /// Properties must still fit in a frame of the negotiated `frame_max` size.
+    /// See also [`properties`].
+    ///
+    /// [`properties`]: #method.properties
+    pub fn properties_mut(&mut self) -> Option<&mut BasicProperties> {
+        match self.frame {
+            Frame::PublishCombo(_, header, _) => Some(&mut header.basic_properties),
+            _ => None,
+        }
+    }
+
+    /// Returns the body of a published message, `None` for other frames.
+    pub fn body(&self) -> Option<&[u8]> {
+        match &*self.frame {
+            Frame::PublishCombo(_, _, body) | Frame::PublishEncodedCombo(_, _, body) => {
+                Some(&body.inner)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Concise representation of the frame, e.g. `PublishCombo(60, 40, body size: 5) on channel 1`.
+impl fmt::Display for OutgoingFrame<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on channel {}", self.frame, self.channel_id)
+    }
+}
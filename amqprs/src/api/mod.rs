@@ -59,7 +59,13 @@ pub mod tls;
 
 pub mod callbacks;
 pub mod channel;
+#[cfg(feature = "codec")]
+pub mod codec;
 pub mod connection;
 pub mod consumer;
 pub mod error;
+pub mod middleware;
+#[cfg(feature = "opentelemetry")]
+pub mod otel;
+pub mod recovery;
 pub mod security;
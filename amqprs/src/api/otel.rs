@@ -0,0 +1,321 @@
+//! OpenTelemetry context propagation through message headers, enabled by feature "opentelemetry".
+//!
+//! The publisher injects the trace context into the message headers by
+//! [`BasicProperties::with_trace_context`], and the consumer extracts it by
+//! [`ConsumerMessage::extract_context`] to continue the trace across the broker.
+//!
+//! The context is carried in the `traceparent` and `tracestate` headers of
+//! [W3C Trace Context](https://www.w3.org/TR/trace-context/) format.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # use amqprs::{channel::{BasicPublishArguments, Channel, ConsumerMessage}, BasicProperties};
+//! # use opentelemetry::{trace::TraceContextExt, Context};
+//! # async fn publish(channel: &Channel) -> Result<(), amqprs::error::Error> {
+//! // publisher
+//! channel
+//!     .basic_publish(
+//!         BasicProperties::default()
+//!             .with_trace_context(&Context::current())
+//!             .finish(),
+//!         b"hello".to_vec(),
+//!         BasicPublishArguments::new("amq.topic", "greetings"),
+//!     )
+//!     .await
+//! # }
+//! # fn consume(message: ConsumerMessage) {
+//! // consumer, spans started with the context are children of the publisher's span
+//! let parent = message.extract_context();
+//! assert!(parent.span().span_context().is_remote());
+//! # }
+//! ```
+//!
+//! [`BasicProperties::with_trace_context`]: ../struct.BasicProperties.html#method.with_trace_context
+//! [`ConsumerMessage::extract_context`]: ../channel/struct.ConsumerMessage.html#method.extract_context
+use opentelemetry::{
+    trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId},
+    Context,
+};
+
+use super::channel::ConsumerMessage;
+use crate::{BasicProperties, FieldTable, FieldValue};
+
+/// Header of the W3C trace parent, i.e. version, trace id, parent span id and trace flags.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+/// Header of the W3C vendor specific trace state.
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+/// The only version of trace parent format supported.
+const SUPPORTED_VERSION: u8 = 0;
+/// Reserved invalid version.
+const MAX_VERSION: u8 = 255;
+
+impl BasicProperties {
+    /// Chainable setter to inject the span context of `cx` into the headers.
+    ///
+    /// Other headers are kept. Nothing is injected if `cx` has no valid span context.
+    pub fn with_trace_context(&mut self, cx: &Context) -> &mut Self {
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return self;
+        }
+        let mut headers = self.headers().cloned().unwrap_or_default();
+        let traceparent = format!(
+            "{:02x}-{:032x}-{:016x}-{:02x}",
+            SUPPORTED_VERSION,
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags() & TraceFlags::SAMPLED
+        );
+        insert_header(&mut headers, TRACEPARENT_HEADER, traceparent);
+        let tracestate = span_context.trace_state().header();
+        if !tracestate.is_empty() {
+            insert_header(&mut headers, TRACESTATE_HEADER, tracestate);
+        }
+        self.with_headers(headers)
+    }
+}
+
+impl ConsumerMessage {
+    /// Extract the span context injected by the publisher as the remote parent in the
+    /// current context.
+    ///
+    /// Returns the current context unchanged if the message has no valid trace parent.
+    pub fn extract_context(&self) -> Context {
+        let cx = Context::current();
+        match self
+            .basic_properties
+            .as_ref()
+            .and_then(|props| props.headers())
+            .and_then(extract_span_context)
+        {
+            Some(span_context) => cx.with_remote_span_context(span_context),
+            None => cx,
+        }
+    }
+}
+
+fn insert_header(headers: &mut FieldTable, name: &str, value: String) {
+    // header names and values are short, they never exceed the size limits
+    headers.insert(name.try_into().unwrap(), value.into());
+}
+
+fn get_header(headers: &FieldTable, name: &str) -> Option<String> {
+    match headers.get(&name.try_into().unwrap()) {
+        Some(FieldValue::S(value)) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn extract_span_context(headers: &FieldTable) -> Option<SpanContext> {
+    let traceparent = get_header(headers, TRACEPARENT_HEADER)?;
+    let (trace_id, span_id, trace_flags) = parse_traceparent(&traceparent)?;
+    // invalid trace state is ignored, the trace parent is still valid
+    let trace_state = get_header(headers, TRACESTATE_HEADER)
+        .and_then(|tracestate| tracestate.parse().ok())
+        .unwrap_or_default();
+    Some(SpanContext::new(
+        trace_id,
+        span_id,
+        trace_flags,
+        true,
+        trace_state,
+    ))
+}
+
+/// Parse `version-trace_id-parent_id-trace_flags`, fields are lowercase hex.
+///
+/// Fields appended by later versions are ignored.
+fn parse_traceparent(traceparent: &str) -> Option<(TraceId, SpanId, TraceFlags)> {
+    let parts: Vec<&str> = traceparent.trim().split_terminator('-').collect();
+    if parts.len() < 4 {
+        return None;
+    }
+    let version = parse_hex_field(parts[0], 2)?;
+    if version == MAX_VERSION as u128 || (version == SUPPORTED_VERSION as u128 && parts.len() != 4)
+    {
+        return None;
+    }
+    let trace_id = TraceId::from(parse_hex_field(parts[1], 32)?);
+    let span_id = SpanId::from(parse_hex_field(parts[2], 16)? as u64);
+    let trace_flags = TraceFlags::new(parse_hex_field(parts[3], 2)? as u8) & TraceFlags::SAMPLED;
+    if trace_id == TraceId::INVALID || span_id == SpanId::INVALID {
+        return None;
+    }
+    Some((trace_id, span_id, trace_flags))
+}
+
+/// Parse a lowercase hex field of exactly `len` digits.
+fn parse_hex_field(field: &str, len: usize) -> Option<u128> {
+    if field.len() != len
+        || !field
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    {
+        return None;
+    }
+    u128::from_str_radix(field, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::{
+        trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId},
+        Context,
+    };
+    use tokio::time;
+
+    use super::{parse_traceparent, TRACEPARENT_HEADER};
+    use crate::{
+        channel::{
+            BasicConsumeArguments, BasicPublishArguments, ConsumerMessage, QueueDeclareArguments,
+        },
+        connection::{Connection, OpenConnectionArguments},
+        test_utils::setup_logging,
+        BasicProperties, FieldTable,
+    };
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn publisher_context() -> Context {
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            false,
+            "vendor=value".parse().unwrap(),
+        );
+        Context::new().with_remote_span_context(span_context)
+    }
+
+    fn message(basic_properties: BasicProperties) -> ConsumerMessage {
+        ConsumerMessage {
+            deliver: None,
+            basic_properties: Some(basic_properties),
+            content: Some(Vec::new()),
+            remaining: 0,
+            buffered: None,
+        }
+    }
+
+    #[test]
+    fn test_inject_and_extract_context() {
+        let mut headers = FieldTable::new();
+        headers.insert("x-app".try_into().unwrap(), "app1".into());
+        let props = BasicProperties::default()
+            .with_headers(headers)
+            .with_trace_context(&publisher_context())
+            .finish();
+        let headers = props.headers().unwrap();
+        assert_eq!(3, headers.as_ref().len());
+        assert_eq!(
+            TRACEPARENT,
+            headers
+                .get(&TRACEPARENT_HEADER.try_into().unwrap())
+                .unwrap()
+                .to_string()
+        );
+
+        // headers survive the wire encoding
+        let props: BasicProperties =
+            amqp_serde::from_bytes(&amqp_serde::to_bytes(&props).unwrap()).unwrap();
+        let cx = message(props).extract_context();
+        let span = cx.span();
+        let span_context = span.span_context();
+        assert!(span_context.is_remote());
+        assert!(span_context.is_sampled());
+        assert_eq!(
+            publisher_context().span().span_context().trace_id(),
+            span_context.trace_id()
+        );
+        assert_eq!(
+            publisher_context().span().span_context().span_id(),
+            span_context.span_id()
+        );
+        assert_eq!(Some("value"), span_context.trace_state().get("vendor"));
+
+        // nothing to inject or extract without valid span context
+        let props = BasicProperties::default()
+            .with_trace_context(&Context::new())
+            .finish();
+        assert!(props.headers().is_none());
+        assert!(!message(props).extract_context().has_active_span());
+    }
+
+    #[test]
+    fn test_parse_traceparent() {
+        assert!(parse_traceparent(TRACEPARENT).is_some());
+        // unsampled flags
+        let (.., flags) =
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").unwrap();
+        assert!(!flags.is_sampled());
+        // future version with more fields
+        assert!(
+            parse_traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-ff")
+                .is_some()
+        );
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-ff",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-0x",
+        ] {
+            assert!(parse_traceparent(invalid).is_none(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_and_consume_trace_context() {
+        setup_logging();
+
+        let args = OpenConnectionArguments::new("localhost", 5672, "user", "bitnami")
+            .connection_name("test_publish_and_consume_trace_context")
+            .finish();
+        let connection = Connection::open(&args).await.unwrap();
+        let channel = connection.open_channel(None).await.unwrap();
+
+        let (queue_name, ..) = channel
+            .queue_declare(QueueDeclareArguments::default())
+            .await
+            .unwrap()
+            .unwrap();
+        let args =
+            BasicConsumeArguments::new(&queue_name, "test_publish_and_consume_trace_context")
+                .auto_ack(true)
+                .finish();
+        let (_ctag, mut rx) = channel.basic_consume_rx(args).await.unwrap();
+
+        channel
+            .basic_publish(
+                BasicProperties::default()
+                    .with_trace_context(&publisher_context())
+                    .finish(),
+                b"traced".to_vec(),
+                BasicPublishArguments::new("", &queue_name),
+            )
+            .await
+            .unwrap();
+
+        let message = time::timeout(time::Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let cx = message.extract_context();
+        assert_eq!(
+            publisher_context().span().span_context().trace_id(),
+            cx.span().span_context().trace_id()
+        );
+        assert!(cx.span().span_context().is_remote());
+
+        channel.close().await.unwrap();
+        connection.close().await.unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+//! This module provides the timing policies to reconnect after a connection is lost.
+//!
+//! A [`RecoveryPolicy`] computes the delays before each reconnect attempt, see [`Backoff`].
+//! Jitter spreads the reconnects of many clients, e.g. after a broker restart, so they do
+//! not hit the broker at the same time.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # use amqprs::connection::{Connection, OpenConnectionArguments};
+//! # use amqprs::recovery::RecoveryPolicy;
+//! # use tokio::time::{self, Duration};
+//! # async fn reconnect(args: &OpenConnectionArguments) -> Option<Connection> {
+//! let policy = RecoveryPolicy::ExponentialJitter {
+//!     initial_delay: Duration::from_millis(100),
+//!     max_delay: Duration::from_secs(30),
+//!     max_attempts: Some(10),
+//! };
+//! for delay in policy.backoff() {
+//!     time::sleep(delay).await;
+//!     if let Ok(connection) = Connection::open(args).await {
+//!         return Some(connection);
+//!     }
+//! }
+//! // give up after max attempts
+//! None
+//! # }
+//! ```
+//!
+//! [`RecoveryPolicy`]: enum.RecoveryPolicy.html
+//! [`Backoff`]: struct.Backoff.html
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Policy of the delays between reconnect attempts.
+///
+/// Each variant stops after `max_attempts` if it is `Some`, otherwise it retries forever.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveryPolicy {
+    /// Same delay before every attempt.
+    Constant {
+        delay: Duration,
+        max_attempts: Option<u32>,
+    },
+    /// Delay doubles after every attempt, starts at `initial_delay` and is capped by `max_delay`.
+    Exponential {
+        initial_delay: Duration,
+        max_delay: Duration,
+        max_attempts: Option<u32>,
+    },
+    /// Random delay between zero and the delay of [`Exponential`] policy, i.e. full jitter.
+    ///
+    /// [`Exponential`]: enum.RecoveryPolicy.html#variant.Exponential
+    ExponentialJitter {
+        initial_delay: Duration,
+        max_delay: Duration,
+        max_attempts: Option<u32>,
+    },
+}
+
+impl Default for RecoveryPolicy {
+    /// Exponential backoff with jitter, from 1 second up to 30 seconds, retries forever.
+    fn default() -> Self {
+        Self::ExponentialJitter {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            max_attempts: None,
+        }
+    }
+}
+
+impl RecoveryPolicy {
+    /// Returns the delays before each reconnect attempt.
+    ///
+    /// Jitter is seeded from the system time, so clients do not follow the same sequence.
+    pub fn backoff(&self) -> Backoff {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default();
+        self.backoff_with_seed(seed)
+    }
+
+    /// Returns the delays before each reconnect attempt, jitter is seeded by `seed`.
+    ///
+    /// Same seed gives same sequence of delays.
+    pub fn backoff_with_seed(&self, seed: u64) -> Backoff {
+        Backoff {
+            policy: self.clone(),
+            attempt: 0,
+            rng_state: seed,
+        }
+    }
+
+    fn max_attempts(&self) -> Option<u32> {
+        match self {
+            Self::Constant { max_attempts, .. }
+            | Self::Exponential { max_attempts, .. }
+            | Self::ExponentialJitter { max_attempts, .. } => *max_attempts,
+        }
+    }
+}
+
+/// Iterator of the delays before each reconnect attempt, created by [`RecoveryPolicy::backoff`].
+///
+/// It ends once max attempts of the policy are reached.
+///
+/// [`RecoveryPolicy::backoff`]: enum.RecoveryPolicy.html#method.backoff
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: RecoveryPolicy,
+    /// number of delays returned
+    attempt: u32,
+    rng_state: u64,
+}
+
+impl Backoff {
+    /// Returns the number of delays returned so far.
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Restart from the first delay, e.g. after reconnected successfully.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Next pseudo random number of SplitMix64 generator.
+    fn next_random(&mut self) -> u64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Returns `initial_delay * 2^attempt`, capped by `max_delay`.
+fn exponential_delay(initial_delay: Duration, max_delay: Duration, attempt: u32) -> Duration {
+    2u32.checked_pow(attempt)
+        .and_then(|factor| initial_delay.checked_mul(factor))
+        .map_or(max_delay, |delay| delay.min(max_delay))
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(max_attempts) = self.policy.max_attempts() {
+            if self.attempt >= max_attempts {
+                return None;
+            }
+        }
+        let delay = match self.policy {
+            RecoveryPolicy::Constant { delay, .. } => delay,
+            RecoveryPolicy::Exponential {
+                initial_delay,
+                max_delay,
+                ..
+            } => exponential_delay(initial_delay, max_delay, self.attempt),
+            RecoveryPolicy::ExponentialJitter {
+                initial_delay,
+                max_delay,
+                ..
+            } => {
+                let cap = exponential_delay(initial_delay, max_delay, self.attempt);
+                let cap_nanos = u64::try_from(cap.as_nanos()).unwrap_or(u64::MAX);
+                match cap_nanos.checked_add(1) {
+                    Some(range) => Duration::from_nanos(self.next_random() % range),
+                    None => Duration::from_nanos(self.next_random()),
+                }
+            }
+        };
+        self.attempt += 1;
+        Some(delay)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::{Backoff, RecoveryPolicy};
+    use std::time::Duration;
+
+    fn millis(delays: Backoff) -> Vec<u128> {
+        delays.map(|delay| delay.as_millis()).collect()
+    }
+
+    #[test]
+    fn test_constant_backoff() {
+        let policy = RecoveryPolicy::Constant {
+            delay: Duration::from_millis(500),
+            max_attempts: Some(3),
+        };
+        assert_eq!(vec![500, 500, 500], millis(policy.backoff()));
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = RecoveryPolicy::Exponential {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            max_attempts: Some(6),
+        };
+        assert_eq!(
+            vec![100, 200, 400, 800, 1000, 1000],
+            millis(policy.backoff())
+        );
+
+        // no overflow after many attempts
+        let policy = RecoveryPolicy::Exponential {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            max_attempts: None,
+        };
+        let mut backoff = policy.backoff().skip(100);
+        assert_eq!(Some(Duration::from_secs(1)), backoff.next());
+    }
+
+    #[test]
+    fn test_exponential_jitter_backoff() {
+        let policy = RecoveryPolicy::ExponentialJitter {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            max_attempts: Some(8),
+        };
+        let delays: Vec<Duration> = policy.backoff_with_seed(42).collect();
+        assert_eq!(8, delays.len());
+        let caps = [100, 200, 400, 800, 1000, 1000, 1000, 1000];
+        for (delay, cap) in delays.iter().zip(caps) {
+            assert!(*delay <= Duration::from_millis(cap));
+        }
+        // same seed, same sequence
+        assert_eq!(delays, policy.backoff_with_seed(42).collect::<Vec<_>>());
+        // clients with different seeds spread out
+        assert_ne!(delays, policy.backoff_with_seed(7).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_backoff_reset() {
+        let policy = RecoveryPolicy::Exponential {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            max_attempts: Some(2),
+        };
+        let mut backoff = policy.backoff();
+        assert_eq!(Some(Duration::from_millis(100)), backoff.next());
+        assert_eq!(Some(Duration::from_millis(200)), backoff.next());
+        assert_eq!(None, backoff.next());
+        assert_eq!(2, backoff.attempts());
+
+        backoff.reset();
+        assert_eq!(Some(Duration::from_millis(100)), backoff.next());
+    }
+}
@@ -25,7 +25,7 @@ enum AuthenticationMechanism {
     PLAIN,
     AMQPLAIN,
     // EXTERNAL,
-    // RABBIT-CR-DEMO,
+    RabbitCrDemo,
 }
 
 impl SecurityCredentials {
@@ -50,11 +50,26 @@ impl SecurityCredentials {
         }
     }
 
+    /// Create and return a RABBIT-CR-DEMO credential with given `username` and `password`.
+    ///
+    /// The password is not sent in `start-ok`, but in response to the `secure` challenge
+    /// from server. This mechanism is for demonstration of challenge-response only.
+    ///
+    /// See [RabbitMQ access control](https://www.rabbitmq.com/access-control.html#mechanisms).
+    pub fn new_rabbit_cr_demo(username: &str, password: &str) -> Self {
+        Self {
+            username: username.to_owned(),
+            password: password.to_owned(),
+            mechanism: AuthenticationMechanism::RabbitCrDemo,
+        }
+    }
+
     /// Get the name of authentication mechanism of current credential
     pub(crate) fn get_mechanism_name(&self) -> &str {
         match self.mechanism {
             AuthenticationMechanism::PLAIN => "PLAIN",
             AuthenticationMechanism::AMQPLAIN => "AMQPLAIN",
+            AuthenticationMechanism::RabbitCrDemo => "RABBIT-CR-DEMO",
         }
     }
     /// Get the security challenge `response` string, to be sent to server.
@@ -88,6 +103,19 @@ impl SecurityCredentials {
                 .unwrap();
                 String::from_utf8(buf.to_vec()).unwrap()
             }
+            AuthenticationMechanism::RabbitCrDemo => self.username.clone(),
+        }
+    }
+
+    /// Get the `response` to a `secure` challenge from server.
+    ///
+    /// Returns [`None`] if the mechanism does not support challenge-response.
+    pub(crate) fn get_secure_response(&self, _challenge: &str) -> Option<String> {
+        match self.mechanism {
+            AuthenticationMechanism::PLAIN | AuthenticationMechanism::AMQPLAIN => None,
+            AuthenticationMechanism::RabbitCrDemo => {
+                Some(format!("My password is {}", self.password))
+            }
         }
     }
 }
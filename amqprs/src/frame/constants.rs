@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
 use amqp_serde::types::{LongUint, Octect, ShortUint};
+use std::fmt;
 
 pub const FRAME_HEADER_SIZE: usize = 7;
 pub const DEFAULT_CONN_CHANNEL: ShortUint = 0;
@@ -39,6 +40,68 @@ pub const NOT_ALLOWED: ShortUint = 530;
 pub const NOT_IMPLEMENTED: ShortUint = 540;
 pub const INTERNAL_ERROR: ShortUint = 541;
 
+/// Classification of the reply code of a connection or channel closed by server.
+///
+/// See [reply codes](https://www.rabbitmq.com/amqp-0-9-1-reference.html#constants).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseKind {
+    /// The cause is transient, e.g. server is shutting down or a resource is temporarily
+    /// unavailable, so reconnecting or reopening the channel may succeed.
+    Retryable,
+    /// The cause is in the client or its configuration, e.g. access refused, a missing
+    /// entity or a protocol error, so retrying as it is fails again.
+    Fatal,
+}
+
+impl CloseKind {
+    /// Returns the classification of `reply_code`, unknown reply codes are fatal.
+    pub fn from_reply_code(reply_code: ShortUint) -> Self {
+        match reply_code {
+            REPLY_SUCCESS | NO_CONSUMERS | RESOURCE_LOCKED | CONNECTION_FORCED | RESOURCE_ERROR
+            | INTERNAL_ERROR => Self::Retryable,
+            _ => Self::Fatal,
+        }
+    }
+}
+
+/// Log a close request from server, as a warning if it is retryable, otherwise as an error.
+#[cfg(feature = "traces")]
+pub(crate) fn trace_server_close(
+    reply_code: ShortUint,
+    reply_text: &str,
+    class_id: ShortUint,
+    method_id: ShortUint,
+    target: fmt::Arguments<'_>,
+) {
+    let kind = CloseKind::from_reply_code(reply_code);
+    macro_rules! trace_close {
+        ($level:ident) => {
+            tracing::$level!(
+                reply_code,
+                reply_text,
+                class_id,
+                method_id,
+                kind = %kind,
+                "server closes {}",
+                target
+            )
+        };
+    }
+    match kind {
+        CloseKind::Retryable => trace_close!(warn),
+        CloseKind::Fatal => trace_close!(error),
+    }
+}
+
+impl fmt::Display for CloseKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloseKind::Retryable => f.write_str("retryable"),
+            CloseKind::Fatal => f.write_str("fatal"),
+        }
+    }
+}
+
 /// class id
 pub const CLASS_CONNECTION: ShortUint = 10;
 pub const CLASS_CHANNEL: ShortUint = 20;
@@ -1,12 +1,23 @@
+use std::fmt;
+
 use serde::Serialize;
 
 use super::Frame;
 
-#[derive(Debug, Serialize)]
+#[derive(Serialize)]
 pub struct ContentBody {
     pub(crate) inner: Vec<u8>,
 }
 
+/// Only the length is shown, content body can be large.
+impl fmt::Debug for ContentBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContentBody")
+            .field("length", &self.inner.len())
+            .finish()
+    }
+}
+
 impl ContentBody {
     pub fn new(inner: Vec<u8>) -> Self {
         Self { inner }
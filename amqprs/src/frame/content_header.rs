@@ -1,8 +1,12 @@
-use std::fmt;
+use std::{
+    fmt,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use amqp_serde::types::{FieldTable, LongLongUint, Octect, ShortStr, ShortUint, TimeStamp};
-use serde::{de::Visitor, Deserialize, Serialize};
 use crate::{DELIVERY_MODE_PERSISTENT, DELIVERY_MODE_TRANSIENT};
+use amqp_serde::types::{FieldTable, LongLongUint, Octect, ShortStr, ShortUint, TimeStamp};
+use serde::{de::Visitor, ser::SerializeStruct, Deserialize, Serialize, Serializer};
 
 use super::Frame;
 
@@ -33,6 +37,125 @@ pub struct ContentHeaderCommon {
     pub body_size: LongLongUint,
 }
 
+////////////////////////////////////////////////////////////////////////////////
+/// Content header of a message whose properties are already encoded.
+#[derive(Debug)]
+pub struct EncodedContentHeader {
+    pub common: ContentHeaderCommon,
+    pub basic_properties: EncodedProperties,
+}
+
+impl EncodedContentHeader {
+    pub fn new(common: ContentHeaderCommon, basic_properties: EncodedProperties) -> Self {
+        Self {
+            common,
+            basic_properties,
+        }
+    }
+}
+
+impl Serialize for EncodedContentHeader {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("EncodedContentHeader", 2)?;
+        state.serialize_field("common", &self.common)?;
+        state.serialize_field("basic_properties", &self.basic_properties)?;
+        state.end()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// [`BasicProperties`] encoded once, to be published many times without encoding them again.
+///
+/// Cloning is cheap, the encoded bytes are shared.
+///
+/// # Example
+///
+/// ```
+/// # use amqprs::{BasicProperties, EncodedProperties};
+/// let basic_props = BasicProperties::default()
+///     .with_content_type("application/json")
+///     .finish();
+/// let encoded = EncodedProperties::new(&basic_props);
+/// // property flags, then the content type as short string
+/// assert_eq!(&[0x80, 0x00, 16], &encoded.as_bytes()[..3]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EncodedProperties {
+    bytes: Arc<[u8]>,
+}
+
+impl EncodedProperties {
+    /// Returns the encoded `basic_properties`.
+    pub fn new(basic_properties: &BasicProperties) -> Self {
+        // every property has a valid AMQP type, so encoding cannot fail
+        let bytes = amqp_serde::to_bytes(basic_properties).expect("encode message properties");
+        Self {
+            bytes: bytes.into(),
+        }
+    }
+
+    /// Returns the encoded bytes, i.e. the property flags followed by the property list.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Serialize for EncodedProperties {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // already encoded, written as it is
+        serializer.serialize_bytes(&self.bytes)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Source of the current time for the `timestamp` message property.
+///
+/// [`SystemClock`] is used by [`BasicProperties::with_timestamp_now`]. Any other clock,
+/// e.g. a fixed clock in tests or a logical clock, can be used by
+/// [`BasicProperties::with_timestamp_from`]. Closures returning the timestamp implement
+/// `Clock` as well.
+///
+/// # Example
+///
+/// ```
+/// # use amqprs::BasicProperties;
+/// let fixed_clock = || 1_700_000_000;
+/// let basic_props = BasicProperties::default()
+///     .with_timestamp_from(&fixed_clock)
+///     .finish();
+/// assert_eq!(Some(1_700_000_000), basic_props.timestamp());
+/// ```
+pub trait Clock {
+    /// Returns the current time in seconds since Unix epoch.
+    fn now(&self) -> TimeStamp;
+}
+
+/// Clock of the system time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> TimeStamp {
+        // a system time before Unix epoch is not representable
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+impl<F: Fn() -> TimeStamp> Clock for F {
+    fn now(&self) -> TimeStamp {
+        self()
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 /// AMQP message properties.
 ///
@@ -51,7 +174,7 @@ pub struct ContentHeaderCommon {
 ///     .with_app_id("app1")
 ///     .finish();
 /// ```
-#[derive(Debug, Serialize, Default, Clone)]
+#[derive(Debug, Default, Clone)]
 pub struct BasicProperties {
     // property flags bits are included in order to
     // manage the value according to optional property
@@ -256,6 +379,107 @@ impl BasicProperties {
         flags[1] |= 1 << 2;
     }
 
+    /// Returns the raw property flags, as sent on the wire.
+    pub fn property_flags(&self) -> [Octect; 2] {
+        self.property_flags
+    }
+
+    /// Returns `true` if no property is set.
+    ///
+    /// Empty properties are encoded as the property flags only, without any property field.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use amqprs::BasicProperties;
+    /// let mut props = BasicProperties::default();
+    /// assert!(props.is_empty());
+    /// props.with_app_id("app1");
+    /// assert!(!props.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.encoded_property_flags() == [0, 0]
+    }
+
+    /// Set the properties which are not set yet to the values in `defaults`.
+    ///
+    /// Each property is merged as a whole, e.g. `headers` is taken from `defaults`
+    /// only if no `headers` is set.
+    pub(crate) fn with_defaults(&mut self, defaults: &BasicProperties) -> &mut Self {
+        fn merge<T: Clone>(value: &mut Option<T>, default: &Option<T>) {
+            if value.is_none() {
+                *value = default.clone();
+            }
+        }
+        merge(&mut self.content_type, &defaults.content_type);
+        merge(&mut self.content_encoding, &defaults.content_encoding);
+        merge(&mut self.headers, &defaults.headers);
+        merge(&mut self.delivery_mode, &defaults.delivery_mode);
+        merge(&mut self.priority, &defaults.priority);
+        merge(&mut self.correlation_id, &defaults.correlation_id);
+        merge(&mut self.reply_to, &defaults.reply_to);
+        merge(&mut self.expiration, &defaults.expiration);
+        merge(&mut self.message_id, &defaults.message_id);
+        merge(&mut self.timestamp, &defaults.timestamp);
+        merge(&mut self.message_type, &defaults.message_type);
+        merge(&mut self.user_id, &defaults.user_id);
+        merge(&mut self.app_id, &defaults.app_id);
+        merge(&mut self.cluster_id, &defaults.cluster_id);
+        self.property_flags = self.encoded_property_flags();
+        self
+    }
+
+    /// Property flags of the properties which are set.
+    ///
+    /// Flags are derived from the properties instead of using the flags received from server,
+    /// so that bits which are reserved or for unset properties are never encoded.
+    fn encoded_property_flags(&self) -> [Octect; 2] {
+        let mut flags = [0u8; 2];
+        if self.content_type.is_some() {
+            Self::set_content_type_flag(&mut flags);
+        }
+        if self.content_encoding.is_some() {
+            Self::set_content_encoding_flag(&mut flags);
+        }
+        if self.headers.is_some() {
+            Self::set_headers_flag(&mut flags);
+        }
+        if self.delivery_mode.is_some() {
+            Self::set_delivery_mode_flag(&mut flags);
+        }
+        if self.priority.is_some() {
+            Self::set_priority_flag(&mut flags);
+        }
+        if self.correlation_id.is_some() {
+            Self::set_correlation_id_flag(&mut flags);
+        }
+        if self.reply_to.is_some() {
+            Self::set_reply_to_flag(&mut flags);
+        }
+        if self.expiration.is_some() {
+            Self::set_expiration_flag(&mut flags);
+        }
+        if self.message_id.is_some() {
+            Self::set_message_id_flag(&mut flags);
+        }
+        if self.timestamp.is_some() {
+            Self::set_timestamp_flag(&mut flags);
+        }
+        if self.message_type.is_some() {
+            Self::set_message_type_flag(&mut flags);
+        }
+        if self.user_id.is_some() {
+            Self::set_user_id_flag(&mut flags);
+        }
+        if self.app_id.is_some() {
+            Self::set_app_id_flag(&mut flags);
+        }
+        if self.cluster_id.is_some() {
+            Self::set_cluster_id_flag(&mut flags);
+        }
+        flags
+    }
+
     pub fn content_type(&self) -> Option<&String> {
         self.content_type.as_ref().map(|v| v.as_ref())
     }
@@ -435,6 +659,20 @@ impl BasicProperties {
         self
     }
 
+    /// Chainable setter of timestamp to the current system time.
+    ///
+    /// # Default: [`None`]
+    pub fn with_timestamp_now(&mut self) -> &mut Self {
+        self.with_timestamp_from(&SystemClock)
+    }
+
+    /// Chainable setter of timestamp to the current time of `clock`.
+    ///
+    /// # Default: [`None`]
+    pub fn with_timestamp_from<C: Clock + ?Sized>(&mut self, clock: &C) -> &mut Self {
+        self.with_timestamp(clock.now())
+    }
+
     pub fn message_type(&self) -> Option<&String> {
         self.message_type.as_ref().map(|v| v.as_ref())
     }
@@ -488,12 +726,118 @@ impl BasicProperties {
     }
 }
 
+impl Serialize for BasicProperties {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let flags = self.encoded_property_flags();
+        let len = 1 + flags.iter().map(|v| v.count_ones() as usize).sum::<usize>();
+        let mut state = serializer.serialize_struct("BasicPropertities", len)?;
+        state.serialize_field("property_flags", &flags)?;
+        if self.is_empty() {
+            return state.end();
+        }
+        // properties are encoded in the same order as flag bits, only if present
+        if let Some(ref v) = self.content_type {
+            state.serialize_field("content_type", v)?;
+        }
+        if let Some(ref v) = self.content_encoding {
+            state.serialize_field("content_encoding", v)?;
+        }
+        if let Some(ref v) = self.headers {
+            state.serialize_field("headers", v)?;
+        }
+        if let Some(ref v) = self.delivery_mode {
+            state.serialize_field("delivery_mode", v)?;
+        }
+        if let Some(ref v) = self.priority {
+            state.serialize_field("priority", v)?;
+        }
+        if let Some(ref v) = self.correlation_id {
+            state.serialize_field("correlation_id", v)?;
+        }
+        if let Some(ref v) = self.reply_to {
+            state.serialize_field("reply_to", v)?;
+        }
+        if let Some(ref v) = self.expiration {
+            state.serialize_field("expiration", v)?;
+        }
+        if let Some(ref v) = self.message_id {
+            state.serialize_field("message_id", v)?;
+        }
+        if let Some(ref v) = self.timestamp {
+            state.serialize_field("timestamp", v)?;
+        }
+        if let Some(ref v) = self.message_type {
+            state.serialize_field("typ", v)?;
+        }
+        if let Some(ref v) = self.user_id {
+            state.serialize_field("user_id", v)?;
+        }
+        if let Some(ref v) = self.app_id {
+            state.serialize_field("app_id", v)?;
+        }
+        if let Some(ref v) = self.cluster_id {
+            state.serialize_field("cluster_id", v)?;
+        }
+        state.end()
+    }
+}
+
+/// Property flags of basic class, followed by the continuation flags words if any.
+struct PropertyFlags([Octect; 2]);
+
+impl<'de> Deserialize<'de> for PropertyFlags {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct PropertyFlagsVisitor;
+
+        impl<'de> Visitor<'de> for PropertyFlagsVisitor {
+            type Value = PropertyFlags;
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("property flags")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let flags: [Octect; 2] = seq
+                    .next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+                // bit 0 is the continuation bit, another flags word follows if it is set,
+                // basic class has only 14 properties, so no property is flagged in it
+                let mut continuation = (flags[1] & 1) != 0;
+                while continuation {
+                    let more: [Octect; 2] = seq
+                        .next_element()?
+                        .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+                    if more[0] != 0 || (more[1] & !1) != 0 {
+                        return Err(serde::de::Error::custom(format!(
+                            "unknown basic properties flagged by {:#04x}{:02x}",
+                            more[0], more[1]
+                        )));
+                    }
+                    continuation = (more[1] & 1) != 0;
+                }
+                Ok(PropertyFlags(flags))
+            }
+        }
+        // number of flags words is given by the continuation bits
+        deserializer.deserialize_tuple(usize::MAX, PropertyFlagsVisitor)
+    }
+}
+
 impl<'de> Deserialize<'de> for BasicProperties {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
         const FIELDS: &[&str] = &[
+            "property_flags",
             "content_type",
             "content_encoding",
             "headers",
@@ -522,7 +866,7 @@ impl<'de> Deserialize<'de> for BasicProperties {
             where
                 A: serde::de::SeqAccess<'de>,
             {
-                let flags: [Octect; 2] = seq
+                let PropertyFlags(flags) = seq
                     .next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
                 let mut basic_properties = BasicProperties {
@@ -676,9 +1020,13 @@ impl fmt::Display for BasicProperties {
 
 #[cfg(test)]
 mod tests {
-    use amqp_serde::types::FieldTable;
+    use amqp_serde::{from_bytes, to_bytes, types::FieldTable};
 
-    use crate::{BasicProperties, DELIVERY_MODE_TRANSIENT};
+    use super::{
+        Clock, ContentHeader, ContentHeaderCommon, EncodedContentHeader, EncodedProperties,
+        SystemClock,
+    };
+    use crate::{BasicProperties, DELIVERY_MODE_PERSISTENT, DELIVERY_MODE_TRANSIENT};
 
     #[test]
     fn test_basic_properties_internal_flags() {
@@ -725,4 +1073,184 @@ mod tests {
         props.with_timestamp(1674404425);
         assert_eq!([0xC8, 0xC8], props.property_flags);
     }
+
+    #[test]
+    fn test_serialize_basic_properties_with_gaps() {
+        let props = BasicProperties::default()
+            .with_content_type("text/plain")
+            .with_app_id("app")
+            .finish();
+
+        let bytes = to_bytes(&props).unwrap();
+        // bit 15 for content_type, bit 3 for app_id, followed by the two properties only
+        let mut expected = vec![0b1000_0000, 0b0000_1000];
+        expected.extend_from_slice(b"\x0atext/plain");
+        expected.extend_from_slice(b"\x03app");
+        assert_eq!(expected, bytes);
+
+        let decoded: BasicProperties = from_bytes(&bytes).unwrap();
+        assert_eq!(props.property_flags(), decoded.property_flags());
+        assert_eq!("text/plain", decoded.content_type().unwrap());
+        assert_eq!("app", decoded.app_id().unwrap());
+        assert!(decoded.headers().is_none());
+        assert!(decoded.timestamp().is_none());
+
+        let props = BasicProperties::default()
+            .with_delivery_mode(DELIVERY_MODE_TRANSIENT)
+            .with_timestamp(0x0102)
+            .with_cluster_id("c")
+            .finish();
+        let mut expected = vec![0b0001_0000, 0b0100_0100, DELIVERY_MODE_TRANSIENT];
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 2]);
+        expected.extend_from_slice(b"\x01c");
+        assert_eq!(expected, to_bytes(&props).unwrap());
+    }
+
+    #[test]
+    fn test_serialize_empty_basic_properties() {
+        let props = BasicProperties::default();
+        assert!(props.is_empty());
+        assert_eq!(vec![0, 0], to_bytes(&props).unwrap());
+
+        // reserved bits received from server are not encoded,
+        // bit 0 is the continuation bit followed by an empty flags word
+        let props: BasicProperties = from_bytes(&[0x00, 0x03, 0x00, 0x00]).unwrap();
+        assert_eq!([0x00, 0x03], props.property_flags());
+        assert!(props.is_empty());
+        assert_eq!(vec![0, 0], to_bytes(&props).unwrap());
+    }
+
+    #[test]
+    fn test_deserialize_all_basic_properties() {
+        let mut headers = FieldTable::new();
+        headers.insert("k".try_into().unwrap(), "v".into());
+        let props = BasicProperties::new(
+            Some("application/json".to_owned()),
+            Some("gzip".to_owned()),
+            Some(headers.clone()),
+            Some(DELIVERY_MODE_PERSISTENT),
+            Some(9),
+            Some("correlation".to_owned()),
+            Some("reply".to_owned()),
+            Some("60000".to_owned()),
+            Some("message".to_owned()),
+            Some(1674404425),
+            Some("type".to_owned()),
+            Some("user".to_owned()),
+            Some("app".to_owned()),
+            Some("cluster".to_owned()),
+        );
+
+        let decoded: BasicProperties = from_bytes(&to_bytes(&props).unwrap()).unwrap();
+        assert_eq!([0xff, 0xfc], decoded.property_flags());
+        assert_eq!(
+            Some("application/json"),
+            decoded.content_type().map(String::as_str)
+        );
+        assert_eq!(Some("gzip"), decoded.content_encoding().map(String::as_str));
+        assert_eq!(Some(&headers), decoded.headers());
+        assert_eq!(Some(DELIVERY_MODE_PERSISTENT), decoded.delivery_mode());
+        assert_eq!(Some(9), decoded.priority());
+        assert_eq!(
+            Some("correlation"),
+            decoded.correlation_id().map(String::as_str)
+        );
+        assert_eq!(Some("reply"), decoded.reply_to().map(String::as_str));
+        assert_eq!(Some("60000"), decoded.expiration().map(String::as_str));
+        assert_eq!(Some("message"), decoded.message_id().map(String::as_str));
+        assert_eq!(Some(1674404425), decoded.timestamp());
+        assert_eq!(Some("type"), decoded.message_type().map(String::as_str));
+        assert_eq!(Some("user"), decoded.user_id().map(String::as_str));
+        assert_eq!(Some("app"), decoded.app_id().map(String::as_str));
+        assert_eq!(Some("cluster"), decoded.cluster_id().map(String::as_str));
+
+        // continuation flags words are skipped before the properties
+        let mut bytes = vec![0b1000_0000, 0b0000_0001, 0x00, 0x01, 0x00, 0x00];
+        bytes.extend_from_slice(b"\x04text");
+        let decoded: BasicProperties = from_bytes(&bytes).unwrap();
+        assert_eq!("text", decoded.content_type().unwrap());
+
+        // unknown properties can't be skipped
+        let bytes = [0x00, 0x01, 0x80, 0x00, 0x04, b't', b'e', b'x', b't'];
+        assert!(from_bytes::<BasicProperties>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_timestamp_from_clock() {
+        struct LogicalClock(std::cell::Cell<u64>);
+        impl Clock for LogicalClock {
+            fn now(&self) -> u64 {
+                self.0.replace(self.0.get() + 1)
+            }
+        }
+
+        let clock = LogicalClock(std::cell::Cell::new(7));
+        let props = BasicProperties::default()
+            .with_timestamp_from(&clock)
+            .finish();
+        assert_eq!(Some(7), props.timestamp());
+        assert_eq!(
+            vec![0x00, 0x40, 0, 0, 0, 0, 0, 0, 0, 7],
+            to_bytes(&props).unwrap()
+        );
+        let props = BasicProperties::default()
+            .with_timestamp_from(&clock)
+            .finish();
+        assert_eq!(Some(8), props.timestamp());
+
+        let before = SystemClock.now();
+        let props = BasicProperties::default().with_timestamp_now().finish();
+        assert!(props.timestamp().unwrap() >= before);
+        assert!(props.timestamp().unwrap() <= SystemClock.now());
+    }
+
+    #[test]
+    fn test_basic_properties_with_defaults() {
+        let defaults = BasicProperties::default()
+            .with_app_id("app")
+            .with_content_type("application/json")
+            .with_persistence(true)
+            .finish();
+
+        let mut props = BasicProperties::default()
+            .with_content_type("text/plain")
+            .with_message_id("101")
+            .finish();
+        props.with_defaults(&defaults);
+        assert_eq!("text/plain", props.content_type().unwrap());
+        assert_eq!("101", props.message_id().unwrap());
+        assert_eq!("app", props.app_id().unwrap());
+        assert_eq!(Some(DELIVERY_MODE_PERSISTENT), props.delivery_mode());
+        assert!(props.headers().is_none());
+        assert_eq!([0x90, 0x88], props.property_flags());
+
+        // no default to merge
+        let mut props = BasicProperties::default().with_priority(1).finish();
+        props.with_defaults(&BasicProperties::default());
+        assert_eq!([0x08, 0x00], props.property_flags());
+    }
+
+    #[test]
+    fn test_encoded_content_header() {
+        let mut headers = FieldTable::new();
+        headers.insert("x-fan-out".try_into().unwrap(), true.into());
+        let props = BasicProperties::default()
+            .with_content_type("application/json")
+            .with_headers(headers)
+            .with_persistence(true)
+            .finish();
+        let common = || ContentHeaderCommon {
+            class: 60,
+            weight: 0,
+            body_size: 5,
+        };
+        let encoded = EncodedProperties::new(&props);
+        assert_eq!(to_bytes(&props).unwrap(), encoded.as_bytes());
+
+        // same bytes on the wire as encoding the properties on each publish
+        assert_eq!(
+            to_bytes(&ContentHeader::new(common(), props)).unwrap(),
+            to_bytes(&EncodedContentHeader::new(common(), encoded)).unwrap()
+        );
+    }
 }
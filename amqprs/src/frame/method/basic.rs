@@ -102,6 +102,10 @@ impl Consume {
             self.bits &= !bit_flag::consume::NO_WAIT;
         }
     }
+    #[cfg(test)]
+    pub(crate) fn arguments(&self) -> &FieldTable {
+        &self.arguments
+    }
 }
 
 // RX
@@ -169,6 +173,13 @@ impl Publish {
         }
     }
 
+    pub fn exchange(&self) -> &str {
+        self.exchange.as_ref()
+    }
+    pub fn routing_key(&self) -> &str {
+        self.routing_key.as_ref()
+    }
+
     pub fn set_mandatory(&mut self, value: bool) {
         if value {
             self.bits |= bit_flag::publish::MANDATORY;
@@ -210,6 +221,20 @@ impl fmt::Display for Return {
     }
 }
 impl Return {
+    pub(crate) fn new(
+        reply_code: ShortUint,
+        reply_text: ShortStr,
+        exchange: AmqpExchangeName,
+        routing_key: ShortStr,
+    ) -> Self {
+        Self {
+            reply_code,
+            reply_text,
+            exchange,
+            routing_key,
+        }
+    }
+
     pub fn reply_code(&self) -> u16 {
         self.reply_code
     }
@@ -226,6 +251,34 @@ impl Return {
         self.routing_key.as_ref()
     }
 }
+/// Delivery tag of a message delivered by server, see [`Deliver::delivery_tag`]
+/// and [`GetOk::delivery_tag`].
+///
+/// A distinct type from other integers, so that it can not be accidentally mixed up
+/// when acknowledging, rejecting or negatively acknowledging a message.
+///
+/// Converts from and into `u64` for interoperability.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeliveryTag(LongLongUint);
+
+impl From<u64> for DeliveryTag {
+    fn from(tag: u64) -> Self {
+        Self(tag)
+    }
+}
+
+impl From<DeliveryTag> for u64 {
+    fn from(tag: DeliveryTag) -> Self {
+        tag.0
+    }
+}
+
+impl fmt::Display for DeliveryTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
 /// Used by consumer [`consume`] callback.
 ///
 /// AMQP method frame [deliver](https://www.rabbitmq.com/amqp-0-9-1-reference.html#basic.deliver).
@@ -252,8 +305,8 @@ impl Deliver {
     pub fn consumer_tag(&self) -> &String {
         self.consumer_tag.as_ref()
     }
-    pub fn delivery_tag(&self) -> u64 {
-        self.delivery_tag
+    pub fn delivery_tag(&self) -> DeliveryTag {
+        DeliveryTag(self.delivery_tag)
     }
 
     pub fn redelivered(&self) -> bool {
@@ -307,8 +360,8 @@ impl fmt::Display for GetOk {
     }
 }
 impl GetOk {
-    pub fn delivery_tag(&self) -> u64 {
-        self.delivery_tag
+    pub fn delivery_tag(&self) -> DeliveryTag {
+        DeliveryTag(self.delivery_tag)
     }
 
     pub fn redelivered(&self) -> bool {
@@ -1,6 +1,6 @@
 use std::fmt;
 
-use crate::frame::REPLY_SUCCESS;
+use crate::frame::{CloseKind, REPLY_SUCCESS};
 use amqp_serde::types::{Boolean, LongStr, ShortStr, ShortUint};
 use serde::{Deserialize, Serialize};
 
@@ -28,7 +28,7 @@ pub struct OpenChannelOk {
 ///
 /// [`close`]: callbacks/trait.ChannelCallback.html#tymethod.close
 // TX + RX
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CloseChannel {
     reply_code: ShortUint,
     reply_text: ShortStr,
@@ -52,6 +52,11 @@ impl CloseChannel {
     pub fn method_id(&self) -> u16 {
         self.method_id
     }
+
+    /// Returns whether the close is retryable or fatal, see [`CloseKind`].
+    pub fn kind(&self) -> CloseKind {
+        CloseKind::from_reply_code(self.reply_code)
+    }
 }
 
 impl fmt::Display for CloseChannel {
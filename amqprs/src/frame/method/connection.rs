@@ -1,6 +1,6 @@
 use std::fmt;
 
-use crate::frame::REPLY_SUCCESS;
+use crate::frame::{CloseKind, REPLY_SUCCESS};
 use amqp_serde::types::{AmqpPeerProperties, LongStr, LongUint, Octect, ShortStr, ShortUint};
 use serde::{Deserialize, Serialize};
 
@@ -18,7 +18,7 @@ pub struct StartOk {
     client_properties: AmqpPeerProperties,
     machanisms: ShortStr,
     response: LongStr,
-    locale: ShortStr,
+    pub(crate) locale: ShortStr,
 }
 
 impl StartOk {
@@ -50,9 +50,9 @@ impl Default for StartOk {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Tune {
-    channel_max: ShortUint,
-    frame_max: LongUint,
-    heartbeat: ShortUint,
+    pub(crate) channel_max: ShortUint,
+    pub(crate) frame_max: LongUint,
+    pub(crate) heartbeat: ShortUint,
 }
 
 impl Tune {
@@ -120,7 +120,7 @@ impl Default for Open {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenOk {
     ///  Deprecated: "known-hosts", must be zero
-    know_hosts: ShortStr,
+    pub(crate) know_hosts: ShortStr,
 }
 /// Used by connection's [`close`] callback.
 ///
@@ -128,12 +128,12 @@ pub struct OpenOk {
 ///
 /// [`close`]: callbacks/trait.ConnectionCallback.html#tymethod.close
 // TX + RX
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Close {
-    reply_code: ShortUint,
-    reply_text: ShortStr,
-    class_id: ShortUint,
-    method_id: ShortUint,
+    pub(crate) reply_code: ShortUint,
+    pub(crate) reply_text: ShortStr,
+    pub(crate) class_id: ShortUint,
+    pub(crate) method_id: ShortUint,
 }
 impl fmt::Display for Close {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -162,6 +162,11 @@ impl Close {
     pub fn method_id(&self) -> u16 {
         self.method_id
     }
+
+    /// Returns whether the close is retryable or fatal, see [`CloseKind`].
+    pub fn kind(&self) -> CloseKind {
+        CloseKind::from_reply_code(self.reply_code)
+    }
 }
 impl Default for Close {
     // compliance:
@@ -188,11 +193,10 @@ pub struct Secure {
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct SecureOk {
-    response: LongStr,
+    pub(crate) response: LongStr,
 }
 
 impl SecureOk {
-    #[allow(dead_code, /* challenge response not used in PLAIN/AMQPLIAN auth machanism*/)]
     pub fn new(response: LongStr) -> Self {
         Self { response }
     }
@@ -1,5 +1,5 @@
 use amqp_serde::{
-    from_bytes,
+    from_bytes, from_bytes_with_context,
     types::{AmqpChannelId, LongUint, Octect, ShortUint},
 };
 
@@ -32,7 +32,9 @@ mod helpers {
                 match header.class_id() {
                     $($class_id => {
                         match header.method_id() {
-                            $($method_id => Ok(from_bytes::<$method>(content)?.into_frame()),)+
+                            $($method_id => {
+                                Ok(from_bytes_with_context::<$method>(content)?.into_frame())
+                            })+
                             _ => unimplemented!("unknown method id"),
                         }
                     })+
@@ -59,6 +61,44 @@ mod helpers {
                 ContentBody(ContentBody),
                 // speical frame combination for publish
                 PublishCombo(Publish, Box<ContentHeader>, ContentBody),
+                // same as above, but message properties are already encoded
+                PublishEncodedCombo(Publish, Box<EncodedContentHeader>, ContentBody),
+            }
+
+            /// Concise representation for logs, payloads are not dumped.
+            impl fmt::Display for Frame {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    match self {
+                        $($(Frame::$method(header, _) => write!(
+                            f,
+                            "{}({}, {})",
+                            stringify!($method),
+                            header.class_id(),
+                            header.method_id()
+                        ),)+)+
+                        Frame::HeartBeat(_) => write!(f, "HeartBeat"),
+                        Frame::ContentHeader(header) => write!(
+                            f,
+                            "ContentHeader(class: {}, body size: {})",
+                            header.common.class, header.common.body_size
+                        ),
+                        Frame::ContentBody(body) => write!(f, "ContentBody({} bytes)", body.inner.len()),
+                        Frame::PublishCombo(_, header, _) => write!(
+                            f,
+                            "PublishCombo({}, {}, body size: {})",
+                            Publish::header().class_id(),
+                            Publish::header().method_id(),
+                            header.common.body_size
+                        ),
+                        Frame::PublishEncodedCombo(_, header, _) => write!(
+                            f,
+                            "PublishEncodedCombo({}, {}, body size: {})",
+                            Publish::header().class_id(),
+                            Publish::header().method_id(),
+                            header.common.body_size
+                        ),
+                    }
+                }
             }
         };
     }
@@ -167,12 +207,6 @@ pub struct FrameHeader {
     pub payload_size: LongUint,
 }
 
-impl fmt::Display for Frame {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
-
 impl Frame {
     pub fn get_frame_type(&self) -> Octect {
         match self {
@@ -239,17 +273,19 @@ impl Frame {
             FRAME_CONTENT_HEADER => {
                 let mut start = FRAME_HEADER_SIZE;
                 let mut end = start + 12;
-                let header_common: ContentHeaderCommon = from_bytes(match buf.get(start..end) {
-                    Some(s) => s,
-                    None => unreachable!("out of bound"),
-                })?;
+                let header_common: ContentHeaderCommon =
+                    from_bytes_with_context(match buf.get(start..end) {
+                        Some(s) => s,
+                        None => unreachable!("out of bound"),
+                    })?;
 
                 start = end;
                 end = total_size - 1;
-                let basic_properties: BasicProperties = from_bytes(match buf.get(start..end) {
-                    Some(s) => s,
-                    None => unreachable!("out of bound"),
-                })?;
+                let basic_properties: BasicProperties =
+                    from_bytes_with_context(match buf.get(start..end) {
+                        Some(s) => s,
+                        None => unreachable!("out of bound"),
+                    })?;
 
                 Ok(Some((
                     total_size,
@@ -276,3 +312,46 @@ impl Frame {
 }
 
 /////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::{ContentBody, Deliver, Frame};
+    use amqp_serde::from_bytes;
+
+    #[test]
+    fn test_display_deliver_frame_is_concise() {
+        // consumer tag, delivery tag, redelivered, exchange, routing key
+        let raw = [
+            4, b'c', b't', b'a', b'g', 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 2, b'r', b'k',
+        ];
+        let deliver: Deliver = from_bytes(&raw).unwrap();
+        assert_eq!("Deliver(60, 60)", format!("{}", deliver.into_frame()));
+    }
+
+    #[test]
+    fn test_decode_error_has_context() {
+        // routing key claims 5 bytes but only 2 are present
+        let method = [
+            0, 60, 0, 60, 4, b'c', b't', b'a', b'g', 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 5, b'r', b'k',
+        ];
+        let mut buf = vec![1, 0, 1, 0, 0, 0, method.len() as u8];
+        buf.extend_from_slice(&method);
+        buf.push(0xCE);
+
+        let err = Frame::decode(&buf).unwrap_err();
+        assert_eq!(
+            "serde error: unexpected syntax at offset 16 while decoding ShortStr in Deliver",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_display_content_body_frame_summarized() {
+        let frame = Frame::ContentBody(ContentBody::new(vec![0; 1024 * 1024]));
+        assert_eq!("ContentBody(1048576 bytes)", format!("{}", frame));
+        assert_eq!(
+            "ContentBody(ContentBody { length: 1048576 })",
+            format!("{:?}", frame)
+        );
+    }
+}
@@ -1,6 +1,11 @@
+use std::fmt;
+
 use amqp_serde::types::*;
 use serde::{Deserialize, Serialize};
 
+/// Size of the protocol header in bytes.
+pub const PROTOCOL_HEADER_SIZE: usize = 8;
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 struct ProtocolName(Octect, Octect, Octect, Octect);
 
@@ -32,6 +37,35 @@ impl Default for ProtocolHeader {
     }
 }
 
+impl From<[u8; PROTOCOL_HEADER_SIZE]> for ProtocolHeader {
+    fn from(bytes: [u8; PROTOCOL_HEADER_SIZE]) -> Self {
+        Self {
+            name: ProtocolName(bytes[0], bytes[1], bytes[2], bytes[3]),
+            id: bytes[4],
+            version: ProtocolVersion {
+                major: bytes[5],
+                minor: bytes[6],
+                revision: bytes[7],
+            },
+        }
+    }
+}
+
+impl fmt::Display for ProtocolHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ProtocolName(a, b, c, d) = self.name;
+        write!(
+            f,
+            "{} {}-{}-{}-{}",
+            String::from_utf8_lossy(&[a, b, c, d]),
+            self.id,
+            self.version.major,
+            self.version.minor,
+            self.version.revision
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use amqp_serde::{from_bytes, to_bytes};
@@ -63,4 +97,14 @@ mod tests {
             version
         );
     }
+
+    #[test]
+    fn test_from_bytes() {
+        let header = ProtocolHeader::from(*b"AMQP\x00\x00\x09\x01");
+        assert_eq!(
+            to_bytes(&ProtocolHeader::default()).unwrap(),
+            to_bytes(&header).unwrap()
+        );
+        assert_eq!("AMQP 0-0-9-1", header.to_string());
+    }
 }
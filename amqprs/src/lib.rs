@@ -52,10 +52,21 @@
 //!     If disabled, then it relies on server to reject.
 //! - "tls": enable SSL/TLS.
 //! - "urispec": enable support of [RabbitMQ URI Specification](https://www.rabbitmq.com/uri-spec.html)
+//! - "body_pool": enable reuse of consumed content buffers given back by `ConsumerMessage::recycle`,
+//!   to reduce allocations of high-throughput consumers. Requires Rust 1.63 or later.
+//! - "codec": enable publishing and consuming typed messages with a pluggable [`Codec`].
+//! - "json": enable "codec" with the JSON format, see `Channel::publish_json`.
+//! - "compression": enable decompressing consumed content by its `content_encoding`,
+//!   see `ConsumerMessage::body_decoded`.
+//! - "opentelemetry": enable propagation of OpenTelemetry trace context in message headers,
+//!   see `BasicProperties::with_trace_context` and `ConsumerMessage::extract_context`.
+//! - "unstable": enable APIs for testing only, which may change in any release,
+//!   e.g. `OpenConnectionArguments::protocol_header` to send a custom protocol header.
 //!
 //! [`Connection`]: connection/struct.Connection.html
 //! [`Channel`]: channel/struct.Channel.html
 //! [`callbacks`]: callbacks/index.html
+//! [`Codec`]: codec/trait.Codec.html
 //!
 /////////////////////////////////////////////////////////////////////////////
 #[cfg(test)]
@@ -71,12 +82,17 @@ pub use api::*;
 pub use frame::Ack;
 pub use frame::BasicProperties;
 pub use frame::Cancel;
+pub use frame::Clock;
 pub use frame::Close;
 pub use frame::CloseChannel;
+pub use frame::CloseKind;
 pub use frame::Deliver;
+pub use frame::DeliveryTag;
+pub use frame::EncodedProperties;
 pub use frame::GetOk;
 pub use frame::Nack;
 pub use frame::Return;
+pub use frame::SystemClock;
 
 pub use frame::DELIVERY_MODE_PERSISTENT;
 pub use frame::DELIVERY_MODE_TRANSIENT;
@@ -5,10 +5,12 @@ use tokio::sync::mpsc::error::SendError;
 
 #[derive(Debug)]
 pub(crate) enum Error {
-    NetworkIo(String),
+    NetworkIo(io::Error),
     SyncChannel(String),
     Serde(String),
     Framing(String),
+    /// server replied its supported protocol header instead of `connection.start`
+    UnsupportedProtocol(String),
     Callback,
     PeerShutdown,
     Interrupted,
@@ -16,7 +18,7 @@ pub(crate) enum Error {
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
-        Error::NetworkIo(err.to_string())
+        Error::NetworkIo(err)
     }
 }
 impl From<amqp_serde::Error> for Error {
@@ -35,13 +37,37 @@ impl<T> From<SendError<T>> for Error {
     }
 }
 
+impl Error {
+    /// Convert a failure of the transport into an I/O error, for example a connection reset
+    /// or the socket closed by peer. Returns `None` for other errors.
+    pub(crate) fn into_io_error(self) -> Option<io::Error> {
+        match self {
+            Error::NetworkIo(err) => Some(err),
+            Error::PeerShutdown => Some(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "socket closed by peer",
+            )),
+            Error::Interrupted => Some(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "socket closed by peer in the middle of a frame",
+            )),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::NetworkIo(msg) => write!(f, "network io error: {}", msg),
+            Error::NetworkIo(err) => write!(f, "network io error: {}", err),
             Error::SyncChannel(msg) => write!(f, "internal communication error: {}", msg),
             Error::Serde(msg) => write!(f, "serde error: {}", msg),
             Error::Framing(msg) => write!(f, "framing error: {}", msg),
+            Error::UnsupportedProtocol(supported) => write!(
+                f,
+                "protocol header rejected by server, server supports '{}'",
+                supported
+            ),
             Error::Callback => write!(f, "callback error"),
             Error::PeerShutdown => f.write_str("peer shutdown"),
             Error::Interrupted => f.write_str("connection interrupted"),
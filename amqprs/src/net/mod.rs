@@ -5,8 +5,6 @@ mod reader_handler;
 mod split_connection;
 mod writer_handler;
 
-
-
 pub(crate) use channel_manager::*;
 pub(crate) use error::*;
 pub(crate) use reader_handler::*;
@@ -18,12 +16,16 @@ use crate::{
     frame::{Frame, MethodHeader},
 };
 use amqp_serde::types::AmqpChannelId;
+use std::io;
 use tokio::sync::oneshot;
 
 pub type OutgoingMessage = (AmqpChannelId, Frame);
 
 pub(crate) type IncomingMessage = Frame;
 
+/// Request to be notified once all outgoing messages enqueued before it are written.
+pub(crate) type FlushRequest = oneshot::Sender<()>;
+
 pub(crate) struct RegisterChannelResource {
     /// If None, `net` handler will allocate a channel id for client
     pub channel_id: Option<AmqpChannelId>,
@@ -49,4 +51,6 @@ pub(crate) enum ConnManagementCommand {
 
     RegisterResponder(RegisterResponder),
     RegisterConnectionCallback(RegisterConnectionCallback),
+    /// `WriterHandler` failed to write to socket
+    ReportIoError(io::Error),
 }
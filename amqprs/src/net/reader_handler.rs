@@ -10,9 +10,12 @@ use tokio::{
 #[cfg(feature = "traces")]
 use tracing::{debug, error, info, trace, warn};
 
+#[cfg(feature = "traces")]
+use crate::frame::trace_server_close;
+
 use crate::{
     api::{callbacks::ConnectionCallback, connection::Connection},
-    frame::{CloseOk, Frame, DEFAULT_CONN_CHANNEL},
+    frame::{Close, CloseOk, Frame, DEFAULT_CONN_CHANNEL, FRAME_ERROR},
 };
 
 use super::{
@@ -27,7 +30,8 @@ pub(crate) struct ReaderHandler {
     /// AMQ connection
     amqp_connection: Connection,
 
-    /// sender half to forward outgoing message to `WriterHandler`
+    /// sender half to forward connection-level outgoing message to `WriterHandler`,
+    /// which writes them ahead of other outgoing messages
     outgoing_tx: Sender<OutgoingMessage>,
 
     /// receiver half to receive management command from AMQ Connection/Channel
@@ -129,6 +133,16 @@ impl ReaderHandler {
             // Method frames of asynchronous request
             // Server request to close connection
             Frame::Close(_, close) => {
+                #[cfg(feature = "traces")]
+                trace_server_close(
+                    close.reply_code(),
+                    close.reply_text(),
+                    close.class_id(),
+                    close.method_id(),
+                    format_args!("connection {}", self.amqp_connection),
+                );
+                // pending and later requests fail with the reason
+                self.amqp_connection.set_close_reason(close.clone());
                 if let Some(ref mut callback) = self.callback {
                     if let Err(err) = callback.close(&self.amqp_connection, close).await {
                         #[cfg(feature = "traces")]
@@ -161,7 +175,26 @@ impl ReaderHandler {
                 Ok(())
             }
 
+            Frame::UpdateSecretOk(method_header, update_secret_ok) => {
+                match self
+                    .channel_manager
+                    .remove_responder(&channel_id, method_header)
+                {
+                    Some(responder) => responder
+                        .send(update_secret_ok.into_frame())
+                        .map_err(|response| Error::SyncChannel(response.to_string())),
+                    None => {
+                        #[cfg(feature = "traces")]
+                        warn!(
+                            "UpdateSecretOk responder not found on connection {}",
+                            self.amqp_connection
+                        );
+                        Ok(())
+                    }
+                }
+            }
             Frame::Blocked(_, blocked) => {
+                self.amqp_connection.set_is_blocked(true);
                 if let Some(ref mut callback) = self.callback {
                     callback
                         .blocked(&self.amqp_connection, blocked.reason.into())
@@ -176,6 +209,7 @@ impl ReaderHandler {
                 Ok(())
             }
             Frame::Unblocked(_, _unblocked) => {
+                self.amqp_connection.set_is_blocked(false);
                 if let Some(ref mut callback) = self.callback {
                     callback.unblocked(&self.amqp_connection).await;
                 } else {
@@ -188,6 +222,8 @@ impl ReaderHandler {
                 Ok(())
             }
             // dispatch other frames to channel dispatcher
+            // frames are routed by channel id only, so content frames of a channel can
+            // interleave with frames of other channels
             _ => {
                 let dispatcher = self.channel_manager.get_dispatcher(&channel_id);
                 match dispatcher {
@@ -196,16 +232,61 @@ impl ReaderHandler {
                         Ok(())
                     }
                     None => {
-                        unreachable!(
-                            "dispatcher must be registered for channel {} of {}",
-                            channel_id, self.amqp_connection,
+                        // server may still send frames, e.g. deliveries, to a channel
+                        // which has been closed by client
+                        #[cfg(feature = "traces")]
+                        warn!(
+                            "drop {} received on unknown channel {} of {}",
+                            frame, channel_id, self.amqp_connection,
                         );
+                        Ok(())
                     }
                 }
             }
         }
     }
 
+    /// Notify the callback of network I/O failure, before the connection is torn down.
+    async fn handle_io_error(&mut self, err: std::io::Error) {
+        if let Some(ref mut callback) = self.callback {
+            callback.io_error(&self.amqp_connection, err).await;
+        } else {
+            #[cfg(feature = "traces")]
+            warn!(
+                "callback not registered on connection {}",
+                self.amqp_connection
+            );
+        }
+    }
+
+    /// Close the connection with `FRAME_ERROR` due to a malformed or oversized frame from server.
+    ///
+    /// Not waiting for `close-ok`, because frames following the bad one can't be parsed reliably.
+    async fn close_with_frame_error(&mut self, reason: String) {
+        let mut reply_text = reason;
+        // short string of at most 255 bytes
+        while reply_text.len() > 255 {
+            reply_text.pop();
+        }
+        let close = Close {
+            reply_code: FRAME_ERROR,
+            reply_text: reply_text.try_into().unwrap(),
+            class_id: 0,
+            method_id: 0,
+        };
+        if let Err(_err) = self
+            .outgoing_tx
+            .send((DEFAULT_CONN_CHANNEL, close.into_frame()))
+            .await
+        {
+            #[cfg(feature = "traces")]
+            error!(
+                "failed to send close with frame error on connection {}, cause: {}",
+                self.amqp_connection, _err
+            );
+        }
+    }
+
     pub async fn run_until_shutdown(mut self, heartbeat: ShortUint) {
         // max interval to consider heartbeat is timeout
         let max_interval: u64 = heartbeat.into();
@@ -246,11 +327,19 @@ impl ReaderHandler {
                             #[cfg(feature="traces")]
                             debug!("callback registered on connection {}", self.amqp_connection);
                         },
+                        ConnManagementCommand::ReportIoError(err) => {
+                            is_network_failure = true;
+                            #[cfg(feature="traces")]
+                            error!("socket will be closed due to failure of writing frame, cause: {}", err);
+                            self.handle_io_error(err).await;
+                            break;
+                        },
                     }
                 }
                 res = self.stream.read_frame() => {
                     // any frame can be considered as heartbeat
                     expiration = time::Instant::now() + time::Duration::from_secs(max_interval);
+                    self.amqp_connection.frame_received();
                     #[cfg(feature="traces")]
                     trace!("server heartbeat deadline is updated to {:?}", expiration);
 
@@ -270,11 +359,22 @@ impl ReaderHandler {
                                 break;
                             }
                         },
+                        Err(Error::Framing(reason)) => {
+                            // protocol violation by server, no more frames can be trusted
+                            is_network_failure = true;
+                            #[cfg(feature="traces")]
+                            error!("connection {} will be closed due to frame error, cause: {}", self.amqp_connection, reason);
+                            self.close_with_frame_error(reason).await;
+                            break;
+                        },
                         Err(err) => {
                             // notifiy network failure
                             is_network_failure = true;
                             #[cfg(feature="traces")]
                             error!("socket will be closed due to failure of reading frame, cause: {}", err);
+                            if let Some(err) = err.into_io_error() {
+                                self.handle_io_error(err).await;
+                            }
                             break;
                         },
                     }
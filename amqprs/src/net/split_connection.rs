@@ -1,5 +1,6 @@
 use crate::frame::{
-    ContentBody, Frame, FrameHeader, FRAME_CONTENT_BODY, FRAME_END, FRAME_HEADER_SIZE,
+    ContentBody, EncodedContentHeader, Frame, FrameHeader, ProtocolHeader, FRAME_CONTENT_BODY,
+    FRAME_CONTENT_HEADER, FRAME_END, FRAME_HEADER_SIZE, PROTOCOL_HEADER_SIZE,
 };
 
 use amqp_serde::{
@@ -32,6 +33,8 @@ pub(crate) struct SplitConnection {
 pub(crate) struct BufIoReader {
     stream: ReadHalf<SplitIoStream>,
     buffer: BytesMut,
+    /// negotiated max frame size, `None` before tuning or if no limit
+    frame_max: Option<usize>,
 }
 pub(crate) struct BufIoWriter {
     stream: WriteHalf<SplitIoStream>,
@@ -106,28 +109,37 @@ impl AsyncWrite for SplitIoStream {
     }
 }
 
+impl From<TcpStream> for SplitConnection {
+    fn from(stream: TcpStream) -> Self {
+        Self::new(stream.into())
+    }
+}
+
 // Support to split socket connection into reader half and wirter half, which can be run in different tasks cocurrently
 // Same interfaces to read/write packet before and after split.
 impl SplitConnection {
-    pub async fn open(addr: &str) -> Result<Self> {
-        let stream = TcpStream::connect(addr).await?;
-
-        let stream: SplitIoStream = stream.into();
+    fn new(stream: SplitIoStream) -> Self {
         let (reader, writer) = tokio::io::split(stream);
 
         let read_buffer = BytesMut::with_capacity(DEFAULT_IO_BUFFER_SIZE);
         let write_buffer = BytesMut::with_capacity(DEFAULT_IO_BUFFER_SIZE);
 
-        Ok(Self {
+        Self {
             reader: BufIoReader {
                 stream: reader,
                 buffer: read_buffer,
+                frame_max: None,
             },
             writer: BufIoWriter {
                 stream: writer,
                 buffer: write_buffer,
             },
-        })
+        }
+    }
+
+    pub async fn open(addr: &str) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Self::new(stream.into()))
     }
 
     #[cfg(feature = "tls")]
@@ -138,22 +150,7 @@ impl SplitConnection {
         let stream = connector
             .connect(domain, TcpStream::connect(addr).await?)
             .await?;
-        let stream: SplitIoStream = stream.into();
-        let (reader, writer) = tokio::io::split(stream);
-
-        let read_buffer = BytesMut::with_capacity(DEFAULT_IO_BUFFER_SIZE);
-        let write_buffer = BytesMut::with_capacity(DEFAULT_IO_BUFFER_SIZE);
-
-        Ok(Self {
-            reader: BufIoReader {
-                stream: reader,
-                buffer: read_buffer,
-            },
-            writer: BufIoWriter {
-                stream: writer,
-                buffer: write_buffer,
-            },
-        })
+        Ok(Self::new(stream.into()))
     }
 
     /// split connection into reader half and writer half
@@ -185,8 +182,11 @@ impl SplitConnection {
     pub async fn read_frame(&mut self) -> Result<ChannelFrame> {
         self.reader.read_frame().await
     }
-}
 
+    pub fn set_frame_max(&mut self, frame_max: LongUint) {
+        self.reader.set_frame_max(frame_max)
+    }
+}
 
 impl BufIoWriter {
     // write any serializable value to socket
@@ -278,6 +278,27 @@ impl BufIoWriter {
 
         Ok(())
     }
+
+    /// specific version for serialize content header with encoded properties,
+    /// the properties are copied to buffer as they are
+    async fn serialize_encoded_content_header_into_buffer(
+        &mut self,
+        channel: AmqpChannelId,
+        content_header: &EncodedContentHeader,
+    ) -> Result<()> {
+        // class (2 octets), weight (2 octets), body size (8 octets), then properties
+        let payload_size = 12 + content_header.basic_properties.as_bytes().len();
+        let header = FrameHeader {
+            frame_type: FRAME_CONTENT_HEADER,
+            channel,
+            payload_size: payload_size as u32,
+        };
+        to_buffer(&header, &mut self.buffer).unwrap();
+        to_buffer(content_header, &mut self.buffer)?;
+        self.buffer.put_u8(FRAME_END);
+        Ok(())
+    }
+
     // write a AMQP frame over a specific channel
     pub async fn write_frame(
         &mut self,
@@ -289,16 +310,26 @@ impl BufIoWriter {
         #[cfg(feature = "traces")]
         trace!("SENT on channel {}: {}", channel, frame);
 
-        if let Frame::PublishCombo(publish, content_header, content_body) = frame {
-            self.serialize_frame_into_buffer(channel, publish.into_frame())
-                .await?;
-            self.serialize_frame_into_buffer(channel, content_header.into_frame())
-                .await?;
+        match frame {
+            Frame::PublishCombo(publish, content_header, content_body) => {
+                self.serialize_frame_into_buffer(channel, publish.into_frame())
+                    .await?;
+                self.serialize_frame_into_buffer(channel, content_header.into_frame())
+                    .await?;
 
-            self.serialize_content_body_into_buffer(channel, content_body, frame_max as usize)
-                .await?;
-        } else {
-            self.serialize_frame_into_buffer(channel, frame).await?;
+                self.serialize_content_body_into_buffer(channel, content_body, frame_max as usize)
+                    .await?;
+            }
+            Frame::PublishEncodedCombo(publish, content_header, content_body) => {
+                self.serialize_frame_into_buffer(channel, publish.into_frame())
+                    .await?;
+                self.serialize_encoded_content_header_into_buffer(channel, &content_header)
+                    .await?;
+
+                self.serialize_content_body_into_buffer(channel, content_body, frame_max as usize)
+                    .await?;
+            }
+            frame => self.serialize_frame_into_buffer(channel, frame).await?,
         }
         // flush whole buffer
         self.stream.write_all(&self.buffer).await?;
@@ -319,10 +350,51 @@ impl BufIoWriter {
 type ChannelFrame = (AmqpChannelId, Frame);
 
 impl BufIoReader {
+    /// Reject frames larger than `frame_max` negotiated with server, zero means no limit.
+    pub fn set_frame_max(&mut self, frame_max: LongUint) {
+        self.frame_max = match frame_max {
+            0 => None,
+            frame_max => Some(frame_max as usize),
+        };
+    }
+
+    /// Check the size of the frame being received from its header, so that an oversized
+    /// frame is rejected before its payload is buffered.
+    fn check_frame_size(&self) -> Result<()> {
+        let (frame_max, header) = match (self.frame_max, self.buffer.get(..FRAME_HEADER_SIZE)) {
+            (Some(frame_max), Some(header)) => (frame_max, header),
+            _ => return Ok(()),
+        };
+        // frame type (1 octet), channel (2 octets), payload size (4 octets)
+        let payload_size = u32::from_be_bytes([header[3], header[4], header[5], header[6]]);
+        let frame_size = payload_size as usize + FRAME_HEADER_SIZE + 1;
+        if frame_size > frame_max {
+            return Err(Error::Framing(format!(
+                "frame size {} exceeds frame_max {}",
+                frame_size, frame_max
+            )));
+        }
+        Ok(())
+    }
+
     // try to decode a whole frame from the bufferred data.
     // If it is incomplete data, return None;
     // If the frame syntax is corrupted, return Error.
     fn decode(&mut self) -> Result<Option<ChannelFrame>> {
+        // server replies the protocol header it supports instead of any frame
+        // if it rejects the protocol header sent by client, 'A' is not a frame type
+        if self.buffer.starts_with(b"AMQP") {
+            return match self.buffer.get(..PROTOCOL_HEADER_SIZE) {
+                Some(bytes) => {
+                    let header = ProtocolHeader::from(
+                        <[u8; PROTOCOL_HEADER_SIZE]>::try_from(bytes).unwrap(),
+                    );
+                    Err(Error::UnsupportedProtocol(header.to_string()))
+                }
+                None => Ok(None),
+            };
+        }
+        self.check_frame_size()?;
         match Frame::decode(&self.buffer)? {
             Some((len, channel_id, frame)) => {
                 // discard parsed data in read buffer
@@ -8,34 +8,99 @@ use tokio::{
 use tracing::{debug, error, info, trace};
 
 use crate::{
+    channel::publish_frame_count,
     connection::Connection,
     frame::{Frame, HeartBeat, DEFAULT_CONN_CHANNEL},
 };
 
-use super::{BufIoWriter, OutgoingMessage};
+use super::{BufIoWriter, Error, FlushRequest, OutgoingMessage};
 
 pub(crate) struct WriterHandler {
     stream: BufIoWriter,
     /// receiver half to forward outgoing messages from AMQ connection/channel to server
     outgoing_rx: mpsc::Receiver<OutgoingMessage>,
+    /// receiver half of connection-level messages, which have priority over `outgoing_rx`
+    priority_rx: mpsc::Receiver<OutgoingMessage>,
+    /// receiver half of flush requests from AMQ connection/channel
+    flush_rx: mpsc::Receiver<FlushRequest>,
     /// listener of shutdown signal
     shutdown: broadcast::Receiver<bool>,
     /// connection
     amqp_connection: Connection,
+    /// limits the rate of frames written from `outgoing_rx`
+    frame_limiter: Option<FrameRateLimiter>,
+}
+
+/// Token bucket of frames, refilled at a fixed rate per second.
+struct FrameRateLimiter {
+    /// frames per second
+    rate: f64,
+    /// max tokens, i.e. the max burst of frames
+    capacity: f64,
+    /// available tokens, negative if a message of many frames exceeded them
+    tokens: f64,
+    /// when `tokens` was last refilled
+    refilled: time::Instant,
+}
+
+impl FrameRateLimiter {
+    fn new(max_frames_per_sec: u32) -> Self {
+        let rate = max_frames_per_sec as f64;
+        // burst of a tenth of a second
+        let capacity = (rate / 10.0).max(1.0);
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            refilled: time::Instant::now(),
+        }
+    }
+
+    /// Returns `true` if a frame can be written now.
+    fn is_ready(&mut self) -> bool {
+        let now = time::Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled = now;
+        self.tokens >= 1.0
+    }
+
+    /// Returns when a frame can be written, after `is_ready` returns `false`.
+    fn ready_at(&self) -> time::Instant {
+        self.refilled + time::Duration::from_secs_f64((1.0 - self.tokens) / self.rate)
+    }
+
+    /// Take tokens of written frames, the tokens can go negative.
+    fn take(&mut self, frames: u64) {
+        self.tokens -= frames as f64;
+    }
 }
 
 impl WriterHandler {
     pub fn new(
         stream: BufIoWriter,
         outgoing_rx: mpsc::Receiver<OutgoingMessage>,
+        priority_rx: mpsc::Receiver<OutgoingMessage>,
+        flush_rx: mpsc::Receiver<FlushRequest>,
         shutdown: broadcast::Receiver<bool>,
         amqp_connection: Connection,
+        max_frames_per_sec: Option<u32>,
     ) -> Self {
         Self {
             stream,
             outgoing_rx,
+            priority_rx,
+            flush_rx,
             shutdown,
             amqp_connection,
+            frame_limiter: max_frames_per_sec.map(FrameRateLimiter::new),
+        }
+    }
+
+    /// Report failure of writing to socket to `ReaderHandler`, which notifies the callback.
+    fn report_io_error(&self, err: Error) {
+        if let Some(err) = err.into_io_error() {
+            self.amqp_connection.report_io_error(err);
         }
     }
 
@@ -46,23 +111,71 @@ impl WriterHandler {
         let mut expiration = time::Instant::now() + time::Duration::from_secs(interval);
 
         loop {
+            // outgoing messages and flush requests wait while frame rate is above the limit
+            let ready = self
+                .frame_limiter
+                .as_mut()
+                .map_or(true, FrameRateLimiter::is_ready);
+            let ready_at = match &self.frame_limiter {
+                Some(limiter) if !ready => limiter.ready_at(),
+                _ => time::Instant::now(),
+            };
             tokio::select! {
                 biased;
 
-                channel_frame = self.outgoing_rx.recv() => {
-                    let (channel_id, frame) = match channel_frame {
+                // connection-level frames are polled first, so they are not delayed by
+                // a backlog of outgoing messages, e.g. under heavy publish load
+                Some((channel_id, mut frame)) = self.priority_rx.recv() => {
+                    self.amqp_connection.apply_outgoing_middleware(channel_id, &mut frame);
+                    if let Err(err) = self.stream.write_frame(channel_id, frame, self.amqp_connection.frame_max()).await {
+                        #[cfg(feature = "traces")]
+                        error!("failed to send frame over connection {}, cause: {}", self.amqp_connection, err);
+                        self.report_io_error(err);
+                        break;
+                    }
+                    expiration = time::Instant::now() + time::Duration::from_secs(interval);
+                }
+                channel_frame = self.outgoing_rx.recv(), if ready => {
+                    let (channel_id, mut frame) = match channel_frame {
                         None => break,
                         Some(v) => v,
                     };
-                    if let Err(err) = self.stream.write_frame(channel_id, frame, self.amqp_connection.frame_max()).await {
+                    self.amqp_connection.apply_outgoing_middleware(channel_id, &mut frame);
+                    // content of published message is counted in buffered bytes until written
+                    let buffered = match &frame {
+                        Frame::PublishCombo(_, _, content_body)
+                        | Frame::PublishEncodedCombo(_, _, content_body) => content_body.inner.len(),
+                        _ => 0,
+                    };
+                    if let Some(limiter) = self.frame_limiter.as_mut() {
+                        let frames = match &frame {
+                            Frame::PublishCombo(..) | Frame::PublishEncodedCombo(..) => {
+                                publish_frame_count(buffered, self.amqp_connection.frame_max())
+                            }
+                            _ => 1,
+                        };
+                        limiter.take(frames);
+                    }
+                    let result = self.stream.write_frame(channel_id, frame, self.amqp_connection.frame_max()).await;
+                    self.amqp_connection.release_buffer(buffered);
+                    if let Err(err) = result {
                         #[cfg(feature="tracing")]
                         error!("failed to send frame over connection {}, cause: {}", self.amqp_connection, err);
+                        self.report_io_error(err);
                         break;
                     }
                     expiration = time::Instant::now() + time::Duration::from_secs(interval);
                     #[cfg(feature="tracing")]
                     trace!("connection {} heartbeat deadline is updated to {:?}", self.amqp_connection, expiration);
                 }
+                // `biased` polls outgoing messages first, so a flush request is only handled
+                // when all messages enqueued before it have been written
+                Some(responder) = self.flush_rx.recv(), if ready => {
+                    responder.send(()).ok();
+                }
+                _ = time::sleep_until(ready_at), if !ready => {
+                    // tokens are refilled on next iteration
+                }
                 _ = time::sleep_until(expiration) => {
                     if expiration <= time::Instant::now() {
                         expiration = time::Instant::now() + time::Duration::from_secs(interval);
@@ -70,8 +183,10 @@ impl WriterHandler {
                         if let Err(err) = self.stream.write_frame(DEFAULT_CONN_CHANNEL, Frame::HeartBeat(HeartBeat), self.amqp_connection.frame_max()).await {
                             #[cfg(feature="tracing")]
                             error!("failed to send heartbeat over connection {}, cause: {}", self.amqp_connection, err);
+                            self.report_io_error(err);
                             break;
                         }
+                        self.amqp_connection.heartbeat_sent();
                         #[cfg(feature="tracing")]
                         debug!("sent heartbeat over connection {}", self.amqp_connection,);
                     }
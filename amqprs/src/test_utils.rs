@@ -1,6 +1,30 @@
 #[cfg(test)]
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
+#[cfg(test)]
+use crate::frame::{Ack, Cancel, Nack, Return};
+#[cfg(test)]
+use crate::frame::{
+    CancelOk, CloseChannel, ConsumeOk, ContentHeader, ContentHeaderCommon, DeclareQueueOk, Deliver,
+};
+#[cfg(test)]
+use crate::frame::{
+    CloseChannelOk, CloseOk, Frame, OpenChannelOk, OpenOk, Start, Tune, DEFAULT_CONN_CHANNEL,
+    FRAME_MIN_SIZE,
+};
+#[cfg(test)]
+use crate::net::SplitConnection;
+#[cfg(test)]
+use crate::{callbacks::ChannelCallback, channel::Channel, error::Error, BasicProperties};
+#[cfg(test)]
+use amqp_serde::types::{AmqpChannelId, AmqpPeerProperties};
+#[cfg(test)]
+use async_trait::async_trait;
+#[cfg(test)]
+use tokio::sync::mpsc;
+#[cfg(test)]
+use tokio::{io::AsyncReadExt, net::TcpListener, task::JoinHandle};
+
 //////////////////////////////////////////////////////////////////
 // construct a subscriber that prints formatted traces to stdout
 #[cfg(test)]
@@ -12,3 +36,328 @@ pub fn setup_logging() {
         .try_init()
         .ok();
 }
+
+//////////////////////////////////////////////////////////////////
+// assert the encoded payload of `frame`, i.e. without frame header and end,
+// panics with a hex diff of the mismatched rows
+#[cfg(test)]
+pub fn assert_frame_bytes(frame: &Frame, expected: &[u8]) {
+    let actual = amqp_serde::to_bytes(frame).unwrap();
+    if actual != expected {
+        panic!("frame {} {}", frame, hex_diff(expected, &actual));
+    }
+}
+
+// rows of 16 bytes which differ, with `^^` under the differing bytes
+#[cfg(test)]
+fn hex_diff(expected: &[u8], actual: &[u8]) -> String {
+    const ROW: usize = 16;
+    let hex_row = |bytes: &[u8], start: usize| {
+        (start..start + ROW)
+            .map(|i| {
+                bytes
+                    .get(i)
+                    .map_or("  ".to_owned(), |b| format!("{:02x}", b))
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let first = expected
+        .iter()
+        .zip(actual)
+        .position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+    let mut diff = format!(
+        "encoded {} bytes, expected {} bytes, first difference at offset {}",
+        actual.len(),
+        expected.len(),
+        first
+    );
+    for start in (0..expected.len().max(actual.len())).step_by(ROW) {
+        let end = start + ROW;
+        if expected.get(start..end.min(expected.len())) == actual.get(start..end.min(actual.len()))
+        {
+            continue;
+        }
+        let marks = (start..end)
+            .map(|i| {
+                if expected.get(i) == actual.get(i) {
+                    "  "
+                } else {
+                    "^^"
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        diff.push_str(&format!(
+            "\n{:04x} expected: {}\n     actual: {}\n             {}",
+            start,
+            hex_row(expected, start),
+            hex_row(actual, start),
+            marks.trim_end()
+        ));
+    }
+    diff
+}
+
+//////////////////////////////////////////////////////////////////
+// mock server accepts a connection and completes the handshake of `Connection::open`
+#[cfg(test)]
+pub async fn accept_mock_connection(listener: &TcpListener) -> SplitConnection {
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let mut protocol_header = [0u8; 8];
+    stream.read_exact(&mut protocol_header).await.unwrap();
+    let mut io_conn = SplitConnection::from(stream);
+
+    let start = Start {
+        version_major: 0,
+        version_minor: 9,
+        server_properties: AmqpPeerProperties::new(),
+        mechanisms: "PLAIN AMQPLAIN".try_into().unwrap(),
+        locales: "en_US".try_into().unwrap(),
+    };
+    io_conn
+        .write_frame(DEFAULT_CONN_CHANNEL, start.into_frame(), FRAME_MIN_SIZE)
+        .await
+        .unwrap();
+    let (_, frame) = io_conn.read_frame().await.unwrap();
+    assert!(matches!(frame, Frame::StartOk(..)));
+
+    let tune = Tune {
+        channel_max: 16,
+        frame_max: FRAME_MIN_SIZE,
+        heartbeat: 0,
+    };
+    io_conn
+        .write_frame(DEFAULT_CONN_CHANNEL, tune.into_frame(), FRAME_MIN_SIZE)
+        .await
+        .unwrap();
+    let (_, frame) = io_conn.read_frame().await.unwrap();
+    assert!(matches!(frame, Frame::TuneOk(..)));
+
+    let (_, frame) = io_conn.read_frame().await.unwrap();
+    assert!(matches!(frame, Frame::Open(..)));
+    let open_ok = OpenOk {
+        know_hosts: "".try_into().unwrap(),
+    };
+    io_conn
+        .write_frame(DEFAULT_CONN_CHANNEL, open_ok.into_frame(), FRAME_MIN_SIZE)
+        .await
+        .unwrap();
+    io_conn
+}
+
+//////////////////////////////////////////////////////////////////
+// mock broker serving one connection on a local port, returns the port and the server task.
+//
+// After the handshake, `respond` is called with `state`, and the channel id and frame of
+// each frame received, the returned frames are sent back on the same channel.
+// The broker replies `connection.close` itself, then the task returns `state`,
+// it also returns once the client drops the connection.
+#[cfg(test)]
+pub async fn mock_broker<S, F>(mut state: S, mut respond: F) -> (u16, JoinHandle<S>)
+where
+    S: Send + 'static,
+    F: FnMut(&mut S, AmqpChannelId, Frame) -> Vec<Frame> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+        let mut io_conn = accept_mock_connection(&listener).await;
+        while let Ok((channel_id, frame)) = io_conn.read_frame().await {
+            if let Frame::Close(..) = frame {
+                io_conn
+                    .write_frame(channel_id, CloseOk.into_frame(), FRAME_MIN_SIZE)
+                    .await
+                    .unwrap();
+                break;
+            }
+            for response in respond(&mut state, channel_id, frame) {
+                io_conn
+                    .write_frame(channel_id, response, FRAME_MIN_SIZE)
+                    .await
+                    .unwrap();
+            }
+        }
+        state
+    });
+    (port, server)
+}
+
+// default reply of mock broker to open and close a channel, panics on any other frame
+#[cfg(test)]
+pub fn channel_reply(frame: Frame) -> Vec<Frame> {
+    match frame {
+        Frame::OpenChannel(..) => vec![OpenChannelOk {
+            channel_id: "".try_into().unwrap(),
+        }
+        .into_frame()],
+        Frame::CloseChannel(..) => vec![CloseChannelOk.into_frame()],
+        Frame::CloseChannelOk(..) => vec![],
+        other => panic!("unexpected frame {}", other),
+    }
+}
+
+// frames sent by mock broker, built from their fields
+#[cfg(test)]
+pub fn consume_ok(consumer_tag: &str) -> Frame {
+    ConsumeOk {
+        consumer_tag: consumer_tag.try_into().unwrap(),
+    }
+    .into_frame()
+}
+
+#[cfg(test)]
+pub fn cancel_ok(consumer_tag: &str) -> Frame {
+    CancelOk::new(consumer_tag.try_into().unwrap()).into_frame()
+}
+
+#[cfg(test)]
+pub fn declare_queue_ok(queue: &str) -> Frame {
+    DeclareQueueOk {
+        queue: queue.try_into().unwrap(),
+        message_count: 0,
+        consumer_count: 0,
+    }
+    .into_frame()
+}
+
+// close a channel with the reply code and text, as caused by the class and method
+#[cfg(test)]
+pub fn close_channel(reply_code: u16, reply_text: &str, class_id: u16, method_id: u16) -> Frame {
+    let mut bytes = reply_code.to_be_bytes().to_vec();
+    bytes.push(reply_text.len() as u8);
+    bytes.extend_from_slice(reply_text.as_bytes());
+    bytes.extend_from_slice(&class_id.to_be_bytes());
+    bytes.extend_from_slice(&method_id.to_be_bytes());
+    amqp_serde::from_bytes::<CloseChannel>(&bytes)
+        .unwrap()
+        .into_frame()
+}
+
+// read `count` short strings of method arguments after the ticket,
+// returns them and the offset of the next argument
+#[cfg(test)]
+pub fn short_strs(bytes: &[u8], count: usize) -> (Vec<String>, usize) {
+    let mut cursor = 2;
+    let mut strs = vec![];
+    for _ in 0..count {
+        let len = bytes[cursor] as usize;
+        strs.push(String::from_utf8(bytes[cursor + 1..][..len].to_vec()).unwrap());
+        cursor += 1 + len;
+    }
+    (strs, cursor)
+}
+
+#[cfg(test)]
+pub fn deliver(consumer_tag: &str, delivery_tag: u64) -> Deliver {
+    // consumer tag, delivery tag, not redelivered, exchange "", routing key "q"
+    let mut bytes = vec![consumer_tag.len() as u8];
+    bytes.extend_from_slice(consumer_tag.as_bytes());
+    bytes.extend_from_slice(&delivery_tag.to_be_bytes());
+    bytes.extend_from_slice(&[0, 0, 1, b'q']);
+    amqp_serde::from_bytes(&bytes).unwrap()
+}
+
+#[cfg(test)]
+pub fn content_header(body_size: u64) -> ContentHeader {
+    ContentHeader::new(
+        ContentHeaderCommon {
+            class: 60,
+            weight: 0,
+            body_size,
+        },
+        BasicProperties::default(),
+    )
+}
+
+//////////////////////////////////////////////////////////////////
+// forward acks and returned messages to test
+#[cfg(test)]
+pub struct ReturnRecorder(pub mpsc::UnboundedSender<String>);
+
+#[cfg(test)]
+#[async_trait]
+impl ChannelCallback for ReturnRecorder {
+    async fn close(&mut self, _: &Channel, _: CloseChannel) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn cancel(&mut self, _: &Channel, _: Cancel) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn flow(&mut self, _: &Channel, active: bool) -> Result<bool, Error> {
+        Ok(active)
+    }
+    async fn publish_ack(&mut self, _: &Channel, ack: Ack) {
+        self.0.send(format!("ack {}", ack.delivery_tag())).unwrap();
+    }
+    async fn publish_nack(&mut self, _: &Channel, _: Nack) {}
+    async fn publish_return(
+        &mut self,
+        _: &Channel,
+        ret: Return,
+        _: BasicProperties,
+        content: Vec<u8>,
+    ) {
+        let content = String::from_utf8(content).unwrap();
+        self.0
+            .send(format!("return {} {}", ret.reply_text(), content))
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assert_frame_bytes, hex_diff};
+    use crate::frame::{Ack, Nack};
+
+    #[test]
+    fn test_assert_frame_bytes() {
+        // class id, method id, delivery tag, multiple bit
+        assert_frame_bytes(
+            &Ack::new(1, true).into_frame(),
+            &[0, 60, 0, 80, 0, 0, 0, 0, 0, 0, 0, 1, 1],
+        );
+        let mut nack = Nack::new(2);
+        nack.set_requeue(true);
+        assert_frame_bytes(
+            &nack.into_frame(),
+            &[0, 60, 0, 120, 0, 0, 0, 0, 0, 0, 0, 2, 0b10],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "first difference at offset 11")]
+    fn test_assert_frame_bytes_catches_corrupted_encoding() {
+        // delivery tag corrupted
+        assert_frame_bytes(
+            &Ack::new(1, true).into_frame(),
+            &[0, 60, 0, 80, 0, 0, 0, 0, 0, 0, 0, 2, 1],
+        );
+    }
+
+    #[test]
+    fn test_hex_diff() {
+        let expected: Vec<u8> = (0..20).collect();
+        let mut actual = expected.clone();
+        actual[17] = 0xff;
+        actual.push(0xce);
+        let diff = hex_diff(&expected, &actual);
+        let lines: Vec<&str> = diff.lines().collect();
+        assert_eq!(
+            "encoded 21 bytes, expected 20 bytes, first difference at offset 17",
+            lines[0]
+        );
+        // only the second row differs
+        assert_eq!(4, lines.len());
+        assert_eq!(
+            "0010 expected: 10 11 12 13                                    ",
+            lines[1]
+        );
+        assert_eq!(
+            "     actual: 10 ff 12 13 ce                                 ",
+            lines[2]
+        );
+        assert_eq!("                ^^       ^^", lines[3]);
+    }
+}
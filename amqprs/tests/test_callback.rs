@@ -28,7 +28,10 @@ async fn test_connection_callback() {
     // the connection callback for `Close` method should be called and all internal channel services are closed
     // which results in error of below API call
     channel
-        .exchange_declare(ExchangeDeclareArguments::new("amq.direct", "invalid_type"))
+        .exchange_declare(ExchangeDeclareArguments::new(
+            "amqprs.test.invalid_type",
+            "invalid_type",
+        ))
         .await
         .unwrap();
 }
@@ -50,8 +53,12 @@ async fn test_channel_callback() {
         .await
         .unwrap();
 
-    // expect panic because "amp.topic" is `durable = true`, we declare "durable = false",
+    // expect panic because the exchange is `durable = true`, we redeclare "durable = false",
     // which is the default value in arguments.
-    let args = ExchangeDeclareArguments::of_type("amq.topic", ExchangeType::Topic);
+    let args = ExchangeDeclareArguments::of_type("amqprs.test.durable_topic", ExchangeType::Topic)
+        .durable(true)
+        .finish();
+    channel.exchange_declare(args).await.unwrap();
+    let args = ExchangeDeclareArguments::of_type("amqprs.test.durable_topic", ExchangeType::Topic);
     channel.exchange_declare(args).await.unwrap();
 }
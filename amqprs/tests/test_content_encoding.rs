@@ -0,0 +1,62 @@
+#![cfg(feature = "compression")]
+use amqprs::{
+    channel::{BasicConsumeArguments, BasicPublishArguments, QueueDeclareArguments},
+    connection::Connection,
+    BasicProperties,
+};
+mod common;
+
+/// "hello amqprs" compressed by gzip
+const GZIP_CONTENT: [u8; 32] = [
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57,
+    0x48, 0xcc, 0x2d, 0x2c, 0x28, 0x2a, 0x06, 0x00, 0x7e, 0x00, 0x8f, 0x32, 0x0c, 0x00, 0x00, 0x00,
+];
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_consume_gzip_encoded_content() {
+    common::setup_logging();
+
+    // open a connection to RabbitMQ server
+    let args = common::build_conn_args();
+
+    let connection = Connection::open(&args).await.unwrap();
+    let channel = connection.open_channel(None).await.unwrap();
+
+    let (queue_name, ..) = channel
+        .queue_declare(QueueDeclareArguments::default())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // compressed and plain messages can be consumed from the same queue
+    channel
+        .basic_publish(
+            BasicProperties::default()
+                .with_content_encoding("gzip")
+                .finish(),
+            GZIP_CONTENT.to_vec(),
+            BasicPublishArguments::new("", &queue_name),
+        )
+        .await
+        .unwrap();
+    channel
+        .basic_publish(
+            BasicProperties::default(),
+            b"hello amqprs".to_vec(),
+            BasicPublishArguments::new("", &queue_name),
+        )
+        .await
+        .unwrap();
+
+    let consume_args = BasicConsumeArguments::new(&queue_name, "")
+        .auto_ack(true)
+        .finish();
+    let (_, mut messages_rx) = channel.basic_consume_rx(consume_args).await.unwrap();
+    for _ in 0..2 {
+        let message = messages_rx.recv().await.unwrap();
+        assert_eq!(b"hello amqprs", &*message.body_decoded().unwrap());
+    }
+
+    channel.close().await.unwrap();
+    connection.close().await.unwrap();
+}
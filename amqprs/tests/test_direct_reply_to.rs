@@ -0,0 +1,98 @@
+use amqprs::{
+    channel::{
+        BasicConsumeArguments, BasicPublishArguments, QueueDeclareArguments, DIRECT_REPLY_TO_QUEUE,
+    },
+    connection::Connection,
+    error::Error,
+    BasicProperties,
+};
+use tokio::time;
+mod common;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_direct_reply_to_rpc() {
+    common::setup_logging();
+
+    // open a connection to RabbitMQ server
+    let args = common::build_conn_args();
+
+    let connection = Connection::open(&args).await.unwrap();
+
+    // rpc server: reply to each request by its `reply_to` address
+    let server_channel = connection.open_channel(None).await.unwrap();
+    let (request_queue, ..) = server_channel
+        .queue_declare(QueueDeclareArguments::default())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut requests = server_channel
+        .basic_consume_stream(
+            BasicConsumeArguments::new(&request_queue, "")
+                .auto_ack(true)
+                .finish(),
+        )
+        .await
+        .unwrap();
+    let server = tokio::spawn(async move {
+        let request = requests.recv().await.unwrap();
+        let props = request.basic_properties.unwrap();
+        let mut reply = request.content.unwrap();
+        reply.reverse();
+        server_channel
+            .basic_publish(
+                BasicProperties::default()
+                    .with_correlation_id(props.correlation_id().unwrap())
+                    .finish(),
+                reply,
+                BasicPublishArguments::new("", props.reply_to().unwrap()),
+            )
+            .await
+            .unwrap();
+        server_channel
+    });
+
+    // rpc client: consume replies from the pseudo-queue before publishing requests
+    let client_channel = connection.open_channel(None).await.unwrap();
+
+    // manual ack is not allowed on the pseudo-queue
+    let result = client_channel
+        .basic_consume_stream(BasicConsumeArguments::new(DIRECT_REPLY_TO_QUEUE, ""))
+        .await;
+    assert!(matches!(result, Err(Error::ChannelUseError(_))));
+
+    let mut replies = client_channel
+        .basic_consume_stream(BasicConsumeArguments::direct_reply_to())
+        .await
+        .unwrap();
+    client_channel
+        .basic_publish(
+            BasicProperties::default()
+                .with_reply_to(DIRECT_REPLY_TO_QUEUE)
+                .with_correlation_id("rpc-1")
+                .finish(),
+            b"ping".to_vec(),
+            BasicPublishArguments::new("", &request_queue),
+        )
+        .await
+        .unwrap();
+
+    let reply = replies
+        .recv_timeout(time::Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(
+        "rpc-1",
+        reply
+            .basic_properties
+            .as_ref()
+            .unwrap()
+            .correlation_id()
+            .unwrap()
+    );
+    assert_eq!(b"gnip".to_vec(), reply.content.unwrap());
+
+    let server_channel = server.await.unwrap();
+    server_channel.close().await.unwrap();
+    client_channel.close().await.unwrap();
+    connection.close().await.unwrap();
+}
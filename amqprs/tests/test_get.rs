@@ -215,3 +215,55 @@ async fn test_get_empty() {
         panic!("expect ReturnEmpty message");
     }
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_get_message_count() {
+    common::setup_logging();
+
+    let args = common::build_conn_args();
+    let connection = Connection::open(&args).await.unwrap();
+    let channel = connection.open_channel(None).await.unwrap();
+
+    let (queue_name, ..) = channel
+        .queue_declare(QueueDeclareArguments::default())
+        .await
+        .unwrap()
+        .unwrap();
+    for i in 0..3 {
+        channel
+            .basic_publish(
+                BasicProperties::default(),
+                format!("message {}", i).into_bytes(),
+                BasicPublishArguments::new("", &queue_name),
+            )
+            .await
+            .unwrap();
+    }
+    // wait until all messages are queued
+    loop {
+        let (_, message_count, _) = channel
+            .queue_declare(
+                QueueDeclareArguments::new(&queue_name)
+                    .passive(true)
+                    .finish(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        if message_count == 3 {
+            break;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+    }
+
+    // remaining count decreases with each get
+    let get_args = BasicGetArguments::new(&queue_name).no_ack(true).finish();
+    for remaining in [2, 1, 0] {
+        let (get_ok, ..) = channel.basic_get(get_args.clone()).await.unwrap().unwrap();
+        assert_eq!(remaining, get_ok.message_count());
+    }
+    assert!(channel.basic_get(get_args).await.unwrap().is_none());
+
+    channel.close().await.unwrap();
+    connection.close().await.unwrap();
+}
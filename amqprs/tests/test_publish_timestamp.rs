@@ -0,0 +1,49 @@
+use amqprs::{
+    channel::{BasicGetArguments, BasicPublishArguments, QueueDeclareArguments},
+    connection::Connection,
+    BasicProperties,
+};
+mod common;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_publish_timestamp_from_clock() {
+    common::setup_logging();
+
+    // open a connection to RabbitMQ server
+    let args = common::build_conn_args();
+
+    let connection = Connection::open(&args).await.unwrap();
+    let channel = connection.open_channel(None).await.unwrap();
+
+    let (queue_name, ..) = channel
+        .queue_declare(QueueDeclareArguments::default())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // timestamp is taken from a fixed clock instead of system time
+    let fixed_clock = || 1_674_404_425;
+    channel
+        .basic_publish(
+            BasicProperties::default()
+                .with_timestamp_from(&fixed_clock)
+                .finish(),
+            b"timestamped message".to_vec(),
+            BasicPublishArguments::new("", &queue_name),
+        )
+        .await
+        .unwrap();
+
+    let get_args = BasicGetArguments::new(&queue_name).no_ack(true).finish();
+    let (_, basic_props, content) = loop {
+        // wait until the message is routed to the queue
+        if let Some(message) = channel.basic_get(get_args.clone()).await.unwrap() {
+            break message;
+        }
+    };
+    assert_eq!(Some(1_674_404_425), basic_props.timestamp());
+    assert_eq!(b"timestamped message".to_vec(), content);
+
+    channel.close().await.unwrap();
+    connection.close().await.unwrap();
+}
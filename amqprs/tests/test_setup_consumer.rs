@@ -0,0 +1,105 @@
+use amqprs::{
+    channel::{
+        BasicPublishArguments, BasicQosArguments, QueueDeclareArguments, QueueDeleteArguments,
+        SetupConsumerArguments,
+    },
+    connection::Connection,
+    BasicProperties,
+};
+use tokio::time;
+mod common;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_setup_consumer() {
+    common::setup_logging();
+
+    // open a connection to RabbitMQ server
+    let args = common::build_conn_args();
+
+    let connection = Connection::open(&args).await.unwrap();
+    let channel = connection.open_channel(None).await.unwrap();
+
+    // declare, bind, qos and consume in one call
+    let args = SetupConsumerArguments::new(QueueDeclareArguments::default())
+        .bind("amq.topic", "amqprs.setup.#")
+        .qos(BasicQosArguments::new(0, 10, false))
+        .finish();
+    let (queue_name, mut stream) = channel.setup_consumer(args).await.unwrap();
+    assert!(!queue_name.is_empty());
+    assert_eq!(10, channel.qos().unwrap().prefetch_count);
+
+    channel
+        .basic_publish(
+            BasicProperties::default(),
+            b"setup".to_vec(),
+            BasicPublishArguments::new("amq.topic", "amqprs.setup.test"),
+        )
+        .await
+        .unwrap();
+    let message = stream
+        .recv_timeout(time::Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(b"setup".to_vec(), message.content.unwrap());
+
+    channel.close().await.unwrap();
+    connection.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_setup_consumer_cleanup_on_failure() {
+    common::setup_logging();
+
+    // open a connection to RabbitMQ server
+    let args = common::build_conn_args();
+
+    let connection = Connection::open(&args).await.unwrap();
+
+    // binding to a non-existing exchange fails after the queue is declared,
+    // the queue created by setup is deleted
+    let created_queue = "amqprs.test.setup_consumer_created";
+    let channel = connection.open_channel(None).await.unwrap();
+    let args = SetupConsumerArguments::new(QueueDeclareArguments::new(created_queue))
+        .bind("amqprs.test.no_such_exchange", "#")
+        .finish();
+    assert!(channel.setup_consumer(args).await.is_err());
+
+    let channel = connection.open_channel(None).await.unwrap();
+    let result = channel
+        .queue_declare(
+            QueueDeclareArguments::new(created_queue)
+                .passive(true)
+                .finish(),
+        )
+        .await;
+    assert!(result.is_err(), "created queue should be deleted");
+
+    // an existing queue is never deleted
+    let existing_queue = "amqprs.test.setup_consumer_existing";
+    let channel = connection.open_channel(None).await.unwrap();
+    channel
+        .queue_declare(QueueDeclareArguments::new(existing_queue))
+        .await
+        .unwrap();
+    let args = SetupConsumerArguments::new(QueueDeclareArguments::new(existing_queue))
+        .bind("amqprs.test.no_such_exchange", "#")
+        .finish();
+    assert!(channel.setup_consumer(args).await.is_err());
+
+    let channel = connection.open_channel(None).await.unwrap();
+    channel
+        .queue_declare(
+            QueueDeclareArguments::new(existing_queue)
+                .passive(true)
+                .finish(),
+        )
+        .await
+        .unwrap();
+    channel
+        .queue_delete(QueueDeleteArguments::new(existing_queue))
+        .await
+        .unwrap();
+
+    channel.close().await.unwrap();
+    connection.close().await.unwrap();
+}
@@ -95,11 +95,7 @@ mod client_amqprs {
             // publish  messages of variable sizes
             for &i in msg_size_list.iter().take(count) {
                 channel
-                    .basic_publish(
-                        BasicProperties::default(),
-                        vec![0xc5; i],
-                        pubargs.clone(),
-                    )
+                    .basic_publish(BasicProperties::default(), vec![0xc5; i], pubargs.clone())
                     .await
                     .unwrap();
             }
@@ -203,7 +199,7 @@ mod client_lapin {
 
             assert_eq!(0, q_state.message_count());
             // publish  messages of variable sizes
-            for &i in msg_size_list.iter().take(count)  {
+            for &i in msg_size_list.iter().take(count) {
                 let _confirm = channel
                     .basic_publish(
                         exchange_name,
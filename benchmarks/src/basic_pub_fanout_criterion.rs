@@ -0,0 +1,186 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+mod common;
+use common::*;
+
+/// benchmark fanning out the same message to many exchanges,
+/// comparing encoding its properties per publish with publishing pre-encoded properties.
+mod client_amqprs {
+    use super::{rt, setup_tracing, Criterion};
+    use amqprs::{
+        callbacks::{DefaultChannelCallback, DefaultConnectionCallback},
+        channel::{
+            BasicPublishArguments, ExchangeDeclareArguments, ExchangeDeleteArguments,
+            QueueBindArguments, QueueDeclareArguments, QueuePurgeArguments,
+        },
+        connection::{Connection, OpenConnectionArguments},
+        BasicProperties, EncodedProperties, FieldTable, FieldValue,
+    };
+
+    /// number of exchanges each message is fanned out to
+    const EXCHANGE_COUNT: usize = 10;
+    /// number of entries in the headers table
+    const HEADERS_COUNT: usize = 64;
+    /// number of messages fanned out per iteration
+    const MSG_COUNT: usize = 100;
+
+    fn headers() -> FieldTable {
+        let mut headers = FieldTable::new();
+        for i in 0..HEADERS_COUNT {
+            headers.insert(
+                format!("x-bench-header-{}", i).try_into().unwrap(),
+                FieldValue::S(format!("bench-value-{}", i).try_into().unwrap()),
+            );
+        }
+        headers
+    }
+
+    pub fn amqprs_basic_pub_fanout(c: &mut Criterion) {
+        setup_tracing();
+
+        let rt = rt();
+
+        // open a connection to RabbitMQ server
+        let connection = rt.block_on(async {
+            let connection = Connection::open(&OpenConnectionArguments::new(
+                "localhost",
+                5672,
+                "user",
+                "bitnami",
+            ))
+            .await
+            .unwrap();
+            connection
+                .register_callback(DefaultConnectionCallback)
+                .await
+                .unwrap();
+            connection
+        });
+
+        // open a channel on the connection
+        let channel = rt.block_on(async {
+            let channel = connection.open_channel(None).await.unwrap();
+            channel
+                .register_callback(DefaultChannelCallback)
+                .await
+                .unwrap();
+            channel
+        });
+
+        //////////////////////////////////////////////////////////////////////////////
+        // publish message
+        let queue_name = "bench-amqprs-fanout-q";
+        let exchange_names: Vec<String> = (0..EXCHANGE_COUNT)
+            .map(|i| format!("bench.amqprs.fanout.{}", i))
+            .collect();
+        rt.block_on(async {
+            // declare a queue
+            let (_, _, _) = channel
+                .queue_declare(QueueDeclareArguments::new(queue_name))
+                .await
+                .unwrap()
+                .unwrap();
+            // declare exchanges, all bound to the queue
+            for exchange_name in &exchange_names {
+                channel
+                    .exchange_declare(ExchangeDeclareArguments::new(exchange_name, "fanout"))
+                    .await
+                    .unwrap();
+                channel
+                    .queue_bind(QueueBindArguments::new(queue_name, exchange_name, ""))
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let pubargs: Vec<BasicPublishArguments> = exchange_names
+            .iter()
+            .map(|exchange_name| BasicPublishArguments::new(exchange_name, ""))
+            .collect();
+        let declargs = QueueDeclareArguments::new(queue_name)
+            .passive(true)
+            .finish();
+        let props = BasicProperties::default()
+            .with_content_type("application/json")
+            .with_headers(headers())
+            .finish();
+        let encoded = EncodedProperties::new(&props);
+
+        // check all messages arrived at queue
+        let wait_queue = || async {
+            loop {
+                let (_, msg_cnt, _) = channel
+                    .queue_declare(declargs.clone())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                if MSG_COUNT * EXCHANGE_COUNT == msg_cnt as usize {
+                    break;
+                }
+            }
+        };
+
+        // tasks to be benchmarked
+        let reencode_task = || async {
+            channel
+                .queue_purge(QueuePurgeArguments::new(queue_name))
+                .await
+                .unwrap();
+            for _ in 0..MSG_COUNT {
+                for args in &pubargs {
+                    channel
+                        .basic_publish(props.clone(), vec![0xc5; 64], args.clone())
+                        .await
+                        .unwrap();
+                }
+            }
+            wait_queue().await;
+        };
+        let preencoded_task = || async {
+            channel
+                .queue_purge(QueuePurgeArguments::new(queue_name))
+                .await
+                .unwrap();
+            for _ in 0..MSG_COUNT {
+                for args in &pubargs {
+                    channel
+                        .basic_publish_encoded(&encoded, vec![0xc5; 64], args.clone())
+                        .await
+                        .unwrap();
+                }
+            }
+            wait_queue().await;
+        };
+        // start benchmark
+        let mut group = c.benchmark_group("amqprs-basic-pub-fanout");
+        group.bench_function("reencode", |b| {
+            b.iter(|| {
+                rt.block_on(reencode_task());
+            })
+        });
+        group.bench_function("preencoded", |b| {
+            b.iter(|| {
+                rt.block_on(preencoded_task());
+            })
+        });
+        group.finish();
+        // explicitly close
+        rt.block_on(async {
+            for exchange_name in &exchange_names {
+                channel
+                    .exchange_delete(ExchangeDeleteArguments::new(exchange_name))
+                    .await
+                    .unwrap();
+            }
+            channel.close().await.unwrap();
+            connection.close().await.unwrap();
+        });
+    }
+}
+
+criterion_group! {
+    name = basic_pub_fanout;
+    config = Criterion::default();
+    targets = client_amqprs::amqprs_basic_pub_fanout
+}
+
+criterion_main!(basic_pub_fanout);
@@ -0,0 +1,143 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+mod common;
+use common::*;
+
+/// benchmark publishing the same message with a large headers table many times,
+/// which exercises the serialization path of `FieldTable` repeatedly.
+mod client_amqprs {
+    use super::{rt, setup_tracing, Criterion};
+    use amqprs::{
+        callbacks::{DefaultChannelCallback, DefaultConnectionCallback},
+        channel::{
+            BasicPublishArguments, QueueBindArguments, QueueDeclareArguments, QueuePurgeArguments,
+        },
+        connection::{Connection, OpenConnectionArguments},
+        BasicProperties, FieldTable, FieldValue,
+    };
+
+    /// number of entries in the headers table
+    const HEADERS_COUNT: usize = 256;
+    /// number of messages published per iteration
+    const MSG_COUNT: usize = 1000;
+
+    fn large_headers() -> FieldTable {
+        let mut headers = FieldTable::new();
+        for i in 0..HEADERS_COUNT {
+            headers.insert(
+                format!("x-bench-header-{}", i).try_into().unwrap(),
+                FieldValue::S(format!("bench-value-{}", i).try_into().unwrap()),
+            );
+        }
+        headers
+    }
+
+    pub fn amqprs_basic_pub_headers(c: &mut Criterion) {
+        setup_tracing();
+
+        let rt = rt();
+
+        // open a connection to RabbitMQ server
+        let connection = rt.block_on(async {
+            let connection = Connection::open(&OpenConnectionArguments::new(
+                "localhost",
+                5672,
+                "user",
+                "bitnami",
+            ))
+            .await
+            .unwrap();
+            connection
+                .register_callback(DefaultConnectionCallback)
+                .await
+                .unwrap();
+            connection
+        });
+
+        // open a channel on the connection
+        let channel = rt.block_on(async {
+            let channel = connection.open_channel(None).await.unwrap();
+            channel
+                .register_callback(DefaultChannelCallback)
+                .await
+                .unwrap();
+            channel
+        });
+
+        //////////////////////////////////////////////////////////////////////////////
+        // publish message
+        let rounting_key = "bench.amqprs.pub.headers";
+        let exchange_name = "amq.topic";
+        let queue_name = "bench-amqprs-headers-q";
+        rt.block_on(async {
+            // declare a queue
+            let (_, _, _) = channel
+                .queue_declare(QueueDeclareArguments::new(queue_name))
+                .await
+                .unwrap()
+                .unwrap();
+            // bind queue to exchange
+            channel
+                .queue_bind(QueueBindArguments::new(
+                    queue_name,
+                    exchange_name,
+                    rounting_key,
+                ))
+                .await
+                .unwrap();
+        });
+
+        let pubargs = BasicPublishArguments::new(exchange_name, rounting_key);
+        let declargs = QueueDeclareArguments::new(queue_name)
+            .passive(true)
+            .finish();
+        let props = BasicProperties::default()
+            .with_headers(large_headers())
+            .finish();
+
+        // task to be benchmarked
+        let task = || async {
+            // purge queue
+            channel
+                .queue_purge(QueuePurgeArguments::new(queue_name))
+                .await
+                .unwrap();
+            // publish the same message repeatedly
+            for _ in 0..MSG_COUNT {
+                channel
+                    .basic_publish(props.clone(), vec![0xc5; 64], pubargs.clone())
+                    .await
+                    .unwrap();
+            }
+            // check all messages arrived at queue
+            loop {
+                let (_, msg_cnt, _) = channel
+                    .queue_declare(declargs.clone())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                if MSG_COUNT == msg_cnt as usize {
+                    break;
+                }
+            }
+        };
+        // start benchmark
+        c.bench_function("amqprs-basic-pub-headers", |b| {
+            b.iter(|| {
+                rt.block_on(task());
+            })
+        });
+        // explicitly close
+        rt.block_on(async {
+            channel.close().await.unwrap();
+            connection.close().await.unwrap();
+        });
+    }
+}
+
+criterion_group! {
+    name = basic_pub_headers;
+    config = Criterion::default();
+    targets = client_amqprs::amqprs_basic_pub_headers
+}
+
+criterion_main!(basic_pub_headers);
@@ -9,6 +9,8 @@ use tokio::sync::Notify;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 /// We use Fibonacci sequences to generate size list for publish messages
+// not every benchmark uses all of the helpers below
+#[allow(dead_code)]
 pub struct Fib {
     max: usize,
     state: usize,
@@ -16,6 +18,7 @@ pub struct Fib {
     n_2: usize,
 }
 
+#[allow(dead_code)]
 impl Fib {
     pub fn new(max: usize) -> Self {
         assert!(max > 0);
@@ -49,6 +52,7 @@ impl Iterator for Fib {
 }
 
 /// common algorithm for generating size list
+#[allow(dead_code)]
 pub fn get_size_list(limit: usize) -> Vec<usize> {
     // construct message size list for publish
     let fib = Fib::new(100);
@@ -87,11 +91,13 @@ pub fn setup_tracing() {
         .ok();
 }
 
+#[allow(dead_code)]
 pub struct BenchMarkConsumer {
     end_tag: u64,
     notify: Arc<Notify>,
 }
 
+#[allow(dead_code)]
 impl BenchMarkConsumer {
     pub fn new(end_tag: u64, notify: Arc<Notify>) -> Self {
         Self { end_tag, notify }
@@ -108,7 +114,7 @@ impl AsyncConsumer for BenchMarkConsumer {
         _content: Vec<u8>,
     ) {
         // check all messages received
-        if deliver.delivery_tag() % self.end_tag == 0 {
+        if u64::from(deliver.delivery_tag()) % self.end_tag == 0 {
             // println!("{} % {}", deliver.delivery_tag(), self.end_tag);
             channel
                 .basic_ack(BasicAckArguments::new(deliver.delivery_tag(), true))
@@ -83,11 +83,7 @@ fn main() {
         // publish  messages of variable sizes
         for &i in msg_size_list.iter().take(count) {
             channel
-                .basic_publish(
-                    BasicProperties::default(),
-                    vec![0xc5; i],
-                    pubargs.clone(),
-                )
+                .basic_publish(BasicProperties::default(), vec![0xc5; i], pubargs.clone())
                 .await
                 .unwrap();
         }
@@ -0,0 +1,159 @@
+//! Count heap allocations when consuming a million messages,
+//! without and with recycling content buffers by `ConsumerMessage::recycle`.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use amqprs::{
+    callbacks::{DefaultChannelCallback, DefaultConnectionCallback},
+    channel::{
+        BasicAckArguments, BasicConsumeArguments, BasicPublishArguments, Channel, ConsumerMessage,
+        QueueBindArguments, QueueDeclareArguments, QueuePurgeArguments,
+    },
+    connection::{Connection, OpenConnectionArguments},
+    BasicProperties,
+};
+mod common;
+use common::*;
+
+/// Global allocator which counts number of allocations.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const MSG_COUNT: usize = 1_000_000;
+const MSG_SIZE: usize = 128;
+
+/// publish `MSG_COUNT` messages, then consume them all and
+/// return the number of allocations during consuming.
+async fn consume_all(
+    channel: &Channel,
+    queue_name: &str,
+    routing_key: &str,
+    recycle: bool,
+) -> usize {
+    let exchange_name = "amq.topic";
+    channel
+        .queue_purge(QueuePurgeArguments::new(queue_name))
+        .await
+        .unwrap();
+    let pubargs = BasicPublishArguments::new(exchange_name, routing_key);
+    for _ in 0..MSG_COUNT {
+        channel
+            .basic_publish(
+                BasicProperties::default(),
+                vec![0xc5; MSG_SIZE],
+                pubargs.clone(),
+            )
+            .await
+            .unwrap();
+    }
+    let declargs = QueueDeclareArguments::new(queue_name)
+        .passive(true)
+        .finish();
+    loop {
+        let (_, msg_cnt, _) = channel
+            .queue_declare(declargs.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        if MSG_COUNT == msg_cnt as usize {
+            break;
+        }
+    }
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let (_, mut rx) = channel
+        .basic_consume_rx(BasicConsumeArguments::new(queue_name, ""))
+        .await
+        .unwrap();
+    let mut received = 0;
+    while let Some(mut msg) = rx.recv().await {
+        received += 1;
+        let content = msg.content.take().unwrap();
+        if recycle {
+            ConsumerMessage::recycle(content);
+        }
+        if received == MSG_COUNT {
+            let tag = msg.deliver.unwrap().delivery_tag();
+            channel
+                .basic_ack(BasicAckArguments::new(tag, true))
+                .await
+                .unwrap();
+            break;
+        }
+    }
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn main() {
+    setup_tracing();
+
+    let rt = rt();
+
+    rt.block_on(async {
+        let connection = Connection::open(&OpenConnectionArguments::new(
+            "localhost",
+            5672,
+            "user",
+            "bitnami",
+        ))
+        .await
+        .unwrap();
+        connection
+            .register_callback(DefaultConnectionCallback)
+            .await
+            .unwrap();
+
+        let rounting_key = "bench.amqprs.consume.pool";
+        let exchange_name = "amq.topic";
+        let queue_name = "bench-amqprs-pool-q";
+
+        for recycle in [false, true] {
+            // use a new channel for each round, so that the consumers do not interfere
+            let channel = connection.open_channel(None).await.unwrap();
+            channel
+                .register_callback(DefaultChannelCallback)
+                .await
+                .unwrap();
+            channel
+                .queue_declare(QueueDeclareArguments::new(queue_name))
+                .await
+                .unwrap()
+                .unwrap();
+            channel
+                .queue_bind(QueueBindArguments::new(
+                    queue_name,
+                    exchange_name,
+                    rounting_key,
+                ))
+                .await
+                .unwrap();
+
+            let now = std::time::Instant::now();
+            let allocations = consume_all(&channel, queue_name, rounting_key, recycle).await;
+            let eclapsed = now.elapsed();
+            println!(
+                "amqprs consume {} messages, recycle = {}: {} allocations, {:?}",
+                MSG_COUNT, recycle, allocations, eclapsed
+            );
+            channel.close().await.unwrap();
+        }
+
+        connection.close().await.unwrap();
+    });
+}